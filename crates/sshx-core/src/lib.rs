@@ -4,6 +4,7 @@
 #![warn(missing_docs)]
 
 use std::fmt::Display;
+use std::str::FromStr;
 use std::sync::atomic::{AtomicU32, Ordering};
 
 use serde::{Deserialize, Serialize};
@@ -18,6 +19,17 @@ pub mod proto {
     pub const FILE_DESCRIPTOR_SET: &[u8] = tonic::include_file_descriptor_set!("sshx");
 }
 
+/// Redact the fragment of a URL so that it can be safely logged.
+///
+/// Session URLs carry the encryption key (and write password) in the fragment,
+/// which must never reach the server or any log output.
+pub fn redact_url(url: &str) -> String {
+    match url.split_once('#') {
+        Some((base, _)) => format!("{base}#<redacted>"),
+        None => url.to_string(),
+    }
+}
+
 /// Generate a cryptographically-secure, random alphanumeric value.
 pub fn rand_alphanumeric(len: usize) -> String {
     use rand::{distributions::Alphanumeric, thread_rng, Rng};
@@ -39,6 +51,14 @@ impl Display for Sid {
     }
 }
 
+impl FromStr for Sid {
+    type Err = ParseIdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_id(s, "terminal").map(Sid)
+    }
+}
+
 /// Unique identifier for a user within the session.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 #[serde(transparent)]
@@ -50,6 +70,40 @@ impl Display for Uid {
     }
 }
 
+impl FromStr for Uid {
+    type Err = ParseIdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_id(s, "user").map(Uid)
+    }
+}
+
+/// Error from parsing a [`Sid`] or [`Uid`] that is not a valid ID.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseIdError {
+    kind: &'static str,
+    input: String,
+}
+
+impl Display for ParseIdError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "invalid {} ID {:?}, expected a number such as 1",
+            self.kind, self.input
+        )
+    }
+}
+
+impl std::error::Error for ParseIdError {}
+
+fn parse_id(s: &str, kind: &'static str) -> Result<u32, ParseIdError> {
+    s.trim().parse().map_err(|_| ParseIdError {
+        kind,
+        input: s.to_string(),
+    })
+}
+
 /// A counter for generating unique identifiers.
 #[derive(Debug)]
 pub struct IdCounter {
@@ -91,3 +145,28 @@ impl IdCounter {
         self.next_uid.store(uid.0, Ordering::Relaxed);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Sid, Uid};
+
+    #[test]
+    fn id_round_trip() {
+        for id in [0, 1, 42, u32::MAX] {
+            assert_eq!(Sid(id).to_string().parse(), Ok(Sid(id)));
+            assert_eq!(Uid(id).to_string().parse(), Ok(Uid(id)));
+        }
+        assert_eq!(" 7 ".parse(), Ok(Sid(7)));
+    }
+
+    #[test]
+    fn id_invalid() {
+        for input in ["", "abc", "-1", "1.5", "4294967296"] {
+            assert!(input.parse::<Sid>().is_err(), "{input:?} should not parse");
+        }
+        let err = "abc".parse::<Sid>().unwrap_err();
+        assert_eq!(err.to_string(), "invalid terminal ID \"abc\", expected a number such as 1");
+        let err = "x".parse::<Uid>().unwrap_err();
+        assert!(err.to_string().starts_with("invalid user ID"));
+    }
+}
@@ -0,0 +1,130 @@
+//! Throughput and latency of relaying terminal output to subscribers.
+//!
+//! This pumps synthetic output through [`Session::add_data`] as fast as
+//! possible, while each subscriber reads it back through
+//! [`Session::subscribe_chunks`], the same path used by WebSocket clients.
+//! Subscribers that fall behind lose output pruned from the session's
+//! scrollback, which shows up as less than 100% delivered.
+//!
+//! Run with `cargo bench -p sshx-server --bench relay`, optionally followed by
+//! `-- <SUBSCRIBERS>...` to pick the subscriber counts.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use bytes::{BufMut, Bytes, BytesMut};
+use sshx_core::Sid;
+use sshx_server::session::{Metadata, ResumePoint, Session};
+use tokio_stream::StreamExt;
+
+/// Total bytes of output written to the shell in each run.
+const TOTAL_BYTES: u64 = 64 << 20; // 64 MiB
+
+/// Size of each chunk of output, about what a busy shell sends at once.
+const CHUNK_SIZE: usize = 4096;
+
+/// Results of one subscriber.
+#[derive(Default)]
+struct Received {
+    bytes: u64,
+    latencies: Vec<Duration>,
+}
+
+fn session() -> Session {
+    Session::new(Metadata {
+        encrypted_zeros: Bytes::from_static(&[0; 16]),
+        name: "bench".into(),
+        write_password_hash: None,
+        labels: Default::default(),
+        allow_spectators: false,
+        max_clients: None,
+        write_window: None,
+        write_window_renew: false,
+    })
+}
+
+/// A chunk of output that starts with the time it was written, relative to
+/// `base`, so subscribers can measure delivery latency.
+fn chunk(base: Instant) -> Bytes {
+    let mut buf = BytesMut::with_capacity(CHUNK_SIZE);
+    buf.put_u64(base.elapsed().as_nanos() as u64);
+    buf.resize(CHUNK_SIZE, b'x');
+    buf.freeze()
+}
+
+async fn subscribe(session: Arc<Session>, id: Sid, base: Instant) -> Received {
+    let mut received = Received::default();
+    let stream = session.subscribe_chunks(id, ResumePoint::Chunk(0));
+    tokio::pin!(stream);
+    while let Some((mut seqnum, chunks)) = stream.next().await {
+        let now = base.elapsed();
+        for chunk in chunks {
+            if chunk.len() >= 8 {
+                let sent = u64::from_be_bytes(chunk[..8].try_into().unwrap());
+                received.latencies.push(now.saturating_sub(Duration::from_nanos(sent)));
+            }
+            received.bytes += chunk.len() as u64;
+            seqnum += chunk.len() as u64;
+        }
+        if seqnum >= TOTAL_BYTES {
+            break;
+        }
+    }
+    received
+}
+
+async fn run(subscribers: usize) {
+    let session = Arc::new(session());
+    let id = Sid(1);
+    session.add_shell(id, (0, 0)).unwrap();
+
+    let base = Instant::now();
+    let tasks: Vec<_> = (0..subscribers)
+        .map(|_| tokio::spawn(subscribe(Arc::clone(&session), id, base)))
+        .collect();
+
+    let start = Instant::now();
+    let mut seq = 0;
+    while seq < TOTAL_BYTES {
+        session.add_data(id, chunk(base), seq).unwrap();
+        seq += CHUNK_SIZE as u64;
+        // Let subscribers run, as the gRPC handler would between messages.
+        tokio::task::yield_now().await;
+    }
+    let written = start.elapsed();
+
+    let mut bytes = 0;
+    let mut latencies = Vec::new();
+    for task in tasks {
+        let received = task.await.unwrap();
+        bytes += received.bytes;
+        latencies.extend(received.latencies);
+    }
+    let elapsed = start.elapsed();
+    session.shutdown();
+
+    latencies.sort_unstable();
+    let percentile = |p: usize| latencies.get(latencies.len() * p / 100).copied();
+    let mib = |bytes: u64, time: Duration| bytes as f64 / (1 << 20) as f64 / time.as_secs_f64();
+    println!(
+        "relay/{subscribers:<4} write {:>8.1} MiB/s  relay {:>8.1} MiB/s total  \
+         p50 {:>9.2?}  p99 {:>9.2?}  delivered {:>5.1}%",
+        mib(TOTAL_BYTES, written),
+        mib(bytes, elapsed),
+        percentile(50).unwrap_or_default(),
+        percentile(99).unwrap_or_default(),
+        100.0 * bytes as f64 / (TOTAL_BYTES * subscribers as u64) as f64,
+    );
+}
+
+#[tokio::main]
+async fn main() {
+    // Cargo passes `--bench` to benchmark binaries, which is not a count.
+    let mut counts: Vec<usize> = std::env::args().skip(1).filter_map(|a| a.parse().ok()).collect();
+    if counts.is_empty() {
+        counts = vec![1, 8, 64];
+    }
+    for subscribers in counts {
+        run(subscribers).await;
+    }
+}
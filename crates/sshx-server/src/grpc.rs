@@ -1,10 +1,9 @@
 //! Defines gRPC routes and application request logic.
 
+use std::collections::BTreeSet;
 use std::sync::Arc;
 use std::time::{Duration, SystemTime};
 
-use base64::prelude::{Engine as _, BASE64_STANDARD};
-use hmac::Mac;
 use sshx_core::proto::{
     client_update::ClientMessage, server_update::ServerMessage, sshx_service_server::SshxService,
     ClientUpdate, CloseRequest, CloseResponse, OpenRequest, OpenResponse, ServerUpdate,
@@ -16,7 +15,8 @@ use tokio_stream::{wrappers::ReceiverStream, StreamExt};
 use tonic::{Request, Response, Status, Streaming};
 use tracing::{error, info, warn};
 
-use crate::session::{Metadata, Session};
+use crate::session::{validate_labels, Metadata, Session, ENCRYPTED_ZEROS_LEN};
+use crate::state::events::Event;
 use crate::ServerState;
 
 /// Interval for synchronizing sequence numbers with the client.
@@ -43,31 +43,65 @@ impl SshxService for GrpcServer {
     type ChannelStream = ReceiverStream<Result<ServerUpdate, Status>>;
 
     async fn open(&self, request: Request<OpenRequest>) -> RR<OpenResponse> {
+        let authorization = request.metadata().get("authorization");
+        if !self.0.check_auth(authorization.and_then(|value| value.to_str().ok())) {
+            return Err(Status::unauthenticated("missing or invalid bearer token"));
+        }
         let request = request.into_inner();
-        let origin = self.0.override_origin().unwrap_or(request.origin);
+        let origin = self.0.session_origin(request.origin);
         if origin.is_empty() {
             return Err(Status::invalid_argument("origin is empty"));
         }
+        if request.encrypted_zeros.len() != ENCRYPTED_ZEROS_LEN {
+            return Err(Status::invalid_argument("encrypted_zeros has invalid length"));
+        }
+        let labels = validate_labels(request.labels)
+            .map_err(|err| Status::invalid_argument(err.to_string()))?;
+        if self.0.options().is_name_blocked(&request.name) {
+            let message = format!("session name {:?} is not allowed on this server", request.name);
+            return Err(Status::invalid_argument(message));
+        }
         let name = rand_alphanumeric(10);
         info!(%name, "creating new session");
 
-        match self.0.lookup(&name) {
+        let host_key = match self.0.lookup(&name) {
             Some(_) => return Err(Status::already_exists("generated duplicate ID")),
             None => {
                 let metadata = Metadata {
                     encrypted_zeros: request.encrypted_zeros,
                     name: request.name,
                     write_password_hash: request.write_password_hash,
+                    labels,
+                    allow_spectators: request.allow_spectators,
+                    max_clients: Some(request.max_clients).filter(|&max| max > 0),
+                    write_window: (request.write_window_secs > 0)
+                        .then(|| Duration::from_secs(request.write_window_secs.into())),
+                    write_window_renew: request.write_window_renew,
                 };
-                self.0.insert(&name, Arc::new(Session::new(metadata)));
+                let session = Session::new(metadata);
+                if request.start_locked {
+                    session.set_locked(true);
+                }
+                let host_key = session.host_key().to_owned();
+                self.0.insert(&name, Arc::new(session));
+                self.0.events().emit(Event::SessionOpen {
+                    session: name.clone(),
+                });
+                host_key
             }
         };
-        let token = self.0.mac().chain_update(&name).finalize();
+        let token = self.0.issue_token(&name);
         let url = format!("{origin}/s/{name}");
+        let spectator_url = match request.allow_spectators {
+            true => format!("{origin}/spectate/{name}"),
+            false => String::new(),
+        };
         Ok(Response::new(OpenResponse {
             name,
-            token: BASE64_STANDARD.encode(token.into_bytes()),
+            token,
             url,
+            host_key,
+            spectator_url,
         }))
     }
 
@@ -82,7 +116,7 @@ impl SshxService for GrpcServer {
                 let (name, token) = hello
                     .split_once(',')
                     .ok_or_else(|| Status::invalid_argument("missing name and token"))?;
-                validate_token(self.0.mac(), name, token)?;
+                validate_token(&self.0, name, token)?;
                 name.to_string()
             }
             _ => return Err(Status::invalid_argument("invalid first message")),
@@ -92,6 +126,10 @@ impl SshxService for GrpcServer {
             Ok(None) => return Err(Status::not_found("session not found")),
             Err(err) => {
                 error!(?err, "failed to connect to backend session");
+                self.0.events().emit(Event::Error {
+                    session: Some(session_name),
+                    message: format!("backend connect: {err}"),
+                });
                 return Err(Status::internal(err.to_string()));
             }
         };
@@ -100,8 +138,10 @@ impl SshxService for GrpcServer {
         // when this task finishes, the sender end is dropped, so the receiver is
         // automatically closed.
         let (tx, rx) = mpsc::channel(16);
+        let max_shells = self.0.options().max_shells();
         tokio::spawn(async move {
-            if let Err(err) = handle_streaming(&tx, &session, stream).await {
+            let _backend_guard = session.backend_scope();
+            if let Err(err) = handle_streaming(&tx, &session, stream, max_shells).await {
                 warn!(?err, "connection exiting early due to an error");
             }
         });
@@ -111,7 +151,7 @@ impl SshxService for GrpcServer {
 
     async fn close(&self, request: Request<CloseRequest>) -> RR<CloseResponse> {
         let request = request.into_inner();
-        validate_token(self.0.mac(), &request.name, &request.token)?;
+        validate_token(&self.0, &request.name, &request.token)?;
         info!("closing session {}", request.name);
         if let Err(err) = self.0.close_session(&request.name).await {
             error!(?err, "failed to close session {}", request.name);
@@ -123,11 +163,9 @@ impl SshxService for GrpcServer {
 
 /// Validate the client token for a session.
 #[allow(clippy::result_large_err)]
-fn validate_token(mac: impl Mac, name: &str, token: &str) -> tonic::Result<()> {
-    if let Ok(token) = BASE64_STANDARD.decode(token) {
-        if mac.chain_update(name).verify_slice(&token).is_ok() {
-            return Ok(());
-        }
+fn validate_token(state: &ServerState, name: &str, token: &str) -> tonic::Result<()> {
+    if state.verify_token(name, token) {
+        return Ok(());
     }
     Err(Status::unauthenticated("invalid token"))
 }
@@ -139,6 +177,7 @@ async fn handle_streaming(
     tx: &ServerTx,
     session: &Session,
     mut stream: Streaming<ClientUpdate>,
+    max_shells: usize,
 ) -> Result<(), &'static str> {
     let mut sync_interval = time::interval(SYNC_INTERVAL);
     sync_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
@@ -146,6 +185,9 @@ async fn handle_streaming(
     let mut ping_interval = time::interval(PING_INTERVAL);
     ping_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
 
+    let mut known_users = BTreeSet::new();
+    let mut last_user_count = None;
+
     loop {
         tokio::select! {
             // Send periodic sync messages to the client.
@@ -158,6 +200,14 @@ async fn handle_streaming(
             // Send periodic pings to the client.
             _ = ping_interval.tick() => {
                 send_msg(tx, ServerMessage::Ping(get_time_ms())).await;
+                for msg in session.presence_changes(&mut known_users) {
+                    send_msg(tx, msg).await;
+                }
+                let user_count = session.user_count();
+                if last_user_count != Some(user_count) {
+                    last_user_count = Some(user_count);
+                    send_msg(tx, ServerMessage::UserCount(user_count as u32)).await;
+                }
             }
             // Send buffered server updates to the client.
             Ok(msg) = session.update_rx().recv() => {
@@ -168,7 +218,7 @@ async fn handle_streaming(
             // Handle incoming client messages.
             maybe_update = stream.next() => {
                 if let Some(Ok(update)) = maybe_update {
-                    if !handle_update(tx, session, update).await {
+                    if !handle_update(tx, session, update, max_shells).await {
                         return Err("error responding to client update");
                     }
                 } else {
@@ -187,7 +237,12 @@ async fn handle_streaming(
 }
 
 /// Handles a singe update from the client. Returns `true` on success.
-async fn handle_update(tx: &ServerTx, session: &Session, update: ClientUpdate) -> bool {
+async fn handle_update(
+    tx: &ServerTx,
+    session: &Session,
+    update: ClientUpdate,
+    max_shells: usize,
+) -> bool {
     session.access();
     match update.client_message {
         Some(ClientMessage::Hello(_)) => {
@@ -198,9 +253,19 @@ async fn handle_update(tx: &ServerTx, session: &Session, update: ClientUpdate) -
                 return send_err(tx, format!("add data: {:?}", err)).await;
             }
         }
+        Some(ClientMessage::SpectatorData(data)) => {
+            if let Err(err) = session.add_spectator_data(Sid(data.id), data.data, data.seq) {
+                return send_err(tx, format!("add spectator data: {:?}", err)).await;
+            }
+        }
         Some(ClientMessage::CreatedShell(new_shell)) => {
             let id = Sid(new_shell.id);
             let center = (new_shell.x, new_shell.y);
+            if let Err(err) = session.check_shell_limit(max_shells) {
+                // The host already spawned this shell, so ask it to close it.
+                session.update_tx().send(ServerMessage::CloseShell(id.0)).await.ok();
+                return send_err(tx, format!("add shell: {err}")).await;
+            }
             if let Err(err) = session.add_shell(id, center) {
                 return send_err(tx, format!("add shell: {:?}", err)).await;
             }
@@ -210,6 +275,12 @@ async fn handle_update(tx: &ServerTx, session: &Session, update: ClientUpdate) -
                 return send_err(tx, format!("close shell: {:?}", err)).await;
             }
         }
+        Some(ClientMessage::ExitedShell(exit)) => {
+            if let Err(err) = session.set_exit_code(Sid(exit.id), exit.exit_code) {
+                return send_err(tx, format!("exit shell: {:?}", err)).await;
+            }
+        }
+        Some(ClientMessage::Locked(locked)) => session.set_locked(locked),
         Some(ClientMessage::Pong(ts)) => {
             let latency = get_time_ms().saturating_sub(ts);
             session.send_latency_measurement(latency);
@@ -6,16 +6,24 @@
 //! web listener.
 //!
 //! Most web requests are routed directly to static files located in the
-//! `build/` folder relative to where this binary is running, allowing the
-//! frontend to be separately developed from the server.
+//! `build/` folder relative to where this binary is running (configurable with
+//! [`ServerOptions::assets_dir`]), allowing the frontend to be separately
+//! developed from the server.
 
 #![forbid(unsafe_code)]
 #![warn(missing_docs)]
 
-use std::{fmt::Debug, net::SocketAddr, sync::Arc};
+use std::{
+    fmt::Debug,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
 
 use anyhow::Result;
 use axum::serve::{Listener, ListenerExt};
+use regex::Regex;
 use tokio::net::TcpListener;
 use tracing::debug;
 use utils::Shutdown;
@@ -36,6 +44,19 @@ pub struct ServerOptions {
     /// Secret used for signing tokens. Set randomly if not provided.
     pub secret: Option<String>,
 
+    /// Previous secret, whose tokens are still accepted after a rotation.
+    ///
+    /// To rotate secrets, move the current value of `secret` here and set a
+    /// new `secret`. New tokens are always signed with `secret`.
+    pub previous_secret: Option<String>,
+
+    /// How long after startup tokens from `previous_secret` are accepted.
+    ///
+    /// If not set, they are accepted for as long as `previous_secret` is
+    /// configured. Once the grace period has passed, or all sessions from
+    /// before the rotation have ended, `previous_secret` can be removed.
+    pub previous_secret_grace: Option<Duration>,
+
     /// Override the origin returned for the Open() RPC.
     pub override_origin: Option<String>,
 
@@ -44,6 +65,161 @@ pub struct ServerOptions {
 
     /// Hostname of this server, if running multiple servers.
     pub host: Option<String>,
+
+    /// Public base URL of this server, used for externally-visible links.
+    ///
+    /// All absolute links built by the server (session URLs from the Open()
+    /// RPC and dashboard URLs) derive from this, taking precedence over
+    /// `override_origin`. It may include a path when the server is mounted
+    /// under a subpath, e.g. `https://example.com/sshx`.
+    pub public_base_url: Option<String>,
+
+    /// Disable the `/api/cli/{name}` WebSocket route for CLI clients.
+    ///
+    /// Clients must then use gRPC to create sessions, as the WebSocket
+    /// fallback for restrictive networks will be unavailable.
+    pub disable_cli_websocket: bool,
+
+    /// Maximum age of session tokens issued to clients, if limited.
+    ///
+    /// Clients hold their token for the lifetime of a session and do not
+    /// refresh it, so this should exceed the longest expected session.
+    pub token_max_age: Option<Duration>,
+
+    /// How long a session is kept after its last client disconnects, if limited.
+    ///
+    /// Sessions whose users have all left are closed once they have also had
+    /// no `sshx` client connected for this long, so a user reconnecting within
+    /// the grace period finds the session intact. Sessions that never had users
+    /// are not affected.
+    pub empty_session_grace: Option<Duration>,
+
+    /// Directory of static frontend assets, `build` if not set.
+    ///
+    /// Relative paths are resolved against the server's working directory.
+    pub assets_dir: Option<PathBuf>,
+
+    /// Fail at startup if the frontend assets are unavailable, rather than
+    /// only logging a warning.
+    pub require_assets: bool,
+
+    /// Disable on-the-fly compression of static assets.
+    ///
+    /// Precompressed `.br` and `.gz` files next to an asset are always
+    /// preferred. Other responses are compressed per request, which costs CPU
+    /// time on each request but avoids serving uncompressed payloads when the
+    /// build did not emit precompressed variants.
+    pub disable_compression: bool,
+
+    /// Discard terminal output produced while a session is paused.
+    ///
+    /// By default, output is buffered during a pause and relayed to viewers
+    /// once the session resumes. When discarding, it is dropped on resume, so
+    /// it is never sent to any viewer, while earlier scrollback is kept.
+    pub discard_paused_output: bool,
+
+    /// Require this bearer token to open sessions and connect WebSockets.
+    ///
+    /// When set, the gRPC `Open` call and the WebSocket upgrades on `/api/cli`
+    /// and `/api/s` must carry a matching `Authorization: Bearer` header. This
+    /// is transport-level gating of who may reach the server, not end-to-end
+    /// security: terminal data is still protected by the session key. Browsers
+    /// cannot set this header on WebSockets, so the web app needs a reverse
+    /// proxy that adds it.
+    pub auth_token: Option<String>,
+
+    /// Bearer token for admin routes, such as the `/api/admin/events` stream.
+    ///
+    /// Admin routes are disabled when this is not set.
+    pub admin_token: Option<String>,
+
+    /// Maximum number of open shells in each session, [`DEFAULT_MAX_SHELLS`]
+    /// if not set.
+    ///
+    /// Requests to create more shells are rejected with an error, and shells
+    /// created by the host beyond the limit are closed again.
+    pub max_shells: Option<usize>,
+
+    /// Connect to other servers in the mesh over TLS.
+    ///
+    /// WebSockets for sessions owned by another server are proxied to it with
+    /// `wss://` instead of plaintext `ws://`, so each server's `host` must be
+    /// reachable with a valid certificate, e.g. through a TLS-terminating
+    /// proxy in front of it.
+    pub mesh_tls: bool,
+
+    /// Maximum size in bytes of a message received over WebSocket,
+    /// [`DEFAULT_MAX_MESSAGE_SIZE`] if not set.
+    ///
+    /// This also bounds each frame, so oversized messages are rejected by the
+    /// protocol layer as they arrive, before being buffered in full.
+    pub max_message_size: Option<usize>,
+
+    /// Words that session names may not contain, compared case-insensitively.
+    ///
+    /// A word blocks a name if it equals the whole name or any of its
+    /// alphanumeric words, so `admin` blocks both `admin` and `Admin@host`.
+    pub blocked_names: Vec<String>,
+
+    /// Pattern that session names may not match, if set.
+    ///
+    /// This is matched anywhere in the name, so it should be anchored with
+    /// `^` and `$` to only block whole names.
+    pub blocked_name_pattern: Option<Regex>,
+
+    /// How long to collect terminal output before sending it to WebSocket
+    /// clients, if coalescing.
+    ///
+    /// Output that arrives within this window after the first write is sent
+    /// to each client in one message, which reduces frame overhead for bursty
+    /// output such as progress bars or echoed keystrokes. Output is never
+    /// delayed for longer than the window. Disabled if not set or zero.
+    pub chunk_coalesce: Option<Duration>,
+}
+
+/// Default limit on the number of open shells in a session.
+pub const DEFAULT_MAX_SHELLS: usize = 100;
+
+/// Default limit on the size of a WebSocket message, in bytes.
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 1 << 20;
+
+impl ServerOptions {
+    /// Returns the directory that static frontend assets are served from.
+    pub fn assets_path(&self) -> &Path {
+        self.assets_dir.as_deref().unwrap_or(Path::new("build"))
+    }
+
+    /// Returns the maximum number of open shells in each session.
+    pub fn max_shells(&self) -> usize {
+        self.max_shells.unwrap_or(DEFAULT_MAX_SHELLS)
+    }
+
+    /// Returns the maximum size of a message received over WebSocket.
+    pub fn max_message_size(&self) -> usize {
+        self.max_message_size.unwrap_or(DEFAULT_MAX_MESSAGE_SIZE)
+    }
+
+    /// Returns the window for coalescing terminal output, zero if disabled.
+    pub fn chunk_coalesce(&self) -> Duration {
+        self.chunk_coalesce.unwrap_or(Duration::ZERO)
+    }
+
+    /// Returns whether a session name is disallowed by the name blocklist.
+    pub fn is_name_blocked(&self, name: &str) -> bool {
+        let blocked = |word: &str| {
+            self.blocked_names
+                .iter()
+                .any(|blocked| !blocked.is_empty() && blocked.eq_ignore_ascii_case(word))
+        };
+        blocked(name)
+            || name
+                .split(|c: char| !c.is_alphanumeric())
+                .any(blocked)
+            || self
+                .blocked_name_pattern
+                .as_ref()
+                .is_some_and(|pattern| pattern.is_match(name))
+    }
 }
 
 /// Stateful object that manages the sshx server, with graceful termination.
@@ -55,6 +231,8 @@ pub struct Server {
 impl Server {
     /// Create a new application server, but do not listen for connections yet.
     pub fn new(options: ServerOptions) -> Result<Self> {
+        web::check_assets(&options)?;
+
         // Start dashboard cleanup task
         web::start_dashboard_cleanup();
 
@@ -73,7 +251,7 @@ impl Server {
     pub async fn listen<L>(&self, listener: L) -> Result<()>
     where
         L: Listener,
-        L::Addr: Debug,
+        L::Addr: Debug + 'static,
     {
         let state = self.state.clone();
         let terminated = self.shutdown.wait();
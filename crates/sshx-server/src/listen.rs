@@ -1,16 +1,43 @@
-use std::{fmt::Debug, future::Future, sync::Arc};
+use std::{any::Any, convert::Infallible, fmt::Debug, future::Future, net::SocketAddr, sync::Arc};
 
 use anyhow::Result;
 use axum::body::Body;
-use axum::serve::Listener;
-use http::{header::CONTENT_TYPE, Request};
+use axum::extract::ConnectInfo;
+use axum::middleware::{self, Next};
+use axum::response::Response;
+use axum::serve::{IncomingStream, Listener};
+use axum::Extension;
+use http::{header::CONTENT_TYPE, HeaderValue, Request};
 use sshx_core::proto::{sshx_service_server::SshxServiceServer, FILE_DESCRIPTOR_SET};
 use tonic::service::Routes as TonicRoutes;
-use tower::{make::Shared, steer::Steer, ServiceExt};
+use tower::{service_fn, steer::Steer, Layer, ServiceExt};
 use tower_http::trace::TraceLayer;
+use tracing::Span;
 
+use crate::utils::{new_request_id, request_id, REQUEST_ID_HEADER};
 use crate::{grpc::GrpcServer, web, ServerState};
 
+/// Tag each HTTP request with a fresh correlation id, echoed in the response.
+///
+/// Any id sent by the client is replaced, so that ids in logs are trustworthy.
+async fn set_request_id(mut request: Request<Body>, next: Next) -> Response {
+    let id = HeaderValue::from_str(&new_request_id()).expect("id is alphanumeric");
+    request.headers_mut().insert(REQUEST_ID_HEADER, id.clone());
+    let mut response = next.run(request).await;
+    response.headers_mut().insert(REQUEST_ID_HEADER, id);
+    response
+}
+
+/// Create the tracing span for an HTTP request, including its correlation id.
+fn make_request_span(request: &Request<Body>) -> Span {
+    tracing::debug_span!(
+        "request",
+        method = %request.method(),
+        uri = %request.uri(),
+        request_id = request_id(request.headers()),
+    )
+}
+
 /// Bind and listen from the application, with a state and termination signal.
 ///
 /// This internal method is responsible for multiplexing the HTTP and gRPC
@@ -22,11 +49,12 @@ pub(crate) async fn start_server<L>(
 ) -> Result<()>
 where
     L: Listener,
-    L::Addr: Debug,
+    L::Addr: Debug + 'static,
 {
-    let http_service = web::app()
+    let http_service = web::app(state.options())
         .with_state(state.clone())
-        .layer(TraceLayer::new_for_http())
+        .layer(TraceLayer::new_for_http().make_span_with(make_request_span))
+        .layer(middleware::from_fn(set_request_id))
         .into_service()
         .boxed_clone();
 
@@ -55,7 +83,15 @@ where
             }
         },
     );
-    let make_svc = Shared::new(svc);
+
+    // Attach the remote IP to each request, for per-client rate limiting. It is
+    // `None` for listeners that are not TCP sockets.
+    let make_svc = service_fn(move |incoming: IncomingStream<'_, L>| {
+        let addr: &dyn Any = incoming.remote_addr();
+        let ip = addr.downcast_ref::<SocketAddr>().map(|addr| addr.ip());
+        let svc = Extension(ConnectInfo(ip)).layer(svc.clone());
+        async move { Ok::<_, Infallible>(svc) }
+    });
 
     axum::serve(listener, make_svc)
         .with_graceful_shutdown(signal)
@@ -1,11 +1,14 @@
 use std::{
     net::{IpAddr, SocketAddr},
+    path::PathBuf,
     process::ExitCode,
+    time::Duration,
 };
 
 use anyhow::Result;
-use clap::Parser;
-use sshx_server::{Server, ServerOptions};
+use clap::{Parser, ValueEnum};
+use regex::Regex;
+use sshx_server::{Server, ServerOptions, DEFAULT_MAX_MESSAGE_SIZE, DEFAULT_MAX_SHELLS};
 use tokio::signal::unix::{signal, SignalKind};
 use tracing::{error, info};
 
@@ -25,6 +28,15 @@ struct Args {
     #[clap(long, env = "SSHX_SECRET")]
     secret: Option<String>,
 
+    /// Previous secret, still accepted for existing sessions after rotation.
+    #[clap(long, env = "SSHX_PREVIOUS_SECRET")]
+    previous_secret: Option<String>,
+
+    /// Seconds after startup to keep accepting tokens from the previous
+    /// secret. Accepted indefinitely if not set.
+    #[clap(long, env = "SSHX_PREVIOUS_SECRET_GRACE", value_name = "SECONDS")]
+    previous_secret_grace: Option<u64>,
+
     /// Override the origin URL returned by the Open() RPC.
     #[clap(long)]
     override_origin: Option<String>,
@@ -36,6 +48,107 @@ struct Args {
     /// Hostname of this server, if running multiple servers.
     #[clap(long, env = "SSHX_HOST")]
     host: Option<String>,
+
+    /// Public base URL of this server, used for links such as dashboards.
+    #[clap(long, env = "SSHX_PUBLIC_BASE_URL")]
+    public_base_url: Option<String>,
+
+    /// Disable the WebSocket route for CLI clients, requiring gRPC.
+    #[clap(long, env = "SSHX_DISABLE_CLI_WEBSOCKET")]
+    disable_cli_websocket: bool,
+
+    /// Maximum age of session tokens in seconds, after which clients can no
+    /// longer control their session. Tokens never expire if not set.
+    #[clap(long, env = "SSHX_TOKEN_MAX_AGE", value_name = "SECONDS")]
+    token_max_age: Option<u64>,
+
+    /// Seconds to keep a session open after its last viewer and the host have
+    /// both disconnected. Such sessions are kept until they expire if not set,
+    /// and closed right away with 0.
+    #[clap(long, env = "SSHX_EMPTY_SESSION_GRACE", value_name = "SECONDS")]
+    empty_session_grace: Option<u64>,
+
+    /// Directory of static frontend assets, relative to the working directory
+    /// unless absolute.
+    #[clap(long, env = "SSHX_ASSETS_DIR", value_name = "DIR", default_value = "build")]
+    assets_dir: PathBuf,
+
+    /// Exit at startup if the frontend assets directory is missing.
+    #[clap(long)]
+    require_assets: bool,
+
+    /// Disable on-the-fly compression of static assets that have no
+    /// precompressed `.br` or `.gz` variant, saving CPU time.
+    #[clap(long, env = "SSHX_DISABLE_COMPRESSION")]
+    disable_compression: bool,
+
+    /// Drop terminal output produced while a session is paused, instead of
+    /// relaying it once the session resumes.
+    #[clap(long, env = "SSHX_DISCARD_PAUSED_OUTPUT")]
+    discard_paused_output: bool,
+
+    /// Require `Authorization: Bearer <TOKEN>` to open sessions and connect
+    /// WebSockets. This gates the transport only, not end-to-end.
+    #[clap(long, env = "SSHX_AUTH_TOKEN", value_name = "TOKEN")]
+    auth_token: Option<String>,
+
+    /// Require `Authorization: Bearer <TOKEN>` for admin routes, such as the
+    /// `/api/admin/events` stream. Admin routes are disabled if not set.
+    #[clap(long, env = "SSHX_ADMIN_TOKEN", value_name = "TOKEN")]
+    admin_token: Option<String>,
+
+    /// Maximum number of open terminals in each session.
+    #[clap(
+        long,
+        env = "SSHX_MAX_SHELLS",
+        value_name = "COUNT",
+        default_value_t = DEFAULT_MAX_SHELLS
+    )]
+    max_shells: usize,
+
+    /// Maximum size in bytes of a message received over WebSocket. Larger
+    /// messages are rejected and close the connection.
+    #[clap(
+        long,
+        env = "SSHX_MAX_MESSAGE_SIZE",
+        value_name = "BYTES",
+        default_value_t = DEFAULT_MAX_MESSAGE_SIZE
+    )]
+    max_message_size: usize,
+
+    /// Comma-separated words that session names may not contain, such as
+    /// `admin,api`. Compared case-insensitively against each word of a name.
+    #[clap(long, env = "SSHX_BLOCKED_NAMES", value_name = "WORDS", value_delimiter = ',')]
+    blocked_names: Vec<String>,
+
+    /// Regular expression that session names may not match.
+    #[clap(long, env = "SSHX_BLOCKED_NAME_PATTERN", value_name = "REGEX")]
+    blocked_name_pattern: Option<Regex>,
+
+    /// Milliseconds to collect terminal output before sending it to web
+    /// clients, batching bursts of small writes into fewer messages. Output
+    /// is sent right away with 0.
+    #[clap(long, env = "SSHX_COALESCE_MS", value_name = "MILLISECONDS", default_value_t = 0)]
+    coalesce_ms: u64,
+
+    /// Connect to other servers over TLS (`wss://`) when proxying WebSockets
+    /// for sessions they own.
+    #[clap(long, env = "SSHX_MESH_TLS")]
+    mesh_tls: bool,
+
+    /// Format of log output on stderr. With `json`, each line is an object
+    /// that includes the fields of its spans, such as the session name.
+    #[clap(long, value_enum, env = "SSHX_LOG_FORMAT", default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+}
+
+/// Output format of server logs.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum LogFormat {
+    /// Human-readable lines.
+    Text,
+    /// One JSON object per line, for log ingestion.
+    Json,
 }
 
 #[tokio::main]
@@ -47,9 +160,27 @@ async fn start(args: Args) -> Result<()> {
 
     let mut options = ServerOptions::default();
     options.secret = args.secret;
+    options.previous_secret = args.previous_secret;
+    options.previous_secret_grace = args.previous_secret_grace.map(Duration::from_secs);
     options.override_origin = args.override_origin;
     options.redis_url = args.redis_url;
     options.host = args.host;
+    options.public_base_url = args.public_base_url;
+    options.disable_cli_websocket = args.disable_cli_websocket;
+    options.token_max_age = args.token_max_age.map(Duration::from_secs);
+    options.empty_session_grace = args.empty_session_grace.map(Duration::from_secs);
+    options.assets_dir = Some(args.assets_dir);
+    options.require_assets = args.require_assets;
+    options.disable_compression = args.disable_compression;
+    options.discard_paused_output = args.discard_paused_output;
+    options.auth_token = args.auth_token.filter(|token| !token.is_empty());
+    options.admin_token = args.admin_token.filter(|token| !token.is_empty());
+    options.max_shells = Some(args.max_shells);
+    options.mesh_tls = args.mesh_tls;
+    options.max_message_size = Some(args.max_message_size);
+    options.blocked_names = args.blocked_names.iter().map(|w| w.trim().to_owned()).collect();
+    options.blocked_name_pattern = args.blocked_name_pattern;
+    options.chunk_coalesce = Some(Duration::from_millis(args.coalesce_ms));
 
     let server = Server::new(options)?;
 
@@ -76,10 +207,13 @@ async fn start(args: Args) -> Result<()> {
 fn main() -> ExitCode {
     let args = Args::parse();
 
-    tracing_subscriber::fmt()
+    let subscriber = tracing_subscriber::fmt()
         .with_env_filter(std::env::var("RUST_LOG").unwrap_or("info".into()))
-        .with_writer(std::io::stderr)
-        .init();
+        .with_writer(std::io::stderr);
+    match args.log_format {
+        LogFormat::Text => subscriber.init(),
+        LogFormat::Json => subscriber.json().with_current_span(true).with_span_list(true).init(),
+    }
 
     match start(args) {
         Ok(()) => ExitCode::SUCCESS,
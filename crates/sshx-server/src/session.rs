@@ -1,15 +1,17 @@
 //! Core logic for sshx sessions, independent of message transport.
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::ops::DerefMut;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
-use anyhow::{bail, Context, Result};
+use anyhow::{bail, ensure, Context, Result};
 use bytes::Bytes;
 use parking_lot::{Mutex, RwLock, RwLockWriteGuard};
 use sshx_core::{
     proto::{server_update::ServerMessage, SequenceNumbers},
-    IdCounter, Sid, Uid,
+    rand_alphanumeric, IdCounter, Sid, Uid,
 };
 use tokio::sync::{broadcast, watch, Notify};
 use tokio::time::Instant;
@@ -17,7 +19,7 @@ use tokio_stream::wrappers::{errors::BroadcastStreamRecvError, BroadcastStream,
 use tokio_stream::Stream;
 use tracing::{debug, warn};
 
-use crate::utils::Shutdown;
+use crate::utils::{constant_time_eq, Shutdown};
 use crate::web::protocol::{WsServer, WsUser, WsWinsize};
 
 mod snapshot;
@@ -25,6 +27,31 @@ mod snapshot;
 /// Store a rolling buffer with at most this quantity of output, per shell.
 const SHELL_STORED_BYTES: u64 = 1 << 21; // 2 MiB
 
+/// Bytes of recent output sent per terminal cell when redrawing a screen,
+/// allowing for escape sequences and redraws.
+const SNAPSHOT_BYTES_PER_CELL: u64 = 16;
+
+/// Minimum bytes of recent output sent when redrawing a screen.
+const SNAPSHOT_MIN_BYTES: u64 = 1 << 14; // 16 KiB
+
+/// Store a rolling buffer with at most this quantity of plaintext output for
+/// spectators, per shell.
+const SPECTATOR_STORED_BYTES: u64 = 1 << 18; // 256 KiB
+
+/// Where a subscription to a shell's output starts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResumePoint {
+    /// Start at a chunk index, counting from the shell's first chunk.
+    Chunk(u64),
+
+    /// Start at the chunk containing a byte sequence number, which is the
+    /// first byte that the subscriber has not seen.
+    ///
+    /// Batches carry their own sequence number, so subscribers can skip any
+    /// bytes of the first chunk that they already have.
+    Seqnum(u64),
+}
+
 /// Static metadata for this session.
 #[derive(Debug, Clone)]
 pub struct Metadata {
@@ -35,7 +62,101 @@ pub struct Metadata {
     pub name: String,
 
     /// Password for write access to the session.
+    ///
+    /// This is either an Argon2id hash in PHC string format, or for older
+    /// clients the raw token that browsers present.
     pub write_password_hash: Option<Bytes>,
+
+    /// Arbitrary key-value labels attached by the client.
+    pub labels: BTreeMap<String, String>,
+
+    /// Whether the client sends unencrypted output for key-less spectators.
+    pub allow_spectators: bool,
+
+    /// Maximum number of users connected at once, if limited.
+    ///
+    /// Only web clients count as users, so the host's own connection and
+    /// key-less spectators are exempt.
+    pub max_clients: Option<u32>,
+
+    /// How long users keep write access before becoming read-only, if limited.
+    pub write_window: Option<Duration>,
+
+    /// Whether the write window starts when each user joins, rather than when
+    /// the session was opened.
+    pub write_window_renew: bool,
+}
+
+/// Length of the encrypted zero block used to validate the encryption key.
+pub const ENCRYPTED_ZEROS_LEN: usize = 16;
+
+/// Maximum number of labels attached to a session.
+pub const MAX_LABELS: usize = 32;
+
+/// Maximum length of a label key, in bytes.
+pub const MAX_LABEL_KEY_LEN: usize = 64;
+
+/// Maximum length of a label value, in bytes.
+pub const MAX_LABEL_VALUE_LEN: usize = 256;
+
+/// Maximum length of a shell name, in characters.
+pub const MAX_SHELL_NAME_LEN: usize = 64;
+
+/// Validate labels sent by a client against the size limits.
+///
+/// Keys must be non-empty and contain only ASCII alphanumerics, `-`, `_`,
+/// `.` or `/`. Values may be any string within the length limit.
+pub fn validate_labels(labels: HashMap<String, String>) -> Result<BTreeMap<String, String>> {
+    ensure!(labels.len() <= MAX_LABELS, "too many labels (max {MAX_LABELS})");
+    for (key, value) in &labels {
+        ensure!(!key.is_empty(), "label key is empty");
+        ensure!(
+            key.len() <= MAX_LABEL_KEY_LEN,
+            "label key is too long (max {MAX_LABEL_KEY_LEN} bytes)"
+        );
+        ensure!(
+            key.bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b"-_./".contains(&b)),
+            "label key {key:?} has invalid characters"
+        );
+        ensure!(
+            value.len() <= MAX_LABEL_VALUE_LEN,
+            "label value for {key:?} is too long (max {MAX_LABEL_VALUE_LEN} bytes)"
+        );
+    }
+    Ok(labels.into_iter().collect())
+}
+
+/// Version marker for write password hashes stored in PHC string format.
+const ARGON2_HASH_PREFIX: &[u8] = b"$argon2id$";
+
+impl Metadata {
+    /// Check whether a write password token matches the stored hash.
+    ///
+    /// This is computationally expensive for Argon2id hashes, so it should be
+    /// run on a blocking thread. Sessions without a write password always
+    /// return `true`.
+    pub fn verify_write_password(&self, provided: &[u8]) -> bool {
+        use argon2::password_hash::{PasswordHash, PasswordVerifier};
+
+        let Some(stored) = &self.write_password_hash else {
+            return true;
+        };
+        if stored.starts_with(ARGON2_HASH_PREFIX) {
+            // Argon2 compares the final hash output in constant time.
+            let Ok(stored) = std::str::from_utf8(stored) else {
+                return false;
+            };
+            match PasswordHash::new(stored) {
+                Ok(hash) => argon2::Argon2::default()
+                    .verify_password(provided, &hash)
+                    .is_ok(),
+                Err(_) => false,
+            }
+        } else {
+            constant_time_eq(provided, stored)
+        }
+    }
 }
 
 /// In-memory state for a single sshx session.
@@ -44,6 +165,9 @@ pub struct Session {
     /// Static metadata for this session.
     metadata: Metadata,
 
+    /// Secret shared with the client, identifying the host in the browser.
+    host_key: String,
+
     /// In-memory state for the session.
     shells: RwLock<HashMap<Sid, State>>,
 
@@ -53,11 +177,27 @@ pub struct Session {
     /// Atomic counter to get new, unique IDs.
     counter: IdCounter,
 
+    /// Wall-clock time of when this session was opened, kept across restores.
+    created: SystemTime,
+
     /// Timestamp of the last backend client message from an active connection.
     last_accessed: Mutex<Instant>,
 
-    /// Watch channel source for the ordered list of open shells and sizes.
-    source: watch::Sender<Vec<(Sid, WsWinsize)>>,
+    /// Set when the last user left, and cleared when a user joins.
+    empty_since: Mutex<Option<Instant>>,
+
+    /// Number of `sshx` clients currently streaming to this session.
+    backends: Mutex<usize>,
+
+    /// Watch channel source for the ordered list of open shells, with their
+    /// sizes and names.
+    source: watch::Sender<Vec<(Sid, WsWinsize, Option<String>)>>,
+
+    /// Set while relaying output to subscribers and accepting input is paused.
+    paused: watch::Sender<bool>,
+
+    /// Set while the host keeps the session paused, so users cannot resume it.
+    locked: AtomicBool,
 
     /// Broadcasts updates to all WebSocket clients.
     ///
@@ -97,10 +237,70 @@ struct State {
     /// Set when this shell is terminated.
     closed: bool,
 
+    /// Exit code of the shell process, if it ended on its own.
+    exit_code: Option<i32>,
+
+    /// Chunk number where output started being held back by a pause.
+    paused_from: u64,
+
+    /// Number of bytes discarded by pauses, keyed by the chunk that follows.
+    ///
+    /// Discarded chunks stay in `data` as empty placeholders, so that chunk
+    /// numbers are unchanged, but their bytes are missing from the total.
+    discarded: BTreeMap<u64, u64>,
+
+    /// Unencrypted copy of recent output, kept only for spectator sessions.
+    spectator_data: Vec<Bytes>,
+
+    /// Number of bytes received for spectators, like `seqnum`.
+    spectator_seqnum: u64,
+
+    /// Number of bytes pruned from before `spectator_data[0]`.
+    spectator_offset: u64,
+
     /// Updated when any of the above fields change.
     notify: Arc<Notify>,
 }
 
+impl State {
+    /// Returns the sequence number of the first byte of a stored chunk.
+    fn seqnum_at(&self, chunknum: u64) -> u64 {
+        let start = (chunknum - self.chunk_offset) as usize;
+        let stored: u64 = self.data[..start].iter().map(|x| x.len() as u64).sum();
+        let discarded: u64 = self.discarded.range(..=chunknum).map(|(_, n)| n).sum();
+        self.byte_offset + stored + discarded
+    }
+
+    /// Returns how many chunks to prune to store at most `max_bytes`, along
+    /// with the byte offset after pruning them.
+    fn prune_point(&self, max_bytes: u64) -> (usize, u64) {
+        let mut offset = 0;
+        let mut byte_offset = self.byte_offset;
+        while offset < self.data.len() && self.seqnum - byte_offset > max_bytes {
+            byte_offset += self.data[offset].len() as u64;
+            offset += 1;
+            let chunknum = self.chunk_offset + offset as u64;
+            byte_offset += self.discarded.get(&chunknum).copied().unwrap_or_default();
+        }
+        (offset, byte_offset)
+    }
+
+    /// Discard stored chunks from the given chunk number onward.
+    fn discard_from(&mut self, chunknum: u64) {
+        let start = chunknum.saturating_sub(self.chunk_offset) as usize;
+        if start >= self.data.len() {
+            return;
+        }
+        let mut bytes = 0;
+        for chunk in &mut self.data[start..] {
+            bytes += chunk.len() as u64;
+            *chunk = Bytes::new();
+        }
+        let end = self.chunk_offset + self.data.len() as u64;
+        *self.discarded.entry(end).or_default() += bytes;
+    }
+}
+
 impl Session {
     /// Construct a new session.
     pub fn new(metadata: Metadata) -> Self {
@@ -108,11 +308,17 @@ impl Session {
         let (update_tx, update_rx) = async_channel::bounded(256);
         Session {
             metadata,
+            host_key: rand_alphanumeric(22),
             shells: RwLock::new(HashMap::new()),
             users: RwLock::new(HashMap::new()),
             counter: IdCounter::default(),
+            created: SystemTime::now(),
             last_accessed: Mutex::new(now),
+            empty_since: Mutex::new(None),
+            backends: Mutex::new(0),
             source: watch::channel(Vec::new()).0,
+            paused: watch::channel(false).0,
+            locked: AtomicBool::new(false),
             broadcast: broadcast::channel(64).0,
             update_tx,
             update_rx,
@@ -126,6 +332,11 @@ impl Session {
         &self.metadata
     }
 
+    /// Returns the secret that identifies the host of this session.
+    pub fn host_key(&self) -> &str {
+        &self.host_key
+    }
+
     /// Gives access to the ID counter for obtaining new IDs.
     pub fn counter(&self) -> &IdCounter {
         &self.counter
@@ -151,18 +362,86 @@ impl Session {
     }
 
     /// Receive a notification every time the set of shells is changed.
-    pub fn subscribe_shells(&self) -> impl Stream<Item = Vec<(Sid, WsWinsize)>> + Unpin {
+    pub fn subscribe_shells(
+        &self,
+    ) -> impl Stream<Item = Vec<(Sid, WsWinsize, Option<String>)>> + Unpin {
         WatchStream::new(self.source.subscribe())
     }
 
+    /// Receive a notification every time the session is paused or resumed.
+    pub fn subscribe_paused(&self) -> impl Stream<Item = bool> + Unpin {
+        WatchStream::new(self.paused.subscribe())
+    }
+
+    /// Returns whether the session is currently paused.
+    pub fn is_paused(&self) -> bool {
+        *self.paused.borrow()
+    }
+
+    /// Pause or resume relaying terminal output and accepting input.
+    ///
+    /// Output from the client is still recorded while paused. When resuming
+    /// with `discard` set, it is dropped instead of being relayed, so it never
+    /// reaches a viewer. Output from before the pause is kept.
+    pub fn set_paused(&self, paused: bool, discard: bool) {
+        let changed = self
+            .paused
+            .send_if_modified(|value| std::mem::replace(value, paused) != paused);
+        if !changed {
+            return;
+        }
+        for shell in self.shells.write().values_mut() {
+            if paused {
+                shell.paused_from = shell.chunk_offset + shell.data.len() as u64;
+            } else if discard {
+                shell.discard_from(shell.paused_from);
+            }
+            shell.notify.notify_waiters();
+        }
+        self.sync_now();
+    }
+
+    /// Lock or unlock the session on behalf of the host.
+    ///
+    /// A locked session is paused, and users cannot resume it. Output produced
+    /// while locked is discarded on unlock, so viewers never see it.
+    pub fn set_locked(&self, locked: bool) {
+        self.locked.store(locked, Ordering::Relaxed);
+        self.set_paused(locked, true);
+    }
+
+    /// Returns whether the host has locked the session.
+    pub fn is_locked(&self) -> bool {
+        self.locked.load(Ordering::Relaxed)
+    }
+
     /// Subscribe for chunks from a shell, until it is closed.
+    ///
+    /// No chunks are sent while the session is paused. Output discarded by a
+    /// pause is sent as empty chunks, so that subscribers can keep counting
+    /// chunk numbers, and chunks on either side of it come in separate batches.
     pub fn subscribe_chunks(
         &self,
         id: Sid,
-        mut chunknum: u64,
+        from: ResumePoint,
     ) -> impl Stream<Item = (u64, Vec<Bytes>)> + '_ {
+        let mut chunknum = match from {
+            ResumePoint::Chunk(chunknum) => chunknum,
+            ResumePoint::Seqnum(seqnum) => self.chunk_at_seqnum(id, seqnum),
+        };
+        let mut paused = self.paused.subscribe();
         async_stream::stream! {
             while !self.shutdown.is_terminated() {
+                if *paused.borrow_and_update() {
+                    tokio::select! {
+                        result = paused.changed() => match result {
+                            Ok(()) => continue,
+                            Err(_) => return,
+                        },
+                        _ = self.terminated() => return,
+                    }
+                }
+
                 // We absolutely cannot hold `shells` across an await point,
                 // since that would cause deadlocks.
                 let (seqnum, chunks, notified) = {
@@ -173,20 +452,31 @@ impl Session {
                     };
                     let notify = Arc::clone(&shell.notify);
                     let notified = async move { notify.notified().await };
+                    if self.is_paused() {
+                        continue; // Paused since the check above, wait for resume.
+                    }
                     let mut seqnum = shell.byte_offset;
                     let mut chunks = Vec::new();
                     let current_chunks = shell.chunk_offset + shell.data.len() as u64;
                     if chunknum < current_chunks {
-                        let start = chunknum.saturating_sub(shell.chunk_offset) as usize;
-                        seqnum += shell.data[..start].iter().map(|x| x.len() as u64).sum::<u64>();
-                        chunks = shell.data[start..].to_vec();
-                        chunknum = current_chunks;
+                        let first = chunknum.max(shell.chunk_offset);
+                        seqnum = shell.seqnum_at(first);
+                        // Bytes are missing where output was discarded, so stop there.
+                        let last = match shell.discarded.range(first + 1..).next() {
+                            Some((&next, _)) => next.min(current_chunks),
+                            None => current_chunks,
+                        };
+                        let start = (first - shell.chunk_offset) as usize;
+                        let end = (last - shell.chunk_offset) as usize;
+                        chunks = shell.data[start..end].to_vec();
+                        chunknum = last;
                     }
                     (seqnum, chunks, notified)
                 };
 
                 if !chunks.is_empty() {
                     yield (seqnum, chunks);
+                    continue; // There may be more chunks after discarded output.
                 }
                 tokio::select! {
                     _ = notified => (),
@@ -196,20 +486,143 @@ impl Session {
         }
     }
 
+    /// Returns the index of the chunk containing a byte sequence number.
+    ///
+    /// Bytes that are no longer stored resume from the oldest stored chunk,
+    /// and bytes not yet received resume after the latest chunk.
+    fn chunk_at_seqnum(&self, id: Sid, seqnum: u64) -> u64 {
+        let shells = self.shells.read();
+        let Some(shell) = shells.get(&id) else {
+            return 0;
+        };
+        let mut end = shell.byte_offset;
+        for (i, chunk) in shell.data.iter().enumerate() {
+            end += chunk.len() as u64;
+            if seqnum < end {
+                return shell.chunk_offset + i as u64;
+            }
+        }
+        shell.chunk_offset + shell.data.len() as u64
+    }
+
+    /// Subscribe for plaintext output from a shell, until it is closed.
+    ///
+    /// This starts from the oldest stored output and yields byte offsets with
+    /// each batch. Like [`Self::subscribe_chunks`], nothing is sent while the
+    /// session is paused.
+    pub fn subscribe_spectator(&self, id: Sid) -> impl Stream<Item = (u64, Vec<Bytes>)> + '_ {
+        let mut paused = self.paused.subscribe();
+        let mut seqnum = 0;
+        async_stream::stream! {
+            while !self.shutdown.is_terminated() {
+                if *paused.borrow_and_update() {
+                    tokio::select! {
+                        result = paused.changed() => match result {
+                            Ok(()) => continue,
+                            Err(_) => return,
+                        },
+                        _ = self.terminated() => return,
+                    }
+                }
+
+                let (start, chunks, notified) = {
+                    let shells = self.shells.read();
+                    let shell = match shells.get(&id) {
+                        Some(shell) if !shell.closed => shell,
+                        _ => return,
+                    };
+                    let notify = Arc::clone(&shell.notify);
+                    let notified = async move { notify.notified().await };
+                    if self.is_paused() {
+                        continue;
+                    }
+                    let start = seqnum.max(shell.spectator_offset);
+                    let mut offset = shell.spectator_offset;
+                    let mut chunks = Vec::new();
+                    for chunk in &shell.spectator_data {
+                        let end = offset + chunk.len() as u64;
+                        if end > start {
+                            chunks.push(chunk.slice((start.max(offset) - offset) as usize..));
+                        }
+                        offset = end;
+                    }
+                    seqnum = shell.spectator_seqnum;
+                    (start, chunks, notified)
+                };
+
+                if !chunks.is_empty() {
+                    yield (start, chunks);
+                }
+                tokio::select! {
+                    _ = notified => (),
+                    _ = self.terminated() => return,
+                }
+            }
+        }
+    }
+
+    /// Return the recent output of a shell, enough to redraw its screen.
+    ///
+    /// Output is encrypted, so the server cannot tell where the current screen
+    /// begins. Instead, this returns a tail of output sized for the terminal's
+    /// dimensions, as a starting byte offset and chunks. Output held back by a
+    /// pause is not included.
+    pub fn recent_output(&self, id: Sid) -> Result<(u64, Vec<Bytes>)> {
+        let cells = self
+            .source
+            .borrow()
+            .iter()
+            .find(|(sid, ..)| *sid == id)
+            .map_or(0, |(_, winsize, _)| winsize.rows as u64 * winsize.cols as u64);
+        let budget =
+            (cells * SNAPSHOT_BYTES_PER_CELL).clamp(SNAPSHOT_MIN_BYTES, SHELL_STORED_BYTES);
+
+        let shells = self.shells.read();
+        let shell = match shells.get(&id) {
+            Some(shell) if !shell.closed => shell,
+            _ => bail!("cannot snapshot shell with id={id}, does not exist"),
+        };
+        let mut end = shell.data.len();
+        if self.is_paused() {
+            end = end.min(shell.paused_from.saturating_sub(shell.chunk_offset) as usize);
+        }
+        let available = &shell.data[..end];
+        let mut seqnum = shell.byte_offset + available.iter().map(|x| x.len() as u64).sum::<u64>();
+        let mut chunks = Vec::new();
+        let mut remaining = budget;
+        for chunk in available.iter().rev() {
+            if remaining == 0 {
+                break;
+            }
+            let len = chunk.len() as u64;
+            let take = len.min(remaining);
+            chunks.push(chunk.slice((len - take) as usize..));
+            seqnum -= take;
+            remaining -= take;
+        }
+        chunks.reverse();
+        Ok((seqnum, chunks))
+    }
+
     /// Add a new shell to the session.
     pub fn add_shell(&self, id: Sid, center: (i32, i32)) -> Result<()> {
         use std::collections::hash_map::Entry::*;
-        let _guard = match self.shells.write().entry(id) {
-            Occupied(_) => bail!("shell already exists with id={id}"),
-            Vacant(v) => v.insert(State::default()),
-        };
+        let mut shells = self.shells.write();
+        match shells.entry(id) {
+            Occupied(o) if !o.get().closed => bail!("shell already exists with id={id}"),
+            // A closed shell's ID may be reused, starting again from empty.
+            Occupied(mut o) => *o.get_mut() = State::default(),
+            Vacant(v) => {
+                v.insert(State::default());
+            }
+        }
         self.source.send_modify(|source| {
             let winsize = WsWinsize {
                 x: center.0,
                 y: center.1,
                 ..Default::default()
             };
-            source.push((id, winsize));
+            source.push((id, winsize, None));
         });
         self.sync_now();
         Ok(())
@@ -226,12 +639,24 @@ impl Session {
             None => bail!("cannot close shell with id={id}, does not exist"),
         }
         self.source.send_modify(|source| {
-            source.retain(|&(x, _)| x != id);
+            source.retain(|(x, ..)| *x != id);
         });
         self.sync_now();
         Ok(())
     }
 
+    /// Record the exit status of a shell process, notifying web clients.
+    pub fn set_exit_code(&self, id: Sid, exit_code: Option<i32>) -> Result<()> {
+        self.get_shell_mut(id)?.exit_code = exit_code;
+        self.broadcast.send(WsServer::ShellExit(id, exit_code)).ok();
+        Ok(())
+    }
+
+    /// Return the recorded exit code of a shell, if its process has exited.
+    pub fn exit_code(&self, id: Sid) -> Option<i32> {
+        self.shells.read().get(&id).and_then(|shell| shell.exit_code)
+    }
+
     fn get_shell_mut(&self, id: Sid) -> Result<impl DerefMut<Target = State> + '_> {
         let shells = self.shells.write();
         match shells.get(&id) {
@@ -247,14 +672,33 @@ impl Session {
     pub fn move_shell(&self, id: Sid, winsize: Option<WsWinsize>) -> Result<()> {
         let _guard = self.get_shell_mut(id)?; // Ensures mutual exclusion.
         self.source.send_modify(|source| {
-            if let Some(idx) = source.iter().position(|&(sid, _)| sid == id) {
-                let (_, oldsize) = source.remove(idx);
-                source.push((id, winsize.unwrap_or(oldsize)));
+            if let Some(idx) = source.iter().position(|(sid, ..)| *sid == id) {
+                let (_, oldsize, name) = source.remove(idx);
+                source.push((id, winsize.unwrap_or(oldsize), name));
             }
         });
         Ok(())
     }
 
+    /// Name a shell for all users, or clear its name if `name` is empty.
+    pub fn set_shell_name(&self, id: Sid, name: &str) -> Result<()> {
+        let name = name.trim();
+        ensure!(
+            name.chars().count() <= MAX_SHELL_NAME_LEN,
+            "shell name is too long (max {MAX_SHELL_NAME_LEN} characters)"
+        );
+        ensure!(!name.contains(char::is_control), "shell name has control characters");
+        let _guard = self.get_shell_mut(id)?; // Ensures mutual exclusion.
+        self.source.send_if_modified(|source| {
+            let Some((_, _, old)) = source.iter_mut().find(|(sid, ..)| *sid == id) else {
+                return false;
+            };
+            let name = (!name.is_empty()).then(|| name.to_owned());
+            std::mem::replace(old, name.clone()) != name
+        });
+        Ok(())
+    }
+
     /// Receive new data into the session.
     pub fn add_data(&self, id: Sid, data: Bytes, seq: u64) -> Result<()> {
         let mut shell = self.get_shell_mut(id)?;
@@ -267,18 +711,41 @@ impl Session {
             shell.data.push(segment);
 
             // Prune old chunks if we've exceeded the maximum stored bytes.
-            let mut stored_bytes = shell.seqnum - shell.byte_offset;
-            if stored_bytes > SHELL_STORED_BYTES {
-                let mut offset = 0;
-                while offset < shell.data.len() && stored_bytes > SHELL_STORED_BYTES {
-                    let bytes = shell.data[offset].len() as u64;
-                    stored_bytes -= bytes;
-                    shell.chunk_offset += 1;
-                    shell.byte_offset += bytes;
-                    offset += 1;
-                }
+            let (offset, byte_offset) = shell.prune_point(SHELL_STORED_BYTES);
+            if offset > 0 {
                 shell.data.drain(..offset);
+                shell.chunk_offset += offset as u64;
+                shell.byte_offset = byte_offset;
+                let chunk_offset = shell.chunk_offset;
+                shell.discarded.retain(|&chunk, _| chunk > chunk_offset);
+            }
+
+            shell.notify.notify_waiters();
+        }
+
+        Ok(())
+    }
+
+    /// Receive a plaintext copy of output, if this session allows spectators.
+    pub fn add_spectator_data(&self, id: Sid, data: Bytes, seq: u64) -> Result<()> {
+        ensure!(self.metadata.allow_spectators, "session does not allow spectators");
+        let mut shell = self.get_shell_mut(id)?;
+
+        // Output is resent after reconnections, so only keep the new part.
+        if seq <= shell.spectator_seqnum && seq + data.len() as u64 > shell.spectator_seqnum {
+            let segment = data.slice((shell.spectator_seqnum - seq) as usize..);
+            shell.spectator_seqnum += segment.len() as u64;
+            shell.spectator_data.push(segment);
+
+            let mut stored_bytes = shell.spectator_seqnum - shell.spectator_offset;
+            let mut pruned = 0;
+            while stored_bytes > SPECTATOR_STORED_BYTES {
+                let bytes = shell.spectator_data[pruned].len() as u64;
+                stored_bytes -= bytes;
+                shell.spectator_offset += bytes;
+                pruned += 1;
             }
+            shell.spectator_data.drain(..pruned);
 
             shell.notify.notify_waiters();
         }
@@ -295,12 +762,36 @@ impl Session {
             .collect()
     }
 
+    /// Returns messages announcing users that joined or left the session since
+    /// the last call, updating the set of `known` users.
+    pub fn presence_changes(&self, known: &mut BTreeSet<Uid>) -> Vec<ServerMessage> {
+        let users: BTreeSet<Uid> = self.users.read().keys().copied().collect();
+        let joined = users.difference(known).map(|id| ServerMessage::UserJoined(id.0));
+        let left = known.difference(&users).map(|id| ServerMessage::UserLeft(id.0));
+        let changes = joined.chain(left).collect();
+        *known = users;
+        changes
+    }
+
+    /// Get the number of users connected to the session.
+    pub fn user_count(&self) -> usize {
+        self.users.read().len()
+    }
+
     /// Get the number of active shells in the session.
     pub fn shell_count(&self) -> usize {
         let shells = self.shells.read();
         shells.iter().filter(|(_, state)| !state.closed).count()
     }
 
+    /// Check that another shell can be created without exceeding a limit.
+    pub fn check_shell_limit(&self, max_shells: usize) -> Result<()> {
+        if self.shell_count() >= max_shells {
+            bail!("session has reached the limit of {max_shells} terminals");
+        }
+        Ok(())
+    }
+
     /// Update a user in place by ID, applying a callback to the object.
     pub fn update_user(&self, id: Uid, f: impl FnOnce(&mut WsUser)) -> Result<()> {
         let updated_user = {
@@ -316,7 +807,9 @@ impl Session {
     }
 
     /// Add a new user, and return a guard that removes the user when dropped.
-    pub fn user_scope(&self, id: Uid, can_write: bool) -> Result<impl Drop + '_> {
+    ///
+    /// Returns `None` if the session is already at its maximum number of users.
+    pub fn user_scope(&self, id: Uid, can_write: bool) -> Result<Option<impl Drop + '_>> {
         use std::collections::hash_map::Entry::*;
 
         #[must_use]
@@ -327,27 +820,34 @@ impl Session {
             }
         }
 
-        match self.users.write().entry(id) {
+        let mut users = self.users.write();
+        if let Some(max) = self.metadata.max_clients {
+            if users.len() >= max as usize {
+                return Ok(None);
+            }
+        }
+        match users.entry(id) {
             Occupied(_) => bail!("user already exists with id={id}"),
             Vacant(v) => {
-                let user = WsUser {
-                    name: format!("User {id}"),
-                    cursor: None,
-                    focus: None,
-                    can_write,
-                };
+                let user = WsUser::new(format!("User {id}"), can_write);
                 v.insert(user.clone());
+                *self.empty_since.lock() = None;
                 self.broadcast.send(WsServer::UserDiff(id, Some(user))).ok();
-                Ok(UserGuard(self, id))
+                Ok(Some(UserGuard(self, id)))
             }
         }
     }
 
     /// Remove an existing user.
     fn remove_user(&self, id: Uid) {
-        if self.users.write().remove(&id).is_none() {
+        let mut users = self.users.write();
+        if users.remove(&id).is_none() {
             warn!(%id, "invariant violation: removed user that does not exist");
         }
+        if users.is_empty() {
+            *self.empty_since.lock() = Some(Instant::now());
+        }
+        drop(users);
         self.broadcast.send(WsServer::UserDiff(id, None)).ok();
     }
 
@@ -361,6 +861,27 @@ impl Session {
         Ok(())
     }
 
+    /// Returns whether a user joining now without a password gets write access.
+    ///
+    /// This is the case unless the session has a write password, or its write
+    /// window has already ended.
+    pub fn default_writable(&self) -> bool {
+        self.metadata.write_password_hash.is_none()
+            && self.write_deadline().is_none_or(|deadline| deadline > Instant::now())
+    }
+
+    /// Returns when a user joining now should lose write access, if ever.
+    pub fn write_deadline(&self) -> Option<Instant> {
+        let window = self.metadata.write_window?;
+        let remaining = match self.metadata.write_window_renew {
+            true => window,
+            false => (self.created + window)
+                .duration_since(SystemTime::now())
+                .unwrap_or_default(),
+        };
+        Some(Instant::now() + remaining)
+    }
+
     /// Send a chat message into the room.
     pub fn send_chat(&self, id: Uid, msg: &str) -> Result<()> {
         // Populate the message with the current name in case it's not known later.
@@ -389,6 +910,42 @@ impl Session {
         *self.last_accessed.lock()
     }
 
+    /// Returns when the session was last left without any clients, if it has
+    /// had users but has none connected now.
+    ///
+    /// This is the later of when the last user left and when the last `sshx`
+    /// client disconnected.
+    pub fn empty_since(&self) -> Option<Instant> {
+        *self.empty_since.lock()
+    }
+
+    /// Register an `sshx` client as connected until the guard is dropped.
+    pub fn backend_scope(&self) -> impl Drop + '_ {
+        #[must_use]
+        struct BackendGuard<'a>(&'a Session);
+        impl Drop for BackendGuard<'_> {
+            fn drop(&mut self) {
+                let mut backends = self.0.backends.lock();
+                *backends -= 1;
+                if *backends == 0 {
+                    // Give users the full grace period after the host leaves.
+                    let mut empty_since = self.0.empty_since.lock();
+                    if empty_since.is_some() {
+                        *empty_since = Some(Instant::now());
+                    }
+                }
+            }
+        }
+
+        *self.backends.lock() += 1;
+        BackendGuard(self)
+    }
+
+    /// Returns whether an `sshx` client is currently connected.
+    pub fn has_backend(&self) -> bool {
+        *self.backends.lock() > 0
+    }
+
     /// Access the sender of the client message channel for this session.
     pub fn update_tx(&self) -> &async_channel::Sender<ServerMessage> {
         &self.update_tx
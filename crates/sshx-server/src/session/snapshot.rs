@@ -1,6 +1,8 @@
 //! Snapshot and restore sessions from serialized state.
 
 use std::collections::BTreeMap;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, SystemTime};
 
 use anyhow::{ensure, Context, Result};
 use prost::Message;
@@ -21,7 +23,12 @@ impl Session {
     /// Snapshot the session, returning a compressed representation.
     pub fn snapshot(&self) -> Result<Vec<u8>> {
         let ids = self.counter.get_current_values();
-        let winsizes: BTreeMap<Sid, WsWinsize> = self.source.borrow().iter().cloned().collect();
+        let winsizes: BTreeMap<Sid, (WsWinsize, Option<String>)> = self
+            .source
+            .borrow()
+            .iter()
+            .map(|(sid, winsize, name)| (*sid, (*winsize, name.clone())))
+            .collect();
         let message = SerializedSession {
             encrypted_zeros: self.metadata().encrypted_zeros.clone(),
             shells: self
@@ -30,31 +37,28 @@ impl Session {
                 .iter()
                 .map(|(sid, shell)| {
                     // Prune off data until its total length is at most `SHELL_SNAPSHOT_BYTES`.
-                    let mut prefix = 0;
-                    let mut chunk_offset = shell.chunk_offset;
-                    let mut byte_offset = shell.byte_offset;
+                    let (prefix, byte_offset) = shell.prune_point(SHELL_SNAPSHOT_BYTES);
+                    let chunk_offset = shell.chunk_offset + prefix as u64;
 
-                    for i in 0..shell.data.len() {
-                        if shell.seqnum - byte_offset > SHELL_SNAPSHOT_BYTES {
-                            prefix += 1;
-                            chunk_offset += 1;
-                            byte_offset += shell.data[i].len() as u64;
-                        } else {
-                            break;
-                        }
-                    }
-
-                    let winsize = winsizes.get(sid).cloned().unwrap_or_default();
+                    let (winsize, name) = winsizes.get(sid).cloned().unwrap_or_default();
                     let shell = SerializedShell {
                         seqnum: shell.seqnum,
                         data: shell.data[prefix..].to_vec(),
                         chunk_offset,
                         byte_offset,
                         closed: shell.closed,
+                        exit_code: shell.exit_code,
+                        paused_from: shell.paused_from,
+                        discarded: shell
+                            .discarded
+                            .range(chunk_offset + 1..)
+                            .map(|(&chunk, &bytes)| (chunk, bytes))
+                            .collect(),
                         winsize_x: winsize.x,
                         winsize_y: winsize.y,
                         winsize_rows: winsize.rows.into(),
                         winsize_cols: winsize.cols.into(),
+                        name,
                     };
                     (sid.0, shell)
                 })
@@ -63,6 +67,18 @@ impl Session {
             next_uid: ids.1 .0,
             name: self.metadata().name.clone(),
             write_password_hash: self.metadata().write_password_hash.clone(),
+            labels: self.metadata().labels.clone().into_iter().collect(),
+            paused: self.is_paused(),
+            host_key: self.host_key.clone(),
+            allow_spectators: self.metadata().allow_spectators,
+            max_clients: self.metadata().max_clients.unwrap_or_default(),
+            write_window_secs: self.metadata().write_window.map_or(0, |w| w.as_secs() as u32),
+            write_window_renew: self.metadata().write_window_renew,
+            created_ms: self
+                .created
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |d| d.as_millis() as u64),
+            locked: self.is_locked(),
         };
         let data = message.encode_to_vec();
         ensure!(data.len() < MAX_SNAPSHOT_SIZE, "snapshot too large");
@@ -78,9 +94,21 @@ impl Session {
             encrypted_zeros: message.encrypted_zeros,
             name: message.name,
             write_password_hash: message.write_password_hash,
+            labels: message.labels.into_iter().collect(),
+            allow_spectators: message.allow_spectators,
+            max_clients: Some(message.max_clients).filter(|&max| max > 0),
+            write_window: (message.write_window_secs > 0)
+                .then(|| Duration::from_secs(message.write_window_secs.into())),
+            write_window_renew: message.write_window_renew,
         };
 
-        let session = Self::new(metadata);
+        let mut session = Self::new(metadata);
+        if !message.host_key.is_empty() {
+            session.host_key = message.host_key;
+        }
+        if message.created_ms > 0 {
+            session.created = SystemTime::UNIX_EPOCH + Duration::from_millis(message.created_ms);
+        }
         let mut shells = session.shells.write();
         let mut winsizes = Vec::new();
         for (sid, shell) in message.shells {
@@ -92,6 +120,7 @@ impl Session {
                     rows: shell.winsize_rows.try_into().context("rows overflow")?,
                     cols: shell.winsize_cols.try_into().context("cols overflow")?,
                 },
+                shell.name,
             ));
             let shell = State {
                 seqnum: shell.seqnum,
@@ -99,12 +128,18 @@ impl Session {
                 chunk_offset: shell.chunk_offset,
                 byte_offset: shell.byte_offset,
                 closed: shell.closed,
-                notify: Default::default(),
+                exit_code: shell.exit_code,
+                paused_from: shell.paused_from,
+                discarded: shell.discarded.into_iter().collect(),
+                // Plaintext output for spectators is never persisted.
+                ..Default::default()
             };
             shells.insert(Sid(sid), shell);
         }
         drop(shells);
         session.source.send_replace(winsizes);
+        session.paused.send_replace(message.paused);
+        session.locked.store(message.locked, Ordering::Relaxed);
         session
             .counter
             .set_current_values(Sid(message.next_sid), Uid(message.next_uid));
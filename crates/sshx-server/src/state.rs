@@ -1,10 +1,12 @@
 //! Stateful components of the server, managing multiple sessions.
 
 use std::pin::pin;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime};
 
 use anyhow::Result;
+use base64::prelude::{Engine as _, BASE64_STANDARD};
 use dashmap::DashMap;
 use hmac::{Hmac, Mac as _};
 use sha2::Sha256;
@@ -13,11 +15,16 @@ use tokio::time;
 use tokio_stream::StreamExt;
 use tracing::error;
 
+use self::events::{Event, EventTap};
 use self::mesh::StorageMesh;
+use self::throttle::AuthThrottle;
 use crate::session::Session;
+use crate::utils::constant_time_eq;
 use crate::ServerOptions;
 
+pub mod events;
 pub mod mesh;
+pub mod throttle;
 
 /// Timeout for a disconnected session to be evicted and closed.
 ///
@@ -31,6 +38,9 @@ pub struct ServerState {
     /// Message authentication code for signing tokens.
     mac: Hmac<Sha256>,
 
+    /// Code for the previous secret, still accepted until an optional deadline.
+    previous_mac: Option<(Hmac<Sha256>, Option<Instant>)>,
+
     /// Override the origin returned for the Open() RPC.
     override_origin: Option<String>,
 
@@ -42,6 +52,15 @@ pub struct ServerState {
 
     /// Server options for configuration access.
     options: ServerOptions,
+
+    /// Failed authentication attempts, by session and remote address.
+    auth_throttle: AuthThrottle,
+
+    /// Session events streamed to administrators.
+    events: EventTap,
+
+    /// Counter for identifying CLI WebSocket connections in logs.
+    connection_counter: AtomicU64,
 }
 
 impl ServerState {
@@ -55,12 +74,20 @@ impl ServerState {
             Some(url) => Some(StorageMesh::new(url, options.host.as_deref())?),
             None => None,
         };
+        let previous_mac = options.previous_secret.as_ref().map(|secret| {
+            let deadline = options.previous_secret_grace.map(|grace| Instant::now() + grace);
+            (Hmac::new_from_slice(secret.as_bytes()).unwrap(), deadline)
+        });
         Ok(Self {
             mac: Hmac::new_from_slice(secret.as_bytes()).unwrap(),
+            previous_mac,
             override_origin: options.override_origin.clone(),
             store: DashMap::new(),
             mesh,
             options,
+            auth_throttle: AuthThrottle::default(),
+            events: EventTap::default(),
+            connection_counter: AtomicU64::new(0),
         })
     }
 
@@ -69,16 +96,137 @@ impl ServerState {
         self.mac.clone()
     }
 
+    /// Issue a signed token that authorizes control of a session.
+    pub fn issue_token(&self, name: &str) -> String {
+        self.issue_token_at(name, SystemTime::now())
+    }
+
+    /// Issue a session token with an explicit issued-at time.
+    ///
+    /// The token has the form `{issued_at}:{signature}`, where the signature
+    /// covers both the session name and the issue time in UNIX seconds.
+    pub fn issue_token_at(&self, name: &str, issued_at: SystemTime) -> String {
+        let issued_at = issued_at
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let signature = sign_token(&self.mac, name, issued_at).finalize().into_bytes();
+        format!("{issued_at}:{}", BASE64_STANDARD.encode(signature))
+    }
+
+    /// Check that a token is valid for a session and has not expired.
+    ///
+    /// Tokens signed with the previous secret are accepted during its grace
+    /// period. Tokens without an issue time, from older server versions, are
+    /// only accepted when no maximum token age is configured.
+    pub fn verify_token(&self, name: &str, token: &str) -> bool {
+        let previous_mac = self.previous_mac.as_ref().and_then(|(mac, deadline)| {
+            match deadline {
+                Some(deadline) if Instant::now() > *deadline => None,
+                _ => Some(mac),
+            }
+        });
+        [Some(&self.mac), previous_mac]
+            .into_iter()
+            .flatten()
+            .any(|mac| self.verify_token_with(mac, name, token))
+    }
+
+    fn verify_token_with(&self, mac: &Hmac<Sha256>, name: &str, token: &str) -> bool {
+        let Some((issued_at, signature)) = token.split_once(':') else {
+            return self.options.token_max_age.is_none()
+                && BASE64_STANDARD.decode(token).is_ok_and(|token| {
+                    mac.clone().chain_update(name).verify_slice(&token).is_ok()
+                });
+        };
+        let (Ok(issued_at), Ok(signature)) =
+            (issued_at.parse::<u64>(), BASE64_STANDARD.decode(signature))
+        else {
+            return false;
+        };
+        if sign_token(mac, name, issued_at).verify_slice(&signature).is_err() {
+            return false;
+        }
+        match self.options.token_max_age {
+            Some(max_age) => {
+                let issued_at = SystemTime::UNIX_EPOCH + Duration::from_secs(issued_at);
+                let age = SystemTime::now()
+                    .duration_since(issued_at)
+                    .unwrap_or_default();
+                age <= max_age
+            }
+            None => true,
+        }
+    }
+
     /// Returns the override origin for the Open() RPC.
     pub fn override_origin(&self) -> Option<String> {
         self.override_origin.clone()
     }
 
+    /// Returns the configured public base URL, without a trailing slash.
+    pub fn public_base_url(&self) -> Option<&str> {
+        let base_url = self.options.public_base_url.as_deref()?;
+        Some(base_url.trim_end_matches('/'))
+    }
+
+    /// Returns the origin used to build session URLs for the Open() RPC.
+    ///
+    /// This is the public base URL if configured, then the override origin,
+    /// and finally the origin sent by the client.
+    pub fn session_origin(&self, client_origin: String) -> String {
+        match self.public_base_url() {
+            Some(base_url) => base_url.to_string(),
+            None => self.override_origin().unwrap_or(client_origin),
+        }
+    }
+
     /// Returns the server options for configuration access.
     pub fn options(&self) -> &ServerOptions {
         &self.options
     }
 
+    /// Check an `Authorization` header value against the configured token.
+    ///
+    /// Always succeeds when no token is configured.
+    pub fn check_auth(&self, authorization: Option<&str>) -> bool {
+        let Some(expected) = &self.options.auth_token else {
+            return true;
+        };
+        let provided = authorization
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .unwrap_or_default();
+        constant_time_eq(provided.as_bytes(), expected.as_bytes())
+    }
+
+    /// Check an `Authorization` header value against the admin token.
+    ///
+    /// Always fails when no admin token is configured.
+    pub fn check_admin(&self, authorization: Option<&str>) -> bool {
+        let Some(expected) = &self.options.admin_token else {
+            return false;
+        };
+        let provided = authorization
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .unwrap_or_default();
+        constant_time_eq(provided.as_bytes(), expected.as_bytes())
+    }
+
+    /// Returns the stream of session events.
+    pub fn events(&self) -> &EventTap {
+        &self.events
+    }
+
+    /// Returns the tracker of failed authentication attempts.
+    pub fn auth_throttle(&self) -> &AuthThrottle {
+        &self.auth_throttle
+    }
+
+    /// Returns a unique, monotonically increasing ID for a new connection.
+    pub fn next_connection_id(&self) -> u64 {
+        self.connection_counter.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
     /// Lookup a local session by name.
     pub fn lookup(&self, name: &str) -> Option<Arc<Session>> {
         let result = self.store.get(name).map(|s| s.clone());
@@ -125,7 +273,11 @@ impl ServerState {
 
     /// Close a session permanently on this and other servers.
     pub async fn close_session(&self, name: &str) -> Result<()> {
-        self.remove(name);
+        if self.remove(name) {
+            self.events.emit(Event::SessionClose {
+                session: name.into(),
+            });
+        }
         if let Some(mesh) = &self.mesh {
             mesh.mark_closed(name).await?;
         }
@@ -194,14 +346,27 @@ impl ServerState {
         }
     }
 
-    /// Close all sessions that have been disconnected for too long.
+    /// Close all sessions that have been disconnected for too long, or have
+    /// had no clients for longer than the empty session grace period.
     pub async fn close_old_sessions(&self) {
+        let grace = self.options.empty_session_grace;
+        let interval = match grace {
+            Some(grace) => (grace / 2)
+                .clamp(Duration::from_millis(100), DISCONNECTED_SESSION_EXPIRY / 5),
+            None => DISCONNECTED_SESSION_EXPIRY / 5,
+        };
         loop {
-            time::sleep(DISCONNECTED_SESSION_EXPIRY / 5).await;
+            time::sleep(interval).await;
+            self.auth_throttle.prune();
             let mut to_close = Vec::new();
             for entry in &self.store {
                 let session = entry.value();
-                if session.last_accessed().elapsed() > DISCONNECTED_SESSION_EXPIRY {
+                // The host would only reconnect and fail, so wait for it to leave.
+                let abandoned = !session.has_backend()
+                    && grace.is_some_and(|grace| {
+                        session.empty_since().is_some_and(|since| since.elapsed() >= grace)
+                    });
+                if abandoned || session.last_accessed().elapsed() > DISCONNECTED_SESSION_EXPIRY {
                     to_close.push(entry.key().clone());
                 }
             }
@@ -218,5 +383,14 @@ impl ServerState {
         for entry in &self.store {
             entry.value().shutdown();
         }
+        self.events.close();
     }
 }
+
+/// Sign a session token's name and issue time in UNIX seconds.
+fn sign_token(mac: &Hmac<Sha256>, name: &str, issued_at: u64) -> Hmac<Sha256> {
+    mac.clone()
+        .chain_update(name)
+        .chain_update(b":")
+        .chain_update(issued_at.to_string())
+}
@@ -0,0 +1,166 @@
+//! Stream of session events for external observability.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use sshx_core::Uid;
+use tokio::sync::broadcast;
+use tracing::warn;
+
+use crate::utils::Shutdown;
+
+/// Number of events buffered for each subscriber before it starts dropping.
+pub const EVENT_BUFFER: usize = 1024;
+
+/// An event in the lifetime of a session, as emitted to subscribers.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum Event {
+    /// A client opened a new session.
+    SessionOpen {
+        /// Name of the session.
+        session: String,
+    },
+    /// A session was closed, by its client or after expiring.
+    SessionClose {
+        /// Name of the session.
+        session: String,
+    },
+    /// A user joined a session from the web interface.
+    UserJoin {
+        /// Name of the session.
+        session: String,
+        /// ID of the user within the session.
+        user: Uid,
+        /// Whether the user has write access.
+        can_write: bool,
+    },
+    /// A user left a session.
+    UserLeave {
+        /// Name of the session.
+        session: String,
+        /// ID of the user within the session.
+        user: Uid,
+    },
+    /// A connection to a session failed on the server.
+    Error {
+        /// Name of the session, if known.
+        session: Option<String>,
+        /// Description of the error.
+        message: String,
+    },
+}
+
+/// An event with the time it happened, serialized as one JSON line.
+#[derive(Serialize)]
+struct Record<'a> {
+    /// UNIX timestamp in milliseconds.
+    time: u64,
+    #[serde(flatten)]
+    event: &'a Event,
+}
+
+/// Fans out session events to subscribers without ever blocking the sender.
+///
+/// Subscribers that fall behind by more than [`EVENT_BUFFER`] events miss the
+/// oldest ones, which are counted in [`EventTap::dropped`].
+#[derive(Debug)]
+pub struct EventTap {
+    sender: broadcast::Sender<Arc<str>>,
+    dropped: AtomicU64,
+    shutdown: Shutdown,
+}
+
+impl Default for EventTap {
+    fn default() -> Self {
+        Self {
+            sender: broadcast::channel(EVENT_BUFFER).0,
+            dropped: AtomicU64::new(0),
+            shutdown: Shutdown::new(),
+        }
+    }
+}
+
+impl EventTap {
+    /// Emit an event, doing nothing if there are no subscribers.
+    pub fn emit(&self, event: Event) {
+        if self.sender.receiver_count() == 0 {
+            return;
+        }
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let line = serde_json::to_string(&Record { time, event: &event })
+            .expect("events serialize to JSON");
+        self.sender.send(line.into()).ok();
+    }
+
+    /// Subscribe to JSON lines of future events.
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<str>> {
+        self.sender.subscribe()
+    }
+
+    /// Record events that a lagging subscriber missed.
+    pub fn record_dropped(&self, count: u64) {
+        let total = self.dropped.fetch_add(count, Ordering::Relaxed) + count;
+        warn!(count, total, "event subscriber fell behind, dropped events");
+    }
+
+    /// Returns the total number of events dropped by lagging subscribers.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// End all subscriptions, for server shutdown.
+    pub fn close(&self) {
+        self.shutdown.shutdown();
+    }
+
+    /// Wait until subscriptions are ended by [`EventTap::close`].
+    pub fn closed(&self) -> impl std::future::Future<Output = ()> + Send {
+        self.shutdown.wait()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sshx_core::Uid;
+
+    use super::{Event, EventTap, EVENT_BUFFER};
+
+    #[test]
+    fn serializes_events() {
+        let tap = EventTap::default();
+        let mut rx = tap.subscribe();
+        tap.emit(Event::UserJoin {
+            session: "abc".into(),
+            user: Uid(2),
+            can_write: true,
+        });
+        let line = rx.try_recv().unwrap();
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["type"], "userJoin");
+        assert_eq!(value["session"], "abc");
+        assert_eq!(value["user"], 2);
+        assert_eq!(value["canWrite"], true);
+        assert!(value["time"].as_u64().unwrap() > 0);
+    }
+
+    #[test]
+    fn lagging_subscribers_drop() {
+        let tap = EventTap::default();
+        let mut rx = tap.subscribe();
+        for _ in 0..EVENT_BUFFER + 3 {
+            tap.emit(Event::SessionOpen { session: "abc".into() });
+        }
+        let Err(tokio::sync::broadcast::error::TryRecvError::Lagged(count)) = rx.try_recv()
+        else {
+            panic!("expected subscriber to lag");
+        };
+        tap.record_dropped(count);
+        assert_eq!(tap.dropped(), 3);
+    }
+}
@@ -0,0 +1,109 @@
+//! Lockout of clients that repeatedly fail authentication.
+
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+
+/// Failed attempts after which further attempts are locked out.
+pub const MAX_AUTH_FAILURES: u32 = 5;
+
+/// Length of the first lockout, doubled for each further failure.
+pub const AUTH_LOCKOUT: Duration = Duration::from_secs(30);
+
+/// Upper bound on the length of a single lockout.
+pub const MAX_AUTH_LOCKOUT: Duration = Duration::from_secs(15 * 60);
+
+/// Subject that failed authentication attempts are counted against.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum AuthKey {
+    /// All clients connecting to a session, by name.
+    Session(String),
+    /// All connections from a remote address.
+    Ip(IpAddr),
+}
+
+#[derive(Debug)]
+struct Failures {
+    count: u32,
+    last: Instant,
+}
+
+impl Failures {
+    /// Returns when the lockout for this many failures ends, if any.
+    fn locked_until(&self) -> Option<Instant> {
+        let excess = self.count.checked_sub(MAX_AUTH_FAILURES)?;
+        let lockout = AUTH_LOCKOUT.saturating_mul(1 << excess.min(16));
+        Some(self.last + lockout.min(MAX_AUTH_LOCKOUT))
+    }
+}
+
+/// Counts failed authentication attempts, with exponential backoff once a
+/// threshold is exceeded.
+#[derive(Debug, Default)]
+pub struct AuthThrottle {
+    failures: DashMap<AuthKey, Failures>,
+}
+
+impl AuthThrottle {
+    /// Returns the remaining lockout if any of the keys is locked out.
+    pub fn check(&self, keys: &[AuthKey]) -> Option<Duration> {
+        let now = Instant::now();
+        keys.iter()
+            .filter_map(|key| self.failures.get(key)?.locked_until())
+            .filter(|&until| until > now)
+            .map(|until| until - now)
+            .max()
+    }
+
+    /// Record a failed attempt against each of the keys.
+    pub fn fail(&self, keys: &[AuthKey]) {
+        let now = Instant::now();
+        for key in keys {
+            let mut entry = self.failures.entry(key.clone()).or_insert(Failures {
+                count: 0,
+                last: now,
+            });
+            entry.count += 1;
+            entry.last = now;
+        }
+    }
+
+    /// Clear the failures of each key after a successful attempt.
+    pub fn reset(&self, keys: &[AuthKey]) {
+        for key in keys {
+            self.failures.remove(key);
+        }
+    }
+
+    /// Forget keys whose last failure is older than the longest lockout.
+    pub fn prune(&self) {
+        self.failures
+            .retain(|_, failures| failures.last.elapsed() < MAX_AUTH_LOCKOUT);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AuthKey, AuthThrottle, AUTH_LOCKOUT, MAX_AUTH_FAILURES};
+
+    #[test]
+    fn lockout_after_threshold() {
+        let throttle = AuthThrottle::default();
+        let keys = [AuthKey::Session("abc".into()), AuthKey::Ip([127, 0, 0, 1].into())];
+        for _ in 1..MAX_AUTH_FAILURES {
+            throttle.fail(&keys);
+            assert_eq!(throttle.check(&keys), None);
+        }
+
+        throttle.fail(&keys[..1]);
+        let remaining = throttle.check(&keys).unwrap();
+        assert!(remaining > AUTH_LOCKOUT.mul_f32(0.9) && remaining <= AUTH_LOCKOUT);
+        throttle.fail(&keys[..1]);
+        assert!(throttle.check(&keys[..1]).unwrap() > AUTH_LOCKOUT);
+        assert_eq!(throttle.check(&[AuthKey::Session("other".into())]), None);
+
+        throttle.reset(&keys);
+        assert_eq!(throttle.check(&keys), None);
+    }
+}
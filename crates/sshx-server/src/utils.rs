@@ -5,8 +5,26 @@ use std::future::Future;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
+use axum::http::{HeaderMap, HeaderName};
+use subtle::{Choice, ConstantTimeEq};
 use tokio::sync::Notify;
 
+/// Header carrying the correlation id that the server assigns to each request.
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Generate a short correlation id for tracing a request across log lines.
+pub fn new_request_id() -> String {
+    sshx_core::rand_alphanumeric(12)
+}
+
+/// Returns the correlation id assigned to a request, or an empty string.
+pub fn request_id(headers: &HeaderMap) -> &str {
+    headers
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+}
+
 /// A cloneable structure that handles shutdown signals.
 #[derive(Clone)]
 pub struct Shutdown {
@@ -61,3 +79,33 @@ impl Debug for Shutdown {
             .finish()
     }
 }
+
+/// Compare two byte strings in constant time, including when lengths differ.
+///
+/// Unlike `ct_eq` on slices, this does not return early on a length mismatch,
+/// so the time taken only depends on the length of the longer input.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    let len = a.len().max(b.len());
+    let mut equal: Choice = (a.len() as u64).ct_eq(&(b.len() as u64));
+    for i in 0..len {
+        let x = a.get(i).copied().unwrap_or(0);
+        let y = b.get(i).copied().unwrap_or(0);
+        equal &= x.ct_eq(&y);
+    }
+    equal.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::constant_time_eq;
+
+    #[test]
+    fn constant_time_eq_lengths() {
+        assert!(constant_time_eq(b"", b""));
+        assert!(constant_time_eq(b"zeros", b"zeros"));
+        assert!(!constant_time_eq(b"zeros", b"zeroz"));
+        assert!(!constant_time_eq(b"zeros", b"zeros\0"));
+        assert!(!constant_time_eq(b"zeros\0", b"zeros"));
+        assert!(!constant_time_eq(b"", &[0; 16]));
+    }
+}
@@ -1,22 +1,37 @@
 //! HTTP and WebSocket handlers for the sshx web interface.
 
+use std::convert::Infallible;
+use std::path::Path as FsPath;
 use std::sync::Arc;
 
-use axum::extract::{Path, Query, State};
-use axum::http::{HeaderMap, StatusCode};
+use anyhow::{bail, Result};
+use axum::extract::{ConnectInfo, Path, Query, Request, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::sse::{Event as SseEvent, KeepAlive, Sse};
+use axum::response::{Html, IntoResponse, Response};
 use axum::routing::{any, get, get_service, post};
 use axum::{Json, Router};
+use base64::prelude::{Engine as _, BASE64_STANDARD};
 use once_cell::sync::Lazy;
 use parking_lot::RwLock;
 use rand::Rng;
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use sshx_core::{Sid, Uid};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::IpAddr;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast::error::RecvError;
 use tokio::time::interval;
+use tower::ServiceExt;
+use tower_http::compression::CompressionLayer;
 use tower_http::services::{ServeDir, ServeFile};
 
-use crate::ServerState;
+use crate::state::throttle::AuthKey;
+use crate::utils::constant_time_eq;
+use crate::{ServerOptions, ServerState};
 
+#[cfg(feature = "embed-assets")]
+mod assets;
 pub mod protocol;
 mod socket;
 
@@ -71,14 +86,59 @@ pub struct SessionInfo {
     pub user_count: usize,
     /// Whether the session requires a write password
     pub has_write_password: bool,
+    /// Whether anyone joining with the link can type, without a write password
+    pub default_writable: bool,
     /// Unix timestamp of last activity (milliseconds)
     pub last_accessed: u64,
     /// List of connected user names
     pub users: Vec<String>,
+    /// Labels attached to the session by the client
+    pub labels: BTreeMap<String, String>,
     /// Session metadata if registered to a dashboard
     pub metadata: Option<SessionMetadata>,
 }
 
+/// A user connected to a session, returned by the session users API.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionUser {
+    /// Unique user ID within the session
+    pub id: Uid,
+    /// The user's display name
+    pub name: String,
+    /// Whether the user has write permissions in the session
+    pub can_write: bool,
+    /// Currently focused terminal window ID
+    pub focus: Option<Sid>,
+}
+
+/// Credentials proving ownership of a session, sent by `sshx sessions list`.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct OwnedSessionQuery {
+    /// Session name/ID
+    pub name: String,
+    /// Token returned to the client when it opened the session
+    pub token: String,
+}
+
+/// An active session whose ownership was verified.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct OwnedSession {
+    /// Session name/ID
+    pub name: String,
+    /// Number of active terminal shells
+    pub shell_count: usize,
+    /// Number of connected users
+    pub user_count: usize,
+    /// Milliseconds since the session was last accessed
+    pub idle_ms: u64,
+}
+
+/// Maximum number of sessions checked in one ownership query.
+const MAX_OWNED_QUERY: usize = 256;
+
 /// Request payload for dashboard registration
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
@@ -118,6 +178,9 @@ pub struct SessionListQuery {
     /// Search query for filtering sessions
     #[serde(default)]
     pub search: Option<String>,
+    /// Label filter, either `key` to require a label or `key=value`
+    #[serde(default)]
+    pub label: Option<String>,
     /// Sort field (name, lastAccessed, userCount, shellCount)
     #[serde(default = "default_sort")]
     pub sort: String,
@@ -201,6 +264,27 @@ pub fn start_dashboard_cleanup() {
     });
 }
 
+/// Returns the externally-visible base URL of the server, without a trailing
+/// slash.
+///
+/// This uses the `public_base_url` option if set. Otherwise it is derived from
+/// the configured host or the request's `Host` header, with the scheme taken
+/// from `X-Forwarded-Proto` so that links work behind reverse proxies.
+fn public_base_url(state: &ServerState, headers: &HeaderMap) -> String {
+    if let Some(base_url) = state.public_base_url() {
+        return base_url.to_string();
+    }
+    let header = |name: &str| {
+        let value = headers.get(name)?.to_str().ok()?;
+        Some(value.split(',').next()?.trim())
+    };
+    let scheme = header("x-forwarded-proto").unwrap_or("http");
+    let host = state.options().host.as_deref()
+        .or_else(|| header("host"))
+        .unwrap_or("localhost");
+    format!("{scheme}://{host}")
+}
+
 /// Handler for registering a session with a dashboard
 async fn register_dashboard(
     State(state): axum::extract::State<Arc<ServerState>>,
@@ -245,12 +329,7 @@ async fn register_dashboard(
         .write()
         .insert(request.session_name, metadata);
 
-    // Build dashboard URL - use configured host, fallback to Host header, then localhost
-    // This allows dashboard URLs to work correctly behind reverse proxies
-    let host = state.options().host.as_deref()
-        .or_else(|| headers.get("host").and_then(|h| h.to_str().ok()))
-        .unwrap_or("localhost");
-    let dashboard_url = format!("https://{}/d/{}", host, dashboard_key);
+    let dashboard_url = format!("{}/d/{}", public_base_url(&state, &headers), dashboard_key);
 
     Ok(Json(RegisterDashboardResponse {
         dashboard_key,
@@ -299,6 +378,7 @@ async fn list_dashboard_sessions(
             let last_accessed = session.last_accessed().elapsed().as_millis() as u64;
 
             let has_write_password = session.metadata().write_password_hash.is_some();
+            let default_writable = session.default_writable();
 
             // Get stored metadata for this session
             let metadata = SESSION_METADATA.read().get(&name).cloned();
@@ -308,8 +388,10 @@ async fn list_dashboard_sessions(
                 shell_count,
                 user_count,
                 has_write_password,
+                default_writable,
                 last_accessed,
                 users,
+                labels: session.metadata().labels.clone(),
                 metadata,
             });
         }
@@ -334,6 +416,14 @@ async fn list_dashboard_sessions(
         }
     }
 
+    // Apply label filter
+    if let Some(label) = query.label.as_deref().filter(|l| !l.is_empty()) {
+        sessions.retain(|session| match label.split_once('=') {
+            Some((key, value)) => session.labels.get(key).is_some_and(|v| v == value),
+            None => session.labels.contains_key(label),
+        });
+    }
+
     // Apply sorting
     match query.sort.as_str() {
         "name" => {
@@ -345,31 +435,31 @@ async fn list_dashboard_sessions(
         }
         "userCount" => {
             if query.order == "desc" {
-                sessions.sort_by(|a, b| b.user_count.cmp(&a.user_count));
+                sessions.sort_by_key(|s| std::cmp::Reverse(s.user_count));
             } else {
-                sessions.sort_by(|a, b| a.user_count.cmp(&b.user_count));
+                sessions.sort_by_key(|s| s.user_count);
             }
         }
         "shellCount" => {
             if query.order == "desc" {
-                sessions.sort_by(|a, b| b.shell_count.cmp(&a.shell_count));
+                sessions.sort_by_key(|s| std::cmp::Reverse(s.shell_count));
             } else {
-                sessions.sort_by(|a, b| a.shell_count.cmp(&b.shell_count));
+                sessions.sort_by_key(|s| s.shell_count);
             }
         }
         "lastAccessed" => {
             if query.order == "desc" {
-                sessions.sort_by(|a, b| b.last_accessed.cmp(&a.last_accessed));
+                sessions.sort_by_key(|s| std::cmp::Reverse(s.last_accessed));
             } else {
-                sessions.sort_by(|a, b| a.last_accessed.cmp(&b.last_accessed));
+                sessions.sort_by_key(|s| s.last_accessed);
             }
         }
         _ => {
             // Default to sorting by last accessed time for unknown sort fields
             if query.order == "desc" {
-                sessions.sort_by(|a, b| b.last_accessed.cmp(&a.last_accessed));
+                sessions.sort_by_key(|s| std::cmp::Reverse(s.last_accessed));
             } else {
-                sessions.sort_by(|a, b| a.last_accessed.cmp(&b.last_accessed));
+                sessions.sort_by_key(|s| s.last_accessed);
             }
         }
     }
@@ -446,33 +536,265 @@ async fn get_dashboard_info(Path(dashboard_key): Path<String>) -> Json<Dashboard
     }
 }
 
-/// Returns the web application server, routed with Axum.
-pub fn app() -> Router<Arc<ServerState>> {
-    let root_spa = ServeFile::new("build/spa.html")
+/// Page shown in browsers for links to sessions that do not exist.
+const SESSION_NOT_FOUND_HTML: &str = include_str!("web/not_found.html");
+
+/// Check that the static assets directory exists, logging where it was expected.
+///
+/// A missing directory leaves the API working but every page returning 404,
+/// so this is surfaced at startup. It is an error if assets are required.
+pub(crate) fn check_assets(options: &ServerOptions) -> Result<()> {
+    let dir = options.assets_path();
+    if dir.is_dir() {
+        return Ok(());
+    }
+    let resolved = std::path::absolute(dir).unwrap_or_else(|_| dir.to_path_buf());
+
+    if cfg!(feature = "embed-assets") {
+        tracing::info!(
+            path = %resolved.display(),
+            "static assets directory not found, serving embedded assets"
+        );
+    } else if options.require_assets {
+        bail!("static assets directory not found: {}", resolved.display());
+    } else {
+        tracing::warn!(
+            path = %resolved.display(),
+            "static assets directory not found, the web interface will return 404"
+        );
+    }
+    Ok(())
+}
+
+/// Returns the single-page app entrypoint, served for client-side routes.
+fn root_spa(dir: &FsPath) -> ServeFile {
+    ServeFile::new(dir.join("spa.html"))
         .precompressed_gzip()
-        .precompressed_br();
+        .precompressed_br()
+}
 
+/// Returns the web application server, routed with Axum.
+pub fn app(options: &ServerOptions) -> Router<Arc<ServerState>> {
     // Serves static SvelteKit build files.
-    let static_files = ServeDir::new("build")
+    let dir = options.assets_path();
+    let static_files = ServeDir::new(dir)
         .precompressed_gzip()
         .precompressed_br()
-        .fallback(root_spa);
+        .fallback(root_spa(dir));
+
+    // Compress pages on the fly, unless a precompressed file was served.
+    let compress = !options.disable_compression;
+    let compression = CompressionLayer::new().br(compress).gzip(compress);
+
+    let router = Router::new()
+        .nest("/api", backend(options))
+        .route("/s/{name}", get(get_session_page).layer(compression.clone()));
+
+    // Prefer the on-disk build during development, falling back to the
+    // assets embedded at compile time when the directory is missing.
+    #[cfg(feature = "embed-assets")]
+    if !dir.is_dir() {
+        use axum::handler::Handler;
+        return router.fallback(assets::serve.layer(compression));
+    }
+
+    router.fallback_service(get_service(static_files).layer(compression))
+}
+
+/// Serve the app for a session link, or a 404 page if the session is unknown.
+///
+/// Sessions owned by other servers in the mesh are treated as present, since
+/// the WebSocket connection will be proxied to them.
+async fn get_session_page(
+    Path(name): Path<String>,
+    State(state): State<Arc<ServerState>>,
+    request: Request,
+) -> Response {
+    match state.frontend_connect(&name).await {
+        Ok(Err(None)) => {
+            let accept = request
+                .headers()
+                .get(header::ACCEPT)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default();
+            if accept.contains("application/json") && !accept.contains("text/html") {
+                let body = serde_json::json!({ "error": "session not found" });
+                (StatusCode::NOT_FOUND, Json(body)).into_response()
+            } else {
+                (StatusCode::NOT_FOUND, Html(SESSION_NOT_FOUND_HTML)).into_response()
+            }
+        }
+        Ok(_) => spa_response(state.options().assets_path(), request).await,
+        Err(err) => {
+            tracing::error!(?err, "failed to look up session for page");
+            spa_response(state.options().assets_path(), request).await
+        }
+    }
+}
+
+/// Serve the single-page app entrypoint in response to a request.
+async fn spa_response(dir: &FsPath, request: Request) -> Response {
+    #[cfg(feature = "embed-assets")]
+    if !dir.is_dir() {
+        let (parts, _) = request.into_parts();
+        return assets::serve(parts.method, parts.uri, parts.headers).await;
+    }
+
+    match root_spa(dir).oneshot(request).await {
+        Ok(response) => response.into_response(),
+        Err(err) => match err {},
+    }
+}
+
+/// Header carrying the session's base64-encoded encrypted zeros.
+const ENCRYPTED_ZEROS_HEADER: &str = "x-sshx-encrypted-zeros";
+
+/// List the users connected to a session.
+///
+/// Requires the same credentials as joining over WebSocket: the session's
+/// encrypted zeros, base64-encoded in an `X-Sshx-Encrypted-Zeros` header.
+///
+/// Unlike WebSocket connections, requests are not proxied across a server
+/// mesh. Sessions owned by another server return 421 Misdirected Request.
+///
+/// Failed attempts count towards the same lockout as WebSocket logins.
+async fn list_session_users(
+    Path(name): Path<String>,
+    State(state): State<Arc<ServerState>>,
+    ConnectInfo(ip): ConnectInfo<Option<IpAddr>>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<SessionUser>>, StatusCode> {
+    let session = match state.frontend_connect(&name).await {
+        Ok(Ok(session)) => session,
+        Ok(Err(Some(_))) => return Err(StatusCode::MISDIRECTED_REQUEST),
+        Ok(Err(None)) => return Err(StatusCode::NOT_FOUND),
+        Err(err) => {
+            tracing::error!(?err, "failed to look up session users");
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    let mut auth_keys = vec![AuthKey::Session(name)];
+    auth_keys.extend(ip.map(AuthKey::Ip));
+    let throttle = state.auth_throttle();
+    if throttle.check(&auth_keys).is_some() {
+        return Err(StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    let authorized = headers
+        .get(ENCRYPTED_ZEROS_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| BASE64_STANDARD.decode(value.trim()).ok())
+        .is_some_and(|zeros| constant_time_eq(&zeros, &session.metadata().encrypted_zeros));
+    if !authorized {
+        throttle.fail(&auth_keys);
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    throttle.reset(&auth_keys);
+
+    let mut users: Vec<_> = session
+        .list_users()
+        .into_iter()
+        .map(|(id, user)| SessionUser {
+            id,
+            name: user.name,
+            can_write: user.can_write,
+            focus: user.focus,
+        })
+        .collect();
+    users.sort_by_key(|user| user.id);
+    Ok(Json(users))
+}
+
+/// List which of the given sessions are active, for clients holding tokens.
+///
+/// Sessions that do not exist on this server or whose token does not match
+/// are left out, so the response reveals nothing without a valid token.
+async fn list_owned_sessions(
+    State(state): State<Arc<ServerState>>,
+    Json(query): Json<Vec<OwnedSessionQuery>>,
+) -> Result<Json<Vec<OwnedSession>>, StatusCode> {
+    if query.len() > MAX_OWNED_QUERY {
+        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+    let sessions = query
+        .into_iter()
+        .filter(|owned| state.verify_token(&owned.name, &owned.token))
+        .filter_map(|owned| {
+            let session = state.lookup(&owned.name)?;
+            Some(OwnedSession {
+                shell_count: session.shell_count(),
+                user_count: session.list_users().len(),
+                idle_ms: session.last_accessed().elapsed().as_millis() as u64,
+                name: owned.name,
+            })
+        })
+        .collect();
+    Ok(Json(sessions))
+}
 
-    Router::new()
-        .nest("/api", backend())
-        .fallback_service(get_service(static_files))
+/// Stream session events to administrators as server-sent events.
+///
+/// Each event carries one JSON object in its data field. Requires the admin
+/// token, and returns 404 if none is configured.
+async fn admin_events(State(state): State<Arc<ServerState>>, headers: HeaderMap) -> Response {
+    if state.options().admin_token.is_none() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    let authorization = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+    if !state.check_admin(authorization) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    let mut events = state.events().subscribe();
+    let stream = async_stream::stream! {
+        let closed = state.events().closed();
+        tokio::pin!(closed);
+        loop {
+            let line = tokio::select! {
+                _ = &mut closed => break,
+                result = events.recv() => match result {
+                    Ok(line) => line,
+                    Err(RecvError::Lagged(count)) => {
+                        state.events().record_dropped(count);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+            };
+            yield Ok::<_, Infallible>(SseEvent::default().data(&*line));
+        }
+    };
+    Sse::new(stream).keep_alive(KeepAlive::default()).into_response()
 }
 
 /// Routes for the backend web API server.
-fn backend() -> Router<Arc<ServerState>> {
-    Router::new()
+fn backend(options: &ServerOptions) -> Router<Arc<ServerState>> {
+    let mut router = Router::new()
         // Session WebSocket routes (unprotected - clients need direct access)
         .route("/s/{name}", any(socket::get_session_ws))
+        // Key-less spectator streams, only for sessions that opt in
+        .route("/spectate/{name}", any(socket::get_spectator_ws));
+
+    if !options.disable_cli_websocket {
         // CLI WebSocket route for gRPC-like operations
-        .route("/cli/{name}", any(socket::get_cli_ws))
+        router = router.route("/cli/{name}", any(socket::get_cli_ws));
+    }
+
+    router
+        // Session REST routes (authenticated with the session's encrypted zeros)
+        .route("/s/{name}/users", get(list_session_users))
+        // Sessions owned by a client (authenticated with each session's token)
+        .route("/sessions/owned", post(list_owned_sessions))
         // Dashboard API routes
         .route("/dashboards/{key}/sessions", get(list_dashboard_sessions))
         .route("/dashboards/{key}/status", get(check_dashboard_status))
         .route("/dashboards/{key}/info", get(get_dashboard_info))
         .route("/dashboards/register", post(register_dashboard))
+        // Admin routes (authenticated with the admin token)
+        .route("/admin/events", get(admin_events))
+        // Unknown API routes should not fall through to the web app.
+        .fallback(|| async { StatusCode::NOT_FOUND })
 }
@@ -0,0 +1,75 @@
+//! Static frontend assets compiled into the server binary.
+//!
+//! This is only used with the `embed-assets` feature, when the `build/`
+//! directory is not present on disk at runtime.
+
+use axum::body::Body;
+use axum::http::{header, HeaderMap, HeaderValue, Method, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use rust_embed::RustEmbed;
+
+/// Files from the SvelteKit build, including precompressed variants.
+#[derive(RustEmbed)]
+#[folder = "../../build/"]
+struct Assets;
+
+/// Name of the single-page app entrypoint, served for unknown paths.
+const SPA_FILE: &str = "spa.html";
+
+/// Serve an embedded static file, falling back to the single-page app.
+pub async fn serve(method: Method, uri: Uri, headers: HeaderMap) -> Response {
+    if method != Method::GET && method != Method::HEAD {
+        return StatusCode::METHOD_NOT_ALLOWED.into_response();
+    }
+
+    let path = uri.path().trim_start_matches('/');
+    let path = match path {
+        "" => "index.html".to_string(),
+        p if p.ends_with('/') => format!("{p}index.html"),
+        p => p.to_string(),
+    };
+
+    let response = match file_response(&path, &headers) {
+        Some(response) => response,
+        None => match file_response(SPA_FILE, &headers) {
+            Some(response) => response,
+            None => return StatusCode::NOT_FOUND.into_response(),
+        },
+    };
+    if method == Method::HEAD {
+        let (parts, _) = response.into_parts();
+        return Response::from_parts(parts, Body::empty());
+    }
+    response
+}
+
+/// Build a response for an embedded file, preferring precompressed variants
+/// accepted by the client.
+fn file_response(path: &str, headers: &HeaderMap) -> Option<Response> {
+    let file = Assets::get(path)?;
+    let mime = file.metadata.mimetype().to_string();
+
+    let accepted = headers
+        .get(header::ACCEPT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let encodings = [("br", ".br"), ("gzip", ".gz")];
+    let compressed = encodings.into_iter().find_map(|(encoding, ext)| {
+        if !accepted.split(',').any(|e| e.trim().starts_with(encoding)) {
+            return None;
+        }
+        Some((encoding, Assets::get(&format!("{path}{ext}"))?))
+    });
+
+    let mut builder = Response::builder()
+        .header(header::CONTENT_TYPE, mime)
+        .header(header::VARY, HeaderValue::from_static("accept-encoding"));
+    let data = match compressed {
+        Some((encoding, compressed)) => {
+            builder = builder.header(header::CONTENT_ENCODING, encoding);
+            compressed.data
+        }
+        None => file.data,
+    };
+    builder.body(Body::from(data.into_owned())).ok()
+}
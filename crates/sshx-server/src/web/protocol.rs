@@ -41,6 +41,41 @@ pub struct WsUser {
     pub focus: Option<Sid>,
     /// Whether the user has write permissions in the session.
     pub can_write: bool,
+    /// Hue from 0 to 359 for rendering the user, derived from their name.
+    pub hue: u16,
+    /// Terminal type reported by the user's client, such as `xterm-256color`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub terminal: Option<String>,
+}
+
+impl WsUser {
+    /// Create a user with the given name and default state.
+    pub fn new(name: String, can_write: bool) -> Self {
+        Self {
+            hue: name_hue(&name),
+            name,
+            cursor: None,
+            focus: None,
+            can_write,
+            terminal: None,
+        }
+    }
+
+    /// Change the user's display name, updating their color to match.
+    pub fn set_name(&mut self, name: String) {
+        self.hue = name_hue(&name);
+        self.name = name;
+    }
+}
+
+/// Deterministic hue for a display name, so the same name always gets the
+/// same color across clients and reconnects. Uses 32-bit FNV-1a.
+pub fn name_hue(name: &str) -> u16 {
+    let mut hash: u32 = 2166136261;
+    for byte in name.bytes() {
+        hash = (hash ^ byte as u32).wrapping_mul(16777619);
+    }
+    (hash % 360) as u16
 }
 
 /// A real-time message sent from the server over WebSocket.
@@ -55,10 +90,18 @@ pub enum WsServer {
     Users(Vec<(Uid, WsUser)>),
     /// Info about a single user in the session: joined, left, or changed.
     UserDiff(Uid, Option<WsUser>),
-    /// Notification when the set of open shells has changed.
-    Shells(Vec<(Sid, WsWinsize)>),
+    /// Notification when the set of open shells has changed, with the size of
+    /// each and its name, if a user has set one.
+    Shells(Vec<(Sid, WsWinsize, Option<String>)>),
+    /// A shell process exited on its own, with its exit code if known.
+    ShellExit(Sid, Option<i32>),
+    /// Whether terminal output and input are currently paused by a host.
+    Paused(bool),
     /// Subscription results, in the form of terminal data chunks.
     Chunks(Sid, u64, Vec<Bytes>),
+    /// Recent output of a shell for redrawing its screen, starting at a byte
+    /// offset, in response to [`WsClient::RequestSnapshot`].
+    Snapshot(Sid, u64, Vec<Bytes>),
     /// Get a chat message tuple `(uid, name, text)` from the room.
     Hear(Uid, String, String),
     /// Forward a latency measurement between the server and backend shell.
@@ -78,6 +121,8 @@ pub enum WsClient {
     Authenticate(Bytes, Option<Bytes>),
     /// Set the name of the current user.
     SetName(String),
+    /// Report the terminal type of the current user, for display only.
+    SetTerminal(String),
     /// Send real-time information about the user's cursor.
     SetCursor(Option<(i32, i32)>),
     /// Set the currently focused shell.
@@ -88,14 +133,26 @@ pub enum WsClient {
     Close(Sid),
     /// Move a shell window to a new position and focus it.
     Move(Sid, Option<WsWinsize>),
+    /// Name a shell for all users, or clear its name if empty. Requires write
+    /// access.
+    SetShellName(Sid, String),
     /// Add user data to a given shell.
     Data(Sid, Bytes, u64),
     /// Subscribe to a shell, starting at a given chunk index.
     Subscribe(Sid, u64),
+    /// Subscribe to shells after reconnecting, each from the first byte
+    /// sequence number not yet received.
+    Resume(Vec<(Sid, u64)>),
     /// Send a a chat message to the room.
     Chat(String),
     /// Send a ping to the server, for latency measurement.
     Ping(u64),
+    /// Prove that this client is the host, with the key from the session URL.
+    ClaimHost(String),
+    /// Pause or resume terminal output and input, requires being the host.
+    SetPaused(bool),
+    /// Request a one-shot [`WsServer::Snapshot`] of a shell's recent output.
+    RequestSnapshot(Sid),
 }
 
 /// CLI WebSocket request message with correlation ID.
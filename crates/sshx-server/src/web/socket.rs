@@ -1,26 +1,40 @@
-use std::collections::HashSet;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::net::IpAddr;
 use std::sync::Arc;
+use std::time::Duration;
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use axum::extract::{
     ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade},
-    Path, State,
+    ConnectInfo, Path, State,
 };
-use axum::response::IntoResponse;
+use axum::http::header::{
+    HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, CONNECTION, CONTENT_LENGTH, HOST,
+    PROXY_AUTHORIZATION, SEC_WEBSOCKET_EXTENSIONS, SEC_WEBSOCKET_KEY, SEC_WEBSOCKET_PROTOCOL,
+    SEC_WEBSOCKET_VERSION, TE, TRAILER, TRANSFER_ENCODING, UPGRADE,
+};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
 use bytes::Bytes;
 use futures_util::SinkExt;
 use sshx_core::proto::{
     server_update::ServerMessage, NewShell, ServerUpdate, TerminalInput, TerminalSize,
     SequenceNumbers,
 };
-use sshx_core::Sid;
-use subtle::ConstantTimeEq;
+use sshx_core::{Sid, Uid};
+use tokio::net::TcpStream;
 use tokio::sync::mpsc;
-use tokio_stream::StreamExt;
+use tokio::task::JoinHandle;
+use tokio::time::{self, Instant};
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+use tokio_stream::{Stream, StreamExt};
 use tracing::{debug, error, info_span, warn, Instrument};
 
-use crate::session::Session;
-use crate::web::protocol::{WsClient, WsServer};
+use crate::session::{validate_labels, ResumePoint, Session, ENCRYPTED_ZEROS_LEN};
+use crate::state::events::Event;
+use crate::state::throttle::AuthKey;
+use crate::utils::{constant_time_eq, request_id};
+use crate::web::protocol::{WsClient, WsServer, WsWinsize};
 use sshx_core::proto::{CliRequest, CliResponse, cli_request, cli_response};
 use prost::Message as ProstMessage;
 
@@ -30,17 +44,58 @@ type ActiveSession = (
 );
 use crate::ServerState;
 
+/// Echo the subprotocols requested by the client, so gateways that require a
+/// `Sec-WebSocket-Protocol` see it accepted. The server does not interpret it.
+fn echo_protocols(ws: WebSocketUpgrade, headers: &HeaderMap) -> WebSocketUpgrade {
+    let requested: Vec<String> = headers
+        .get_all(SEC_WEBSOCKET_PROTOCOL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|protocol| protocol.trim().to_string())
+        .filter(|protocol| !protocol.is_empty())
+        .collect();
+    ws.protocols(requested)
+}
+
+/// Limit the size of messages and frames accepted on a WebSocket.
+fn limit_size(ws: WebSocketUpgrade, state: &ServerState) -> WebSocketUpgrade {
+    let max_size = state.options().max_message_size();
+    ws.max_message_size(max_size).max_frame_size(max_size)
+}
+
+/// Reject a WebSocket upgrade that lacks the server's bearer token, if set.
+fn reject_unauthorized(state: &ServerState, headers: &HeaderMap) -> Option<Response> {
+    let authorization = headers.get(AUTHORIZATION).and_then(|v| v.to_str().ok());
+    if state.check_auth(authorization) {
+        None
+    } else {
+        Some((StatusCode::UNAUTHORIZED, "missing or invalid bearer token").into_response())
+    }
+}
+
 pub async fn get_session_ws(
     Path(name): Path<String>,
     ws: WebSocketUpgrade,
+    headers: HeaderMap,
+    ConnectInfo(ip): ConnectInfo<Option<IpAddr>>,
     State(state): State<Arc<ServerState>>,
-) -> impl IntoResponse {
-    ws.on_upgrade(move |mut socket| {
-        let span = info_span!("ws", %name);
+) -> Response {
+    if let Some(response) = reject_unauthorized(&state, &headers) {
+        return response;
+    }
+    let request_id = request_id(&headers).to_string();
+    let protocols = echo_protocols(limit_size(ws, &state), &headers);
+    protocols.on_upgrade(move |mut socket| {
+        let span = info_span!("ws", %name, %request_id);
         async move {
             match state.frontend_connect(&name).await {
                 Ok(Ok(session)) => {
-                    if let Err(err) = handle_socket(&mut socket, session).await {
+                    let mut auth_keys = vec![AuthKey::Session(name.clone())];
+                    auth_keys.extend(ip.map(AuthKey::Ip));
+                    let result =
+                        handle_socket(&mut socket, &name, session, &state, &auth_keys).await;
+                    if let Err(err) = result {
                         // Distinguish between normal connection closures and actual errors
                         let err_msg = err.to_string();
                         if err_msg.contains("Connection reset without closing handshake") 
@@ -55,11 +110,17 @@ pub async fn get_session_ws(
                     }
                 }
                 Ok(Err(Some(host))) => {
-                    if let Err(err) = proxy_redirect(&mut socket, &host, &name).await {
+                    let url = mesh_ws_url(&state, &host, &format!("/api/s/{name}"));
+                    let result = proxy_redirect(&mut socket, &url, &headers, ip).await;
+                    if let Err(err) = result {
                         error!(?err, "failed to proxy websocket");
+                        state.events().emit(Event::Error {
+                            session: Some(name.clone()),
+                            message: format!("proxy redirect: {err}"),
+                        });
                         let frame = CloseFrame {
                             code: 4500,
-                            reason: format!("proxy redirect: {err}").into(),
+                            reason: format!("proxy redirect: {err} (request {request_id})").into(),
                         };
                         socket.send(Message::Close(Some(frame))).await.ok();
                     } else {
@@ -75,9 +136,13 @@ pub async fn get_session_ws(
                 }
                 Err(err) => {
                     error!(?err, "failed to connect to frontend session");
+                    state.events().emit(Event::Error {
+                        session: Some(name.clone()),
+                        message: format!("session connect: {err}"),
+                    });
                     let frame = CloseFrame {
                         code: 4500,
-                        reason: format!("session connect: {err}").into(),
+                        reason: format!("session connect: {err} (request {request_id})").into(),
                     };
                     socket.send(Message::Close(Some(frame))).await.ok();
                 }
@@ -85,24 +150,169 @@ pub async fn get_session_ws(
         }
         .instrument(span)
     })
+    .into_response()
 }
 
-/// Handle an incoming live WebSocket connection to a given session.
-async fn handle_socket(socket: &mut WebSocket, session: Arc<Session>) -> Result<()> {
-    /// Send a message to the client over WebSocket.
-    async fn send(socket: &mut WebSocket, msg: WsServer) -> Result<()> {
-        let mut buf = Vec::new();
-        ciborium::ser::into_writer(&msg, &mut buf)?;
-        socket.send(Message::Binary(Bytes::from(buf))).await?;
-        Ok(())
+/// Send a message to the client over WebSocket.
+async fn send(socket: &mut WebSocket, msg: WsServer) -> Result<()> {
+    let mut buf = Vec::new();
+    ciborium::ser::into_writer(&msg, &mut buf)?;
+    socket.send(Message::Binary(Bytes::from(buf))).await?;
+    Ok(())
+}
+
+/// Tasks forwarding the output of subscribed shells, aborted when dropped.
+#[derive(Default)]
+struct Subscriptions(HashMap<Sid, JoinHandle<()>>);
+
+impl Subscriptions {
+    fn contains(&self, id: Sid) -> bool {
+        self.0.contains_key(&id)
+    }
+
+    fn insert(&mut self, id: Sid, task: JoinHandle<()>) {
+        if let Some(old) = self.0.insert(id, task) {
+            old.abort();
+        }
+    }
+
+    /// Forward a shell's chunks from a resume point, unless already subscribed.
+    fn subscribe(
+        &mut self,
+        session: &Arc<Session>,
+        chunks_tx: &mpsc::Sender<(Sid, u64, Vec<Bytes>)>,
+        id: Sid,
+        from: ResumePoint,
+        coalesce: Duration,
+    ) {
+        if self.contains(id) {
+            return;
+        }
+        let session = Arc::clone(session);
+        let chunks_tx = chunks_tx.clone();
+        let task = tokio::spawn(async move {
+            let stream = session.subscribe_chunks(id, from);
+            forward_chunks(stream, id, chunks_tx, coalesce).await;
+        });
+        self.insert(id, task);
+    }
+
+    /// Stop forwarding shells that are no longer open, so that a shell
+    /// reopened with the same ID can be subscribed to again.
+    fn retain(&mut self, shells: &[(Sid, WsWinsize, Option<String>)]) {
+        self.0.retain(|id, task| {
+            let open = shells.iter().any(|(shell, ..)| shell == id);
+            if !open {
+                task.abort();
+            }
+            open
+        });
+    }
+}
+
+impl Drop for Subscriptions {
+    fn drop(&mut self) {
+        for task in self.0.values() {
+            task.abort();
+        }
+    }
+}
+
+/// Forward batches of chunks from a shell until the stream ends.
+///
+/// With a nonzero `coalesce` window, batches that arrive within the window
+/// after the first are merged into one message, so bursts of small writes
+/// are sent as fewer frames. No output is held back for longer than the
+/// window, and a batch that doesn't continue the previous one is sent on its
+/// own.
+async fn forward_chunks(
+    stream: impl Stream<Item = (u64, Vec<Bytes>)>,
+    id: Sid,
+    chunks_tx: mpsc::Sender<(Sid, u64, Vec<Bytes>)>,
+    coalesce: Duration,
+) {
+    tokio::pin!(stream);
+    let mut pending = None;
+    loop {
+        let batch = match pending.take() {
+            Some(batch) => batch,
+            None => match stream.next().await {
+                Some(batch) => batch,
+                None => break,
+            },
+        };
+        let (seqnum, mut chunks) = batch;
+        if !coalesce.is_zero() {
+            let deadline = Instant::now() + coalesce;
+            let mut end = seqnum + chunks.iter().map(|c| c.len() as u64).sum::<u64>();
+            while let Ok(Some((next_seqnum, next_chunks))) =
+                time::timeout_at(deadline, stream.next()).await
+            {
+                if next_seqnum != end {
+                    pending = Some((next_seqnum, next_chunks));
+                    break;
+                }
+                end += next_chunks.iter().map(|c| c.len() as u64).sum::<u64>();
+                chunks.extend(next_chunks);
+            }
+        }
+        if chunks_tx.send((id, seqnum, chunks)).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Emits events for a user joining a session, and leaving once dropped.
+struct UserEvents<'a> {
+    state: &'a ServerState,
+    session: &'a str,
+    user: Uid,
+}
+
+impl<'a> UserEvents<'a> {
+    fn join(state: &'a ServerState, session: &'a str, user: Uid, can_write: bool) -> Self {
+        state.events().emit(Event::UserJoin {
+            session: session.into(),
+            user,
+            can_write,
+        });
+        Self { state, session, user }
+    }
+}
+
+impl Drop for UserEvents<'_> {
+    fn drop(&mut self) {
+        self.state.events().emit(Event::UserLeave {
+            session: self.session.into(),
+            user: self.user,
+        });
     }
+}
 
+/// Handle an incoming live WebSocket connection to a given session.
+async fn handle_socket(
+    socket: &mut WebSocket,
+    name: &str,
+    session: Arc<Session>,
+    state: &ServerState,
+    auth_keys: &[AuthKey],
+) -> Result<()> {
     /// Receive a message from the client over WebSocket.
+    ///
+    /// Malformed messages are reported to the client and skipped, rather than
+    /// closing the connection. Fragmented messages arrive here reassembled, up
+    /// to the size limit set in [`limit_size`], so each holds one CBOR value.
     async fn recv(socket: &mut WebSocket) -> Result<Option<WsClient>> {
         Ok(loop {
             match socket.recv().await.transpose()? {
                 Some(Message::Text(_)) => warn!("ignoring text message over WebSocket"),
-                Some(Message::Binary(msg)) => break Some(ciborium::de::from_reader(&*msg)?),
+                Some(Message::Binary(msg)) => match ciborium::de::from_reader(&*msg) {
+                    Ok(msg) => break Some(msg),
+                    Err(err) => {
+                        debug!(?err, "ignoring malformed message over WebSocket");
+                        send(socket, WsServer::Error("malformed message".into())).await?;
+                    }
+                },
                 Some(_) => (), // ignore other message types, keep looping
                 None => break None,
             }
@@ -114,18 +324,29 @@ async fn handle_socket(socket: &mut WebSocket, session: Arc<Session>) -> Result<
     session.sync_now();
     send(socket, WsServer::Hello(user_id, metadata.name.clone())).await?;
 
+    let throttle = state.auth_throttle();
     let can_write = match recv(socket).await? {
         Some(WsClient::Authenticate(bytes, write_password_bytes)) => {
+            if let Some(remaining) = throttle.check(auth_keys) {
+                let secs = remaining.as_secs() + 1;
+                let frame = CloseFrame {
+                    code: 4429,
+                    reason: format!("too many failed attempts, retry in {secs}s").into(),
+                };
+                socket.send(Message::Close(Some(frame))).await?;
+                return Ok(());
+            }
+
             tracing::debug!(
                 browser_bytes_len = bytes.len(),
                 stored_bytes_len = metadata.encrypted_zeros.len(),
-                bytes_equal = bool::from(bytes.ct_eq(metadata.encrypted_zeros.as_ref())),
                 "Browser authentication attempt"
             );
-            
-            // Constant-time comparison of bytes, converting Choice to bool
-            if !bool::from(bytes.ct_eq(metadata.encrypted_zeros.as_ref())) {
+
+            // Constant-time comparison of bytes, independent of their lengths
+            if !constant_time_eq(&bytes, &metadata.encrypted_zeros) {
                 tracing::debug!("Authentication failed: encrypted_zeros mismatch");
+                throttle.fail(auth_keys);
                 send(socket, WsServer::InvalidAuth()).await?;
                 return Ok(());
             }
@@ -138,8 +359,14 @@ async fn handle_socket(socket: &mut WebSocket, session: Arc<Session>) -> Result<
                 (None, Some(_)) => false,
 
                 // Password stored and provided, compare them.
-                (Some(provided), Some(stored)) => {
-                    if !bool::from(provided.ct_eq(stored)) {
+                (Some(provided), Some(_)) => {
+                    let metadata = metadata.clone();
+                    let verified = tokio::task::spawn_blocking(move || {
+                        metadata.verify_write_password(&provided)
+                    })
+                    .await?;
+                    if !verified {
+                        throttle.fail(auth_keys);
                         send(socket, WsServer::InvalidAuth()).await?;
                         return Ok(());
                     }
@@ -153,16 +380,43 @@ async fn handle_socket(socket: &mut WebSocket, session: Arc<Session>) -> Result<
         }
     };
 
-    let _user_guard = session.user_scope(user_id, can_write)?;
+    // A read-only join proves nothing about the write password, so only full
+    // access clears earlier failures.
+    if can_write {
+        throttle.reset(auth_keys);
+    }
+
+    // Writers that join after the session's write window has ended, or stay
+    // past it, are downgraded to read-only. Users who proved the write password
+    // are exempt, and so is the host once it claims the session.
+    let has_password = metadata.write_password_hash.is_some();
+    let write_deadline = session.write_deadline().filter(|_| can_write && !has_password);
+    let mut write_expired = write_deadline.is_some_and(|deadline| deadline <= Instant::now());
+    let can_write = can_write && !write_expired;
+    let mut write_deadline = write_deadline.filter(|_| can_write);
+
+    let Some(_user_guard) = session.user_scope(user_id, can_write)? else {
+        let max = metadata.max_clients.unwrap_or_default();
+        let frame = CloseFrame {
+            code: 4503,
+            reason: format!("session is full, at most {max} clients can join").into(),
+        };
+        socket.send(Message::Close(Some(frame))).await?;
+        return Ok(());
+    };
+    let _event_guard = UserEvents::join(state, name, user_id, can_write);
 
     let update_tx = session.update_tx(); // start listening for updates before any state reads
     let mut broadcast_stream = session.subscribe_broadcast();
     send(socket, WsServer::Users(session.list_users())).await?;
 
-    let mut subscribed = HashSet::new(); // prevent duplicate subscriptions
+    let mut subscribed = Subscriptions::default(); // prevent duplicate subscriptions
+    let mut is_host = false; // set once the client proves it holds the host key
     let (chunks_tx, mut chunks_rx) = mpsc::channel::<(Sid, u64, Vec<Bytes>)>(1);
+    let coalesce = state.options().chunk_coalesce();
 
     let mut shells_stream = session.subscribe_shells();
+    let mut paused_stream = session.subscribe_paused();
     loop {
         let msg = tokio::select! {
             _ = session.terminated() => break,
@@ -172,13 +426,26 @@ async fn handle_socket(socket: &mut WebSocket, session: Arc<Session>) -> Result<
                 continue;
             }
             Some(shells) = shells_stream.next() => {
+                subscribed.retain(&shells);
                 send(socket, WsServer::Shells(shells)).await?;
                 continue;
             }
+            Some(paused) = paused_stream.next() => {
+                send(socket, WsServer::Paused(paused)).await?;
+                continue;
+            }
             Some((id, seqnum, chunks)) = chunks_rx.recv() => {
                 send(socket, WsServer::Chunks(id, seqnum, chunks)).await?;
                 continue;
             }
+            _ = time::sleep_until(write_deadline.unwrap_or_else(Instant::now)),
+                if write_deadline.is_some() =>
+            {
+                write_deadline = None;
+                write_expired = true;
+                session.update_user(user_id, |user| user.can_write = false)?;
+                continue;
+            }
             result = recv(socket) => {
                 match result? {
                     Some(msg) => msg,
@@ -191,9 +458,15 @@ async fn handle_socket(socket: &mut WebSocket, session: Arc<Session>) -> Result<
             WsClient::Authenticate(_, _) => {}
             WsClient::SetName(name) => {
                 if !name.is_empty() {
-                    session.update_user(user_id, |user| user.name = name)?;
+                    session.update_user(user_id, |user| user.set_name(name))?;
                 }
             }
+            WsClient::SetTerminal(terminal) => {
+                let terminal: String = terminal.chars().take(64).collect();
+                session.update_user(user_id, |user| {
+                    user.terminal = (!terminal.is_empty()).then_some(terminal);
+                })?;
+            }
             WsClient::SetCursor(cursor) => {
                 session.update_user(user_id, |user| user.cursor = cursor)?;
             }
@@ -205,6 +478,10 @@ async fn handle_socket(socket: &mut WebSocket, session: Arc<Session>) -> Result<
                     send(socket, WsServer::Error(e.to_string())).await?;
                     continue;
                 }
+                if let Err(e) = session.check_shell_limit(state.options().max_shells()) {
+                    send(socket, WsServer::Error(e.to_string())).await?;
+                    continue;
+                }
                 let id = session.counter().next_sid();
                 session.sync_now();
                 let new_shell = NewShell { id: id.0, x, y };
@@ -237,11 +514,24 @@ async fn handle_socket(socket: &mut WebSocket, session: Arc<Session>) -> Result<
                     session.update_tx().send(msg).await?;
                 }
             }
+            WsClient::SetShellName(id, name) => {
+                if let Err(e) = session.check_write_permission(user_id) {
+                    send(socket, WsServer::Error(e.to_string())).await?;
+                    continue;
+                }
+                if let Err(err) = session.set_shell_name(id, &name) {
+                    send(socket, WsServer::Error(err.to_string())).await?;
+                }
+            }
             WsClient::Data(id, data, offset) => {
                 if let Err(e) = session.check_write_permission(user_id) {
                     send(socket, WsServer::Error(e.to_string())).await?;
                     continue;
                 }
+                if session.is_paused() {
+                    send(socket, WsServer::Error("Session is paused".into())).await?;
+                    continue;
+                }
                 let input = TerminalInput {
                     id: id.0,
                     data,
@@ -250,41 +540,292 @@ async fn handle_socket(socket: &mut WebSocket, session: Arc<Session>) -> Result<
                 update_tx.send(ServerMessage::Input(input)).await?;
             }
             WsClient::Subscribe(id, chunknum) => {
-                if subscribed.contains(&id) {
-                    continue;
+                let from = ResumePoint::Chunk(chunknum);
+                subscribed.subscribe(&session, &chunks_tx, id, from, coalesce);
+            }
+            WsClient::Resume(seqnums) => {
+                for (id, seqnum) in seqnums {
+                    let from = ResumePoint::Seqnum(seqnum);
+                    subscribed.subscribe(&session, &chunks_tx, id, from, coalesce);
                 }
-                subscribed.insert(id);
-                let session = Arc::clone(&session);
-                let chunks_tx = chunks_tx.clone();
-                tokio::spawn(async move {
-                    let stream = session.subscribe_chunks(id, chunknum);
-                    tokio::pin!(stream);
-                    while let Some((seqnum, chunks)) = stream.next().await {
-                        if chunks_tx.send((id, seqnum, chunks)).await.is_err() {
-                            break;
-                        }
-                    }
-                });
             }
+            WsClient::RequestSnapshot(id) => match session.recent_output(id) {
+                Ok((seqnum, chunks)) => {
+                    send(socket, WsServer::Snapshot(id, seqnum, chunks)).await?;
+                }
+                Err(e) => send(socket, WsServer::Error(e.to_string())).await?,
+            },
             WsClient::Chat(msg) => {
                 session.send_chat(user_id, &msg)?;
             }
             WsClient::Ping(ts) => {
                 send(socket, WsServer::Pong(ts)).await?;
             }
+            WsClient::ClaimHost(host_key) => {
+                if throttle.check(auth_keys).is_some() {
+                    let msg = "Too many failed attempts, try again later";
+                    send(socket, WsServer::Error(msg.into())).await?;
+                    continue;
+                }
+                if !constant_time_eq(host_key.as_bytes(), session.host_key().as_bytes()) {
+                    throttle.fail(auth_keys);
+                    send(socket, WsServer::Error("Invalid host key".into())).await?;
+                    continue;
+                }
+                is_host = true;
+                write_deadline = None;
+                if write_expired {
+                    write_expired = false;
+                    session.update_user(user_id, |user| user.can_write = true)?;
+                }
+            }
+            WsClient::SetPaused(paused) => {
+                if !is_host {
+                    let msg = "Only the host can pause the session";
+                    send(socket, WsServer::Error(msg.into())).await?;
+                    continue;
+                }
+                if session.is_locked() {
+                    send(socket, WsServer::Error("Session is locked by the host".into())).await?;
+                    continue;
+                }
+                session.set_paused(paused, state.options().discard_paused_output);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Handler for the key-less spectator WebSocket of a session.
+///
+/// This only works for sessions opened with spectators allowed, whose host
+/// sends an unencrypted copy of terminal output alongside the encrypted one.
+pub async fn get_spectator_ws(
+    Path(name): Path<String>,
+    ws: WebSocketUpgrade,
+    headers: HeaderMap,
+    ConnectInfo(ip): ConnectInfo<Option<IpAddr>>,
+    State(state): State<Arc<ServerState>>,
+) -> Response {
+    if let Some(response) = reject_unauthorized(&state, &headers) {
+        return response;
+    }
+    let request_id = request_id(&headers).to_string();
+    let protocols = echo_protocols(limit_size(ws, &state), &headers);
+    protocols.on_upgrade(move |mut socket| {
+        let span = info_span!("spectator_ws", %name, %request_id);
+        async move {
+            let close = |code, reason: &str| {
+                let reason = reason.into();
+                Message::Close(Some(CloseFrame { code, reason }))
+            };
+            match state.frontend_connect(&name).await {
+                Ok(Ok(session)) if session.metadata().allow_spectators => {
+                    let coalesce = state.options().chunk_coalesce();
+                    if let Err(err) = handle_spectator(&mut socket, session, coalesce).await {
+                        debug!(?err, "spectator websocket exiting early");
+                    } else {
+                        socket.close().await.ok();
+                    }
+                }
+                Ok(Ok(_)) => {
+                    let msg = close(4403, "this session does not allow spectators");
+                    socket.send(msg).await.ok();
+                }
+                Ok(Err(Some(host))) => {
+                    let url = mesh_ws_url(&state, &host, &format!("/api/spectate/{name}"));
+                    proxy_spectator(&mut socket, &url, &headers, ip).await;
+                }
+                Ok(Err(None)) => {
+                    let msg = close(4404, "could not find the requested session");
+                    socket.send(msg).await.ok();
+                }
+                Err(err) => {
+                    error!(?err, "failed to connect to spectator session");
+                    let reason = format!("session connect failed (request {request_id})");
+                    socket.send(close(4500, &reason)).await.ok();
+                }
+            }
+        }
+        .instrument(span)
+    })
+    .into_response()
+}
+
+/// Stream plaintext terminal output to a spectator, ignoring their messages.
+///
+/// Spectators only see the shell layout and output, never users or chat.
+async fn handle_spectator(
+    socket: &mut WebSocket,
+    session: Arc<Session>,
+    coalesce: Duration,
+) -> Result<()> {
+    send(socket, WsServer::Hello(Uid(0), session.metadata().name.clone())).await?;
+
+    let mut subscribed = Subscriptions::default();
+    let (chunks_tx, mut chunks_rx) = mpsc::channel::<(Sid, u64, Vec<Bytes>)>(1);
+    let mut shells_stream = session.subscribe_shells();
+    loop {
+        tokio::select! {
+            _ = session.terminated() => break,
+            Some(shells) = shells_stream.next() => {
+                subscribed.retain(&shells);
+                for &(id, ..) in &shells {
+                    if subscribed.contains(id) {
+                        continue;
+                    }
+                    let session = Arc::clone(&session);
+                    let chunks_tx = chunks_tx.clone();
+                    let task = tokio::spawn(async move {
+                        let stream = session.subscribe_spectator(id);
+                        forward_chunks(stream, id, chunks_tx, coalesce).await;
+                    });
+                    subscribed.insert(id, task);
+                }
+                send(socket, WsServer::Shells(shells)).await?;
+            }
+            Some((id, seqnum, chunks)) = chunks_rx.recv() => {
+                send(socket, WsServer::Chunks(id, seqnum, chunks)).await?;
+            }
+            msg = socket.recv() => match msg.transpose()? {
+                Some(Message::Close(_)) | None => break,
+                Some(_) => (),
+            },
         }
     }
     Ok(())
 }
 
+/// Attempts to connect to another server before giving up on proxying.
+const PROXY_CONNECT_ATTEMPTS: u32 = 4;
+
+/// Delay before retrying a proxy connection, doubled after each retry.
+const PROXY_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Timeout for each attempt to connect to another server.
+const PROXY_CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Header listing the client and proxy addresses a request passed through.
+const X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+
+/// Headers of a WebSocket upgrade that only apply to a single connection, so
+/// they are not forwarded when proxying to another server.
+const HOP_BY_HOP_HEADERS: &[HeaderName] = &[
+    HOST,
+    CONNECTION,
+    UPGRADE,
+    TE,
+    TRAILER,
+    TRANSFER_ENCODING,
+    CONTENT_LENGTH,
+    PROXY_AUTHORIZATION,
+    SEC_WEBSOCKET_KEY,
+    SEC_WEBSOCKET_VERSION,
+    SEC_WEBSOCKET_EXTENSIONS,
+];
+
+/// Build the upgrade request for proxying a client's WebSocket to another
+/// server, forwarding the client's end-to-end headers such as authorization.
+///
+/// The client's address is appended to `X-Forwarded-For` as the latest hop.
+fn upstream_request(
+    url: &str,
+    headers: &HeaderMap,
+    client_ip: Option<IpAddr>,
+) -> Result<tokio_tungstenite::tungstenite::handshake::client::Request> {
+    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+
+    let mut request = url.into_client_request()?;
+    let upstream_headers = request.headers_mut();
+    for (name, value) in headers {
+        if !HOP_BY_HOP_HEADERS.contains(name) && name != X_FORWARDED_FOR {
+            upstream_headers.append(name, value.clone());
+        }
+    }
+
+    let mut forwarded: Vec<&str> = headers
+        .get_all(X_FORWARDED_FOR)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .collect();
+    let client_ip = client_ip.map(|ip| ip.to_string());
+    forwarded.extend(client_ip.as_deref());
+    if !forwarded.is_empty() {
+        upstream_headers.insert(X_FORWARDED_FOR, HeaderValue::from_str(&forwarded.join(", "))?);
+    }
+    Ok(request)
+}
+
+/// Connect to another server for proxying, retrying briefly while it is not
+/// reachable, such as during a restart.
+///
+/// Servers that respond but refuse the upgrade are not retried.
+async fn connect_upstream(
+    request: tokio_tungstenite::tungstenite::handshake::client::Request,
+) -> Result<WebSocketStream<MaybeTlsStream<TcpStream>>> {
+    use tokio_tungstenite::tungstenite::Error;
+
+    let mut delay = PROXY_RETRY_DELAY;
+    let mut attempt = 1;
+    loop {
+        let result = time::timeout(PROXY_CONNECT_TIMEOUT, connect_async(request.clone())).await;
+        let err = match result {
+            Ok(Ok((upstream, _))) => return Ok(upstream),
+            Ok(Err(Error::Http(resp))) => bail!("upstream rejected with {}", resp.status()),
+            Ok(Err(err)) => anyhow::Error::from(err),
+            Err(elapsed) => anyhow::Error::from(elapsed),
+        };
+        if attempt == PROXY_CONNECT_ATTEMPTS {
+            return Err(err.context(format!("upstream not ready after {attempt} attempts")));
+        }
+        debug!(?err, attempt, "upstream not ready, retrying");
+        time::sleep(delay).await;
+        delay *= 2;
+        attempt += 1;
+    }
+}
+
+/// Returns the WebSocket URL of a path on another server in the mesh.
+fn mesh_ws_url(state: &ServerState, host: &str, path: &str) -> String {
+    let scheme = if state.options().mesh_tls { "wss" } else { "ws" };
+    format!("{scheme}://{host}{path}")
+}
+
+/// Proxy a spectator to the host that owns the session.
+///
+/// The socket is closed once the upstream connection ends, so spectators of a
+/// session on another host see it end just like a local one.
+async fn proxy_spectator(
+    socket: &mut WebSocket,
+    url: &str,
+    headers: &HeaderMap,
+    client_ip: Option<IpAddr>,
+) {
+    if let Err(err) = proxy_redirect(socket, url, headers, client_ip).await {
+        error!(?err, "failed to proxy spectator websocket");
+        let request_id = request_id(headers);
+        let reason = format!("proxy redirect failed (request {request_id})").into();
+        let frame = CloseFrame { code: 4500, reason };
+        socket.send(Message::Close(Some(frame))).await.ok();
+    } else {
+        socket.close().await.ok();
+    }
+}
+
 /// Transparently reverse-proxy a WebSocket connection to a different host.
-async fn proxy_redirect(socket: &mut WebSocket, host: &str, name: &str) -> Result<()> {
-    use tokio_tungstenite::{
-        connect_async,
-        tungstenite::protocol::{CloseFrame as TCloseFrame, Message as TMessage},
-    };
+///
+/// The client's headers are forwarded, including `Authorization` since the
+/// other host enforces the same bearer token.
+async fn proxy_redirect(
+    socket: &mut WebSocket,
+    url: &str,
+    headers: &HeaderMap,
+    client_ip: Option<IpAddr>,
+) -> Result<()> {
+    use tokio_tungstenite::tungstenite::protocol::{CloseFrame as TCloseFrame, Message as TMessage};
 
-    let (mut upstream, _) = connect_async(format!("ws://{host}/api/s/{name}")).await?;
+    let request = upstream_request(url, headers, client_ip)?;
+    let mut upstream = connect_upstream(request).await?;
     loop {
         // Due to axum having its own WebSocket API types, we need to manually translate
         // between it and tungstenite's message type.
@@ -340,10 +881,15 @@ async fn proxy_redirect(socket: &mut WebSocket, host: &str, name: &str) -> Resul
 pub async fn get_cli_ws(
     Path(name): Path<String>,
     ws: WebSocketUpgrade,
+    headers: HeaderMap,
     State(state): State<Arc<ServerState>>,
-) -> impl IntoResponse {
-    ws.on_upgrade(move |socket| {
-        let span = info_span!("cli_ws", %name);
+) -> Response {
+    if let Some(response) = reject_unauthorized(&state, &headers) {
+        return response;
+    }
+    let request_id = request_id(&headers).to_string();
+    echo_protocols(limit_size(ws, &state), &headers).on_upgrade(move |socket| {
+        let span = info_span!("cli_ws", %name, %request_id);
         async move {
             if let Err(err) = handle_cli_socket(socket, state, name).await {
                 // Distinguish between normal connection closures and actual errors
@@ -359,6 +905,7 @@ pub async fn get_cli_ws(
         }
         .instrument(span)
     })
+    .into_response()
 }
 
 /// Handle CLI WebSocket connection with protobuf messaging.
@@ -369,8 +916,6 @@ async fn handle_cli_socket(
 ) -> Result<()> {
     use tracing::debug;
     debug!(session_name = %name, "CLI WebSocket connection established");
-    use base64::prelude::{Engine as _, BASE64_STANDARD};
-    use hmac::Mac;
     use sshx_core::{rand_alphanumeric, Sid};
     use std::time::SystemTime;
     use tokio::sync::mpsc;
@@ -402,11 +947,9 @@ async fn handle_cli_socket(
     }
 
     /// Validate the client token for a session.
-    fn validate_token(mac: impl Mac, name: &str, token: &str) -> Result<(), String> {
-        if let Ok(token) = BASE64_STANDARD.decode(token) {
-            if mac.chain_update(name).verify_slice(&token).is_ok() {
-                return Ok(());
-            }
+    fn validate_token(state: &ServerState, name: &str, token: &str) -> Result<(), String> {
+        if state.verify_token(name, token) {
+            return Ok(());
         }
         Err("invalid token".to_string())
     }
@@ -421,11 +964,13 @@ async fn handle_cli_socket(
 
     // Main CLI WebSocket message loop
     let mut active_session: Option<ActiveSession> = None;
+    // Shells this connection may send data for: those open when the channel
+    // started, from before a reconnect, and those created since.
+    let mut owned_shells: HashSet<Sid> = HashSet::new();
+    // Shells that went over the limit, which the client was asked to close.
+    let mut rejected_shells: HashSet<Sid> = HashSet::new();
     let mut streaming_task_handle: Option<tokio::task::JoinHandle<()>> = None;
-    let connection_id = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_nanos();
+    let connection_id = state.next_connection_id();
     debug!(session_name = %name, connection_id = %connection_id, "Starting CLI message loop");
 
     loop {
@@ -440,22 +985,49 @@ async fn handle_cli_socket(
                                 let encrypted_zeros = open_req.encrypted_zeros;
                                 let name = open_req.name;
                                 let write_password_hash = open_req.write_password_hash;
+                                let labels = validate_labels(open_req.labels);
+                                let allow_spectators = open_req.allow_spectators;
+                                let max_clients = Some(open_req.max_clients).filter(|&max| max > 0);
+                                let write_window = (open_req.write_window_secs > 0)
+                                    .then(|| Duration::from_secs(open_req.write_window_secs.into()));
+                                let write_window_renew = open_req.write_window_renew;
+                                let start_locked = open_req.start_locked;
                                 tracing::debug!(
                                     encrypted_zeros_len = encrypted_zeros.len(),
                                     "Received OpenSession request with encrypted_zeros"
                                 );
-                                let origin = state.override_origin().unwrap_or(origin);
+                                let origin = state.session_origin(origin);
                                 if origin.is_empty() {
                                     CliResponse {
                                         id: req.id,
+                                        streaming: false,
                                         cli_response_message: Some(cli_response::CliResponseMessage::Error("origin is empty".to_string()))
                                     }
+                                } else if encrypted_zeros.len() != ENCRYPTED_ZEROS_LEN {
+                                    CliResponse {
+                                        id: req.id,
+                                        streaming: false,
+                                        cli_response_message: Some(cli_response::CliResponseMessage::Error("encrypted_zeros has invalid length".to_string()))
+                                    }
+                                } else if let Err(err) = &labels {
+                                    CliResponse {
+                                        id: req.id,
+                                        streaming: false,
+                                        cli_response_message: Some(cli_response::CliResponseMessage::Error(err.to_string()))
+                                    }
+                                } else if state.options().is_name_blocked(&name) {
+                                    CliResponse {
+                                        id: req.id,
+                                        streaming: false,
+                                        cli_response_message: Some(cli_response::CliResponseMessage::Error(format!("session name {name:?} is not allowed on this server")))
+                                    }
                                 } else {
                                     let session_name = rand_alphanumeric(10);
 
                                     match state.lookup(&session_name) {
                                         Some(_) => CliResponse {
                                             id: req.id,
+                                            streaming: false,
                                             cli_response_message: Some(cli_response::CliResponseMessage::Error("generated duplicate ID".to_string()))
                                         },
                                         None => {
@@ -463,23 +1035,43 @@ async fn handle_cli_socket(
                                                 encrypted_zeros: encrypted_zeros.clone(),
                                                 name,
                                                 write_password_hash,
+                                                labels: labels.unwrap_or_default(),
+                                                allow_spectators,
+                                                max_clients,
+                                                write_window,
+                                                write_window_renew,
                                             };
                                             tracing::debug!(
                                                 session_name = %session_name,
                                                 encrypted_zeros_len = encrypted_zeros.len(),
                                                 "WebSocket CLI session created with encrypted_zeros"
                                             );
-                                            state.insert(&session_name, Arc::new(Session::new(metadata)));
-                                            let token = state.mac().chain_update(&session_name).finalize();
+                                            let session = Session::new(metadata);
+                                            if start_locked {
+                                                session.set_locked(true);
+                                            }
+                                            let host_key = session.host_key().to_owned();
+                                            state.insert(&session_name, Arc::new(session));
+                                            state.events().emit(Event::SessionOpen {
+                                                session: session_name.clone(),
+                                            });
+                                            let token = state.issue_token(&session_name);
                                             let url = format!("{origin}/s/{session_name}");
+                                            let spectator_url = match allow_spectators {
+                                                true => format!("{origin}/spectate/{session_name}"),
+                                                false => String::new(),
+                                            };
 
                                             CliResponse {
                                                 id: req.id,
+                                                streaming: false,
                                                 cli_response_message: Some(cli_response::CliResponseMessage::OpenSession(
                                                     sshx_core::proto::OpenResponse {
                                                         name: session_name,
-                                                        token: BASE64_STANDARD.encode(token.into_bytes()),
+                                                        token,
                                                         url,
+                                                        host_key,
+                                                        spectator_url,
                                                     }
                                                 ))
                                             }
@@ -491,32 +1083,47 @@ async fn handle_cli_socket(
                             Some(cli_request::CliMessage::CloseSession(close_req)) => {
                                 let name = close_req.name;
                                 let token = close_req.token;
-                                match validate_token(state.mac(), &name, &token) {
+                                match validate_token(&state, &name, &token) {
                                     Ok(()) => {
                                         match state.close_session(&name).await {
                                             Ok(()) => CliResponse {
                                                 id: req.id,
+                                                streaming: false,
                                                 cli_response_message: Some(cli_response::CliResponseMessage::CloseSession(
                                                     sshx_core::proto::CloseResponse {}
                                                 ))
                                             },
                                             Err(err) => CliResponse {
                                                 id: req.id,
+                                                streaming: false,
                                                 cli_response_message: Some(cli_response::CliResponseMessage::Error(err.to_string()))
                                             }
                                         }
                                     }
                                     Err(err) => CliResponse {
                                         id: req.id,
+                                        streaming: false,
                                         cli_response_message: Some(cli_response::CliResponseMessage::Error(err))
                                     }
                                 }
                             }
 
+                            Some(cli_request::CliMessage::StartChannel(_)) if active_session.is_some() => {
+                                // A connection streams at most one session, like the
+                                // Hello message that starts a gRPC channel.
+                                CliResponse {
+                                    id: req.id,
+                                    streaming: false,
+                                    cli_response_message: Some(cli_response::CliResponseMessage::Error(
+                                        "unexpected start channel: a channel is already active".to_string()
+                                    ))
+                                }
+                            }
+
                             Some(cli_request::CliMessage::StartChannel(channel_req)) => {
                                 let session_name = channel_req.name;
                                 let token = channel_req.token;
-                                match validate_token(state.mac(), &session_name, &token) {
+                                match validate_token(&state, &session_name, &token) {
                                     Ok(()) => {
                                         match state.backend_connect(&session_name).await {
                                             Ok(Some(session)) => {
@@ -525,14 +1132,9 @@ async fn handle_cli_socket(
                                                 let session_clone = Arc::clone(&session);
                                                 let conn_id = connection_id;
 
-                                                // Cancel any existing streaming task
-                                                if let Some(handle) = streaming_task_handle.take() {
-                                                    debug!(session_name = %session_name, connection_id = %conn_id, "Cancelling previous streaming task");
-                                                    handle.abort();
-                                                }
-
                                                 debug!(session_name = %session_name, connection_id = %conn_id, "Starting CLI streaming task");
                                                 streaming_task_handle = Some(tokio::spawn(async move {
+                                                    let _backend_guard = session_clone.backend_scope();
                                                     if let Err(err) = handle_cli_streaming(&tx, &session_clone, conn_id).await {
                                                         // Connection failures during ping/sync are expected when clients disconnect
                                                         if err.contains("client disconnected") {
@@ -545,27 +1147,36 @@ async fn handle_cli_socket(
                                                     }
                                                 }));
 
+                                                // Report output received so far, so the client can
+                                                // resume by sending only what is missing.
+                                                let sequence_numbers = session.sequence_numbers();
+                                                owned_shells = sequence_numbers.map.keys().map(|&id| Sid(id)).collect();
+                                                let sequence_numbers = Some(sequence_numbers);
                                                 active_session = Some((session, rx));
 
                                                 CliResponse {
                                                     id: req.id,
+                                                    streaming: false,
                                                     cli_response_message: Some(cli_response::CliResponseMessage::StartChannel(
-                                                        sshx_core::proto::ChannelStartResponse {}
+                                                        sshx_core::proto::ChannelStartResponse { sequence_numbers }
                                                     ))
                                                 }
                                             }
                                             Ok(None) => CliResponse {
                                                 id: req.id,
+                                                streaming: false,
                                                 cli_response_message: Some(cli_response::CliResponseMessage::Error("session not found".to_string()))
                                             },
                                             Err(err) => CliResponse {
                                                 id: req.id,
+                                                streaming: false,
                                                 cli_response_message: Some(cli_response::CliResponseMessage::Error(err.to_string()))
                                             }
                                         }
                                     }
                                     Err(err) => CliResponse {
                                         id: req.id,
+                                        streaming: false,
                                         cli_response_message: Some(cli_response::CliResponseMessage::Error(err))
                                     }
                                 }
@@ -574,9 +1185,20 @@ async fn handle_cli_socket(
                             Some(cli_request::CliMessage::TerminalData(data)) => {
                                 if let Some((session, _)) = &active_session {
                                     session.access();
-                                    if let Err(err) = session.add_data(Sid(data.id), data.data, data.seq) {
+                                    if rejected_shells.contains(&Sid(data.id)) {
+                                        continue; // Output from before the shell was closed
+                                    } else if !owned_shells.contains(&Sid(data.id)) {
+                                        CliResponse {
+                                            id: req.id.clone(),
+                                            streaming: false,
+                                            cli_response_message: Some(cli_response::CliResponseMessage::Error(
+                                                format!("add data: shell id={} was not created by this client", data.id)
+                                            ))
+                                        }
+                                    } else if let Err(err) = session.add_data(Sid(data.id), data.data, data.seq) {
                                         CliResponse {
                                             id: req.id.clone(),
+                                            streaming: false,
                                             cli_response_message: Some(cli_response::CliResponseMessage::Error(
                                                 format!("add data: {:?}", err)
                                             ))
@@ -587,6 +1209,41 @@ async fn handle_cli_socket(
                                 } else {
                                     CliResponse {
                                         id: req.id.clone(),
+                                        streaming: false,
+                                        cli_response_message: Some(cli_response::CliResponseMessage::Error(
+                                            "no active session".to_string()
+                                        ))
+                                    }
+                                }
+                            }
+
+                            Some(cli_request::CliMessage::SpectatorData(data)) => {
+                                if let Some((session, _)) = &active_session {
+                                    if rejected_shells.contains(&Sid(data.id)) {
+                                        continue; // Output from before the shell was closed
+                                    } else if !owned_shells.contains(&Sid(data.id)) {
+                                        CliResponse {
+                                            id: req.id.clone(),
+                                            streaming: false,
+                                            cli_response_message: Some(cli_response::CliResponseMessage::Error(
+                                                format!("add spectator data: shell id={} was not created by this client", data.id)
+                                            ))
+                                        }
+                                    } else if let Err(err) = session.add_spectator_data(Sid(data.id), data.data, data.seq) {
+                                        CliResponse {
+                                            id: req.id.clone(),
+                                            streaming: false,
+                                            cli_response_message: Some(cli_response::CliResponseMessage::Error(
+                                                format!("add spectator data: {:?}", err)
+                                            ))
+                                        }
+                                    } else {
+                                        continue;
+                                    }
+                                } else {
+                                    CliResponse {
+                                        id: req.id.clone(),
+                                        streaming: false,
                                         cli_response_message: Some(cli_response::CliResponseMessage::Error(
                                             "no active session".to_string()
                                         ))
@@ -597,19 +1254,33 @@ async fn handle_cli_socket(
                             Some(cli_request::CliMessage::CreatedShell(new_shell)) => {
                                 if let Some((session, _)) = &active_session {
                                     session.access();
-                                    if let Err(err) = session.add_shell(Sid(new_shell.id), (new_shell.x, new_shell.y)) {
+                                    if let Err(err) = session.check_shell_limit(state.options().max_shells()) {
+                                        // The host already spawned this shell, so ask it to close it.
+                                        rejected_shells.insert(Sid(new_shell.id));
+                                        session.update_tx().send(ServerMessage::CloseShell(new_shell.id)).await.ok();
                                         CliResponse {
                                             id: req.id.clone(),
+                                            streaming: false,
+                                            cli_response_message: Some(cli_response::CliResponseMessage::Error(
+                                                format!("add shell: {err}")
+                                            ))
+                                        }
+                                    } else if let Err(err) = session.add_shell(Sid(new_shell.id), (new_shell.x, new_shell.y)) {
+                                        CliResponse {
+                                            id: req.id.clone(),
+                                            streaming: false,
                                             cli_response_message: Some(cli_response::CliResponseMessage::Error(
                                                 format!("add shell: {:?}", err)
                                             ))
                                         }
                                     } else {
+                                        owned_shells.insert(Sid(new_shell.id));
                                         continue; // No response needed
                                     }
                                 } else {
                                     CliResponse {
                                         id: req.id.clone(),
+                                        streaming: false,
                                         cli_response_message: Some(cli_response::CliResponseMessage::Error(
                                             "no active session".to_string()
                                         ))
@@ -620,9 +1291,20 @@ async fn handle_cli_socket(
                             Some(cli_request::CliMessage::ClosedShell(shell_id)) => {
                                 if let Some((session, _)) = &active_session {
                                     session.access();
-                                    if let Err(err) = session.close_shell(Sid(shell_id)) {
+                                    if rejected_shells.remove(&Sid(shell_id)) {
+                                        continue; // Never added to the session
+                                    } else if !owned_shells.remove(&Sid(shell_id)) {
+                                        CliResponse {
+                                            id: req.id.clone(),
+                                            streaming: false,
+                                            cli_response_message: Some(cli_response::CliResponseMessage::Error(
+                                                format!("close shell: shell id={shell_id} was not created by this client")
+                                            ))
+                                        }
+                                    } else if let Err(err) = session.close_shell(Sid(shell_id)) {
                                         CliResponse {
                                             id: req.id.clone(),
+                                            streaming: false,
                                             cli_response_message: Some(cli_response::CliResponseMessage::Error(
                                                 format!("close shell: {:?}", err)
                                             ))
@@ -633,6 +1315,7 @@ async fn handle_cli_socket(
                                 } else {
                                     CliResponse {
                                         id: req.id.clone(),
+                                        streaming: false,
                                         cli_response_message: Some(cli_response::CliResponseMessage::Error(
                                             "no active session".to_string()
                                         ))
@@ -640,6 +1323,47 @@ async fn handle_cli_socket(
                                 }
                             }
 
+                            Some(cli_request::CliMessage::ExitedShell(exit)) => {
+                                if let Some((session, _)) = &active_session {
+                                    session.access();
+                                    if !owned_shells.contains(&Sid(exit.id)) {
+                                        CliResponse {
+                                            id: req.id.clone(),
+                                            streaming: false,
+                                            cli_response_message: Some(cli_response::CliResponseMessage::Error(
+                                                format!("exit shell: shell id={} was not created by this client", exit.id)
+                                            ))
+                                        }
+                                    } else if let Err(err) = session.set_exit_code(Sid(exit.id), exit.exit_code) {
+                                        CliResponse {
+                                            id: req.id.clone(),
+                                            streaming: false,
+                                            cli_response_message: Some(cli_response::CliResponseMessage::Error(
+                                                format!("exit shell: {:?}", err)
+                                            ))
+                                        }
+                                    } else {
+                                        continue; // No response needed
+                                    }
+                                } else {
+                                    CliResponse {
+                                        id: req.id.clone(),
+                                        streaming: false,
+                                        cli_response_message: Some(cli_response::CliResponseMessage::Error(
+                                            "no active session".to_string()
+                                        ))
+                                    }
+                                }
+                            }
+
+                            Some(cli_request::CliMessage::Locked(locked)) => {
+                                if let Some((session, _)) = &active_session {
+                                    session.access();
+                                    session.set_locked(locked);
+                                }
+                                continue; // No response needed
+                            }
+
                             Some(cli_request::CliMessage::Pong(timestamp)) => {
                                 if let Some((session, _)) = &active_session {
                                     session.access();
@@ -657,6 +1381,7 @@ async fn handle_cli_socket(
                             None => {
                                 CliResponse {
                                     id: req.id.clone(),
+                                    streaming: false,
                                     cli_response_message: Some(cli_response::CliResponseMessage::Error(
                                         "empty message received".to_string()
                                     ))
@@ -692,6 +1417,7 @@ async fn handle_cli_socket(
                         Err(err) => {
                             let response = CliResponse {
                                 id: "server_error".to_string(),
+                                streaming: true,
                                 cli_response_message: Some(cli_response::CliResponseMessage::Error(
                                     err.to_string()
                                 ))
@@ -746,16 +1472,27 @@ fn convert_server_message_to_cli(message: ServerMessage) -> CliResponse {
                 cols: resize.cols,
             })
         },
+        ServerMessage::UserJoined(id) => {
+            cli_response::CliResponseMessage::UserJoined(id)
+        },
+        ServerMessage::UserLeft(id) => {
+            cli_response::CliResponseMessage::UserLeft(id)
+        },
         ServerMessage::Ping(timestamp) => {
             cli_response::CliResponseMessage::Ping(timestamp)
         },
+        ServerMessage::UserCount(count) => {
+            cli_response::CliResponseMessage::UserCount(count)
+        },
         ServerMessage::Error(err) => {
             cli_response::CliResponseMessage::Error(err)
         },
     };
 
+    // The ID is kept for older clients, which route streaming pushes by it.
     CliResponse {
         id: "server_update".to_string(),
+        streaming: true,
         cli_response_message: Some(response_message),
     }
 }
@@ -764,10 +1501,10 @@ fn convert_server_message_to_cli(message: ServerMessage) -> CliResponse {
 async fn handle_cli_streaming(
     tx: &mpsc::Sender<Result<ServerUpdate, tonic::Status>>,
     session: &Session,
-    connection_id: u128,
+    connection_id: u64,
 ) -> Result<(), &'static str> {
     debug!(connection_id = %connection_id, "CLI streaming task started");
-    use std::time::{Duration, SystemTime};
+    use std::time::SystemTime;
     use tokio::time::{self, MissedTickBehavior};
 
     const SYNC_INTERVAL: Duration = Duration::from_secs(5);
@@ -798,6 +1535,9 @@ async fn handle_cli_streaming(
     let mut ping_interval = time::interval(PING_INTERVAL);
     ping_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
 
+    let mut known_users = BTreeSet::new();
+    let mut last_user_count = None;
+
     loop {
         tokio::select! {
             // Send periodic sync messages to the client.
@@ -814,6 +1554,16 @@ async fn handle_cli_streaming(
                     debug!(connection_id = %connection_id, "Client disconnected during ping message send");
                     return Err("client disconnected during ping");
                 }
+                for msg in session.presence_changes(&mut known_users) {
+                    if !send_msg(tx, msg).await {
+                        return Err("client disconnected during presence update");
+                    }
+                }
+                let user_count = session.user_count();
+                if last_user_count != Some(user_count) {
+                    last_user_count = Some(user_count);
+                    send_msg(tx, ServerMessage::UserCount(user_count as u32)).await;
+                }
             }
             // Send buffered server updates to the client.
             Ok(msg) = session.update_rx().recv() => {
@@ -832,3 +1582,177 @@ async fn handle_cli_streaming(
         };
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use anyhow::Result;
+    use axum::http::HeaderMap;
+    use axum::{extract::ws::WebSocketUpgrade, routing::get, Router};
+    use bytes::Bytes;
+    use futures_util::StreamExt;
+    use tokio::net::TcpListener;
+    use tokio::time;
+    use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+    use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+    use super::{connect_upstream, mesh_ws_url, proxy_spectator, upstream_request};
+    use crate::session::{Metadata, Session};
+    use crate::web::protocol::WsServer;
+    use crate::{Server, ServerOptions, ServerState};
+
+    #[tokio::test]
+    async fn mesh_tls_scheme() {
+        let state = ServerState::new(ServerOptions::default()).unwrap();
+        assert_eq!(mesh_ws_url(&state, "node1:8051", "/api/s/abc"), "ws://node1:8051/api/s/abc");
+
+        let options = ServerOptions {
+            mesh_tls: true,
+            ..Default::default()
+        };
+        let state = ServerState::new(options).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = mesh_ws_url(&state, &listener.local_addr().unwrap().to_string(), "/api/s/abc");
+        assert!(url.starts_with("wss://"));
+
+        // A plaintext upstream must fail the TLS handshake rather than be used.
+        tokio::spawn(async move {
+            let (_stream, _) = listener.accept().await.unwrap();
+            time::sleep(Duration::from_secs(1)).await;
+        });
+        assert!(connect_async(url).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn proxied_spectator_sees_session_end() -> Result<()> {
+        let upstream = Arc::new(Server::new(ServerOptions::default())?);
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("ws://{}/api/spectate/proxied", listener.local_addr()?);
+        tokio::spawn({
+            let upstream = Arc::clone(&upstream);
+            async move { upstream.listen(listener).await }
+        });
+
+        let session = Arc::new(Session::new(Metadata {
+            encrypted_zeros: Bytes::new(),
+            name: "proxied".into(),
+            write_password_hash: None,
+            labels: Default::default(),
+            allow_spectators: true,
+            max_clients: None,
+            write_window: None,
+            write_window_renew: false,
+        }));
+        upstream.state().insert("proxied", Arc::clone(&session));
+
+        // Stands in for a mesh node that does not own the session.
+        let app = Router::new().route(
+            "/",
+            get(move |ws: WebSocketUpgrade| {
+                let url = url.clone();
+                async move {
+                    ws.on_upgrade(move |mut socket| async move {
+                        proxy_spectator(&mut socket, &url, &HeaderMap::new(), None).await;
+                    })
+                }
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let (mut ws, _) = connect_async(format!("ws://{addr}/")).await?;
+        let Some(Message::Binary(hello)) = ws.next().await.transpose()? else {
+            panic!("expected a hello message");
+        };
+        let hello: WsServer = ciborium::de::from_reader(&*hello)?;
+        assert!(matches!(hello, WsServer::Hello(_, name) if name == "proxied"));
+
+        session.shutdown();
+        let closed = time::timeout(Duration::from_secs(5), async {
+            while let Some(Ok(msg)) = ws.next().await {
+                if let Message::Close(_) = msg {
+                    return true;
+                }
+            }
+            false
+        });
+        assert!(closed.await?, "spectator socket was not closed cleanly");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn forwards_headers_upstream() {
+        // An upstream that only accepts upgrades with the right credentials.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}/api/s/abc", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                #[allow(clippy::result_large_err)]
+                let callback = |req: &Request, resp: Response| {
+                    let headers = req.headers();
+                    if headers.get("authorization").is_none_or(|value| value != "Bearer secret") {
+                        let mut resp = ErrorResponse::new(Some("unauthorized".into()));
+                        *resp.status_mut() = axum::http::StatusCode::UNAUTHORIZED;
+                        return Err(resp);
+                    }
+                    assert_eq!(headers["x-forwarded-for"], "10.0.0.1, 192.0.2.7");
+                    assert!(headers.get("host").is_some_and(|host| host != "sshx.example"));
+                    Ok(resp)
+                };
+                tokio_tungstenite::accept_hdr_async(stream, callback).await.ok();
+            }
+        });
+
+        let mut headers = HeaderMap::new();
+        headers.insert("host", "sshx.example".parse().unwrap());
+        headers.insert("x-forwarded-for", "10.0.0.1".parse().unwrap());
+        headers.insert("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ==".parse().unwrap());
+        let client_ip = Some([192, 0, 2, 7].into());
+
+        let request = upstream_request(&url, &headers, client_ip).unwrap();
+        assert!(connect_async(request).await.is_err());
+
+        headers.insert("authorization", "Bearer secret".parse().unwrap());
+        let request = upstream_request(&url, &headers, client_ip).unwrap();
+        assert_ne!(request.headers()["sec-websocket-key"], "dGhlIHNhbXBsZSBub25jZQ==");
+        connect_async(request).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn retries_upstream_until_ready() {
+        use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+
+        // Reserve a port, then start listening on it only after a delay.
+        let addr = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+        tokio::spawn(async move {
+            time::sleep(Duration::from_millis(250)).await;
+            let listener = TcpListener::bind(addr).await.unwrap();
+            let (stream, _) = listener.accept().await.unwrap();
+            tokio_tungstenite::accept_async(stream).await.ok();
+        });
+        let request = format!("ws://{addr}/api/s/abc").into_client_request().unwrap();
+        connect_upstream(request).await.unwrap();
+
+        // Rejections are reported right away instead of being retried.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            #[allow(clippy::result_large_err)]
+            let reject = |_: &Request, _| {
+                let mut resp = ErrorResponse::new(None);
+                *resp.status_mut() = axum::http::StatusCode::FORBIDDEN;
+                Err(resp)
+            };
+            tokio_tungstenite::accept_hdr_async(stream, reject).await.ok();
+        });
+        let request = format!("ws://{addr}/api/s/abc").into_client_request().unwrap();
+        let err = connect_upstream(request).await.unwrap_err();
+        assert!(err.to_string().starts_with("upstream rejected"), "{err}");
+    }
+}
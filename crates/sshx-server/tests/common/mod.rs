@@ -7,16 +7,21 @@ use anyhow::{ensure, Result};
 use axum::serve::ListenerExt;
 use futures_util::{SinkExt, StreamExt};
 use http::StatusCode;
+use prost::Message as _;
 use sshx::encrypt::Encrypt;
 use sshx_core::proto::sshx_service_client::SshxServiceClient;
+use sshx_core::proto::{cli_request::CliMessage, cli_response::CliResponseMessage};
+use sshx_core::proto::{CliRequest, CliResponse};
 use sshx_core::{Sid, Uid};
 use sshx_server::{
     state::ServerState,
     web::protocol::{WsClient, WsServer, WsUser, WsWinsize},
-    Server,
+    Server, ServerOptions,
 };
 use tokio::net::{TcpListener, TcpStream};
 use tokio::time;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::{Data, OpCode};
+use tokio_tungstenite::tungstenite::protocol::frame::Frame;
 use tokio_tungstenite::{tungstenite::Message, MaybeTlsStream, WebSocketStream};
 use tonic::transport::Channel;
 
@@ -32,10 +37,15 @@ impl TestServer {
     /// Returns an object with the local address, as well as a custom [`Drop`]
     /// implementation that gracefully shuts down the server.
     pub async fn new() -> Self {
+        Self::with_options(Default::default()).await
+    }
+
+    /// Create a fresh server for testing, with custom options.
+    pub async fn with_options(options: ServerOptions) -> Self {
         let listener = TcpListener::bind("[::1]:0").await.unwrap();
         let local_addr = listener.local_addr().unwrap();
 
-        let server = Arc::new(Server::new(Default::default()).unwrap());
+        let server = Arc::new(Server::new(options).unwrap());
         {
             let server = Arc::clone(&server);
             let listener = listener.tap_io(|tcp_stream| {
@@ -81,6 +91,55 @@ impl Drop for TestServer {
     }
 }
 
+/// A CLI WebSocket client that sends raw protobuf requests, used for testing.
+pub struct CliSocket {
+    inner: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    next_id: u64,
+}
+
+impl CliSocket {
+    /// Connect to the CLI WebSocket endpoint of a server.
+    pub async fn connect(server: &TestServer) -> Result<Self> {
+        let uri = format!("ws://{}/api/cli/test", server.local_addr());
+        let (inner, _) = tokio_tungstenite::connect_async(uri).await?;
+        Ok(Self { inner, next_id: 0 })
+    }
+
+    /// Send a request without waiting for a reply.
+    pub async fn send(&mut self, message: CliMessage) {
+        self.next_id += 1;
+        let request = CliRequest {
+            id: format!("req_{}", self.next_id),
+            cli_message: Some(message),
+        };
+        let buf = request.encode_to_vec();
+        self.inner.send(Message::Binary(buf.into())).await.unwrap();
+    }
+
+    /// Receive the next reply to a request, skipping messages pushed from
+    /// the session stream.
+    pub async fn reply(&mut self) -> CliResponseMessage {
+        loop {
+            match self.inner.next().await.unwrap().unwrap() {
+                Message::Binary(msg) => {
+                    let response = CliResponse::decode(&*msg).unwrap();
+                    if !response.streaming {
+                        break response.cli_response_message.unwrap();
+                    }
+                }
+                Message::Close(frame) => panic!("socket closed before reply: {frame:?}"),
+                _ => (),
+            }
+        }
+    }
+
+    /// Send a request and wait for its reply.
+    pub async fn request(&mut self, message: CliMessage) -> CliResponseMessage {
+        self.send(message).await;
+        self.reply().await
+    }
+}
+
 /// A WebSocket client that interacts with the server, used for testing.
 pub struct ClientSocket {
     inner: WebSocketStream<MaybeTlsStream<TcpStream>>,
@@ -90,7 +149,12 @@ pub struct ClientSocket {
     pub user_id: Uid,
     pub users: BTreeMap<Uid, WsUser>,
     pub shells: BTreeMap<Sid, WsWinsize>,
+    pub shell_names: BTreeMap<Sid, String>,
     pub data: HashMap<Sid, String>,
+    pub chunk_messages: usize,
+    pub snapshots: HashMap<Sid, String>,
+    pub exit_codes: HashMap<Sid, Option<i32>>,
+    pub paused: bool,
     pub messages: Vec<(Uid, String, String)>,
     pub errors: Vec<String>,
 }
@@ -108,7 +172,12 @@ impl ClientSocket {
             user_id: Uid(0),
             users: BTreeMap::new(),
             shells: BTreeMap::new(),
+            shell_names: BTreeMap::new(),
             data: HashMap::new(),
+            chunk_messages: 0,
+            snapshots: HashMap::new(),
+            exit_codes: HashMap::new(),
+            paused: false,
             messages: Vec::new(),
             errors: Vec::new(),
         };
@@ -130,6 +199,28 @@ impl ClientSocket {
         self.inner.send(Message::Binary(buf.into())).await.unwrap();
     }
 
+    /// Send a raw binary frame, which need not be a valid message.
+    pub async fn send_raw(&mut self, data: &[u8]) {
+        let data = data.to_vec();
+        self.inner.send(Message::Binary(data.into())).await.unwrap();
+    }
+
+    /// Send a message split into continuation frames of at most `frame_size`
+    /// bytes, like a proxy or browser might deliver a large message.
+    pub async fn send_fragmented(&mut self, msg: WsClient, frame_size: usize) {
+        let mut buf = Vec::new();
+        ciborium::ser::into_writer(&msg, &mut buf).unwrap();
+        let count = buf.len().div_ceil(frame_size);
+        for (i, part) in buf.chunks(frame_size).enumerate() {
+            let opcode = match i {
+                0 => OpCode::Data(Data::Binary),
+                _ => OpCode::Data(Data::Continue),
+            };
+            let frame = Frame::message(part.to_vec(), opcode, i + 1 == count);
+            self.inner.send(Message::Frame(frame)).await.unwrap();
+        }
+    }
+
     pub async fn send_input(&mut self, id: Sid, data: &[u8]) {
         let offset = 42; // arbitrary, don't reuse the offset in real code though
         let data = self.encrypt.segment(0x200000000, offset, data);
@@ -149,6 +240,16 @@ impl ClientSocket {
         }
     }
 
+    pub async fn expect_invalid_auth(&mut self) {
+        loop {
+            match self.recv().await {
+                Some(WsServer::InvalidAuth()) => break,
+                Some(_) => (),
+                None => panic!("socket closed without invalid authentication"),
+            }
+        }
+    }
+
     pub async fn expect_close(&mut self, code: u16) {
         let msg = self.inner.next().await.unwrap().unwrap();
         match msg {
@@ -157,6 +258,27 @@ impl ClientSocket {
         }
     }
 
+    /// Like [`Self::expect_close`], but skips messages sent before the close.
+    pub async fn expect_close_after(&mut self, code: u16) {
+        loop {
+            match self.inner.next().await.unwrap().unwrap() {
+                Message::Close(Some(frame)) => break assert_eq!(frame.code, code.into()),
+                Message::Binary(_) => (),
+                msg => panic!("unexpected message over WebSocket: {:?}", msg),
+            }
+        }
+    }
+
+    /// Wait for the server to drop the connection, skipping earlier messages.
+    pub async fn expect_disconnect(&mut self) {
+        loop {
+            match self.inner.next().await {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => (),
+            }
+        }
+    }
+
     pub async fn flush(&mut self) {
         const FLUSH_DURATION: Duration = Duration::from_millis(50);
         let flush_task = async {
@@ -171,17 +293,38 @@ impl ClientSocket {
                             self.users.insert(id, user);
                         }
                     }
-                    WsServer::Shells(shells) => self.shells = BTreeMap::from_iter(shells),
-                    WsServer::Chunks(id, seqnum, chunks) => {
+                    WsServer::Shells(shells) => {
+                        self.shells.clear();
+                        self.shell_names.clear();
+                        for (id, winsize, name) in shells {
+                            self.shells.insert(id, winsize);
+                            if let Some(name) = name {
+                                self.shell_names.insert(id, name);
+                            }
+                        }
+                    }
+                    WsServer::ShellExit(id, code) => {
+                        self.exit_codes.insert(id, code);
+                    }
+                    WsServer::Paused(paused) => self.paused = paused,
+                    WsServer::Chunks(id, mut seqnum, chunks) => {
+                        self.chunk_messages += 1;
                         let value = self.data.entry(id).or_default();
-                        assert_eq!(seqnum, value.len() as u64);
                         for buf in chunks {
-                            let plaintext = self.encrypt.segment(
-                                0x100000000 | id.0 as u64,
-                                value.len() as u64,
-                                &buf,
-                            );
+                            let plaintext =
+                                self.encrypt.segment(0x100000000 | id.0 as u64, seqnum, &buf);
+                            value.push_str(std::str::from_utf8(&plaintext).unwrap());
+                            seqnum += buf.len() as u64;
+                        }
+                    }
+                    WsServer::Snapshot(id, mut seqnum, chunks) => {
+                        let value = self.snapshots.entry(id).or_default();
+                        value.clear();
+                        for buf in chunks {
+                            let plaintext =
+                                self.encrypt.segment(0x100000000 | id.0 as u64, seqnum, &buf);
                             value.push_str(std::str::from_utf8(&plaintext).unwrap());
+                            seqnum += buf.len() as u64;
                         }
                     }
                     WsServer::Hear(id, name, msg) => {
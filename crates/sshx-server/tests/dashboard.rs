@@ -0,0 +1,243 @@
+use anyhow::Result;
+use serde_json::Value;
+use sshx::encrypt::Encrypt;
+use sshx_core::proto::OpenRequest;
+use sshx_server::ServerOptions;
+
+use crate::common::*;
+
+pub mod common;
+
+/// Register a session with a new dashboard, returning the dashboard URL.
+async fn register(server: &TestServer, headers: &[(&str, &str)]) -> Result<String> {
+    let client = reqwest::Client::new();
+    let mut req = client
+        .post(format!("{}/api/dashboards/register", server.endpoint()))
+        .header("content-type", "application/json")
+        .body(r#"{"sessionName":"abc","url":"/s/abc#key","displayName":"test"}"#);
+    for (name, value) in headers {
+        req = req.header(*name, *value);
+    }
+    let resp: Value = serde_json::from_str(&req.send().await?.error_for_status()?.text().await?)?;
+    let key = resp["dashboardKey"].as_str().unwrap();
+    let url = resp["dashboardUrl"].as_str().unwrap();
+    assert!(url.ends_with(&format!("/d/{key}")));
+    Ok(url.trim_end_matches(&format!("/d/{key}")).to_string())
+}
+
+#[tokio::test]
+async fn test_dashboard_url_scheme() -> Result<()> {
+    let server = TestServer::new().await;
+
+    let base = register(&server, &[("host", "dev.example.com:8080")]).await?;
+    assert_eq!(base, "http://dev.example.com:8080");
+
+    let headers = [("host", "example.com"), ("x-forwarded-proto", "https")];
+    let base = register(&server, &headers).await?;
+    assert_eq!(base, "https://example.com");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_dashboard_url_public_base() -> Result<()> {
+    let mut options = ServerOptions::default();
+    options.public_base_url = Some("http://sshx.internal:3000/".into());
+    let server = TestServer::with_options(options).await;
+
+    let base = register(&server, &[("x-forwarded-proto", "https")]).await?;
+    assert_eq!(base, "http://sshx.internal:3000");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_subpath_deployment() -> Result<()> {
+    let mut options = ServerOptions::default();
+    options.public_base_url = Some("https://example.com/sshx".into());
+    options.override_origin = Some("https://ignored.example.com".into());
+    let server = TestServer::with_options(options).await;
+
+    let base = register(&server, &[("host", "internal:8051")]).await?;
+    assert_eq!(base, "https://example.com/sshx");
+
+    let mut client = server.grpc_client().await;
+    let req = OpenRequest {
+        origin: "http://internal:8051".into(),
+        encrypted_zeros: Encrypt::new("").zeros().into(),
+        name: String::new(),
+        write_password_hash: None,
+        labels: Default::default(),
+        allow_spectators: false,
+        max_clients: 0,
+        write_window_secs: 0,
+        write_window_renew: false,
+        start_locked: false,
+    };
+    let resp = client.open(req).await?.into_inner();
+    assert_eq!(resp.url, format!("https://example.com/sshx/s/{}", resp.name));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_session_labels() -> Result<()> {
+    let server = TestServer::new().await;
+    let mut client = server.grpc_client().await;
+
+    let mut names = Vec::new();
+    for ticket in ["1234", "5678"] {
+        let req = OpenRequest {
+            origin: "http://localhost".into(),
+            encrypted_zeros: Encrypt::new("").zeros().into(),
+            name: String::new(),
+            write_password_hash: (ticket == "5678").then(|| b"hash".to_vec().into()),
+            labels: [("ticket".into(), ticket.into())].into(),
+            allow_spectators: false,
+            max_clients: 0,
+            write_window_secs: 0,
+            write_window_renew: false,
+            start_locked: false,
+        };
+        names.push(client.open(req).await?.into_inner().name);
+    }
+
+    let http = reqwest::Client::new();
+    let mut key: Option<String> = None;
+    for name in &names {
+        let body = serde_json::json!({
+            "sessionName": name,
+            "url": format!("/s/{name}#key"),
+            "displayName": name,
+            "dashboardKey": key,
+        });
+        let resp = http
+            .post(format!("{}/api/dashboards/register", server.endpoint()))
+            .header("content-type", "application/json")
+            .body(body.to_string())
+            .send()
+            .await?;
+        let resp: Value = serde_json::from_str(&resp.error_for_status()?.text().await?)?;
+        key = Some(resp["dashboardKey"].as_str().unwrap().into());
+    }
+    let key = key.unwrap();
+
+    let list = |query: &'static str| {
+        let url = format!("{}/api/dashboards/{key}/sessions?{query}", server.endpoint());
+        async move {
+            let text = reqwest::get(url).await?.error_for_status()?.text().await?;
+            anyhow::Ok(serde_json::from_str::<Value>(&text)?["sessions"].clone())
+        }
+    };
+
+    let sessions = list("").await?;
+    assert_eq!(sessions.as_array().unwrap().len(), 2);
+    let sessions = list("label=ticket%3D5678").await?;
+    assert_eq!(sessions.as_array().unwrap().len(), 1);
+    assert_eq!(sessions[0]["name"], names[1].as_str());
+    assert_eq!(sessions[0]["labels"]["ticket"], "5678");
+    assert_eq!(sessions[0]["hasWritePassword"], true);
+    assert_eq!(sessions[0]["defaultWritable"], false);
+    let sessions = list("label=ticket%3D1234").await?;
+    assert_eq!(sessions[0]["defaultWritable"], true);
+    let sessions = list("label=ticket").await?;
+    assert_eq!(sessions.as_array().unwrap().len(), 2);
+    let sessions = list("label=team").await?;
+    assert_eq!(sessions.as_array().unwrap().len(), 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_invalid_labels() -> Result<()> {
+    let server = TestServer::new().await;
+    let mut client = server.grpc_client().await;
+
+    let invalid = [
+        ("".to_string(), "value".to_string()),
+        ("bad key".into(), "value".into()),
+        ("k".repeat(65), "value".into()),
+        ("key".into(), "v".repeat(257)),
+    ];
+    for label in invalid {
+        let req = OpenRequest {
+            origin: "http://localhost".into(),
+            encrypted_zeros: Encrypt::new("").zeros().into(),
+            name: String::new(),
+            write_password_hash: None,
+            labels: [label].into(),
+            allow_spectators: false,
+            max_clients: 0,
+            write_window_secs: 0,
+            write_window_renew: false,
+            start_locked: false,
+        };
+        let status = client.open(req).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    }
+    assert_eq!(server.state().iter_sessions().count(), 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_dashboard_request_id() -> Result<()> {
+    let server = TestServer::new().await;
+
+    let url = format!("{}/api/dashboards/missing/status", server.endpoint());
+    let resp = reqwest::Client::new()
+        .get(&url)
+        .header("x-request-id", "spoofed")
+        .send()
+        .await?;
+    assert_eq!(resp.status(), 404);
+    let id = resp.headers()["x-request-id"].to_str()?.to_string();
+    assert_eq!(id.len(), 12);
+
+    let resp = reqwest::get(&url).await?;
+    assert_ne!(resp.headers()["x-request-id"], id.as_str());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_owned_sessions() -> Result<()> {
+    let server = TestServer::new().await;
+    let mut client = server.grpc_client().await;
+
+    let req = OpenRequest {
+        origin: "http://localhost".into(),
+        encrypted_zeros: Encrypt::new("").zeros().into(),
+        name: String::new(),
+        write_password_hash: None,
+        labels: Default::default(),
+        allow_spectators: false,
+        max_clients: 0,
+        write_window_secs: 0,
+        write_window_renew: false,
+        start_locked: false,
+    };
+    let resp = client.open(req).await?.into_inner();
+
+    let body = serde_json::json!([
+        { "name": resp.name, "token": resp.token },
+        { "name": resp.name, "token": "forged" },
+        { "name": "missing", "token": server.state().issue_token("missing") },
+    ]);
+    let resp_text = reqwest::Client::new()
+        .post(format!("{}/api/sessions/owned", server.endpoint()))
+        .header("content-type", "application/json")
+        .body(body.to_string())
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let sessions: Value = serde_json::from_str(&resp_text)?;
+    let sessions = sessions.as_array().unwrap();
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sessions[0]["name"], resp.name.as_str());
+    assert_eq!(sessions[0]["shellCount"], 0);
+
+    Ok(())
+}
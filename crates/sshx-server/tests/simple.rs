@@ -1,6 +1,10 @@
+use std::time::{Duration, SystemTime};
+
 use anyhow::Result;
 use sshx::encrypt::Encrypt;
+use sshx::transport::WsConnectOptions;
 use sshx_core::proto::*;
+use sshx_server::ServerOptions;
 
 use crate::common::*;
 
@@ -16,6 +20,12 @@ async fn test_rpc() -> Result<()> {
         encrypted_zeros: Encrypt::new("").zeros().into(),
         name: String::new(),
         write_password_hash: None,
+        labels: Default::default(),
+        allow_spectators: false,
+        max_clients: 0,
+        write_window_secs: 0,
+        write_window_renew: false,
+        start_locked: false,
     };
     let resp = client.open(req).await?;
     assert!(!resp.into_inner().name.is_empty());
@@ -23,6 +33,32 @@ async fn test_rpc() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_rpc_invalid_zeros() -> Result<()> {
+    let server = TestServer::new().await;
+    let mut client = server.grpc_client().await;
+
+    for encrypted_zeros in [vec![], vec![0u8; 32]] {
+        let req = OpenRequest {
+            origin: "sshx.io".into(),
+            encrypted_zeros: encrypted_zeros.into(),
+            name: String::new(),
+            write_password_hash: None,
+            labels: Default::default(),
+            allow_spectators: false,
+            max_clients: 0,
+            write_window_secs: 0,
+            write_window_renew: false,
+            start_locked: false,
+        };
+        let status = client.open(req).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    }
+    assert_eq!(server.state().iter_sessions().count(), 0);
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_web_get() -> Result<()> {
     let server = TestServer::new().await;
@@ -32,3 +68,571 @@ async fn test_web_get() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_session_page_not_found() -> Result<()> {
+    let server = TestServer::new().await;
+    let client = reqwest::Client::new();
+    let url = format!("{}/s/missing", server.endpoint());
+
+    let resp = client.get(&url).header("accept", "text/html").send().await?;
+    assert_eq!(resp.status(), 404);
+    assert!(resp.text().await?.contains("Session not found"));
+
+    let resp = client.get(&url).header("accept", "application/json").send().await?;
+    assert_eq!(resp.status(), 404);
+    assert_eq!(resp.text().await?, r#"{"error":"session not found"}"#);
+
+    let mut grpc = server.grpc_client().await;
+    let req = OpenRequest {
+        origin: "sshx.io".into(),
+        encrypted_zeros: Encrypt::new("").zeros().into(),
+        name: String::new(),
+        write_password_hash: None,
+        labels: Default::default(),
+        allow_spectators: false,
+        max_clients: 0,
+        write_window_secs: 0,
+        write_window_renew: false,
+        start_locked: false,
+    };
+    let name = grpc.open(req).await?.into_inner().name;
+
+    let url = format!("{}/s/{name}", server.endpoint());
+    let resp = client.get(&url).header("accept", "text/html").send().await?;
+    assert!(!resp.text().await?.contains("Session not found"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_blocked_names() -> Result<()> {
+    let mut options = ServerOptions::default();
+    options.blocked_names = vec!["admin".into(), "api".into()];
+    options.blocked_name_pattern = Some(regex::Regex::new("^root@")?);
+    let server = TestServer::with_options(options).await;
+    let mut client = server.grpc_client().await;
+
+    let open = |name: &str| OpenRequest {
+        origin: "sshx.io".into(),
+        encrypted_zeros: Encrypt::new("").zeros().into(),
+        name: name.into(),
+        write_password_hash: None,
+        labels: Default::default(),
+        allow_spectators: false,
+        max_clients: 0,
+        write_window_secs: 0,
+        write_window_renew: false,
+        start_locked: false,
+    };
+    for name in ["admin", "Admin@laptop", "api", "root@box"] {
+        let status = client.open(open(name)).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        assert!(status.message().contains("not allowed"), "{}", status.message());
+    }
+    assert_eq!(server.state().iter_sessions().count(), 0);
+
+    for name in ["", "alice@laptop", "administrator@laptop", "alice@root"] {
+        client.open(open(name)).await?;
+    }
+    assert_eq!(server.state().iter_sessions().count(), 4);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cli_websocket_disabled() -> Result<()> {
+    let mut options = ServerOptions::default();
+    options.disable_cli_websocket = true;
+    let server = TestServer::with_options(options).await;
+
+    let resp = reqwest::get(format!("{}/api/cli/abc", server.endpoint())).await?;
+    assert_eq!(resp.status(), 404);
+
+    let uri = format!("ws://{}/api/cli/abc", server.local_addr());
+    assert!(tokio_tungstenite::connect_async(uri).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_ws_subprotocol() -> Result<()> {
+    let server = TestServer::new().await;
+
+    let options = WsConnectOptions {
+        subprotocol: Some("sshx.v1".into()),
+        ..Default::default()
+    };
+    for path in ["cli", "s"] {
+        let request = options.request(&format!("ws://{}/api/{path}/abc", server.local_addr()))?;
+        let (_, resp) = tokio_tungstenite::connect_async(request).await?;
+        assert_eq!(resp.headers()["sec-websocket-protocol"], "sshx.v1");
+    }
+
+    let uri = format!("ws://{}/api/s/abc", server.local_addr());
+    let (_, resp) = tokio_tungstenite::connect_async(uri).await?;
+    assert!(resp.headers().get("sec-websocket-protocol").is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_auth_token() -> Result<()> {
+    use tokio_tungstenite::tungstenite::{client::IntoClientRequest, Error};
+
+    let mut options = ServerOptions::default();
+    options.auth_token = Some("secret".into());
+    let server = TestServer::with_options(options).await;
+    let mut client = server.grpc_client().await;
+
+    let open = |authorization: Option<&str>| {
+        let mut req = tonic::Request::new(OpenRequest {
+            origin: "sshx.io".into(),
+            encrypted_zeros: Encrypt::new("").zeros().into(),
+            name: String::new(),
+            write_password_hash: None,
+            labels: Default::default(),
+            allow_spectators: false,
+            max_clients: 0,
+            write_window_secs: 0,
+            write_window_renew: false,
+            start_locked: false,
+        });
+        if let Some(value) = authorization {
+            req.metadata_mut().insert("authorization", value.parse().unwrap());
+        }
+        req
+    };
+    let status = client.open(open(None)).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::Unauthenticated);
+    let status = client.open(open(Some("Bearer wrong"))).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::Unauthenticated);
+    client.open(open(Some("Bearer secret"))).await?;
+
+    for path in ["cli", "s"] {
+        let uri = format!("ws://{}/api/{path}/abc", server.local_addr());
+        match tokio_tungstenite::connect_async(&uri).await {
+            Err(Error::Http(resp)) => assert_eq!(resp.status(), 401),
+            other => panic!("expected 401 response, got {other:?}"),
+        }
+        let mut request = uri.into_client_request()?;
+        let headers = request.headers_mut();
+        headers.insert("authorization", "Bearer secret".parse()?);
+        tokio_tungstenite::connect_async(request).await?;
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_admin_events() -> Result<()> {
+    let server = TestServer::new().await;
+    let url = format!("http://{}/api/admin/events", server.local_addr());
+    let http = reqwest::Client::new();
+    assert_eq!(http.get(&url).send().await?.status(), 404);
+
+    let mut options = ServerOptions::default();
+    options.admin_token = Some("admin".into());
+    let server = TestServer::with_options(options).await;
+    let url = format!("http://{}/api/admin/events", server.local_addr());
+    let resp = http.get(&url).bearer_auth("wrong").send().await?;
+    assert_eq!(resp.status(), 401);
+
+    let mut resp = http.get(&url).bearer_auth("admin").send().await?;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.headers()["content-type"], "text/event-stream");
+
+    let req = OpenRequest {
+        origin: "sshx.io".into(),
+        encrypted_zeros: Encrypt::new("").zeros().into(),
+        name: String::new(),
+        write_password_hash: None,
+        labels: Default::default(),
+        allow_spectators: false,
+        max_clients: 0,
+        write_window_secs: 0,
+        write_window_renew: false,
+        start_locked: false,
+    };
+    let name = server.grpc_client().await.open(req).await?.into_inner().name;
+    let chunk = tokio::time::timeout(Duration::from_secs(5), resp.chunk()).await??;
+    let chunk = String::from_utf8(chunk.unwrap_or_default().to_vec())?;
+    let data = chunk.strip_prefix("data: ").expect("event should have data");
+    let event: serde_json::Value = serde_json::from_str(data.trim_end())?;
+    assert_eq!(event["type"], "sessionOpen");
+    assert_eq!(event["session"], name);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_assets_dir() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("sshx-assets-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    std::fs::write(dir.join("index.html"), "index page")?;
+    std::fs::write(dir.join("spa.html"), "spa page")?;
+
+    let mut options = ServerOptions::default();
+    options.assets_dir = Some(dir.clone());
+    let server = TestServer::with_options(options).await;
+
+    let get = |path: &'static str| {
+        let url = format!("{}{path}", server.endpoint());
+        async move { anyhow::Ok(reqwest::get(url).await?.error_for_status()?.text().await?) }
+    };
+    assert_eq!(get("/").await?, "index page");
+    assert_eq!(get("/d/some-dashboard").await?, "spa page");
+
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_assets_compression() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("sshx-compress-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    std::fs::write(dir.join("app.js"), "console.log(1);\n".repeat(256))?;
+    std::fs::write(dir.join("pre.js"), "console.log(2);\n".repeat(256))?;
+    std::fs::write(dir.join("pre.js.gz"), "precompressed")?;
+
+    for disable_compression in [false, true] {
+        let mut options = ServerOptions::default();
+        options.assets_dir = Some(dir.clone());
+        options.disable_compression = disable_compression;
+        let server = TestServer::with_options(options).await;
+
+        let client = reqwest::Client::new();
+        let get = |path: &str| {
+            client
+                .get(format!("{}{path}", server.endpoint()))
+                .header("accept-encoding", "gzip")
+                .send()
+        };
+
+        let resp = get("/app.js").await?.error_for_status()?;
+        let encoding = resp.headers().get("content-encoding").cloned();
+        if disable_compression {
+            assert_eq!(encoding, None);
+        } else {
+            assert_eq!(encoding.unwrap(), "gzip");
+        }
+
+        // Precompressed files are served as-is, never compressed twice.
+        let resp = get("/pre.js").await?.error_for_status()?;
+        assert_eq!(resp.headers()["content-encoding"], "gzip");
+        assert_eq!(resp.text().await?, "precompressed");
+    }
+
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
+
+#[cfg(not(feature = "embed-assets"))]
+#[tokio::test]
+async fn test_missing_assets() -> Result<()> {
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    use sshx_server::Server;
+
+    /// Log writer that captures output in memory.
+    #[derive(Clone, Default)]
+    struct LogBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for LogBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut options = ServerOptions::default();
+    options.assets_dir = Some("does-not-exist/build".into());
+
+    let logs = LogBuffer::default();
+    let writer = logs.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_writer(move || writer.clone())
+        .with_ansi(false)
+        .finish();
+    let server = tracing::subscriber::with_default(subscriber, || Server::new(options.clone()));
+    assert!(server.is_ok());
+    let logs = String::from_utf8(logs.0.lock().unwrap().clone())?;
+    assert!(logs.contains("WARN"), "{logs}");
+    assert!(logs.contains("does-not-exist/build"), "{logs}");
+
+    options.require_assets = true;
+    let err = Server::new(options).err().unwrap().to_string();
+    assert!(err.contains("does-not-exist/build"), "{err}");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_token_lifetime() -> Result<()> {
+    let mut options = ServerOptions::default();
+    options.token_max_age = Some(Duration::from_secs(3600));
+    let server = TestServer::with_options(options).await;
+    let mut client = server.grpc_client().await;
+
+    let req = OpenRequest {
+        origin: "sshx.io".into(),
+        encrypted_zeros: Encrypt::new("").zeros().into(),
+        name: String::new(),
+        write_password_hash: None,
+        labels: Default::default(),
+        allow_spectators: false,
+        max_clients: 0,
+        write_window_secs: 0,
+        write_window_renew: false,
+        start_locked: false,
+    };
+    let OpenResponse { name, token, .. } = client.open(req).await?.into_inner();
+
+    let issued_at = SystemTime::now() - Duration::from_secs(7200);
+    let expired = server.state().issue_token_at(&name, issued_at);
+    let (timestamp, signature) = token.split_once(':').unwrap();
+    let tampered = format!("{}:{signature}", timestamp.parse::<u64>()? + 1);
+    let legacy = signature.to_string();
+
+    for token in [expired, tampered, legacy] {
+        let req = CloseRequest { name: name.clone(), token };
+        let status = client.close(req).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+    }
+    assert!(server.state().lookup(&name).is_some());
+
+    client.close(CloseRequest { name: name.clone(), token }).await?;
+    assert!(server.state().lookup(&name).is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_secret_rotation() -> Result<()> {
+    let mut options = ServerOptions::default();
+    options.secret = Some("old secret".into());
+    let old_server = TestServer::with_options(options).await;
+    let old_token = old_server.state().issue_token("abc");
+
+    let mut options = ServerOptions::default();
+    options.secret = Some("new secret".into());
+    options.previous_secret = Some("old secret".into());
+    options.previous_secret_grace = Some(Duration::from_millis(500));
+    let server = TestServer::with_options(options).await;
+    let state = server.state();
+
+    let new_token = state.issue_token("abc");
+    assert!(state.verify_token("abc", &new_token));
+    assert!(state.verify_token("abc", &old_token));
+    assert!(!old_server.state().verify_token("abc", &new_token));
+
+    tokio::time::sleep(Duration::from_millis(600)).await;
+    assert!(state.verify_token("abc", &new_token));
+    assert!(!state.verify_token("abc", &old_token));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_connection_ids_unique() -> Result<()> {
+    let server = TestServer::new().await;
+    let state = server.state();
+
+    let tasks: Vec<_> = (0..8)
+        .map(|_| {
+            let state = state.clone();
+            tokio::spawn(async move {
+                (0..100)
+                    .map(|_| state.next_connection_id())
+                    .collect::<Vec<_>>()
+            })
+        })
+        .collect();
+    let mut ids = Vec::new();
+    for task in tasks {
+        ids.extend(task.await?);
+    }
+    ids.sort_unstable();
+    ids.dedup();
+    assert_eq!(ids.len(), 800);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cli_websocket_single_channel() -> Result<()> {
+    use sshx_core::proto::{cli_request::CliMessage, cli_response::CliResponseMessage};
+
+    let server = TestServer::new().await;
+    let mut client = server.grpc_client().await;
+
+    let mut sessions = Vec::new();
+    for _ in 0..2 {
+        let req = OpenRequest {
+            origin: "sshx.io".into(),
+            encrypted_zeros: Encrypt::new("").zeros().into(),
+            name: String::new(),
+            write_password_hash: None,
+            labels: Default::default(),
+            allow_spectators: false,
+            max_clients: 0,
+            write_window_secs: 0,
+            write_window_renew: false,
+            start_locked: false,
+        };
+        sessions.push(client.open(req).await?.into_inner());
+    }
+    let start = |resp: &OpenResponse| {
+        CliMessage::StartChannel(ChannelStartRequest {
+            name: resp.name.clone(),
+            token: resp.token.clone(),
+        })
+    };
+
+    let mut cli = CliSocket::connect(&server).await?;
+    let reply = cli.request(start(&sessions[0])).await;
+    assert!(matches!(reply, CliResponseMessage::StartChannel(_)));
+
+    // Starting another channel is rejected, whether or not for the same session.
+    for resp in [&sessions[1], &sessions[0]] {
+        match cli.request(start(resp)).await {
+            CliResponseMessage::Error(err) => assert!(err.contains("already active"), "{err}"),
+            reply => panic!("unexpected reply: {reply:?}"),
+        }
+    }
+
+    // The first channel is still the active one.
+    let shell = NewShell { id: 1, x: 0, y: 0 };
+    cli.send(CliMessage::CreatedShell(shell)).await;
+    cli.request(start(&sessions[1])).await;
+    let first = server.state().lookup(&sessions[0].name).unwrap();
+    let second = server.state().lookup(&sessions[1].name).unwrap();
+    assert!(first.sequence_numbers().map.contains_key(&1));
+    assert!(second.sequence_numbers().map.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cli_websocket_unknown_shell() -> Result<()> {
+    use sshx_core::proto::{cli_request::CliMessage, cli_response::CliResponseMessage};
+
+    let server = TestServer::new().await;
+    let mut client = server.grpc_client().await;
+
+    let req = OpenRequest {
+        origin: "sshx.io".into(),
+        encrypted_zeros: Encrypt::new("").zeros().into(),
+        name: String::new(),
+        write_password_hash: None,
+        labels: Default::default(),
+        allow_spectators: false,
+        max_clients: 0,
+        write_window_secs: 0,
+        write_window_renew: false,
+        start_locked: false,
+    };
+    let resp = client.open(req).await?.into_inner();
+    let start = CliMessage::StartChannel(ChannelStartRequest {
+        name: resp.name.clone(),
+        token: resp.token.clone(),
+    });
+    let data = |id| {
+        CliMessage::TerminalData(TerminalData {
+            id,
+            data: "hello".into(),
+            seq: 0,
+        })
+    };
+
+    let mut cli = CliSocket::connect(&server).await?;
+    cli.request(start.clone()).await;
+    for message in [data(1), CliMessage::ClosedShell(1)] {
+        match cli.request(message).await {
+            CliResponseMessage::Error(err) => assert!(err.contains("not created"), "{err}"),
+            reply => panic!("unexpected reply: {reply:?}"),
+        }
+    }
+
+    let session = server.state().lookup(&resp.name).unwrap();
+    cli.send(CliMessage::CreatedShell(NewShell { id: 1, x: 0, y: 0 })).await;
+    cli.send(data(1)).await;
+    match cli.request(data(2)).await {
+        CliResponseMessage::Error(err) => assert!(err.contains("id=2"), "{err}"),
+        reply => panic!("unexpected reply: {reply:?}"),
+    }
+    assert_eq!(session.sequence_numbers().map[&1], 5);
+
+    // After reconnecting, the client may keep sending data for its shells.
+    drop(cli);
+    let mut cli = CliSocket::connect(&server).await?;
+    cli.request(start).await;
+    cli.send(data(1)).await;
+    cli.send(CliMessage::ClosedShell(1)).await;
+    match cli.request(data(1)).await {
+        CliResponseMessage::Error(err) => assert!(err.contains("not created"), "{err}"),
+        reply => panic!("unexpected reply: {reply:?}"),
+    }
+    assert!(session.sequence_numbers().map.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cli_websocket_rejected_shell() -> Result<()> {
+    use sshx_core::proto::{cli_request::CliMessage, cli_response::CliResponseMessage};
+
+    let mut options = ServerOptions::default();
+    options.max_shells = Some(1);
+    let server = TestServer::with_options(options).await;
+    let mut client = server.grpc_client().await;
+
+    let req = OpenRequest {
+        origin: "sshx.io".into(),
+        encrypted_zeros: Encrypt::new("").zeros().into(),
+        name: String::new(),
+        write_password_hash: None,
+        labels: Default::default(),
+        allow_spectators: false,
+        max_clients: 0,
+        write_window_secs: 0,
+        write_window_renew: false,
+        start_locked: false,
+    };
+    let resp = client.open(req).await?.into_inner();
+    let mut cli = CliSocket::connect(&server).await?;
+    cli.request(CliMessage::StartChannel(ChannelStartRequest {
+        name: resp.name.clone(),
+        token: resp.token.clone(),
+    }))
+    .await;
+
+    cli.send(CliMessage::CreatedShell(NewShell { id: 1, x: 0, y: 0 })).await;
+    match cli.request(CliMessage::CreatedShell(NewShell { id: 2, x: 0, y: 0 })).await {
+        CliResponseMessage::Error(err) => assert!(err.contains("add shell"), "{err}"),
+        reply => panic!("unexpected reply: {reply:?}"),
+    }
+
+    // The client closes the shell it was asked to, without any error.
+    let data = CliMessage::TerminalData(TerminalData {
+        id: 2,
+        data: "hello".into(),
+        seq: 0,
+    });
+    cli.send(data).await;
+    cli.send(CliMessage::ClosedShell(2)).await;
+    match cli.request(CliMessage::ClosedShell(3)).await {
+        CliResponseMessage::Error(err) => assert!(err.contains("id=3"), "{err}"),
+        reply => panic!("unexpected reply: {reply:?}"),
+    }
+
+    let session = server.state().lookup(&resp.name).unwrap();
+    let ids: Vec<u32> = session.sequence_numbers().map.into_keys().collect();
+    assert_eq!(ids, [1]);
+
+    Ok(())
+}
@@ -1,12 +1,14 @@
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::Result;
-use sshx::{controller::Controller, runner::Runner};
-use sshx_core::{Sid, Uid};
+use sshx::{controller::Controller, encrypt::Encrypt, runner::Runner};
+use sshx_core::{proto::OpenRequest, Sid, Uid};
 use sshx_server::{
     session::Session,
     web::protocol::{WsClient, WsWinsize},
 };
+use tokio::time;
 
 use crate::common::*;
 
@@ -56,3 +58,91 @@ async fn test_basic_restore() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_exit_code_restore() -> Result<()> {
+    let server = TestServer::new().await;
+
+    let mut controller = Controller::new(&server.endpoint(), "", Runner::Echo, false).await?;
+    let name = controller.name().to_owned();
+    let key = controller.encryption_key().to_owned();
+    tokio::spawn(async move { controller.run().await });
+
+    let mut s = ClientSocket::connect(&server.ws_endpoint(&name), &key, None).await?;
+    s.send(WsClient::Create(0, 0)).await;
+    s.send(WsClient::Create(0, 0)).await;
+    s.flush().await;
+    assert_eq!(s.shells.len(), 2);
+
+    let session = server.state().lookup(&name).unwrap();
+    session.set_exit_code(Sid(1), Some(3))?;
+    s.flush().await;
+    assert_eq!(s.exit_codes.get(&Sid(1)), Some(&Some(3)));
+
+    let session = Session::restore(&session.snapshot()?)?;
+    assert_eq!(session.exit_code(Sid(1)), Some(3));
+    assert_eq!(session.exit_code(Sid(2)), None);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_paused_restore() -> Result<()> {
+    let server = TestServer::new().await;
+
+    let mut controller = Controller::new(&server.endpoint(), "", Runner::Echo, false).await?;
+    let name = controller.name().to_owned();
+    let key = controller.encryption_key().to_owned();
+    tokio::spawn(async move { controller.run().await });
+    let session = server.state().lookup(&name).unwrap();
+
+    let mut s = ClientSocket::connect(&server.ws_endpoint(&name), &key, None).await?;
+    s.send(WsClient::ClaimHost(session.host_key().into())).await;
+    s.send(WsClient::Create(0, 0)).await;
+    s.send(WsClient::SetPaused(true)).await;
+    s.flush().await;
+    assert!(s.paused);
+
+    let restored = Session::restore(&session.snapshot()?)?;
+    assert_eq!(restored.host_key(), session.host_key());
+    server.state().insert(&name, Arc::new(restored));
+
+    let mut s = ClientSocket::connect(&server.ws_endpoint(&name), &key, None).await?;
+    s.flush().await;
+    assert!(s.paused);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_write_window_restore() -> Result<()> {
+    let server = TestServer::new().await;
+
+    let key = "encryption key";
+    let req = OpenRequest {
+        origin: "sshx.io".into(),
+        encrypted_zeros: Encrypt::new(key).zeros().into(),
+        name: String::new(),
+        write_password_hash: None,
+        labels: Default::default(),
+        allow_spectators: false,
+        max_clients: 0,
+        write_window_secs: 1,
+        write_window_renew: false,
+        start_locked: false,
+    };
+    let name = server.grpc_client().await.open(req).await?.into_inner().name;
+    time::sleep(Duration::from_millis(1200)).await;
+
+    // The window is measured from when the session was first opened.
+    let session = server.state().lookup(&name).unwrap();
+    server
+        .state()
+        .insert(&name, Arc::new(Session::restore(&session.snapshot()?)?));
+
+    let mut s = ClientSocket::connect(&server.ws_endpoint(&name), key, None).await?;
+    s.flush().await;
+    assert!(!s.users[&s.user_id].can_write);
+
+    Ok(())
+}
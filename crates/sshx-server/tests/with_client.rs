@@ -1,11 +1,18 @@
 use anyhow::{Context, Result};
-use sshx::{controller::Controller, encrypt::Encrypt, runner::Runner};
+use sshx::connection::{connect_with_fallback, ConnectionConfig, ConnectionMethod};
+use sshx::controller::{ClientLimits, Controller, ControllerEvent};
+use sshx::transport::GrpcTransport;
+use sshx::terminal::{Respawn, ShellCommand};
+use sshx::{encrypt::Encrypt, runner::Runner};
 use sshx_core::{
-    proto::{server_update::ServerMessage, NewShell, TerminalInput},
+    proto::{server_update::ServerMessage, NewShell, OpenRequest, TerminalInput},
     Sid, Uid,
 };
-use sshx_server::web::protocol::{WsClient, WsWinsize};
+use sshx_server::web::protocol::{name_hue, WsClient, WsWinsize};
+use sshx_server::state::throttle::MAX_AUTH_FAILURES;
+use sshx_server::ServerOptions;
 use tokio::time::{self, Duration};
+use tokio_stream::StreamExt;
 
 use crate::common::*;
 
@@ -52,23 +59,686 @@ async fn test_command() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_controller_events() -> Result<()> {
+    let server = TestServer::new().await;
+    let mut controller = Controller::new(&server.endpoint(), "", Runner::Echo, false).await?;
+    let mut events = Box::pin(controller.events());
+
+    let name = controller.name().to_owned();
+    let key = controller.encryption_key().to_owned();
+    let session = server
+        .state()
+        .lookup(&name)
+        .context("couldn't find session in server state")?;
+    tokio::spawn(async move { controller.run().await });
+
+    let event = events.next().await;
+    let method = ConnectionMethod::Grpc;
+    assert_eq!(event, Some(ControllerEvent::Connected { method }));
+
+    let new_shell = NewShell { id: 1, x: 0, y: 0 };
+    let updates = session.update_tx();
+    updates.send(ServerMessage::CreateShell(new_shell)).await?;
+    updates.send(ServerMessage::CloseShell(1)).await?;
+
+    let mut shell_events = Vec::new();
+    while shell_events.len() < 2 {
+        let event = events.next().await.context("event stream ended")?;
+        if matches!(
+            event,
+            ControllerEvent::ShellOpened(_) | ControllerEvent::ShellClosed(_)
+        ) {
+            shell_events.push(event);
+        }
+    }
+    assert_eq!(
+        shell_events,
+        [
+            ControllerEvent::ShellOpened(Sid(1)),
+            ControllerEvent::ShellClosed(Sid(1)),
+        ]
+    );
+
+    let mut s = ClientSocket::connect(&server.ws_endpoint(&name), &key, None).await?;
+    s.flush().await;
+    let user_id = s.user_id;
+    let mut s = Some(s);
+
+    // Presence is polled by the server, so wait for the join before leaving.
+    let mut presence_events = Vec::new();
+    while presence_events.len() < 2 {
+        let event = events.next().await.context("event stream ended")?;
+        match event {
+            ControllerEvent::UserJoined(_) => drop(s.take()),
+            ControllerEvent::UserLeft(_) => {}
+            _ => continue,
+        }
+        presence_events.push(event);
+    }
+    assert_eq!(
+        presence_events,
+        [
+            ControllerEvent::UserJoined(user_id),
+            ControllerEvent::UserLeft(user_id),
+        ]
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_controller_user_count() -> Result<()> {
+    let server = TestServer::new().await;
+    let mut controller = Controller::new(&server.endpoint(), "", Runner::Echo, false).await?;
+    let name = controller.name().to_owned();
+    let key = controller.encryption_key().to_owned();
+    let mut events = Box::pin(controller.events());
+    tokio::spawn(async move { controller.run().await });
+
+    let _s = ClientSocket::connect(&server.ws_endpoint(&name), &key, None).await?;
+    loop {
+        let event = events.next().await.context("event stream ended")?;
+        if event == ControllerEvent::UsersChanged(1) {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_controller_attach() -> Result<()> {
+    let server = TestServer::new().await;
+    let controller = Controller::new(&server.endpoint(), "", Runner::Echo, false).await?;
+    let name = controller.name().to_owned();
+    let key = controller.encryption_key().to_owned();
+
+    let transport = Box::new(GrpcTransport::connect(&server.endpoint()).await?);
+    let mut attached = Controller::attach(
+        &server.endpoint(),
+        &name,
+        controller.token(),
+        controller.url(),
+        None,
+        Runner::Echo,
+        transport,
+    )
+    .await?;
+    assert_eq!(attached.name(), name);
+    assert_eq!(attached.url(), controller.url());
+    assert_eq!(attached.write_url(), None);
+    tokio::spawn(async move { attached.run().await });
+
+    let mut s = ClientSocket::connect(&server.ws_endpoint(&name), &key, None).await?;
+    s.send(WsClient::Create(0, 0)).await;
+    s.flush().await;
+    assert_eq!(s.shells.len(), 1);
+
+    let transport = Box::new(GrpcTransport::connect(&server.endpoint()).await?);
+    let result = Controller::attach(
+        &server.endpoint(),
+        &name,
+        "bad token",
+        controller.url(),
+        None,
+        Runner::Echo,
+        transport,
+    )
+    .await;
+    assert!(result.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_controller_attach_read_write() -> Result<()> {
+    let server = TestServer::new().await;
+    let controller = Controller::new(&server.endpoint(), "", Runner::Echo, true).await?;
+    let write_url = controller.write_url().context("missing write url")?;
+
+    let transport = Box::new(GrpcTransport::connect(&server.endpoint()).await?);
+    let attached = Controller::attach(
+        &server.endpoint(),
+        controller.name(),
+        controller.token(),
+        write_url,
+        None,
+        Runner::Echo,
+        transport,
+    )
+    .await?;
+    assert_eq!(attached.url(), controller.url());
+    assert_eq!(attached.write_url(), Some(write_url));
+    assert_eq!(attached.encryption_key(), controller.encryption_key());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_connection_probe() -> Result<()> {
+    let server = TestServer::new().await;
+    let config = ConnectionConfig::default();
+    let result = connect_with_fallback(&server.endpoint(), "probe", config).await?;
+    assert_eq!(result.method, ConnectionMethod::Grpc);
+
+    // The connectivity probe should not leave an orphaned session behind.
+    assert_eq!(server.state().iter_sessions().count(), 0);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_controller_stats() -> Result<()> {
+    let server = TestServer::new().await;
+    let mut controller = Controller::new(&server.endpoint(), "", Runner::Echo, false).await?;
+    let name = controller.name().to_owned();
+    let key = controller.encryption_key().to_owned();
+
+    let mut s = ClientSocket::connect(&server.ws_endpoint(&name), &key, None).await?;
+    let activity = async {
+        s.send(WsClient::Create(0, 0)).await;
+        while s.shells.is_empty() {
+            s.flush().await;
+        }
+        s.send(WsClient::Subscribe(Sid(1), 0)).await;
+        s.send_input(Sid(1), b"hello!").await;
+        while s.read(Sid(1)).is_empty() {
+            s.flush().await;
+        }
+    };
+    tokio::select! {
+        _ = controller.run() => unreachable!(),
+        _ = activity => (),
+    }
+    assert_eq!(s.read(Sid(1)), "hello!");
+
+    let stats = controller.close().await?;
+    assert_eq!(stats.shells_opened, 1);
+    assert_eq!(stats.bytes_received, 6);
+    assert_eq!(stats.bytes_sent, 6);
+    assert_eq!(stats.peak_users, Some(1));
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_ws_missing() -> Result<()> {
     let server = TestServer::new().await;
 
-    let bad_endpoint = format!("ws://{}/not/an/endpoint", server.local_addr());
-    assert!(ClientSocket::connect(&bad_endpoint, "", None)
-        .await
-        .is_err());
+    let bad_endpoint = format!("ws://{}/not/an/endpoint", server.local_addr());
+    assert!(ClientSocket::connect(&bad_endpoint, "", None)
+        .await
+        .is_err());
+
+    let mut s = ClientSocket::connect(&server.ws_endpoint("foobar"), "", None).await?;
+    s.expect_close(4404).await;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_ws_basic() -> Result<()> {
+    let server = TestServer::new().await;
+
+    let mut controller = Controller::new(&server.endpoint(), "", Runner::Echo, false).await?;
+    let name = controller.name().to_owned();
+    let key = controller.encryption_key().to_owned();
+    tokio::spawn(async move { controller.run().await });
+
+    let mut s = ClientSocket::connect(&server.ws_endpoint(&name), &key, None).await?;
+    s.flush().await;
+    assert_eq!(s.user_id, Uid(1));
+
+    s.send(WsClient::Create(0, 0)).await;
+    s.flush().await;
+    assert_eq!(s.shells.len(), 1);
+    assert!(s.shells.contains_key(&Sid(1)));
+
+    s.send(WsClient::Subscribe(Sid(1), 0)).await;
+    assert_eq!(s.read(Sid(1)), "");
+
+    s.send_input(Sid(1), b"hello!").await;
+    s.flush().await;
+    assert_eq!(s.read(Sid(1)), "hello!");
+
+    s.send_input(Sid(1), b" 123").await;
+    s.flush().await;
+    assert_eq!(s.read(Sid(1)), "hello! 123");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_ws_resume() -> Result<()> {
+    let server = TestServer::new().await;
+
+    let mut controller = Controller::new(&server.endpoint(), "", Runner::Echo, false).await?;
+    let name = controller.name().to_owned();
+    let key = controller.encryption_key().to_owned();
+    tokio::spawn(async move { controller.run().await });
+
+    let mut s1 = ClientSocket::connect(&server.ws_endpoint(&name), &key, None).await?;
+    s1.flush().await;
+    s1.send(WsClient::Create(0, 0)).await;
+    s1.send(WsClient::Subscribe(Sid(1), 0)).await;
+    s1.send_input(Sid(1), b"hello!").await;
+    s1.flush().await;
+    assert_eq!(s1.read(Sid(1)), "hello!");
+    drop(s1);
+
+    let mut s2 = ClientSocket::connect(&server.ws_endpoint(&name), &key, None).await?;
+    s2.flush().await;
+    s2.send_input(Sid(1), b" 123").await;
+    s2.flush().await;
+
+    // Only the output after the last byte seen by the first connection.
+    s2.send(WsClient::Resume(vec![(Sid(1), 6)])).await;
+    s2.flush().await;
+    assert_eq!(s2.read(Sid(1)), " 123");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_ws_resubscribe_reopened_shell() -> Result<()> {
+    let server = TestServer::new().await;
+
+    let mut controller = Controller::new(&server.endpoint(), "", Runner::Echo, false).await?;
+    let name = controller.name().to_owned();
+    let key = controller.encryption_key().to_owned();
+    tokio::spawn(async move { controller.run().await });
+
+    let mut s = ClientSocket::connect(&server.ws_endpoint(&name), &key, None).await?;
+    s.send(WsClient::Create(0, 0)).await;
+    s.flush().await;
+    s.send(WsClient::Subscribe(Sid(1), 0)).await;
+    s.send_input(Sid(1), b"hello").await;
+    s.flush().await;
+    assert_eq!(s.read(Sid(1)), "hello");
+
+    s.send(WsClient::Close(Sid(1))).await;
+    s.flush().await;
+    assert!(s.shells.is_empty());
+
+    // Reopen the shell with the same ID, starting from empty output.
+    let session = server.state().lookup(&name).context("couldn't find session")?;
+    session.add_shell(Sid(1), (0, 0))?;
+    let data = Encrypt::new(&key).segment(0x100000000 | 1, 0, b" again");
+    session.add_data(Sid(1), data.into(), 0)?;
+
+    s.send(WsClient::Subscribe(Sid(1), 0)).await;
+    s.flush().await;
+    assert_eq!(s.shells.len(), 1);
+    assert_eq!(s.read(Sid(1)), "hello again");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_ws_coalesce_chunks() -> Result<()> {
+    let mut options = ServerOptions::default();
+    options.chunk_coalesce = Some(Duration::from_millis(300));
+    let server = TestServer::with_options(options).await;
+
+    let mut controller = Controller::new(&server.endpoint(), "", Runner::Echo, false).await?;
+    let name = controller.name().to_owned();
+    let key = controller.encryption_key().to_owned();
+    tokio::spawn(async move { controller.run().await });
+
+    let session = server.state().lookup(&name).context("couldn't find session")?;
+    session.add_shell(Sid(1), (0, 0))?;
+
+    let mut s = ClientSocket::connect(&server.ws_endpoint(&name), &key, None).await?;
+    s.send(WsClient::Subscribe(Sid(1), 0)).await;
+    s.flush().await;
+
+    let encrypt = Encrypt::new(&key);
+    let mut seqnum = 0;
+    for part in ["a", "b", "c", "d"] {
+        let data = encrypt.segment(0x100000000 | 1, seqnum, part.as_bytes());
+        session.add_data(Sid(1), data.into(), seqnum)?;
+        seqnum += 1;
+    }
+
+    // Output is held back within the window, then sent in a single message.
+    s.flush().await;
+    assert_eq!(s.read(Sid(1)), "");
+    time::sleep(Duration::from_millis(300)).await;
+    s.flush().await;
+    assert_eq!(s.read(Sid(1)), "abcd");
+    assert_eq!(s.chunk_messages, 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_max_shells() -> Result<()> {
+    let mut options = ServerOptions::default();
+    options.max_shells = Some(2);
+    let server = TestServer::with_options(options).await;
+
+    let mut controller = Controller::new(&server.endpoint(), "", Runner::Echo, false).await?;
+    let name = controller.name().to_owned();
+    let key = controller.encryption_key().to_owned();
+    tokio::spawn(async move { controller.run().await });
+
+    let mut s = ClientSocket::connect(&server.ws_endpoint(&name), &key, None).await?;
+    s.send(WsClient::Create(0, 0)).await;
+    s.send(WsClient::Create(0, 0)).await;
+    s.flush().await;
+    assert_eq!(s.shells.len(), 2);
+    assert!(s.errors.is_empty());
+
+    s.send(WsClient::Create(0, 0)).await;
+    s.flush().await;
+    assert_eq!(s.shells.len(), 2);
+    assert_eq!(s.errors.len(), 1);
+    assert!(s.errors[0].contains("limit of 2"), "{}", s.errors[0]);
+
+    s.send(WsClient::Subscribe(Sid(1), 0)).await;
+    s.send_input(Sid(1), b"still works").await;
+    s.flush().await;
+    assert_eq!(s.read(Sid(1)), "still works");
+
+    // Closing a shell frees up room for another.
+    s.send(WsClient::Close(Sid(2))).await;
+    s.flush().await;
+    s.send(WsClient::Create(0, 0)).await;
+    s.flush().await;
+    assert_eq!(s.shells.len(), 2);
+    assert_eq!(s.errors.len(), 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_ws_snapshot() -> Result<()> {
+    let server = TestServer::new().await;
+
+    let mut controller = Controller::new(&server.endpoint(), "", Runner::Echo, false).await?;
+    let name = controller.name().to_owned();
+    let key = controller.encryption_key().to_owned();
+    tokio::spawn(async move { controller.run().await });
+
+    let mut s = ClientSocket::connect(&server.ws_endpoint(&name), &key, None).await?;
+    s.send(WsClient::Create(0, 0)).await;
+    s.flush().await;
+    s.send_input(Sid(1), b"hello!").await;
+    s.flush().await;
+
+    let mut reader = ClientSocket::connect(&server.ws_endpoint(&name), &key, None).await?;
+    reader.send(WsClient::RequestSnapshot(Sid(1))).await;
+    reader.flush().await;
+    assert_eq!(reader.snapshots[&Sid(1)], "hello!");
+    assert_eq!(reader.read(Sid(1)), "", "snapshots are not subscriptions");
+
+    // Large scrollback is trimmed to a tail sized for the screen.
+    let session = server.state().lookup(&name).context("couldn't find session")?;
+    let seqnum = session.sequence_numbers().map[&1];
+    for i in 0..64 {
+        let chunk = vec![b'x'; 1 << 14];
+        session.add_data(Sid(1), chunk.into(), seqnum + (i << 14))?;
+    }
+    let (start, chunks) = session.recent_output(Sid(1))?;
+    let total: usize = chunks.iter().map(|chunk| chunk.len()).sum();
+    assert!(total < 64 << 14);
+    assert_eq!(start + total as u64, session.sequence_numbers().map[&1]);
+
+    reader.send(WsClient::RequestSnapshot(Sid(2))).await;
+    reader.flush().await;
+    assert_eq!(reader.errors.len(), 1);
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_ws_shell_exit() -> Result<()> {
+    let server = TestServer::new().await;
+
+    let command = ShellCommand {
+        args: vec!["-c".into(), "exit 3".into()],
+        ..ShellCommand::from("/bin/sh")
+    };
+    let runner = Runner::Shell(command);
+    let mut controller = Controller::new(&server.endpoint(), "", runner, false).await?;
+    let name = controller.name().to_owned();
+    let key = controller.encryption_key().to_owned();
+    tokio::spawn(async move { controller.run().await });
+
+    let mut s = ClientSocket::connect(&server.ws_endpoint(&name), &key, None).await?;
+    s.send(WsClient::Create(0, 0)).await;
+    for _ in 0..40 {
+        s.flush().await;
+        if s.exit_codes.contains_key(&Sid(1)) && s.shells.is_empty() {
+            break;
+        }
+    }
+    assert_eq!(s.exit_codes.get(&Sid(1)), Some(&Some(3)));
+    assert!(s.shells.is_empty());
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_ws_shell_respawn() -> Result<()> {
+    let server = TestServer::new().await;
+
+    let command = ShellCommand {
+        args: vec!["-c".into(), "exit 3".into()],
+        respawn: Respawn {
+            max_retries: 2,
+            on_success: false,
+        },
+        ..ShellCommand::from("/bin/sh")
+    };
+    let runner = Runner::Shell(command);
+    let mut controller = Controller::new(&server.endpoint(), "", runner, false).await?;
+    let name = controller.name().to_owned();
+    let key = controller.encryption_key().to_owned();
+    tokio::spawn(async move { controller.run().await });
+
+    let mut s = ClientSocket::connect(&server.ws_endpoint(&name), &key, None).await?;
+    s.send(WsClient::Create(0, 0)).await;
+    s.flush().await;
+    s.send(WsClient::Subscribe(Sid(1), 0)).await;
+    for _ in 0..80 {
+        s.flush().await;
+        if s.exit_codes.contains_key(&Sid(1)) {
+            break;
+        }
+    }
+    // The same shell is replaced twice before its exit is reported.
+    let output = s.read(Sid(1));
+    assert!(output.contains("Shell exited with status 3, starting a new one (1/2)"));
+    assert!(output.contains("(2/2)"));
+    assert_eq!(s.exit_codes.get(&Sid(1)), Some(&Some(3)));
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_ws_motd() -> Result<()> {
+    let server = TestServer::new().await;
+
+    let command = ShellCommand {
+        args: vec!["-c".into(), "echo from-shell; sleep 5".into()],
+        motd: Some("welcome\r\n".into()),
+        ..ShellCommand::from("/bin/sh")
+    };
+    let runner = Runner::Shell(command);
+    let mut controller = Controller::new(&server.endpoint(), "", runner, false).await?;
+    let name = controller.name().to_owned();
+    let key = controller.encryption_key().to_owned();
+    tokio::spawn(async move { controller.run().await });
+
+    let mut s = ClientSocket::connect(&server.ws_endpoint(&name), &key, None).await?;
+    s.send(WsClient::Create(0, 0)).await;
+    s.send(WsClient::Subscribe(Sid(1), 0)).await;
+    for _ in 0..40 {
+        s.flush().await;
+        if s.read(Sid(1)).contains("from-shell") {
+            break;
+        }
+    }
+    assert!(s.read(Sid(1)).starts_with("welcome\r\nfrom-shell"));
+
+    // Resizing the shell does not show the banner again.
+    s.send(WsClient::Move(Sid(1), Some(WsWinsize::default()))).await;
+    s.flush().await;
+    assert_eq!(s.read(Sid(1)).matches("welcome").count(), 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_start_locked() -> Result<()> {
+    let server = TestServer::new().await;
+    let transport = Box::new(GrpcTransport::connect(&server.endpoint()).await?);
+    let limits = ClientLimits {
+        start_locked: true,
+        ..Default::default()
+    };
+    let mut controller = Controller::with_transport(
+        &server.endpoint(), "", Runner::Echo, false, false, limits, Default::default(), transport,
+    )
+    .await?;
+    let name = controller.name().to_owned();
+    let key = controller.encryption_key().to_owned();
+    let lock = controller.lock_handle();
+    tokio::spawn(async move { controller.run().await });
+    let session = server.state().lookup(&name).unwrap();
+
+    let mut s = ClientSocket::connect(&server.ws_endpoint(&name), &key, None).await?;
+    s.send(WsClient::ClaimHost(session.host_key().into())).await;
+    s.send(WsClient::Create(0, 0)).await;
+    s.send(WsClient::Subscribe(Sid(1), 0)).await;
+    s.flush().await;
+    assert!(s.paused);
+
+    // Setup output from the host is held back, and even the host's viewer
+    // cannot resume.
+    let input = TerminalInput {
+        id: 1,
+        data: Encrypt::new(&key).segment(0x200000000, 0, b"setup,").into(),
+        offset: 0,
+    };
+    session.update_tx().send(ServerMessage::Input(input)).await?;
+    s.send(WsClient::SetPaused(false)).await;
+    s.flush().await;
+    assert!(s.paused);
+    assert_eq!(s.errors, ["Session is locked by the host"]);
+    assert_eq!(s.read(Sid(1)), "");
+
+    lock.set_locked(false).await?;
+    wait_for_pause(&mut s, false).await;
+    assert!(!session.is_locked());
+
+    s.send_input(Sid(1), b"live").await;
+    s.flush().await;
+    assert_eq!(s.read(Sid(1)), "live");
+
+    // Locking again drops only the output from while it was locked.
+    lock.set_locked(true).await?;
+    wait_for_pause(&mut s, true).await;
+    let input = TerminalInput {
+        id: 1,
+        data: Encrypt::new(&key).segment(0x200000000, 0, b",hidden").into(),
+        offset: 0,
+    };
+    session.update_tx().send(ServerMessage::Input(input)).await?;
+    time::sleep(Duration::from_millis(200)).await;
+    lock.set_locked(false).await?;
+    wait_for_pause(&mut s, false).await;
+
+    let mut late = ClientSocket::connect(&server.ws_endpoint(&name), &key, None).await?;
+    late.send(WsClient::Subscribe(Sid(1), 0)).await;
+    late.flush().await;
+    assert_eq!(late.read(Sid(1)), "live");
+
+    Ok(())
+}
+
+/// Wait for a client to see the session paused or resumed.
+async fn wait_for_pause(s: &mut ClientSocket, paused: bool) {
+    for _ in 0..50 {
+        s.flush().await;
+        if s.paused == paused {
+            break;
+        }
+        time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(s.paused, paused);
+}
 
-    let mut s = ClientSocket::connect(&server.ws_endpoint("foobar"), "", None).await?;
-    s.expect_close(4404).await;
+#[tokio::test]
+async fn test_ws_pause() -> Result<()> {
+    for discard in [false, true] {
+        let mut options = ServerOptions::default();
+        options.discard_paused_output = discard;
+        let server = TestServer::with_options(options).await;
+
+        let mut controller = Controller::new(&server.endpoint(), "", Runner::Echo, false).await?;
+        let name = controller.name().to_owned();
+        let key = controller.encryption_key().to_owned();
+        let host_url = controller.host_url().unwrap().to_owned();
+        tokio::spawn(async move { controller.run().await });
+        let session = server.state().lookup(&name).unwrap();
+        let host_key = host_url.rsplit(',').next().unwrap();
+        assert_eq!(host_key, session.host_key());
+
+        let mut s = ClientSocket::connect(&server.ws_endpoint(&name), &key, None).await?;
+        s.send(WsClient::ClaimHost(host_key.into())).await;
+        s.send(WsClient::Create(0, 0)).await;
+        s.flush().await;
+        s.send(WsClient::Subscribe(Sid(1), 0)).await;
+        s.send_input(Sid(1), b"one,").await;
+        s.flush().await;
+        assert_eq!(s.read(Sid(1)), "one,");
+
+        s.send(WsClient::SetPaused(true)).await;
+        s.flush().await;
+        assert!(s.paused);
 
+        // Input from users is rejected, but output from the host is held back.
+        s.send_input(Sid(1), b"rejected").await;
+        s.flush().await;
+        assert_eq!(s.errors, ["Session is paused"]);
+        let encrypt = Encrypt::new(&key);
+        let input = TerminalInput {
+            id: 1,
+            data: encrypt.segment(0x200000000, 0, b"secret,").into(),
+            offset: 0,
+        };
+        session.update_tx().send(ServerMessage::Input(input)).await?;
+        s.flush().await;
+        assert_eq!(s.read(Sid(1)), "one,");
+
+        s.send(WsClient::SetPaused(false)).await;
+        s.flush().await;
+        assert!(!s.paused);
+        s.send_input(Sid(1), b"two").await;
+        s.flush().await;
+
+        let mut viewer = ClientSocket::connect(&server.ws_endpoint(&name), &key, None).await?;
+        viewer.send(WsClient::Subscribe(Sid(1), 0)).await;
+        viewer.flush().await;
+
+        if discard {
+            // Output from before the pause is kept for new viewers.
+            assert_eq!(s.read(Sid(1)), "one,two");
+            assert_eq!(viewer.read(Sid(1)), "one,two");
+        } else {
+            assert_eq!(s.read(Sid(1)), "one,secret,two");
+            assert_eq!(viewer.read(Sid(1)), "one,secret,two");
+        }
+    }
     Ok(())
 }
 
 #[tokio::test]
-async fn test_ws_basic() -> Result<()> {
+async fn test_ws_pause_requires_host() -> Result<()> {
     let server = TestServer::new().await;
 
     let mut controller = Controller::new(&server.endpoint(), "", Runner::Echo, false).await?;
@@ -76,25 +746,71 @@ async fn test_ws_basic() -> Result<()> {
     let key = controller.encryption_key().to_owned();
     tokio::spawn(async move { controller.run().await });
 
+    // Writers without the host key cannot pause the session.
     let mut s = ClientSocket::connect(&server.ws_endpoint(&name), &key, None).await?;
+    s.send(WsClient::SetPaused(true)).await;
+    s.send(WsClient::ClaimHost("wrong".into())).await;
+    s.send(WsClient::SetPaused(true)).await;
     s.flush().await;
-    assert_eq!(s.user_id, Uid(1));
+    assert!(!s.paused);
+    assert_eq!(
+        s.errors,
+        [
+            "Only the host can pause the session",
+            "Invalid host key",
+            "Only the host can pause the session",
+        ]
+    );
 
-    s.send(WsClient::Create(0, 0)).await;
-    s.flush().await;
-    assert_eq!(s.shells.len(), 1);
-    assert!(s.shells.contains_key(&Sid(1)));
+    Ok(())
+}
 
-    s.send(WsClient::Subscribe(Sid(1), 0)).await;
-    assert_eq!(s.read(Sid(1)), "");
+#[tokio::test]
+async fn test_user_color() -> Result<()> {
+    let server = TestServer::new().await;
 
-    s.send_input(Sid(1), b"hello!").await;
+    let mut controller = Controller::new(&server.endpoint(), "", Runner::Echo, false).await?;
+    let name = controller.name().to_owned();
+    let key = controller.encryption_key().to_owned();
+    tokio::spawn(async move { controller.run().await });
+
+    let mut hues = Vec::new();
+    for _ in 0..2 {
+        let mut s = ClientSocket::connect(&server.ws_endpoint(&name), &key, None).await?;
+        s.send(WsClient::SetName("alice".into())).await;
+        s.flush().await;
+        let user = &s.users[&s.user_id];
+        assert_eq!(user.hue, name_hue("alice"));
+        hues.push(user.hue);
+    }
+    assert_eq!(hues[0], hues[1]);
+    assert!(name_hue("alice") < 360);
+    assert_ne!(name_hue("alice"), name_hue("bob"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_user_terminal() -> Result<()> {
+    let server = TestServer::new().await;
+
+    let mut controller = Controller::new(&server.endpoint(), "", Runner::Echo, false).await?;
+    let name = controller.name().to_owned();
+    let key = controller.encryption_key().to_owned();
+    tokio::spawn(async move { controller.run().await });
+
+    let mut s = ClientSocket::connect(&server.ws_endpoint(&name), &key, None).await?;
     s.flush().await;
-    assert_eq!(s.read(Sid(1)), "hello!");
+    assert_eq!(s.users[&s.user_id].terminal, None);
 
-    s.send_input(Sid(1), b" 123").await;
+    s.send(WsClient::SetTerminal("xterm-kitty (truecolor)".into())).await;
     s.flush().await;
-    assert_eq!(s.read(Sid(1)), "hello! 123");
+    let terminal = s.users[&s.user_id].terminal.as_deref();
+    assert_eq!(terminal, Some("xterm-kitty (truecolor)"));
+
+    s.send(WsClient::SetTerminal("x".repeat(100))).await;
+    s.flush().await;
+    assert_eq!(s.users[&s.user_id].terminal.as_ref().map(|t| t.len()), Some(64));
 
     Ok(())
 }
@@ -197,6 +913,71 @@ async fn test_users_metadata() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_users_api() -> Result<()> {
+    use base64::prelude::{Engine as _, BASE64_STANDARD};
+
+    let server = TestServer::new().await;
+
+    let mut controller = Controller::new(&server.endpoint(), "", Runner::Echo, false).await?;
+    let name = controller.name().to_owned();
+    let key = controller.encryption_key().to_owned();
+    tokio::spawn(async move { controller.run().await });
+
+    let mut s = ClientSocket::connect(&server.ws_endpoint(&name), &key, None).await?;
+    s.send(WsClient::SetName("mr. foo".into())).await;
+    s.flush().await;
+
+    let client = reqwest::Client::new();
+    let url = format!("{}/api/s/{name}/users", server.endpoint());
+    let resp = client.get(&url).send().await?;
+    assert_eq!(resp.status(), 401);
+
+    let wrong = BASE64_STANDARD.encode(Encrypt::new("wrong").zeros());
+    let resp = client.get(&url).header("x-sshx-encrypted-zeros", wrong).send().await?;
+    assert_eq!(resp.status(), 401);
+
+    let zeros = BASE64_STANDARD.encode(Encrypt::new(&key).zeros());
+    let resp = client.get(&url).header("x-sshx-encrypted-zeros", &zeros).send().await?;
+    let users: serde_json::Value = serde_json::from_str(&resp.error_for_status()?.text().await?)?;
+    let expected = serde_json::json!([
+        { "id": s.user_id.0, "name": "mr. foo", "canWrite": true, "focus": null },
+    ]);
+    assert_eq!(users, expected);
+
+    let url = format!("{}/api/s/missing/users", server.endpoint());
+    let resp = client.get(&url).header("x-sshx-encrypted-zeros", &zeros).send().await?;
+    assert_eq!(resp.status(), 404);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_users_api_lockout() -> Result<()> {
+    use base64::prelude::{Engine as _, BASE64_STANDARD};
+
+    let server = TestServer::new().await;
+
+    let controller = Controller::new(&server.endpoint(), "", Runner::Echo, false).await?;
+    let name = controller.name().to_owned();
+    let key = controller.encryption_key().to_owned();
+
+    let client = reqwest::Client::new();
+    let url = format!("{}/api/s/{name}/users", server.endpoint());
+    let wrong = BASE64_STANDARD.encode(Encrypt::new("wrong").zeros());
+    for _ in 0..MAX_AUTH_FAILURES {
+        let resp = client.get(&url).header("x-sshx-encrypted-zeros", &wrong).send().await?;
+        assert_eq!(resp.status(), 401);
+    }
+
+    // Even the correct key is refused during the lockout.
+    let zeros = BASE64_STANDARD.encode(Encrypt::new(&key).zeros());
+    let resp = client.get(&url).header("x-sshx-encrypted-zeros", &zeros).send().await?;
+    assert_eq!(resp.status(), 429);
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_chat_messages() -> Result<()> {
     let server = TestServer::new().await;
@@ -229,6 +1010,125 @@ async fn test_chat_messages() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_ws_malformed_message() -> Result<()> {
+    let server = TestServer::new().await;
+
+    let mut controller = Controller::new(&server.endpoint(), "", Runner::Echo, false).await?;
+    let name = controller.name().to_owned();
+    let key = controller.encryption_key().to_owned();
+    tokio::spawn(async move { controller.run().await });
+
+    let endpoint = server.ws_endpoint(&name);
+    let mut s = ClientSocket::connect(&endpoint, &key, None).await?;
+    s.flush().await;
+
+    s.send_raw(b"\xff\x00 not cbor").await;
+    s.send(WsClient::Chat("still here".into())).await;
+    s.flush().await;
+    assert_eq!(s.errors, ["malformed message"]);
+    assert_eq!(s.messages.len(), 1);
+    assert_eq!(s.messages[0].2, "still here");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_ws_max_message_size() -> Result<()> {
+    let mut options = ServerOptions::default();
+    options.max_message_size = Some(4096);
+    let server = TestServer::with_options(options).await;
+
+    let mut controller = Controller::new(&server.endpoint(), "", Runner::Echo, false).await?;
+    let name = controller.name().to_owned();
+    let key = controller.encryption_key().to_owned();
+    tokio::spawn(async move { controller.run().await });
+
+    let endpoint = server.ws_endpoint(&name);
+    let mut s = ClientSocket::connect(&endpoint, &key, None).await?;
+    s.flush().await;
+
+    s.send(WsClient::Chat("x".repeat(1000))).await;
+    s.flush().await;
+    assert_eq!(s.messages.len(), 1);
+
+    s.send_raw(&vec![0; 1 << 16]).await;
+    time::timeout(Duration::from_secs(5), s.expect_disconnect()).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_ws_fragmented_message() -> Result<()> {
+    let server = TestServer::new().await;
+
+    let mut controller = Controller::new(&server.endpoint(), "", Runner::Echo, false).await?;
+    let name = controller.name().to_owned();
+    let key = controller.encryption_key().to_owned();
+    tokio::spawn(async move { controller.run().await });
+
+    let mut s = ClientSocket::connect(&server.ws_endpoint(&name), &key, None).await?;
+    s.flush().await;
+
+    // Continuation frames are reassembled into one message before decoding.
+    let text = "0123456789abcdef".repeat(20000);
+    s.send_fragmented(WsClient::Chat(text.clone()), 4096).await;
+    s.flush().await;
+    assert!(s.errors.is_empty(), "{:?}", s.errors);
+    assert_eq!(s.messages.len(), 1);
+    assert_eq!(s.messages[0].2, text);
+
+    s.send_fragmented(WsClient::Create(0, 0), 1).await;
+    s.flush().await;
+    assert_eq!(s.shells.len(), 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_shell_names() -> Result<()> {
+    let server = TestServer::new().await;
+
+    let mut controller = Controller::new(&server.endpoint(), "", Runner::Echo, true).await?;
+    let name = controller.name().to_owned();
+    let key = controller.encryption_key().to_owned();
+    let write_url = controller.write_url().unwrap().to_owned();
+    tokio::spawn(async move { controller.run().await });
+
+    let write_password = write_url.split(',').nth(1).unwrap();
+    let mut writer =
+        ClientSocket::connect(&server.ws_endpoint(&name), &key, Some(write_password)).await?;
+    let mut reader = ClientSocket::connect(&server.ws_endpoint(&name), &key, None).await?;
+
+    writer.send(WsClient::Create(0, 0)).await;
+    writer.flush().await;
+    writer.send(WsClient::SetShellName(Sid(1), "  build  ".into())).await;
+    writer.flush().await;
+    reader.flush().await;
+    assert_eq!(reader.shell_names.get(&Sid(1)).map(String::as_str), Some("build"));
+
+    // Names survive moving the shell.
+    writer.send(WsClient::Move(Sid(1), Some(WsWinsize::default()))).await;
+    writer.flush().await;
+    assert_eq!(writer.shell_names.get(&Sid(1)).map(String::as_str), Some("build"));
+
+    writer.send(WsClient::SetShellName(Sid(1), "x".repeat(65))).await;
+    writer.send(WsClient::SetShellName(Sid(1), "\x1b]0;evil\x07".into())).await;
+    writer.send(WsClient::SetShellName(Sid(2), "missing".into())).await;
+    reader.send(WsClient::SetShellName(Sid(1), "reader".into())).await;
+    writer.flush().await;
+    reader.flush().await;
+    assert_eq!(writer.errors.len(), 3);
+    assert_eq!(reader.errors.len(), 1);
+    assert_eq!(reader.shell_names.get(&Sid(1)).map(String::as_str), Some("build"));
+
+    writer.send(WsClient::SetShellName(Sid(1), String::new())).await;
+    writer.flush().await;
+    assert!(writer.shell_names.is_empty());
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_read_write_permissions() -> Result<()> {
     let server = TestServer::new().await;
@@ -283,3 +1183,300 @@ async fn test_read_write_permissions() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_legacy_write_password() -> Result<()> {
+    let server = TestServer::new().await;
+    let mut client = server.grpc_client().await;
+
+    // Older clients store the raw write token rather than an Argon2id hash.
+    let key = "encryption key";
+    let write_password = "write password";
+    let req = OpenRequest {
+        origin: "sshx.io".into(),
+        encrypted_zeros: Encrypt::new(key).zeros().into(),
+        name: String::new(),
+        write_password_hash: Some(Encrypt::new(write_password).zeros().into()),
+        labels: Default::default(),
+        allow_spectators: false,
+        max_clients: 0,
+        write_window_secs: 0,
+        write_window_renew: false,
+        start_locked: false,
+    };
+    let name = client.open(req).await?.into_inner().name;
+
+    let endpoint = server.ws_endpoint(&name);
+    let mut writer = ClientSocket::connect(&endpoint, key, Some(write_password)).await?;
+    writer.send(WsClient::Create(0, 0)).await;
+    writer.flush().await;
+    assert!(writer.errors.is_empty(), "writer should not receive errors");
+
+    let mut reader = ClientSocket::connect(&endpoint, key, Some("wrong")).await?;
+    reader.expect_invalid_auth().await;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_auth_lockout() -> Result<()> {
+    let server = TestServer::new().await;
+    let mut client = server.grpc_client().await;
+
+    let (key, write_password) = ("encryption key", "write password");
+    let open = || OpenRequest {
+        origin: "sshx.io".into(),
+        encrypted_zeros: Encrypt::new(key).zeros().into(),
+        name: String::new(),
+        write_password_hash: Some(Encrypt::new(write_password).zeros().into()),
+        labels: Default::default(),
+        allow_spectators: false,
+        max_clients: 0,
+        write_window_secs: 0,
+        write_window_renew: false,
+        start_locked: false,
+    };
+    let name = client.open(open()).await?.into_inner().name;
+    let endpoint = server.ws_endpoint(&name);
+
+    for _ in 0..MAX_AUTH_FAILURES {
+        let mut guesser = ClientSocket::connect(&endpoint, key, Some("guess")).await?;
+        guesser.expect_invalid_auth().await;
+    }
+
+    // Even the correct password is refused during the lockout.
+    let mut writer = ClientSocket::connect(&endpoint, key, Some(write_password)).await?;
+    writer.expect_close_after(4429).await;
+
+    // Other sessions are locked for the same client address.
+    let other = client.open(open()).await?.into_inner().name;
+    let endpoint = server.ws_endpoint(&other);
+    let mut writer = ClientSocket::connect(&endpoint, key, Some(write_password)).await?;
+    writer.expect_close_after(4429).await;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_max_clients() -> Result<()> {
+    let server = TestServer::new().await;
+    let mut client = server.grpc_client().await;
+
+    let key = "encryption key";
+    let req = OpenRequest {
+        origin: "sshx.io".into(),
+        encrypted_zeros: Encrypt::new(key).zeros().into(),
+        name: String::new(),
+        write_password_hash: None,
+        labels: Default::default(),
+        allow_spectators: false,
+        max_clients: 2,
+    };
+    let name = client.open(req).await?.into_inner().name;
+    let endpoint = server.ws_endpoint(&name);
+
+    let mut first = ClientSocket::connect(&endpoint, key, None).await?;
+    let mut second = ClientSocket::connect(&endpoint, key, None).await?;
+    first.flush().await;
+    second.flush().await;
+    assert_eq!(second.users.len(), 2);
+
+    let mut third = ClientSocket::connect(&endpoint, key, None).await?;
+    third.expect_close_after(4503).await;
+
+    // A slot opens up again once a client leaves.
+    drop(first);
+    second.flush().await;
+    assert_eq!(second.users.len(), 1);
+    let mut fourth = ClientSocket::connect(&endpoint, key, None).await?;
+    fourth.flush().await;
+    assert_eq!(fourth.users.len(), 2);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_empty_session_grace() -> Result<()> {
+    let mut options = ServerOptions::default();
+    options.empty_session_grace = Some(Duration::from_secs(1));
+    let server = TestServer::with_options(options).await;
+    let mut client = server.grpc_client().await;
+
+    let key = "encryption key";
+    let req = OpenRequest {
+        origin: "sshx.io".into(),
+        encrypted_zeros: Encrypt::new(key).zeros().into(),
+        name: String::new(),
+        write_password_hash: None,
+        labels: Default::default(),
+        allow_spectators: false,
+        max_clients: 0,
+        write_window_secs: 0,
+        write_window_renew: false,
+        start_locked: false,
+    };
+    let name = client.open(req).await?.into_inner().name;
+    let endpoint = server.ws_endpoint(&name);
+
+    // Sessions that never had a user are left alone.
+    time::sleep(Duration::from_millis(1500)).await;
+    assert!(server.state().lookup(&name).is_some());
+
+    let mut s = ClientSocket::connect(&endpoint, key, None).await?;
+    s.flush().await;
+    drop(s);
+
+    // Reconnecting within the grace period keeps the session.
+    time::sleep(Duration::from_millis(500)).await;
+    let mut s = ClientSocket::connect(&endpoint, key, None).await?;
+    s.flush().await;
+    time::sleep(Duration::from_millis(1000)).await;
+    assert!(server.state().lookup(&name).is_some());
+
+    drop(s);
+    time::sleep(Duration::from_millis(2000)).await;
+    assert!(server.state().lookup(&name).is_none());
+
+    // Sessions are kept while the host is connected, since it would only keep
+    // trying to reconnect to a closed session.
+    let mut controller = Controller::new(&server.endpoint(), "", Runner::Echo, false).await?;
+    let name = controller.name().to_owned();
+    let key = controller.encryption_key().to_owned();
+    let host = tokio::spawn(async move { controller.run().await });
+
+    let mut s = ClientSocket::connect(&server.ws_endpoint(&name), &key, None).await?;
+    s.flush().await;
+    drop(s);
+    time::sleep(Duration::from_millis(2000)).await;
+    assert!(server.state().lookup(&name).is_some());
+
+    host.abort();
+    time::sleep(Duration::from_millis(2000)).await;
+    assert!(server.state().lookup(&name).is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_write_window() -> Result<()> {
+    let server = TestServer::new().await;
+    let mut client = server.grpc_client().await;
+
+    let key = "encryption key";
+    let open = |write_window_renew| OpenRequest {
+        origin: "sshx.io".into(),
+        encrypted_zeros: Encrypt::new(key).zeros().into(),
+        name: String::new(),
+        write_password_hash: None,
+        labels: Default::default(),
+        allow_spectators: false,
+        max_clients: 0,
+        write_window_secs: 1,
+        write_window_renew,
+        start_locked: false,
+    };
+    let name = client.open(open(false)).await?.into_inner().name;
+    let endpoint = server.ws_endpoint(&name);
+
+    let mut early = ClientSocket::connect(&endpoint, key, None).await?;
+    early.flush().await;
+    assert!(early.users[&early.user_id].can_write);
+
+    // Everyone is downgraded once the window ends, and sees the change.
+    time::sleep(Duration::from_millis(1200)).await;
+    early.flush().await;
+    assert!(!early.users[&early.user_id].can_write);
+
+    let mut late = ClientSocket::connect(&endpoint, key, None).await?;
+    late.send(WsClient::Create(0, 0)).await;
+    late.flush().await;
+    assert!(!late.users[&late.user_id].can_write);
+    assert_eq!(late.errors.len(), 1);
+
+    // The host is exempt, and gets write access back once it claims the session.
+    let host_key = server.state().lookup(&name).unwrap().host_key().to_owned();
+    late.send(WsClient::ClaimHost(host_key)).await;
+    late.flush().await;
+    assert!(late.users[&late.user_id].can_write);
+
+    // So are users who know the write password.
+    let write_password = "write password";
+    let req = OpenRequest {
+        write_password_hash: Some(Encrypt::new(write_password).zeros().into()),
+        ..open(false)
+    };
+    let name = client.open(req).await?.into_inner().name;
+    time::sleep(Duration::from_millis(1200)).await;
+    let endpoint = server.ws_endpoint(&name);
+    let mut writer = ClientSocket::connect(&endpoint, key, Some(write_password)).await?;
+    writer.flush().await;
+    assert!(writer.users[&writer.user_id].can_write);
+
+    // With renewal, the window starts over for each client that joins.
+    let name = client.open(open(true)).await?.into_inner().name;
+    time::sleep(Duration::from_millis(1200)).await;
+    let mut renewed = ClientSocket::connect(&server.ws_endpoint(&name), key, None).await?;
+    renewed.flush().await;
+    assert!(renewed.users[&renewed.user_id].can_write);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_spectators() -> Result<()> {
+    use sshx_server::web::protocol::WsServer;
+    use tokio_tungstenite::tungstenite::Message;
+
+    let server = TestServer::new().await;
+    let transport = Box::new(GrpcTransport::connect(&server.endpoint()).await?);
+    let (limits, labels) = Default::default();
+    let mut controller = Controller::with_transport(
+        &server.endpoint(), "", Runner::Echo, false, true, limits, labels, transport,
+    )
+    .await?;
+    let name = controller.name().to_string();
+    let spectator_url = controller.spectator_url().context("missing spectator url")?;
+    assert!(spectator_url.ends_with(&format!("/spectate/{name}")));
+
+    let encrypt = Encrypt::new(controller.encryption_key());
+    let session = server.state().lookup(&name).context("couldn't find session")?;
+    tokio::spawn(async move { controller.run().await });
+
+    let updates = session.update_tx();
+    updates.send(ServerMessage::CreateShell(NewShell { id: 1, x: 0, y: 0 })).await?;
+    let data = encrypt.segment(0x200000000, 0, b"hello spectators");
+    let input = TerminalInput { id: 1, data: data.into(), offset: 0 };
+    updates.send(ServerMessage::Input(input)).await?;
+
+    let uri = format!("ws://{}/api/spectate/{name}", server.local_addr());
+    let (mut spectator, _) = tokio_tungstenite::connect_async(&uri).await?;
+    let mut output = String::new();
+    while !output.contains("hello spectators") {
+        let msg = time::timeout(Duration::from_secs(5), spectator.next())
+            .await?
+            .context("spectator socket closed")??;
+        if let Message::Binary(msg) = msg {
+            if let WsServer::Chunks(Sid(1), _, chunks) = ciborium::de::from_reader(&*msg)? {
+                for chunk in chunks {
+                    output.push_str(std::str::from_utf8(&chunk)?);
+                }
+            }
+        }
+    }
+
+    // Sessions that did not opt in refuse spectators and plaintext output.
+    let other = Controller::new(&server.endpoint(), "", Runner::Echo, false).await?;
+    assert!(other.spectator_url().is_none());
+    let other_session = server.state().lookup(other.name()).context("no session")?;
+    other_session.add_shell(Sid(1), (0, 0))?;
+    assert!(other_session.add_spectator_data(Sid(1), "x".into(), 0).is_err());
+
+    let uri = format!("ws://{}/api/spectate/{}", server.local_addr(), other.name());
+    let (mut spectator, _) = tokio_tungstenite::connect_async(&uri).await?;
+    match spectator.next().await.context("spectator socket closed")?? {
+        Message::Close(Some(frame)) => assert_eq!(u16::from(frame.code), 4403),
+        msg => panic!("expected close frame, got {msg:?}"),
+    }
+
+    Ok(())
+}
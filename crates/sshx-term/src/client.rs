@@ -1,15 +1,22 @@
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use bytes::Bytes;
-use ciborium;
 use futures_util::{SinkExt, StreamExt};
+use sshx::dial::Dialer;
 use sshx::encrypt::Encrypt;
-use sshx_core::Sid;
+use sshx::transport::{describe_close, normalize_path_prefix, WsConnectOptions};
+use sshx_core::{redact_url, Sid};
 use std::collections::HashMap;
+use std::time::Duration;
 use tokio::net::TcpStream;
+use tokio::time::{self, Instant};
 use tokio_tungstenite::{
-    connect_async, tungstenite::Message, MaybeTlsStream, WebSocketStream,
+    client_async_tls_with_config,
+    tungstenite::http::header::{HeaderValue, AUTHORIZATION},
+    tungstenite::Message,
+    MaybeTlsStream, WebSocketStream,
 };
 use tracing::{debug, error, warn};
+use url::Url;
 
 // WebSocket protocol types (minimal subset)
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
@@ -28,6 +35,8 @@ pub struct WsUser {
     pub cursor: Option<(i32, i32)>,
     pub focus: Option<Sid>,
     pub can_write: bool,
+    #[serde(default)]
+    pub terminal: Option<String>,
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
@@ -37,8 +46,11 @@ pub enum WsServer {
     InvalidAuth(),
     Users(Vec<(u32, WsUser)>),
     UserDiff(u32, Option<WsUser>),
-    Shells(Vec<(Sid, WsWinsize)>),
+    Shells(Vec<(Sid, WsWinsize, Option<String>)>),
+    ShellExit(Sid, Option<i32>),
+    Paused(bool),
     Chunks(Sid, u64, Vec<Bytes>),
+    Snapshot(Sid, u64, Vec<Bytes>),
     Hear(u32, String, String),
     ShellLatency(u64),
     Pong(u64),
@@ -50,21 +62,27 @@ pub enum WsServer {
 pub enum WsClient {
     Authenticate(Bytes, Option<Bytes>),
     SetName(String),
+    SetTerminal(String),
     SetCursor(Option<(i32, i32)>),
     SetFocus(Option<Sid>),
     Create(i32, i32),
     Close(Sid),
     Move(Sid, Option<WsWinsize>),
+    SetShellName(Sid, String),
     Data(Sid, Bytes, u64),
     Subscribe(Sid, u64),
     Chat(String),
     Ping(u64),
+    RequestSnapshot(Sid),
 }
 
 #[derive(Debug, Clone)]
 pub struct ShellInfo {
     pub id: Sid,
     pub winsize: WsWinsize,
+    /// Name set explicitly by a user of the session, if any.
+    pub name: Option<String>,
+    /// Title inferred from the shell's output.
     pub title: String,
     pub last_activity: std::time::Instant,
     pub bytes_sent: u64,
@@ -74,7 +92,59 @@ pub struct ShellInfo {
     pub status: TerminalStatus,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+/// Find the one shell whose title contains a substring, ignoring case.
+pub fn find_by_title<'a>(shells: &'a [ShellInfo], query: &str) -> Result<&'a ShellInfo> {
+    let query = query.to_lowercase();
+    let matches: Vec<_> = shells
+        .iter()
+        .filter(|shell| shell.display_title().to_lowercase().contains(&query))
+        .collect();
+    let describe = |shells: &[&ShellInfo]| {
+        let list: Vec<_> =
+            shells.iter().map(|s| format!("{} ({})", s.id, s.display_title())).collect();
+        list.join(", ")
+    };
+    match matches[..] {
+        [shell] => Ok(shell),
+        [] if shells.is_empty() => bail!("No terminal title matches {query:?}, session has none"),
+        [] => {
+            let all: Vec<_> = shells.iter().collect();
+            bail!("No terminal title matches {query:?}, available: {}", describe(&all))
+        }
+        _ => bail!("Terminal title {query:?} is ambiguous, matches: {}", describe(&matches)),
+    }
+}
+
+/// A shell as listed by `--list --json`.
+#[derive(serde::Serialize, Debug)]
+pub struct ListedShell<'a> {
+    pub id: Sid,
+    pub rows: u16,
+    pub cols: u16,
+    pub title: &'a str,
+    pub status: &'a TerminalStatus,
+}
+
+impl ShellInfo {
+    /// Title to show for this shell, preferring a name set by a user over the
+    /// title inferred from its output.
+    pub fn display_title(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.title)
+    }
+
+    pub fn listed(&self) -> ListedShell<'_> {
+        ListedShell {
+            id: self.id,
+            rows: self.winsize.rows,
+            cols: self.winsize.cols,
+            title: self.display_title(),
+            status: &self.status,
+        }
+    }
+}
+
+#[derive(serde::Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
 pub enum TerminalStatus {
     Active,    // Recently active
     Idle,      // No activity for a while  
@@ -88,18 +158,71 @@ pub struct SshxClient {
     user_id: u32,
     session_name: String,
     can_write: bool,
+    /// Never send input or create shells, even with write access.
+    read_only: bool,
     shells: Vec<ShellInfo>,
     users: Vec<(u32, WsUser)>,
     chunk_counter: u64,
     subscription_counters: HashMap<Sid, u64>,
+    /// Exit codes reported for shells whose process has ended.
+    exit_codes: HashMap<Sid, i32>,
+    /// Limit on each operation that waits for a server response.
+    timeout: Duration,
+    /// Interval between keepalive pings, if enabled.
+    keepalive: Option<Duration>,
+    /// When the next keepalive ping is due.
+    next_ping: Instant,
+    /// Whether the last keepalive ping is still waiting for its pong.
+    awaiting_pong: bool,
+}
+
+/// Longest display name accepted, matching the limit in the web interface.
+pub const MAX_DISPLAY_NAME_LEN: usize = 50;
+
+/// Check that a display name is non-empty and within the length limit.
+pub fn validate_display_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("display name cannot be empty".into());
+    }
+    if name.chars().count() > MAX_DISPLAY_NAME_LEN {
+        return Err(format!("display name is longer than {MAX_DISPLAY_NAME_LEN} characters"));
+    }
+    Ok(name.to_string())
+}
+
+/// Default display name: the local username, or `sshx-term` if unavailable.
+pub fn default_display_name() -> String {
+    whoami::fallible::username()
+        .ok()
+        .and_then(|name| validate_display_name(&name).ok())
+        .unwrap_or_else(|| "sshx-term".into())
+}
+
+/// Describe the local terminal from `$TERM` and `$COLORTERM`, if known.
+///
+/// Shared shells use the host's terminal settings, so this is only reported
+/// to the session for other users to see.
+pub fn local_terminal() -> Option<String> {
+    let term = std::env::var("TERM").ok().filter(|t| !t.is_empty())?;
+    match std::env::var("COLORTERM").ok().filter(|c| !c.is_empty()) {
+        Some(colorterm) => Some(format!("{term} ({colorterm})")),
+        None => Some(term),
+    }
 }
 
 impl SshxClient {
+    #[allow(clippy::too_many_arguments)]
     pub async fn connect(
         server: String,
         session_id: String,
         key: String,
         write_password: Option<String>,
+        display_name: String,
+        ws_options: &WsConnectOptions,
+        dialer: &Dialer,
+        auth_token: Option<&str>,
+        timeout: Duration,
     ) -> Result<Self> {
         // Create encryption context
         let encrypt = Encrypt::new(&key);
@@ -113,11 +236,26 @@ impl SshxClient {
         };
 
         // Connect WebSocket
-        let ws_url = format!("{}/api/s/{}", server.replace("http", "ws"), session_id);
-        debug!("Connecting to WebSocket: {}", ws_url);
+        let prefix = normalize_path_prefix(&ws_options.path_prefix);
+        let ws_url = format!("{}{prefix}/s/{session_id}", server.replace("http", "ws"));
+        debug!("Connecting to WebSocket: {}", redact_url(&ws_url));
 
-        let (ws_stream, _) = connect_async(&ws_url)
+        let mut request = ws_options.request(&ws_url)?;
+        if let Some(token) = auth_token {
+            let value = HeaderValue::from_str(&format!("Bearer {token}"))
+                .context("invalid auth token")?;
+            request.headers_mut().insert(AUTHORIZATION, value);
+        }
+        let url = Url::parse(&ws_url).context("Invalid server URL")?;
+        let connect = async {
+            let stream = dialer.connect_url(&url).await?;
+            client_async_tls_with_config(request, stream, None, dialer.ws_connector())
+                .await
+                .map_err(anyhow::Error::from)
+        };
+        let (ws_stream, _) = time::timeout(timeout, connect)
             .await
+            .map_err(|_| anyhow!("Timed out after {timeout:?} connecting to WebSocket"))?
             .context("Failed to connect to WebSocket")?;
 
         let mut client = Self {
@@ -126,14 +264,20 @@ impl SshxClient {
             user_id: 0,
             session_name: String::new(),
             can_write: false,
+            read_only: false,
             shells: Vec::new(),
             users: Vec::new(),
             chunk_counter: 0,
             subscription_counters: HashMap::new(),
+            exit_codes: HashMap::new(),
+            timeout,
+            keepalive: None,
+            next_ping: Instant::now(),
+            awaiting_pong: false,
         };
 
         // Authenticate
-        client.authenticate(encrypted_zeros, write_password_hash).await?;
+        client.authenticate(encrypted_zeros, write_password_hash, display_name).await?;
 
         Ok(client)
     }
@@ -142,6 +286,7 @@ impl SshxClient {
         &mut self,
         encrypted_zeros: Vec<u8>,
         write_password_hash: Option<Vec<u8>>,
+        display_name: String,
     ) -> Result<()> {
         // Send authentication
         let auth_msg = WsClient::Authenticate(
@@ -151,11 +296,13 @@ impl SshxClient {
         self.send_message(auth_msg).await?;
 
         // Wait for Hello or InvalidAuth
-        match self.receive_message().await? {
+        let deadline = Instant::now() + self.timeout;
+        match self.receive_before(deadline, "authentication").await? {
             WsServer::Hello(user_id, session_name) => {
                 self.user_id = user_id;
                 self.session_name = session_name;
-                self.can_write = true; // Assume write access unless write password failed
+                // Assume write access until the user list says otherwise
+                self.can_write = !self.read_only;
                 debug!("Authenticated as user {}", user_id);
             }
             WsServer::InvalidAuth() => {
@@ -166,25 +313,43 @@ impl SshxClient {
             }
         }
 
-        // Set name to identify as terminal client
-        self.send_message(WsClient::SetName("sshx-term".to_string()))
-            .await?;
+        // Set name to identify this client in the user list
+        self.send_message(WsClient::SetName(display_name)).await?;
+        if let Some(terminal) = local_terminal() {
+            self.send_message(WsClient::SetTerminal(terminal)).await?;
+        }
 
         Ok(())
     }
 
     pub async fn get_shells(&mut self) -> Result<Vec<ShellInfo>> {
-        // Wait for initial shells message
+        self.get_shells_within(self.timeout).await
+    }
+
+    /// Wait for the session's terminal list, up to a timeout.
+    ///
+    /// If the session sends other state (such as the user list) but no
+    /// terminal list in time, it is treated as having no terminals yet. If it
+    /// sends nothing at all, this returns an error.
+    pub async fn get_shells_within(&mut self, timeout: Duration) -> Result<Vec<ShellInfo>> {
+        let deadline = Instant::now() + timeout;
+        let mut responded = false;
         loop {
-            match self.receive_message().await? {
+            let msg = match time::timeout_at(deadline, self.receive_message()).await {
+                Ok(msg) => msg?,
+                Err(_) if responded => {
+                    debug!("No terminal list received, assuming the session has none");
+                    return Ok(Vec::new());
+                }
+                Err(_) => bail!("Session did not respond within {timeout:?}"),
+            };
+            responded = true;
+            match msg {
                 WsServer::Shells(shells) => {
                     self.update_shells(shells);
                     return Ok(self.shells.clone());
                 }
-                WsServer::Users(users) => {
-                    self.users = users;
-                    self.update_shell_focus_info();
-                }
+                WsServer::Users(users) => self.update_users(users),
                 msg => {
                     debug!("Received message while waiting for shells: {:?}", msg);
                 }
@@ -200,15 +365,17 @@ impl SshxClient {
         self.send_message(WsClient::Create(x, y)).await?;
 
         // Wait for updated shells list with the new shell
+        let deadline = Instant::now() + self.timeout;
         loop {
-            match self.receive_message().await? {
+            match self.receive_before(deadline, "the new terminal").await? {
                 WsServer::Shells(shells) => {
                     let new_shells: Vec<ShellInfo> = shells
                         .into_iter()
-                        .map(|(id, winsize)| ShellInfo { 
-                            id, 
+                        .map(|(id, winsize, name)| ShellInfo {
+                            id,
                             winsize,
-                            title: format!("Terminal {}", id.0),
+                            name,
+                            title: format!("Terminal {id}"),
                             last_activity: std::time::Instant::now(),
                             bytes_sent: 0,
                             bytes_received: 0,
@@ -241,6 +408,52 @@ impl SshxClient {
         Ok(())
     }
 
+    /// Fetch the recent output of a shell, enough to redraw its screen.
+    ///
+    /// This is a one-shot request and doesn't subscribe to the shell.
+    pub async fn request_snapshot(&mut self, shell_id: Sid) -> Result<Vec<u8>> {
+        self.send_message(WsClient::RequestSnapshot(shell_id)).await?;
+
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            match self.receive_before(deadline, "the terminal snapshot").await? {
+                WsServer::Snapshot(id, mut seqnum, chunks) if id == shell_id => {
+                    let stream_num = 0x100000000u64 | (shell_id.0 as u64);
+                    let mut output = Vec::new();
+                    for chunk in chunks {
+                        output.extend(self.encrypt.segment(stream_num, seqnum, &chunk));
+                        seqnum += chunk.len() as u64;
+                    }
+                    return Ok(output);
+                }
+                WsServer::Error(msg) => bail!("Server error: {msg}"),
+                msg => {
+                    debug!("Received message while waiting for snapshot: {:?}", msg);
+                }
+            }
+        }
+    }
+
+    /// Fill in shell titles from their recent output, since titles are
+    /// otherwise only learned from output received after connecting.
+    pub async fn refresh_titles(&mut self) -> Result<Vec<ShellInfo>> {
+        let ids: Vec<Sid> = self.shells.iter().map(|shell| shell.id).collect();
+        for id in ids {
+            let output = self.request_snapshot(id).await?;
+            // Only the most recently set title still applies.
+            let latest = [b"\x1b]0;", b"\x1b]2;"]
+                .iter()
+                .filter_map(|osc| output.windows(osc.len()).rposition(|w| w == *osc))
+                .max();
+            let title = latest.and_then(|start| self.extract_title_from_data(&output[start..]));
+            let shell = self.shells.iter_mut().find(|shell| shell.id == id);
+            if let (Some(title), Some(shell)) = (title, shell) {
+                shell.title = title;
+            }
+        }
+        Ok(self.shells.clone())
+    }
+
     pub async fn send_input(&mut self, shell_id: Sid, data: &[u8]) -> Result<()> {
         if !self.can_write {
             return Err(anyhow!("Cannot send input in read-only mode"));
@@ -321,9 +534,9 @@ impl SshxClient {
             WsServer::Shells(shells) => {
                 // Check if the monitored shell is still present
                 if let Some(monitored_id) = monitored_shell_id {
-                    if !shells.iter().any(|(id, _)| *id == monitored_id) {
-                        debug!("Shell {} was removed from shells list, exiting session", monitored_id.0);
-                        return Err(anyhow!("Remote shell {} has been closed", monitored_id.0));
+                    if !shells.iter().any(|(id, ..)| *id == monitored_id) {
+                        debug!("Shell {monitored_id} was removed from shells list, exiting session");
+                        return Err(anyhow!("Remote shell {monitored_id} has been closed"));
                     }
                 }
                 
@@ -331,6 +544,17 @@ impl SshxClient {
                 self.update_shells(shells);
                 Ok(None)
             }
+            WsServer::Users(users) => {
+                self.update_users(users);
+                Ok(None)
+            }
+            WsServer::ShellExit(shell_id, exit_code) => {
+                debug!("Shell {shell_id} exited with code {exit_code:?}");
+                if let Some(code) = exit_code {
+                    self.exit_codes.insert(shell_id, code);
+                }
+                Ok(None)
+            }
             WsServer::Error(msg) => {
                 error!("Server error: {}", msg);
                 Err(anyhow!("Server error: {}", msg))
@@ -342,6 +566,43 @@ impl SshxClient {
         }
     }
 
+    /// Send a ping every `interval` while receiving, and treat a ping that is
+    /// not answered before the next one as a disconnect.
+    ///
+    /// This keeps idle connections open through NATs and proxies that drop
+    /// quiet TCP streams, and notices when they were dropped anyway.
+    pub fn set_keepalive(&mut self, interval: Option<Duration>) {
+        self.keepalive = interval;
+        self.next_ping = Instant::now() + interval.unwrap_or_default();
+        self.awaiting_pong = false;
+    }
+
+    /// Attach without sending any input, even if the session allows writing.
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+        self.can_write &= !read_only;
+    }
+
+    /// Whether this client may send input to the session's shells.
+    pub fn can_write(&self) -> bool {
+        self.can_write
+    }
+
+    /// Shells of the session, as of the last update from the server.
+    pub fn shells(&self) -> &[ShellInfo] {
+        &self.shells
+    }
+
+    /// Number of users connected to the session, including this client.
+    pub fn user_count(&self) -> usize {
+        self.users.len()
+    }
+
+    /// Exit code reported by the host for a shell, if its process has ended.
+    pub fn exit_code(&self, shell_id: Sid) -> Option<i32> {
+        self.exit_codes.get(&shell_id).copied()
+    }
+
     async fn send_message(&mut self, message: WsClient) -> Result<()> {
         let mut buf = Vec::new();
         ciborium::ser::into_writer(&message, &mut buf)?;
@@ -352,18 +613,48 @@ impl SshxClient {
         Ok(())
     }
 
+    /// Receive the next message, failing if none arrives before the deadline.
+    async fn receive_before(&mut self, deadline: Instant, waiting_for: &str) -> Result<WsServer> {
+        match time::timeout_at(deadline, self.receive_message()).await {
+            Ok(msg) => msg,
+            Err(_) => bail!("Timed out after {:?} waiting for {waiting_for}", self.timeout),
+        }
+    }
+
+    /// Receive the next message from the server, skipping keepalive pongs.
+    ///
+    /// Fragmented messages are reassembled by tungstenite, so every binary
+    /// message holds exactly one CBOR value, however it was framed.
     async fn receive_message(&mut self) -> Result<WsServer> {
         loop {
-            match self.ws_stream.next().await {
+            let frame = match self.keepalive {
+                Some(_) => tokio::select! {
+                    frame = self.ws_stream.next() => Some(frame),
+                    _ = time::sleep_until(self.next_ping) => None,
+                },
+                None => Some(self.ws_stream.next().await),
+            };
+            let Some(frame) = frame else {
+                self.send_ping().await?;
+                continue;
+            };
+            match frame {
                 Some(Ok(Message::Binary(data))) => {
                     let message: WsServer = ciborium::de::from_reader(&*data)
                         .context("Failed to deserialize message")?;
+                    if let WsServer::Pong(_) = message {
+                        self.awaiting_pong = false;
+                        continue;
+                    }
                     return Ok(message);
                 }
                 Some(Ok(Message::Text(_))) => {
                     warn!("Ignoring text message");
                 }
-                Some(Ok(Message::Close(_))) => {
+                Some(Ok(Message::Close(Some(frame)))) => {
+                    return Err(anyhow!(describe_close(frame.code.into(), &frame.reason)));
+                }
+                Some(Ok(Message::Close(None))) => {
                     return Err(anyhow!("WebSocket connection closed"));
                 }
                 Some(Ok(_)) => {
@@ -379,8 +670,32 @@ impl SshxClient {
         }
     }
 
+    /// Send a keepalive ping, failing if the previous one was never answered.
+    async fn send_ping(&mut self) -> Result<()> {
+        let interval = self.keepalive.unwrap_or_default();
+        if self.awaiting_pong {
+            bail!("Server did not answer a keepalive ping within {interval:?}");
+        }
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        self.send_message(WsClient::Ping(timestamp)).await?;
+        self.awaiting_pong = true;
+        self.next_ping = Instant::now() + interval;
+        Ok(())
+    }
+
+    /// Store the user list, taking write access from this client's entry.
+    fn update_users(&mut self, users: Vec<(u32, WsUser)>) {
+        if let Some((_, user)) = users.iter().find(|(id, _)| *id == self.user_id) {
+            self.can_write = user.can_write && !self.read_only;
+        }
+        self.users = users;
+        self.update_shell_focus_info();
+    }
 
-    fn update_shells(&mut self, shells: Vec<(Sid, WsWinsize)>) {
+    fn update_shells(&mut self, shells: Vec<(Sid, WsWinsize, Option<String>)>) {
         let now = std::time::Instant::now();
         
         // Create a map of existing shells for quick lookup
@@ -389,10 +704,11 @@ impl SshxClient {
         
         self.shells = shells
             .into_iter()
-            .map(|(id, winsize)| {
+            .map(|(id, winsize, name)| {
                 if let Some(mut existing) = existing_shells.remove(&id) {
                     // Update existing shell
                     existing.winsize = winsize;
+                    existing.name = name;
                     existing.last_activity = now;
                     existing
                 } else {
@@ -400,7 +716,8 @@ impl SshxClient {
                     ShellInfo {
                         id,
                         winsize,
-                        title: format!("Terminal {}", id.0),
+                        name,
+                        title: format!("Terminal {id}"),
                         last_activity: now,
                         bytes_sent: 0,
                         bytes_received: 0,
@@ -453,31 +770,11 @@ impl SshxClient {
     }
 
     fn extract_title_from_data(&self, data: &[u8]) -> Option<String> {
-        let text = String::from_utf8_lossy(data);
-        
-        // Look for OSC 0 (set window title) escape sequence: \x1b]0;title\x07 or \x1b]0;title\x1b\\
-        if let Some(start) = text.find("\x1b]0;") {
-            let title_start = start + 4;
-            if let Some(end) = text[title_start..].find(|c| c == '\x07' || c == '\x1b') {
-                let title = &text[title_start..title_start + end];
-                if !title.is_empty() {
-                    return Some(self.clean_terminal_title(title));
-                }
-            }
-        }
-        
-        // Also look for OSC 2 (set window title) sequence: \x1b]2;title\x07
-        if let Some(start) = text.find("\x1b]2;") {
-            let title_start = start + 4;
-            if let Some(end) = text[title_start..].find(|c| c == '\x07' || c == '\x1b') {
-                let title = &text[title_start..title_start + end];
-                if !title.is_empty() {
-                    return Some(self.clean_terminal_title(title));
-                }
-            }
-        }
-        
-        None
+        // Look for OSC 0 or OSC 2 (set window title): \x1b]0;title\x07 or \x1b]0;title\x1b\\
+        [b"\x1b]0;", b"\x1b]2;"]
+            .iter()
+            .find_map(|osc| osc_title(data, *osc))
+            .map(|title| self.clean_terminal_title(title))
     }
 
     fn clean_terminal_title(&self, title: &str) -> String {
@@ -494,7 +791,7 @@ impl SshxClient {
                     if clean_path.is_empty() {
                         return "bash".to_string();
                     } else {
-                        return format!("bash:{}", clean_path.split('/').last().unwrap_or(clean_path));
+                        return format!("bash:{}", clean_path.rsplit('/').next().unwrap_or(clean_path));
                     }
                 }
             }
@@ -516,7 +813,7 @@ impl SshxClient {
                     if !args.is_empty() && !args.starts_with('-') {
                         let filename = args.split_whitespace().next().unwrap_or("");
                         if !filename.starts_with('-') && !filename.is_empty() {
-                            let basename = filename.split('/').last().unwrap_or(filename);
+                            let basename = filename.rsplit('/').next().unwrap_or(filename);
                             return format!("{}:{}", process, basename);
                         }
                     }
@@ -537,4 +834,375 @@ impl SshxClient {
         
         title.to_string()
     }
-}
\ No newline at end of file
+}
+
+/// Find the payload of the first OSC sequence with this prefix, if it looks
+/// like a title: well-formed UTF-8 with no control characters.
+///
+/// Binary output can contain the prefix by chance, and decoding whatever
+/// follows it would replace the shell's title with garbage.
+fn osc_title<'a>(data: &'a [u8], osc: &[u8]) -> Option<&'a str> {
+    let start = data.windows(osc.len()).position(|w| w == osc)? + osc.len();
+    let len = data[start..].iter().position(|&b| b == 0x07 || b == 0x1b)?;
+    let Ok(title) = std::str::from_utf8(&data[start..start + len]) else {
+        debug!("Ignoring terminal title that is not valid UTF-8");
+        return None;
+    };
+    if title.chars().any(char::is_control) {
+        debug!("Ignoring terminal title with control characters: {title:?}");
+        return None;
+    }
+    Some(title).filter(|title| !title.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    /// Start a fake session server that authenticates one client and then
+    /// sends the given messages, returning its HTTP address.
+    async fn fake_session(messages: Vec<WsServer>) -> String {
+        fragmented_session(messages, usize::MAX).await
+    }
+
+    /// Like [`fake_session`], but splits messages longer than `frame_size`
+    /// bytes into continuation frames.
+    async fn fragmented_session(messages: Vec<WsServer>, frame_size: usize) -> String {
+        use tokio_tungstenite::tungstenite::protocol::frame::coding::{Data, OpCode};
+        use tokio_tungstenite::tungstenite::protocol::frame::Frame;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            ws.next().await; // Authenticate
+            let hello = WsServer::Hello(1, "test".into());
+            for msg in std::iter::once(hello).chain(messages) {
+                let mut buf = Vec::new();
+                ciborium::ser::into_writer(&msg, &mut buf).unwrap();
+                if buf.len() <= frame_size {
+                    ws.send(Message::Binary(buf)).await.unwrap();
+                    continue;
+                }
+                let count = buf.len().div_ceil(frame_size);
+                for (i, part) in buf.chunks(frame_size).enumerate() {
+                    let opcode = match i {
+                        0 => OpCode::Data(Data::Binary),
+                        _ => OpCode::Data(Data::Continue),
+                    };
+                    let frame = Frame::message(part.to_vec(), opcode, i + 1 == count);
+                    ws.send(Message::Frame(frame)).await.unwrap();
+                }
+            }
+            while ws.next().await.is_some() {}
+        });
+        format!("http://{addr}")
+    }
+
+    async fn connect(server: String) -> SshxClient {
+        let timeout = Duration::from_millis(100);
+        let name = "test".to_string();
+        let ws = WsConnectOptions::default();
+        SshxClient::connect(server, "abc".into(), "key".into(), None, name, &ws, &Dialer::default(), None, timeout)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn empty_shells() {
+        let mut client = connect(fake_session(vec![WsServer::Shells(vec![])]).await).await;
+        let shells = client.get_shells_within(Duration::from_secs(5)).await;
+        assert!(shells.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn missing_shells() {
+        let users = WsServer::Users(vec![]);
+        let mut client = connect(fake_session(vec![users]).await).await;
+        let shells = client.get_shells_within(Duration::from_millis(100)).await;
+        assert!(shells.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn shell_exit_code() {
+        let messages = vec![WsServer::ShellExit(Sid(1), Some(3)), WsServer::Shells(vec![])];
+        let mut client = connect(fake_session(messages).await).await;
+        assert!(client.receive_terminal_data(Some(Sid(1))).await.unwrap().is_none());
+        assert!(client.receive_terminal_data(Some(Sid(1))).await.is_err());
+        assert_eq!(client.exit_code(Sid(1)), Some(3));
+        assert_eq!(client.exit_code(Sid(2)), None);
+    }
+
+    fn shell(id: u32, title: &str) -> ShellInfo {
+        ShellInfo {
+            id: Sid(id),
+            winsize: WsWinsize { x: 0, y: 0, rows: 24, cols: 80 },
+            name: None,
+            title: title.into(),
+            last_activity: std::time::Instant::now(),
+            bytes_sent: 0,
+            bytes_received: 0,
+            is_focused: false,
+            focused_by_users: Vec::new(),
+            status: TerminalStatus::Active,
+        }
+    }
+
+    #[test]
+    fn title_matches() {
+        let shells = [shell(1, "bash:project"), shell(2, "vim:README.md"), shell(3, "htop")];
+        assert_eq!(find_by_title(&shells, "VIM").unwrap().id, Sid(2));
+        assert_eq!(find_by_title(&shells, "readme").unwrap().id, Sid(2));
+
+        let err = find_by_title(&shells, "o").unwrap_err().to_string();
+        assert!(err.contains("ambiguous"), "{err}");
+        assert!(err.contains("1 (bash:project)") && err.contains("3 (htop)"), "{err}");
+
+        let err = find_by_title(&shells, "emacs").unwrap_err().to_string();
+        assert!(err.contains("No terminal title matches"), "{err}");
+        assert!(err.contains("2 (vim:README.md)"), "{err}");
+        assert!(find_by_title(&[], "vim").is_err());
+    }
+
+    #[test]
+    fn names_override_titles() {
+        let mut named = shell(2, "vim:README.md");
+        named.name = Some("docs".into());
+        let shells = [shell(1, "bash:project"), named];
+        assert_eq!(find_by_title(&shells, "docs").unwrap().id, Sid(2));
+        assert!(find_by_title(&shells, "vim").is_err());
+        assert_eq!(shells[1].listed().title, "docs");
+    }
+
+    #[test]
+    fn listed_json() {
+        let shells = [shell(2, "vim:README.md")];
+        let listed: Vec<_> = shells.iter().map(ShellInfo::listed).collect();
+        assert_eq!(
+            serde_json::to_string(&listed).unwrap(),
+            r#"[{"id":2,"rows":24,"cols":80,"title":"vim:README.md","status":"active"}]"#
+        );
+    }
+
+    #[test]
+    fn osc_titles() {
+        assert_eq!(osc_title(b"ls\x1b]0;make test\x07", b"\x1b]0;"), Some("make test"));
+        assert_eq!(osc_title(b"\x1b]2;caf\xc3\xa9\x1b\\", b"\x1b]2;"), Some("caf\u{e9}"));
+        assert_eq!(osc_title(b"\x1b]0;\x07", b"\x1b]0;"), None);
+        assert_eq!(osc_title(b"\x1b]0;unterminated", b"\x1b]0;"), None);
+
+        // Binary output that happens to contain an OSC prefix.
+        assert_eq!(osc_title(b"\x1b]0;\xff\xfe\x00\x01\x07", b"\x1b]0;"), None);
+        assert_eq!(osc_title(b"\x1b]0;ELF\x02\x01\x01\x07", b"\x1b]0;"), None);
+        assert_eq!(osc_title(b"\x1b]2;tab\there\x07", b"\x1b]2;"), None);
+        assert_eq!(osc_title(b"\x1b]2;ok\x7f\x07", b"\x1b]2;"), None);
+    }
+
+    #[tokio::test]
+    async fn binary_output_keeps_title() {
+        let title = b"\x1b]0;vim a.rs\x07";
+        let binary = b"\x1b]0;\x89PNG\r\n\x1a\n\x00\x07\x1b]2;\xc0\x07";
+        let chunk = Encrypt::new("key").segment(0x100000001, 0, title);
+        let binary = Encrypt::new("key").segment(0x100000001, title.len() as u64, binary);
+        let messages = vec![
+            WsServer::Shells(vec![(Sid(1), WsWinsize { x: 0, y: 0, rows: 24, cols: 80 }, None)]),
+            WsServer::Chunks(Sid(1), 0, vec![chunk.into()]),
+            WsServer::Chunks(Sid(1), 13, vec![binary.into()]),
+        ];
+        let mut client = connect(fake_session(messages).await).await;
+        client.get_shells().await.unwrap();
+        for _ in 0..2 {
+            client.receive_terminal_data(Some(Sid(1))).await.unwrap();
+        }
+        assert_eq!(client.shells[0].title, "vim:a.rs");
+    }
+
+    #[tokio::test]
+    async fn fragmented_chunks() {
+        let output = vec![b'x'; 200_000];
+        let chunk = Encrypt::new("key").segment(0x100000001, 0, &output);
+        let messages = vec![
+            WsServer::Shells(vec![(Sid(1), WsWinsize { x: 0, y: 0, rows: 24, cols: 80 }, None)]),
+            WsServer::Chunks(Sid(1), 0, vec![chunk.into()]),
+        ];
+        let mut client = connect(fragmented_session(messages, 1000).await).await;
+        client.get_shells().await.unwrap();
+        let data = client.receive_terminal_data(Some(Sid(1))).await.unwrap();
+        assert_eq!(data, Some((Sid(1), output)));
+    }
+
+    #[tokio::test]
+    async fn titles_from_snapshot() {
+        let output = b"\x1b]0;top\x07\x1b]0;vim a.rs\x07";
+        let title = Encrypt::new("key").segment(0x100000001, 0, output);
+        let messages = vec![
+            WsServer::Shells(vec![(Sid(1), WsWinsize { x: 0, y: 0, rows: 24, cols: 80 }, None)]),
+            WsServer::Snapshot(Sid(1), 0, vec![title.into()]),
+        ];
+        let mut client = connect(fake_session(messages).await).await;
+        client.get_shells().await.unwrap();
+        let shells = client.refresh_titles().await.unwrap();
+        assert_eq!(shells[0].title, "vim:a.rs");
+    }
+
+    fn user(can_write: bool) -> WsUser {
+        WsUser { name: "test".into(), cursor: None, focus: None, can_write, terminal: None }
+    }
+
+    #[tokio::test]
+    async fn read_only_blocks_input() {
+        let users = || WsServer::Users(vec![(1, user(true))]);
+        let mut client = connect(fake_session(vec![users()]).await).await;
+        client.set_read_only(true);
+        assert!(!client.can_write());
+        client.get_shells_within(Duration::from_millis(100)).await.unwrap();
+        assert!(!client.can_write(), "user list should not grant write access");
+        let err = client.send_input(Sid(1), b"rm -rf /\r").await.unwrap_err();
+        assert!(err.to_string().contains("read-only"));
+        assert!(client.create_shell(0, 0).await.is_err());
+
+        let mut client = connect(fake_session(vec![users()]).await).await;
+        client.get_shells_within(Duration::from_millis(100)).await.unwrap();
+        assert!(client.can_write());
+    }
+
+    #[tokio::test]
+    async fn user_list_revokes_write() {
+        let messages = vec![WsServer::Users(vec![(1, user(false))]), WsServer::Shells(vec![])];
+        let mut client = connect(fake_session(messages).await).await;
+        assert!(client.can_write());
+        client.receive_terminal_data(None).await.unwrap();
+        assert!(!client.can_write());
+    }
+
+    #[test]
+    fn display_names() {
+        assert_eq!(validate_display_name("  alice "), Ok("alice".into()));
+        assert!(validate_display_name("").is_err());
+        assert!(validate_display_name("   ").is_err());
+        assert!(validate_display_name(&"é".repeat(MAX_DISPLAY_NAME_LEN)).is_ok());
+        assert!(validate_display_name(&"x".repeat(MAX_DISPLAY_NAME_LEN + 1)).is_err());
+        assert!(!default_display_name().is_empty());
+    }
+
+    #[tokio::test]
+    async fn keepalive_pings() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = format!("http://{}", listener.local_addr().unwrap());
+        let (pings_tx, mut pings_rx) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            ws.next().await; // Authenticate
+            let reply = |msg: WsServer| {
+                let mut buf = Vec::new();
+                ciborium::ser::into_writer(&msg, &mut buf).unwrap();
+                Message::Binary(buf)
+            };
+            ws.send(reply(WsServer::Hello(1, "test".into()))).await.unwrap();
+            while let Some(Ok(Message::Binary(data))) = ws.next().await {
+                if let Ok(WsClient::Ping(ts)) = ciborium::de::from_reader(&*data) {
+                    pings_tx.send(Instant::now()).unwrap();
+                    ws.send(reply(WsServer::Pong(ts))).await.unwrap();
+                }
+            }
+        });
+
+        let mut client = connect(server).await;
+        let interval = Duration::from_millis(50);
+        client.set_keepalive(Some(interval));
+        let start = Instant::now();
+        let mut pings = Vec::new();
+        tokio::select! {
+            result = client.receive_terminal_data(None) => {
+                panic!("pongs should not surface as messages or errors: {result:?}");
+            }
+            _ = async {
+                while pings.len() < 4 {
+                    pings.push(pings_rx.recv().await.unwrap());
+                }
+            } => {}
+            _ = time::sleep(Duration::from_secs(5)) => panic!("timed out waiting for pings"),
+        }
+        assert!(pings[0] >= start + interval);
+        for pair in pings.windows(2) {
+            assert!(pair[1] - pair[0] >= interval);
+        }
+    }
+
+    #[tokio::test]
+    async fn keepalive_missing_pong() {
+        let mut client = connect(fake_session(vec![]).await).await;
+        client.set_keepalive(Some(Duration::from_millis(20)));
+        let err = client.receive_terminal_data(None).await.unwrap_err();
+        assert!(err.to_string().contains("keepalive"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn authentication_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            while ws.next().await.is_some() {}
+        });
+
+        let timeout = Duration::from_millis(100);
+        let server = format!("http://{addr}");
+        let name = "test".to_string();
+        let (key, ws) = ("key".to_string(), WsConnectOptions::default());
+        let result =
+            SshxClient::connect(server, "abc".into(), key, None, name, &ws, &Dialer::default(), None, timeout).await;
+        let err = result.err().unwrap().to_string();
+        assert!(err.contains("Timed out"), "{err}");
+    }
+
+    #[tokio::test]
+    #[allow(clippy::result_large_err)]
+    async fn upgrade_headers() {
+        use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (mut protocol, mut authorization) = (None, None);
+            let callback = |req: &Request, mut resp: Response| {
+                protocol = req.headers().get("sec-websocket-protocol").cloned();
+                authorization = req.headers().get("authorization").cloned();
+                if let Some(value) = &protocol {
+                    resp.headers_mut().insert("sec-websocket-protocol", value.clone());
+                }
+                Ok(resp)
+            };
+            let ws = tokio_tungstenite::accept_hdr_async(stream, callback).await;
+            drop(ws);
+            (protocol, authorization)
+        });
+
+        let ws = WsConnectOptions {
+            subprotocol: Some("sshx.v1".into()),
+            ..Default::default()
+        };
+        let (server_url, key) = (format!("http://{addr}"), "key".to_string());
+        let timeout = Duration::from_millis(100);
+        let name = "test".to_string();
+        let token = Some("secret");
+        SshxClient::connect(server_url, "abc".into(), key, None, name, &ws, &Dialer::default(), token, timeout)
+            .await
+            .ok();
+        let (protocol, authorization) = server.await.unwrap();
+        assert_eq!(protocol.unwrap(), "sshx.v1");
+        assert_eq!(authorization.unwrap(), "Bearer secret");
+    }
+
+    #[tokio::test]
+    async fn unresponsive_session() {
+        let mut client = connect(fake_session(vec![]).await).await;
+        let shells = client.get_shells_within(Duration::from_millis(100)).await;
+        assert!(shells.is_err());
+    }
+}
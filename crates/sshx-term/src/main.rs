@@ -1,16 +1,28 @@
+use std::time::Duration;
+
 use anyhow::Result;
 use clap::Parser;
+use sshx::dial::{Dialer, JumpHost};
+use sshx::transport::{WsConnectOptions, DEFAULT_WS_PATH_PREFIX};
+use sshx_core::Sid;
 
 mod client;
+mod screen;
 mod selector;
 mod session;
+mod split;
 mod terminal;
 
-use client::SshxClient;
-use selector::show_terminal_selector;
+use client::{default_display_name, find_by_title, validate_display_name, SshxClient};
+use selector::{show_terminal_selector, SelectorChoice, Theme, ThemeName};
 use session::parse_sshx_url;
+use screen::Screen;
+use split::run_split_session;
 use terminal::run_terminal_session;
 
+/// Most terminals shown by --split, one for each digit that focuses a panel.
+const MAX_SPLIT: usize = 9;
+
 /// Terminal client for sshx sessions
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
@@ -24,12 +36,24 @@ struct Args {
     
     /// Connect to specific terminal ID
     #[clap(short, long)]
-    terminal: Option<u32>,
+    terminal: Option<Sid>,
+
+    /// Connect to the terminal whose title contains this text (ignoring case)
+    #[clap(long, value_name = "TEXT", conflicts_with_all = ["new", "terminal"])]
+    terminal_title: Option<String>,
     
     /// List terminals and exit
     #[clap(short, long)]
     list: bool,
     
+    /// With --list, print terminals as a JSON array for scripts
+    #[clap(long, requires = "list")]
+    json: bool,
+
+    /// Print the current screen of each terminal (or of --terminal) and exit
+    #[clap(long, conflicts_with_all = ["new", "list"])]
+    snapshot: bool,
+
     /// Read-only mode
     #[clap(short, long)]
     readonly: bool,
@@ -37,6 +61,57 @@ struct Args {
     /// Verbose output
     #[clap(short, long)]
     verbose: bool,
+
+    /// Name shown to other users in the session [default: local username]
+    #[clap(long, value_name = "NAME", value_parser = validate_display_name)]
+    display_name: Option<String>,
+
+    /// Path prefix of the server's WebSocket routes, for reverse proxies
+    #[clap(long, value_name = "PREFIX", default_value = DEFAULT_WS_PATH_PREFIX)]
+    ws_path_prefix: String,
+
+    /// WebSocket subprotocol to request, for gateways that require one
+    #[clap(long, value_name = "PROTOCOL")]
+    ws_subprotocol: Option<String>,
+
+    /// Bearer token for servers started with `--auth-token`
+    #[clap(long, value_name = "TOKEN")]
+    auth_token: Option<String>,
+
+    /// Seconds to wait for each server response before giving up
+    #[clap(long, default_value_t = 15, value_name = "SECONDS")]
+    timeout: u64,
+
+    /// Seconds between keepalive pings to the server, or 0 to disable
+    #[clap(long, default_value_t = 30, value_name = "SECONDS")]
+    keepalive: u64,
+
+    /// Show several terminals side by side: all of them, or those in a
+    /// comma-separated list of IDs as --split=1,3 (at most 9)
+    #[clap(
+        long,
+        value_name = "IDS",
+        num_args = 0..=1,
+        require_equals = true,
+        value_delimiter = ',',
+        conflicts_with_all = ["new", "terminal", "terminal_title", "list", "snapshot"],
+    )]
+    split: Option<Vec<Sid>>,
+
+    /// Color scheme of the terminal selector (default: dark, or mono if
+    /// NO_COLOR is set)
+    #[clap(long, value_enum, value_name = "THEME")]
+    theme: Option<ThemeName>,
+
+    /// Tunnel the connection through this SSH bastion, as [USER@]HOST[:PORT]
+    /// (authenticates with the SSH agent or configured keys, never a password)
+    #[clap(long, value_name = "HOST")]
+    jump: Option<JumpHost>,
+
+    /// Skip TLS certificate verification, for testing against local servers
+    /// with self-signed certificates. Never use this over a real network
+    #[clap(long)]
+    insecure: bool,
 }
 
 #[tokio::main]
@@ -62,6 +137,10 @@ async fn main() -> Result<()> {
         std::process::exit(0);
     });
     
+    if args.insecure {
+        sshx::tls::warn_insecure();
+    }
+
     // Parse sshx URL to extract session info
     let (server, session_id, key, write_password) = parse_sshx_url(&args.url)?;
     
@@ -70,13 +149,35 @@ async fn main() -> Result<()> {
         server, 
         session_id, 
         key,
-        if args.readonly { None } else { write_password }
+        if args.readonly || args.snapshot { None } else { write_password },
+        args.display_name.unwrap_or_else(default_display_name),
+        &WsConnectOptions {
+            path_prefix: args.ws_path_prefix,
+            subprotocol: args.ws_subprotocol,
+        },
+        &Dialer {
+            jump: args.jump,
+            insecure: args.insecure,
+            ..Default::default()
+        },
+        args.auth_token.as_deref(),
+        Duration::from_secs(args.timeout),
     ).await?;
+    client.set_read_only(args.readonly || args.snapshot);
+    if args.keepalive > 0 {
+        client.set_keepalive(Some(Duration::from_secs(args.keepalive)));
+    }
     
     // Get current shells
     let shells = client.get_shells().await?;
     
     // Handle list mode
+    if args.list && args.json {
+        let shells = client.refresh_titles().await?;
+        let listed: Vec<_> = shells.iter().map(|shell| shell.listed()).collect();
+        println!("{}", serde_json::to_string(&listed)?);
+        return Ok(());
+    }
     if args.list {
         if shells.is_empty() {
             println!("No terminals in this session");
@@ -90,15 +191,70 @@ async fn main() -> Result<()> {
         return Ok(());
     }
     
+    // Resolve a terminal title to its ID, which needs the recent output
+    let terminal = match &args.terminal_title {
+        Some(query) => {
+            let shells = client.refresh_titles().await?;
+            Some(find_by_title(&shells, query)?.id)
+        }
+        None => args.terminal,
+    };
+
+    // Handle snapshot mode, which never needs write access
+    if args.snapshot {
+        let selected: Vec<_> = match terminal {
+            Some(id) => shells.iter().filter(|s| s.id == id).collect(),
+            None => shells.iter().collect(),
+        };
+        if selected.is_empty() {
+            eprintln!("No terminals to snapshot");
+            std::process::exit(1);
+        }
+        for (i, shell) in selected.into_iter().enumerate() {
+            let output = client.request_snapshot(shell.id).await?;
+            if i > 0 {
+                println!();
+            }
+            let size = &shell.winsize;
+            println!("=== Terminal {} ({}x{}) ===", shell.id, size.cols, size.rows);
+            println!("{}", Screen::from_output(&shell.winsize, &output).plain_text());
+        }
+        return Ok(());
+    }
+    
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    let theme = Theme::select(args.theme, no_color);
+
+    // Handle split mode, with one panel per shell
+    if let Some(ids) = args.split {
+        let ids: Vec<Sid> = match ids.is_empty() {
+            true => shells.iter().map(|s| s.id).collect(),
+            false => ids,
+        };
+        if let Some(missing) = ids.iter().find(|id| !shells.iter().any(|s| s.id == **id)) {
+            eprintln!("Terminal {missing} not found");
+            std::process::exit(1);
+        }
+        if ids.is_empty() {
+            eprintln!("No terminals to split");
+            std::process::exit(1);
+        }
+        if ids.len() > MAX_SPLIT {
+            eprintln!("Showing the first {MAX_SPLIT} of {} terminals", ids.len());
+        }
+        run_split_session(&mut client, &ids[..ids.len().min(MAX_SPLIT)], &theme).await?;
+        drop(client);
+        std::process::exit(0)
+    }
+
     // Determine which shell to connect to
     let shell_id = if args.new {
         // Always create new terminal
         client.create_shell(0, 0).await?
-    } else if let Some(terminal_id) = args.terminal {
+    } else if let Some(terminal_id) = terminal {
         // Connect to specific terminal
-        let target_sid = sshx_core::Sid(terminal_id);
-        if shells.iter().any(|s| s.id == target_sid) {
-            target_sid
+        if shells.iter().any(|s| s.id == terminal_id) {
+            terminal_id
         } else {
             eprintln!("Terminal {} not found", terminal_id);
             std::process::exit(1);
@@ -111,19 +267,21 @@ async fn main() -> Result<()> {
         shells[0].id
     } else {
         // Multiple terminals - show selector
-        let selected = show_terminal_selector(&shells).await?;
-        if selected.0 == u32::MAX {
-            // User chose "Create new terminal"
-            client.create_shell(0, 0).await?
-        } else {
-            selected
+        match show_terminal_selector(&shells, client.user_count(), &theme).await? {
+            SelectorChoice::Existing(id) => id,
+            SelectorChoice::CreateNew => client.create_shell(0, 0).await?,
+            SelectorChoice::Quit => {
+                // The selector has already restored the terminal
+                drop(client);
+                std::process::exit(0)
+            }
         }
     };
     
     // Enter terminal session
-    run_terminal_session(&mut client, shell_id).await?;
+    let exit_code = run_terminal_session(&mut client, shell_id).await?;
     
-    // Force immediate exit to return control to shell
+    // Force immediate exit to return control to shell, with the remote status
     drop(client);
-    std::process::exit(0)
+    std::process::exit(exit_code)
 }
\ No newline at end of file
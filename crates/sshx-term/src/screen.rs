@@ -0,0 +1,162 @@
+//! Screen of a remote shell, rebuilt from its output stream.
+//!
+//! Output is replayed through a terminal emulator of the shell's size, so
+//! cursor movement and clearing are applied rather than printed. The result
+//! can be drawn with ratatui or read back as plain text.
+
+use ratatui::{
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+};
+
+use crate::client::WsWinsize;
+
+/// Emulated screen of one shell.
+pub struct Screen {
+    parser: vt100::Parser,
+}
+
+impl Screen {
+    /// Create an empty screen, with at least one row and column.
+    pub fn new(rows: u16, cols: u16) -> Self {
+        Self { parser: vt100::Parser::new(rows.max(1), cols.max(1), 0) }
+    }
+
+    /// Replay output onto an empty screen of a shell's size.
+    pub fn from_output(winsize: &WsWinsize, data: &[u8]) -> Self {
+        let mut screen = Self::new(winsize.rows, winsize.cols);
+        screen.process(data);
+        screen
+    }
+
+    /// Apply more output from the shell.
+    pub fn process(&mut self, data: &[u8]) {
+        self.parser.process(data);
+    }
+
+    /// Size of the screen, as (rows, cols).
+    pub fn size(&self) -> (u16, u16) {
+        self.parser.screen().size()
+    }
+
+    /// Change the size of the screen, keeping its contents where they fit.
+    pub fn resize(&mut self, rows: u16, cols: u16) {
+        self.parser.set_size(rows.max(1), cols.max(1));
+    }
+
+    /// Position of the cursor as (row, col), unless the shell hid it.
+    pub fn cursor(&self) -> Option<(u16, u16)> {
+        let screen = self.parser.screen();
+        (!screen.hide_cursor()).then(|| screen.cursor_position())
+    }
+
+    /// Styled rows of the screen, for drawing with ratatui.
+    pub fn render(&self) -> Vec<Line<'static>> {
+        let screen = self.parser.screen();
+        let (rows, cols) = screen.size();
+        (0..rows)
+            .map(|row| {
+                let mut spans: Vec<Span> = Vec::new();
+                let mut text = String::new();
+                let mut style = Style::default();
+                for cell in (0..cols).filter_map(|col| screen.cell(row, col)) {
+                    if cell.is_wide_continuation() {
+                        continue;
+                    }
+                    let cell_style = cell_style(cell);
+                    if cell_style != style && !text.is_empty() {
+                        spans.push(Span::styled(std::mem::take(&mut text), style));
+                    }
+                    style = cell_style;
+                    match cell.has_contents() {
+                        true => text.push_str(&cell.contents()),
+                        false => text.push(' '),
+                    }
+                }
+                spans.push(Span::styled(text, style));
+                Line::from(spans)
+            })
+            .collect()
+    }
+
+    /// Text on the screen, without trailing blank lines or spaces.
+    pub fn plain_text(&self) -> String {
+        self.parser.screen().contents().trim_end().to_owned()
+    }
+}
+
+fn cell_style(cell: &vt100::Cell) -> Style {
+    let color = |color| match color {
+        vt100::Color::Default => Color::Reset,
+        vt100::Color::Idx(i) => Color::Indexed(i),
+        vt100::Color::Rgb(r, g, b) => Color::Rgb(r, g, b),
+    };
+    let mut style = Style::default().fg(color(cell.fgcolor())).bg(color(cell.bgcolor()));
+    for (enabled, modifier) in [
+        (cell.bold(), Modifier::BOLD),
+        (cell.italic(), Modifier::ITALIC),
+        (cell.underline(), Modifier::UNDERLINED),
+        (cell.inverse(), Modifier::REVERSED),
+    ] {
+        if enabled {
+            style = style.add_modifier(modifier);
+        }
+    }
+    style
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn winsize(rows: u16, cols: u16) -> WsWinsize {
+        WsWinsize { x: 0, y: 0, rows, cols }
+    }
+
+    #[test]
+    fn test_render_applies_escapes() {
+        let data = b"old line\r\n\x1b[2J\x1b[Hhello\r\n\x1b[31mworld\x1b[0m\r\n";
+        let screen = Screen::from_output(&winsize(24, 80), data);
+        assert_eq!(screen.plain_text(), "hello\nworld");
+    }
+
+    #[test]
+    fn test_render_keeps_visible_rows() {
+        let data: String = (1..=10).map(|i| format!("line {i}\r\n")).collect();
+        let screen = Screen::from_output(&winsize(3, 20), data.as_bytes());
+        assert_eq!(screen.plain_text(), "line 9\nline 10");
+    }
+
+    #[test]
+    fn test_render_styles() {
+        let mut screen = Screen::new(2, 12);
+        screen.process(b"ab\x1b[1;32mgreen\x1b[0m\x1b[7m!\x1b[0m\r\n\x1b[38;2;1;2;3mrgb");
+        let rows = screen.render();
+        assert_eq!(rows.len(), 2);
+
+        let spans: Vec<_> = rows[0].spans.iter().map(|span| span.content.as_ref()).collect();
+        assert_eq!(spans, ["ab", "green", "!", "    "]);
+        let green = rows[0].spans[1].style;
+        assert_eq!(green.fg, Some(Color::Indexed(2)));
+        assert!(green.add_modifier.contains(Modifier::BOLD));
+        assert!(rows[0].spans[2].style.add_modifier.contains(Modifier::REVERSED));
+        assert_eq!(rows[1].spans[0].style.fg, Some(Color::Rgb(1, 2, 3)));
+        assert_eq!(rows[1].width(), 12);
+    }
+
+    #[test]
+    fn test_cursor_and_resize() {
+        let mut screen = Screen::new(0, 0);
+        assert_eq!(screen.size(), (1, 1));
+
+        screen.resize(4, 10);
+        screen.process(b"12345\r\nab");
+        assert_eq!(screen.cursor(), Some((1, 2)));
+        screen.process(b"\x1b[?25l");
+        assert_eq!(screen.cursor(), None);
+
+        screen.resize(4, 3);
+        assert_eq!(screen.size(), (4, 3));
+        assert_eq!(screen.plain_text(), "123\nab");
+    }
+}
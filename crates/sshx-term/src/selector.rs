@@ -1,5 +1,6 @@
 use anyhow::Result;
 use crossterm::{
+    cursor::Show,
     event::{self, Event, KeyCode, KeyEventKind},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
@@ -8,144 +9,174 @@ use ratatui::{
     backend::CrosstermBackend,
     layout::{Alignment, Constraint, Direction, Layout},
     style::{Color, Modifier, Style},
+    text::Line,
     widgets::{
-        Block, Borders, Paragraph, Table, Row, Cell
+        Block, Borders, Paragraph, Table, TableState, Row, Cell, Wrap
     },
-    Terminal,
+    Frame, Terminal,
 };
-use std::io;
+use std::io::{self, Write};
 use sshx_core::Sid;
 
 use crate::client::{ShellInfo, TerminalStatus};
 
-pub async fn show_terminal_selector(shells: &[ShellInfo]) -> Result<Sid> {
-    enable_raw_mode()?;
-    let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen)?;
-    let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
+/// The user's choice from the terminal selector.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectorChoice {
+    /// Connect to an existing terminal.
+    Existing(Sid),
+    /// Create a new terminal and connect to it.
+    CreateNew,
+    /// Exit without connecting to a terminal.
+    Quit,
+}
+
+/// Color scheme names accepted by `--theme`.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThemeName {
+    /// Light text on a dark background.
+    Dark,
+    /// Dark text on a light background.
+    Light,
+    /// No colors, only bold and reversed text.
+    Mono,
+}
+
+/// Styles used to draw the selector.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    pub text: Style,
+    pub header: Style,
+    pub border: Style,
+    pub footer_border: Style,
+    pub highlight: Style,
+    pub create: Style,
+    pub warning: Style,
+    pub active: Style,
+    pub busy: Style,
+    pub idle: Style,
+    pub focused: Style,
+}
 
-    let result = run_selector(&mut terminal, shells).await;
+impl Theme {
+    /// Pick the theme named by `--theme`, falling back to `mono` when
+    /// `NO_COLOR` is set and to `dark` otherwise.
+    pub fn select(name: Option<ThemeName>, no_color: bool) -> Self {
+        match name {
+            Some(ThemeName::Dark) => Self::dark(),
+            Some(ThemeName::Light) => Self::light(),
+            Some(ThemeName::Mono) => Self::mono(),
+            None if no_color => Self::mono(),
+            None => Self::dark(),
+        }
+    }
 
-    disable_raw_mode()?;
-    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
-    terminal.show_cursor()?;
+    pub fn dark() -> Self {
+        let fg = |color| Style::default().fg(color);
+        Self {
+            text: fg(Color::White),
+            header: fg(Color::Cyan).add_modifier(Modifier::BOLD),
+            border: fg(Color::White),
+            footer_border: fg(Color::Gray),
+            highlight: fg(Color::White).bg(Color::Blue).add_modifier(Modifier::BOLD),
+            create: fg(Color::Green),
+            warning: fg(Color::Yellow),
+            active: fg(Color::Green),
+            busy: fg(Color::Yellow),
+            idle: fg(Color::Gray),
+            focused: fg(Color::Cyan),
+        }
+    }
 
-    result
+    pub fn light() -> Self {
+        let fg = |color| Style::default().fg(color);
+        Self {
+            text: fg(Color::Black),
+            header: fg(Color::Blue).add_modifier(Modifier::BOLD),
+            border: fg(Color::Black),
+            footer_border: fg(Color::DarkGray),
+            highlight: fg(Color::Black).bg(Color::LightBlue).add_modifier(Modifier::BOLD),
+            create: fg(Color::Green),
+            warning: fg(Color::Red),
+            active: fg(Color::Green),
+            busy: fg(Color::Magenta),
+            idle: fg(Color::DarkGray),
+            focused: fg(Color::Blue),
+        }
+    }
+
+    pub fn mono() -> Self {
+        let plain = Style::default();
+        Self {
+            text: plain,
+            header: plain.add_modifier(Modifier::BOLD),
+            border: plain,
+            footer_border: plain,
+            highlight: plain.add_modifier(Modifier::REVERSED | Modifier::BOLD),
+            create: plain,
+            warning: plain.add_modifier(Modifier::BOLD),
+            active: plain,
+            busy: plain.add_modifier(Modifier::BOLD),
+            idle: plain.add_modifier(Modifier::DIM),
+            focused: plain.add_modifier(Modifier::UNDERLINED),
+        }
+    }
+}
+
+/// Raw mode and alternate screen for full-screen views, restored when dropped.
+///
+/// Restoring on drop means the user's terminal is left usable however the
+/// view exits, whether the user quits or drawing fails with an error.
+pub(crate) struct FullScreen;
+
+impl FullScreen {
+    pub(crate) fn enter() -> Result<Self> {
+        enable_raw_mode()?;
+        let screen = Self;
+        execute!(io::stdout(), EnterAlternateScreen)?;
+        Ok(screen)
+    }
+}
+
+impl Drop for FullScreen {
+    fn drop(&mut self) {
+        let _ = disable_raw_mode();
+        let _ = restore_screen(&mut io::stdout());
+    }
+}
+
+/// Leave the alternate screen and show the cursor again.
+fn restore_screen(out: &mut impl Write) -> io::Result<()> {
+    execute!(out, LeaveAlternateScreen, Show)
+}
+
+pub async fn show_terminal_selector(
+    shells: &[ShellInfo],
+    users: usize,
+    theme: &Theme,
+) -> Result<SelectorChoice> {
+    let _screen = FullScreen::enter()?;
+    let backend = CrosstermBackend::new(io::stdout());
+    let mut terminal = Terminal::new(backend)?;
+
+    run_selector(&mut terminal, shells, users, theme).await
 }
 
 async fn run_selector(
     terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
     shells: &[ShellInfo],
-) -> Result<Sid> {
-    let mut selected = 0;
+    users: usize,
+    theme: &Theme,
+) -> Result<SelectorChoice> {
+    let stats = SessionStats::new(shells, users);
+    let mut nav = Navigation::new(shells.len());
+    let mut table_state = TableState::default();
+    let mut page_size = 1;
 
     loop {
+        table_state.select(Some(nav.selected));
         terminal.draw(|f| {
-            let size = f.size();
-            
-            // Simple layout - just table and footer
-            let chunks = Layout::default()
-                .direction(Direction::Vertical)
-                .constraints([
-                    Constraint::Min(0),    // Table
-                    Constraint::Length(3), // Footer
-                ])
-                .split(size);
-
-            // Simple table with essential info
-            let header_cells = ["#", "ID", "Title/Process", "Size", "Activity", "Status"]
-                .iter()
-                .map(|h| Cell::from(*h).style(Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)));
-            
-            let header_row = Row::new(header_cells).height(1);
-
-            let mut rows: Vec<Row> = shells
-                .iter()
-                .enumerate()
-                .map(|(i, shell)| {
-                    let style = if i == selected {
-                        Style::default().bg(Color::Blue).fg(Color::White).add_modifier(Modifier::BOLD)
-                    } else {
-                        Style::default().fg(Color::White)
-                    };
-
-                    let status_style = match shell.status {
-                        TerminalStatus::Active => Style::default().fg(Color::Green),
-                        TerminalStatus::Busy => Style::default().fg(Color::Yellow),
-                        TerminalStatus::Idle => Style::default().fg(Color::Gray),
-                        TerminalStatus::Focused => Style::default().fg(Color::Cyan),
-                    };
-
-                    let status_text = match shell.status {
-                        TerminalStatus::Active => "Active",
-                        TerminalStatus::Busy => "Busy",
-                        TerminalStatus::Idle => "Idle",
-                        TerminalStatus::Focused => "Focused",
-                    };
-
-                    let activity_text = format_duration(shell.last_activity.elapsed());
-
-                    Row::new([
-                        Cell::from((i + 1).to_string()),
-                        Cell::from(shell.id.0.to_string()),
-                        Cell::from(shell.title.clone()),
-                        Cell::from(format!("{}×{}", shell.winsize.cols, shell.winsize.rows)),
-                        Cell::from(activity_text),
-                        Cell::from(status_text).style(status_style),
-                    ]).style(style)
-                })
-                .collect();
-
-            // Add "Create New" option
-            let create_style = if selected == shells.len() {
-                Style::default().bg(Color::Blue).fg(Color::White).add_modifier(Modifier::BOLD)
-            } else {
-                Style::default().fg(Color::Green)
-            };
-
-            rows.push(
-                Row::new([
-                    Cell::from("n"),
-                    Cell::from("NEW"),
-                    Cell::from("Create new terminal"),
-                    Cell::from("-"),
-                    Cell::from("-"),
-                    Cell::from("Ready").style(Style::default().fg(Color::Green)),
-                ]).style(create_style)
-            );
-
-            let table = Table::new(rows, [
-                Constraint::Length(3),  // #
-                Constraint::Length(4),  // ID
-                Constraint::Min(30),    // Title
-                Constraint::Length(8),  // Size
-                Constraint::Length(10), // Activity
-                Constraint::Length(10), // Status
-            ])
-            .header(header_row)
-            .block(Block::default()
-                .title("Select Terminal")
-                .borders(Borders::ALL)
-                .border_style(Style::default().fg(Color::White)))
-            .column_spacing(1);
-
-            f.render_widget(table, chunks[0]);
-
-            // Simple footer
-            let footer_text = format!(
-                "Use ↑↓ to navigate, ENTER to select, 'n' for new terminal, 'q' to quit | {} terminals available",
-                shells.len()
-            );
-
-            let footer = Paragraph::new(footer_text)
-                .block(Block::default()
-                    .borders(Borders::ALL)
-                    .border_style(Style::default().fg(Color::Gray)))
-                .alignment(Alignment::Center)
-                .style(Style::default().fg(Color::White));
-
-            f.render_widget(footer, chunks[1]);
+            page_size = render_selector(f, shells, &stats, &mut table_state, theme);
         })?;
 
         // Handle input
@@ -153,48 +184,317 @@ async fn run_selector(
             if let Event::Key(key) = event::read()? {
                 if key.kind == KeyEventKind::Press {
                     match key.code {
-                        KeyCode::Up => {
-                            if selected > 0 {
-                                selected -= 1;
-                            }
-                        }
-                        KeyCode::Down => {
-                            if selected < shells.len() {
-                                selected += 1;
-                            }
-                        }
+                        KeyCode::Char(c) if c.is_ascii_digit() => nav.digit(c),
+                        KeyCode::Up => nav.up(),
+                        KeyCode::Down => nav.down(),
+                        KeyCode::PageUp => nav.page_up(page_size),
+                        KeyCode::PageDown => nav.page_down(page_size),
+                        KeyCode::Home => nav.home(),
+                        KeyCode::End => nav.end(),
                         KeyCode::Char('q') | KeyCode::Esc => {
-                            std::process::exit(0);
-                        }
-                        KeyCode::Char(c) if c.is_ascii_digit() => {
-                            let num = c.to_digit(10).unwrap() as usize;
-                            if num > 0 && num <= shells.len() {
-                                selected = num - 1;
-                            }
+                            return Ok(SelectorChoice::Quit);
                         }
                         KeyCode::Char('n') => {
                             // Jump to "Create new terminal" option
-                            selected = shells.len();
+                            nav.end();
                         }
                         KeyCode::Char('r') => {
                             // Refresh - just redraw for now
                         }
                         KeyCode::Enter => {
-                            if selected < shells.len() {
-                                return Ok(shells[selected].id);
+                            if nav.selected < shells.len() {
+                                return Ok(SelectorChoice::Existing(shells[nav.selected].id));
                             } else {
-                                // Create new terminal
-                                return Ok(sshx_core::Sid(u32::MAX));
+                                return Ok(SelectorChoice::CreateNew);
                             }
                         }
                         _ => {}
                     }
+                    if !matches!(key.code, KeyCode::Char(c) if c.is_ascii_digit()) {
+                        nav.jump.clear();
+                    }
                 }
             }
         }
     }
 }
 
+/// Keyboard navigation over the selector rows.
+///
+/// There is one row per shell, followed by the "Create new terminal" row,
+/// which is always the last row.
+#[derive(Debug)]
+struct Navigation {
+    selected: usize,
+    rows: usize,
+    /// Digits typed so far, for jumping to terminals numbered above 9.
+    jump: String,
+}
+
+impl Navigation {
+    fn new(shells: usize) -> Self {
+        Self {
+            selected: 0,
+            rows: shells + 1,
+            jump: String::new(),
+        }
+    }
+
+    fn up(&mut self) {
+        self.selected = self.selected.checked_sub(1).unwrap_or(self.rows - 1);
+    }
+
+    fn down(&mut self) {
+        self.selected = (self.selected + 1) % self.rows;
+    }
+
+    fn page_up(&mut self, page_size: usize) {
+        self.selected = self.selected.saturating_sub(page_size);
+    }
+
+    fn page_down(&mut self, page_size: usize) {
+        self.selected = (self.selected + page_size).min(self.rows - 1);
+    }
+
+    fn home(&mut self) {
+        self.selected = 0;
+    }
+
+    fn end(&mut self) {
+        self.selected = self.rows - 1;
+    }
+
+    /// Jump to a terminal by number, accumulating multiple digits.
+    fn digit(&mut self, c: char) {
+        self.jump.push(c);
+        let shells = self.rows - 1;
+        let mut number = self.jump.parse().unwrap_or(0);
+        if !(1..=shells).contains(&number) {
+            // Start a new number from this digit instead.
+            self.jump = c.to_string();
+            number = c.to_digit(10).unwrap_or(0) as usize;
+        }
+        if (1..=shells).contains(&number) {
+            self.selected = number - 1;
+        } else {
+            self.jump.clear();
+        }
+    }
+}
+
+/// Totals across all shells of the session, shown below the list.
+#[derive(Debug, Default, PartialEq)]
+struct SessionStats {
+    terminals: usize,
+    active: usize,
+    idle: usize,
+    busy: usize,
+    focused: usize,
+    bytes_sent: u64,
+    bytes_received: u64,
+    users: usize,
+}
+
+impl SessionStats {
+    fn new(shells: &[ShellInfo], users: usize) -> Self {
+        let mut stats = Self { terminals: shells.len(), users, ..Default::default() };
+        for shell in shells {
+            match shell.status {
+                TerminalStatus::Active => stats.active += 1,
+                TerminalStatus::Idle => stats.idle += 1,
+                TerminalStatus::Busy => stats.busy += 1,
+                TerminalStatus::Focused => stats.focused += 1,
+            }
+            stats.bytes_sent += shell.bytes_sent;
+            stats.bytes_received += shell.bytes_received;
+        }
+        stats
+    }
+
+    /// One-line summary, leaving out byte counts when narrow.
+    fn summary(&self, narrow: bool) -> String {
+        let users = match self.users {
+            1 => "1 user".to_string(),
+            n => format!("{n} users"),
+        };
+        if narrow {
+            return format!(" {} active, {} idle | {users} ", self.active, self.idle);
+        }
+        format!(
+            " {} terminals: {} active, {} idle, {} busy, {} focused | {} sent, {} received \
+             | {users} ",
+            self.terminals,
+            self.active,
+            self.idle,
+            self.busy,
+            self.focused,
+            format_bytes(self.bytes_sent),
+            format_bytes(self.bytes_received),
+        )
+    }
+}
+
+/// Smallest area in which the selector can show a usable list.
+const MIN_WIDTH: u16 = 20;
+const MIN_HEIGHT: u16 = 4;
+
+/// Below this width, only the essential table columns are shown.
+const NARROW_WIDTH: u16 = 50;
+
+/// Below this height, the table is drawn without borders or a footer.
+const COMPACT_HEIGHT: u16 = 10;
+
+/// Draw the selector into a frame, returning the number of visible rows.
+fn render_selector(
+    f: &mut Frame,
+    shells: &[ShellInfo],
+    stats: &SessionStats,
+    table_state: &mut TableState,
+    theme: &Theme,
+) -> usize {
+    let area = f.area();
+    if area.width < MIN_WIDTH || area.height < MIN_HEIGHT {
+        let message = Paragraph::new("Terminal too small")
+            .wrap(Wrap { trim: true })
+            .alignment(Alignment::Center)
+            .style(theme.warning);
+        f.render_widget(message, area);
+        return 1;
+    }
+    let narrow = area.width < NARROW_WIDTH;
+    let compact = area.height < COMPACT_HEIGHT;
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Min(0),                              // Table
+            Constraint::Length(if compact { 1 } else { 3 }), // Footer
+        ])
+        .split(area);
+
+    let status = |status: &TerminalStatus| match status {
+        TerminalStatus::Active => ("Active", theme.active),
+        TerminalStatus::Busy => ("Busy", theme.busy),
+        TerminalStatus::Idle => ("Idle", theme.idle),
+        TerminalStatus::Focused => ("Focused", theme.focused),
+    };
+
+    let (headers, widths): (&[&str], Vec<Constraint>) = if narrow {
+        (
+            &["#", "Title/Process", "Status"],
+            vec![
+                Constraint::Length(3), // #
+                Constraint::Min(0),    // Title
+                Constraint::Length(7), // Status
+            ],
+        )
+    } else {
+        (
+            &["#", "ID", "Title/Process", "Size", "Activity", "Status"],
+            vec![
+                Constraint::Length(3),  // #
+                Constraint::Length(4),  // ID
+                Constraint::Min(10),    // Title
+                Constraint::Length(8),  // Size
+                Constraint::Length(10), // Activity
+                Constraint::Length(10), // Status
+            ],
+        )
+    };
+
+    let header_row = Row::new(headers.iter().map(|h| Cell::from(*h).style(theme.header)));
+
+    let mut rows: Vec<Row> = shells
+        .iter()
+        .enumerate()
+        .map(|(i, shell)| {
+            let (status_text, status_style) = status(&shell.status);
+            let mut cells = vec![Cell::from((i + 1).to_string())];
+            if !narrow {
+                cells.push(Cell::from(shell.id.to_string()));
+            }
+            cells.push(Cell::from(shell.display_title().to_owned()));
+            if !narrow {
+                cells.push(Cell::from(format!("{}×{}", shell.winsize.cols, shell.winsize.rows)));
+                cells.push(Cell::from(format_duration(shell.last_activity.elapsed())));
+            }
+            cells.push(Cell::from(status_text).style(status_style));
+            Row::new(cells).style(theme.text)
+        })
+        .collect();
+
+    // Add "Create New" option
+    let ready = Cell::from("Ready").style(theme.create);
+    let create_cells = if narrow {
+        vec![Cell::from("n"), Cell::from("Create new terminal"), ready]
+    } else {
+        vec![
+            Cell::from("n"),
+            Cell::from("NEW"),
+            Cell::from("Create new terminal"),
+            Cell::from("-"),
+            Cell::from("-"),
+            ready,
+        ]
+    };
+    rows.push(Row::new(create_cells).style(theme.create));
+
+    let mut table = Table::new(rows, widths)
+        .header(header_row)
+        .column_spacing(1)
+        .row_highlight_style(theme.highlight);
+    if !compact {
+        table = table.block(
+            Block::default()
+                .title("Select Terminal")
+                .title_bottom(Line::from(stats.summary(narrow)).centered())
+                .borders(Borders::ALL)
+                .border_style(theme.border),
+        );
+    }
+
+    // Rows visible below the header, and inside the borders if shown
+    let chrome = if compact { 1 } else { 3 };
+    let page_size = (chunks[0].height.saturating_sub(chrome) as usize).max(1);
+    f.render_stateful_widget(table, chunks[0], table_state);
+
+    let footer_text = if narrow || compact {
+        format!("ENTER select, n new, q quit | {} terminals", shells.len())
+    } else {
+        format!(
+            "↑↓/PgUp/PgDn/Home/End to navigate, number to jump, ENTER to select, 'n' for new, 'q' to quit | {} terminals available",
+            shells.len()
+        )
+    };
+    let mut footer = Paragraph::new(footer_text)
+        .alignment(Alignment::Center)
+        .style(theme.text);
+    if !compact {
+        footer = footer.block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(theme.footer_border),
+        );
+    }
+    f.render_widget(footer, chunks[1]);
+
+    page_size
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{bytes} B"),
+        _ => format!("{value:.1} {}", UNITS[unit]),
+    }
+}
+
 fn format_duration(duration: std::time::Duration) -> String {
     let total_seconds = duration.as_secs();
     let hours = total_seconds / 3600;
@@ -210,3 +510,166 @@ fn format_duration(duration: std::time::Duration) -> String {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use ratatui::backend::TestBackend;
+    use ratatui::buffer::Buffer;
+
+    use super::*;
+    use crate::client::WsWinsize;
+
+    #[test]
+    fn navigation_wraps() {
+        let mut nav = Navigation::new(3);
+        nav.up();
+        assert_eq!(nav.selected, 3); // "Create new terminal"
+        nav.down();
+        assert_eq!(nav.selected, 0);
+    }
+
+    #[test]
+    fn navigation_pages() {
+        let mut nav = Navigation::new(30);
+        nav.page_down(10);
+        assert_eq!(nav.selected, 10);
+        nav.page_down(100);
+        assert_eq!(nav.selected, 30);
+        nav.page_up(10);
+        assert_eq!(nav.selected, 20);
+        nav.home();
+        assert_eq!(nav.selected, 0);
+        nav.page_up(10);
+        assert_eq!(nav.selected, 0);
+        nav.end();
+        assert_eq!(nav.selected, 30);
+    }
+
+    #[test]
+    fn navigation_number_jump() {
+        let mut nav = Navigation::new(25);
+        nav.digit('1');
+        assert_eq!(nav.selected, 0);
+        nav.digit('2');
+        assert_eq!(nav.selected, 11);
+        nav.digit('7'); // 127 is out of range, so jump to 7
+        assert_eq!(nav.selected, 6);
+
+        let mut nav = Navigation::new(3);
+        nav.digit('0');
+        assert_eq!(nav.selected, 0);
+        assert!(nav.jump.is_empty());
+    }
+
+    fn test_shells(count: u32) -> Vec<ShellInfo> {
+        (1..=count)
+            .map(|id| ShellInfo {
+                id: Sid(id),
+                winsize: WsWinsize { x: 0, y: 0, rows: 24, cols: 80 },
+                name: None,
+                title: format!("Terminal {id}"),
+                last_activity: std::time::Instant::now(),
+                bytes_sent: 0,
+                bytes_received: 0,
+                is_focused: false,
+                focused_by_users: Vec::new(),
+                status: TerminalStatus::Active,
+            })
+            .collect()
+    }
+
+    /// Render the selector at a size, returning the screen contents.
+    fn render_at(width: u16, height: u16, shells: &[ShellInfo]) -> (String, usize) {
+        let (buffer, page_size) = render_themed(width, height, shells, &Theme::dark());
+        let text = buffer.content().iter().map(|cell| cell.symbol()).collect();
+        (text, page_size)
+    }
+
+    fn render_themed(
+        width: u16,
+        height: u16,
+        shells: &[ShellInfo],
+        theme: &Theme,
+    ) -> (Buffer, usize) {
+        let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+        let mut state = TableState::default().with_selected(Some(shells.len()));
+        let mut page_size = 0;
+        terminal
+            .draw(|f| {
+                let stats = SessionStats::new(shells, 2);
+                page_size = render_selector(f, shells, &stats, &mut state, theme);
+            })
+            .unwrap();
+        (terminal.backend().buffer().clone(), page_size)
+    }
+
+    #[test]
+    fn render_sizes() {
+        let shells = test_shells(40);
+
+        let (text, page_size) = render_at(100, 30, &shells);
+        assert!(text.contains("Select Terminal"));
+        assert!(text.contains("Activity"));
+        assert!(text.contains("Create new terminal"));
+        assert_eq!(page_size, 24);
+
+        let (text, _) = render_at(40, 20, &shells);
+        assert!(!text.contains("Activity"));
+        assert!(text.contains("Create new terminal"));
+
+        let (text, page_size) = render_at(40, 6, &shells);
+        assert!(!text.contains("Select Terminal"));
+        assert!(text.contains("Create new terminal"));
+        assert_eq!(page_size, 4);
+
+        let (text, _) = render_at(15, 3, &shells);
+        assert!(text.contains("Terminal too") && text.contains("small"));
+        assert!(!text.contains("Create new terminal"));
+    }
+
+    #[test]
+    fn session_stats() {
+        let mut shells = test_shells(4);
+        shells[1].status = TerminalStatus::Idle;
+        shells[2].status = TerminalStatus::Focused;
+        shells[3].bytes_received = 3 * 1024 * 1024;
+        shells[0].bytes_sent = 512;
+        let stats = SessionStats::new(&shells, 1);
+        assert_eq!((stats.terminals, stats.active, stats.idle, stats.focused), (4, 2, 1, 1));
+        assert_eq!(
+            stats.summary(false),
+            " 4 terminals: 2 active, 1 idle, 0 busy, 1 focused | 512 B sent, 3.0 MiB received \
+             | 1 user ",
+        );
+        assert_eq!(stats.summary(true), " 2 active, 1 idle | 1 user ");
+
+        let (text, _) = render_at(100, 30, &shells);
+        assert!(text.contains("2 active, 1 idle, 0 busy, 1 focused"));
+        assert!(text.contains("| 2 users"));
+        let (text, _) = render_at(40, 6, &shells);
+        assert!(!text.contains("active"));
+    }
+
+    #[test]
+    fn theme_selection() {
+        assert_eq!(Theme::select(None, false), Theme::dark());
+        assert_eq!(Theme::select(None, true), Theme::mono());
+        assert_eq!(Theme::select(Some(ThemeName::Light), true), Theme::light());
+    }
+
+    #[test]
+    fn mono_theme_has_no_colors() {
+        let (buffer, _) = render_themed(100, 30, &test_shells(3), &Theme::mono());
+        let uncolored = |cell: &ratatui::buffer::Cell| cell.fg == Color::Reset && cell.bg == Color::Reset;
+        assert!(buffer.content().iter().all(uncolored));
+
+        let (buffer, _) = render_themed(100, 30, &test_shells(3), &Theme::dark());
+        assert!(buffer.content().iter().any(|cell| cell.bg == Color::Blue));
+    }
+
+    #[test]
+    fn restore_leaves_alternate_screen() {
+        let mut out = Vec::new();
+        restore_screen(&mut out).unwrap();
+        assert_eq!(out, b"\x1b[?1049l\x1b[?25h");
+    }
+}
@@ -38,13 +38,18 @@ pub fn parse_sshx_url(input: &str) -> Result<(String, String, String, Option<Str
         return Ok((server, session_id, key, write_password));
     }
     
-    // Try to parse with added protocol
+    // Try to parse with added protocol (only when a /s/ path is present, so
+    // that the short form "session#key" is not mistaken for a hostname)
     let with_protocol = if input.starts_with("http://") || input.starts_with("https://") {
         input.to_string()
     } else {
         format!("https://{}", input)
     };
     
+    if !input.contains("/s/") {
+        return parse_session_part(input, "sshx.io");
+    }
+
     if let Ok(url) = Url::parse(&with_protocol) {
         let server = format!("{}://{}", url.scheme(), url.host_str().unwrap_or("sshx.io"));
         let path = url.path();
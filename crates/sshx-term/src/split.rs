@@ -0,0 +1,350 @@
+//! Tiled view of several shells at once, for `--split`.
+//!
+//! Each panel keeps an emulated [`Screen`] of its shell and draws it. Keys go
+//! to the focused panel, except for commands that start with Ctrl+], as in the
+//! single-terminal view.
+
+use std::io;
+
+use anyhow::Result;
+use ratatui::{
+    backend::CrosstermBackend,
+    layout::{Constraint, Layout, Rect},
+    widgets::{Block, Paragraph},
+    Frame, Terminal,
+};
+use sshx_core::Sid;
+use tokio::io::AsyncReadExt;
+use tokio::signal;
+use tracing::debug;
+
+use crate::client::{ShellInfo, SshxClient};
+use crate::screen::Screen;
+use crate::selector::{FullScreen, Theme};
+
+/// Key that starts a split-view command.
+const PREFIX: u8 = 0x1d; // Ctrl+]
+
+/// Show shells side by side until the user quits or all of them close.
+pub async fn run_split_session(
+    client: &mut SshxClient,
+    shell_ids: &[Sid],
+    theme: &Theme,
+) -> Result<()> {
+    for &id in shell_ids {
+        client.subscribe_to_shell(id).await?;
+    }
+
+    let _screen = FullScreen::enter()?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+    let mut view = SplitView::new(shell_ids);
+    view.read_only = !client.can_write();
+    let mut sigwinch = signal::unix::signal(signal::unix::SignalKind::window_change())?;
+    let mut stdin = tokio::io::stdin();
+    let mut input = [0u8; 1024];
+    let mut keys = Keys::default();
+
+    let size = terminal.size()?;
+    view.retile(client, Rect::new(0, 0, size.width, size.height)).await;
+    loop {
+        terminal.draw(|f| view.draw(f, theme))?;
+        if view.panels.iter().all(|panel| panel.closed) {
+            debug!("All split shells closed, exiting");
+            return Ok(());
+        }
+
+        tokio::select! {
+            _ = sigwinch.recv() => {
+                terminal.autoresize()?;
+                let size = terminal.size()?;
+                view.retile(client, Rect::new(0, 0, size.width, size.height)).await;
+            }
+            result = stdin.read(&mut input) => {
+                let n = result?;
+                if n == 0 {
+                    return Ok(());
+                }
+                for action in keys.feed(&input[..n]) {
+                    match action {
+                        Action::Input(data) => {
+                            let Some(id) = view.focused_shell().filter(|_| !view.read_only) else {
+                                continue;
+                            };
+                            if let Err(e) = client.send_input(id, &data).await {
+                                debug!("Failed to send input: {e}");
+                            }
+                        }
+                        Action::Focus(index) => view.focus(index),
+                        Action::Next => view.focus_next(),
+                        Action::Quit => return Ok(()),
+                    }
+                }
+            }
+            result = client.receive_terminal_data(None) => {
+                match result? {
+                    Some((id, data)) => view.output(id, &data),
+                    None => view.sync_closed(client.shells()),
+                }
+                view.read_only = !client.can_write();
+            }
+        }
+    }
+}
+
+/// One shell shown in the split view.
+struct Panel {
+    id: Sid,
+    /// Screen of the shell, sized to the area inside the panel's border.
+    screen: Screen,
+    closed: bool,
+}
+
+/// Panels of the split view and which one has focus.
+struct SplitView {
+    panels: Vec<Panel>,
+    focused: usize,
+    tiles: Vec<Rect>,
+    /// Whether input is disabled, shown in each panel's title.
+    read_only: bool,
+}
+
+impl SplitView {
+    fn new(shell_ids: &[Sid]) -> Self {
+        let panels = shell_ids
+            .iter()
+            .map(|&id| Panel {
+                id,
+                screen: Screen::new(1, 1),
+                closed: false,
+            })
+            .collect();
+        Self { panels, focused: 0, tiles: Vec::new(), read_only: false }
+    }
+
+    /// Lay out panels in an area and resize their shells to fit.
+    async fn retile(&mut self, client: &mut SshxClient, area: Rect) {
+        self.tiles = tile(area, self.panels.len());
+        for (panel, tile) in self.panels.iter_mut().zip(&self.tiles) {
+            let inner = Block::bordered().inner(*tile);
+            let size = (inner.height.max(1), inner.width.max(1));
+            if panel.closed || panel.screen.size() == size {
+                continue;
+            }
+            panel.screen.resize(size.0, size.1);
+            if let Err(e) = client.resize_shell(panel.id, size.0, size.1).await {
+                debug!("Failed to resize shell {}: {e}", panel.id);
+            }
+        }
+    }
+
+    fn focused_shell(&self) -> Option<Sid> {
+        let panel = &self.panels[self.focused];
+        (!panel.closed).then_some(panel.id)
+    }
+
+    fn focus(&mut self, index: usize) {
+        if index < self.panels.len() {
+            self.focused = index;
+        }
+    }
+
+    /// Move focus to the next panel whose shell is still open.
+    fn focus_next(&mut self) {
+        let count = self.panels.len();
+        self.focused = (1..=count)
+            .map(|step| (self.focused + step) % count)
+            .find(|&i| !self.panels[i].closed)
+            .unwrap_or(self.focused);
+    }
+
+    fn output(&mut self, id: Sid, data: &[u8]) {
+        if let Some(panel) = self.panels.iter_mut().find(|panel| panel.id == id) {
+            panel.screen.process(data);
+        }
+    }
+
+    /// Mark panels whose shells are no longer in the session as closed.
+    fn sync_closed(&mut self, shells: &[ShellInfo]) {
+        for panel in &mut self.panels {
+            panel.closed |= !shells.iter().any(|shell| shell.id == panel.id);
+        }
+        if self.panels[self.focused].closed {
+            self.focus_next();
+        }
+    }
+
+    fn draw(&self, f: &mut Frame, theme: &Theme) {
+        for (i, (panel, tile)) in self.panels.iter().zip(&self.tiles).enumerate() {
+            let focused = i == self.focused;
+            let state = match (panel.closed, self.read_only) {
+                (true, _) => " (closed)",
+                (false, true) => " (read-only)",
+                (false, false) => "",
+            };
+            let block = Block::bordered()
+                .title(format!(" [{}] Terminal {}{state} ", i + 1, panel.id))
+                .border_style(if focused { theme.header } else { theme.border });
+            let inner = block.inner(*tile);
+            f.render_widget(Paragraph::new(panel.screen.render()).block(block), *tile);
+
+            if let Some((row, col)) = panel.screen.cursor().filter(|_| focused && !panel.closed) {
+                if row < inner.height && col < inner.width {
+                    f.set_cursor_position((inner.x + col, inner.y + row));
+                }
+            }
+        }
+    }
+}
+
+/// Split an area into a grid for `n` panels, filled row by row.
+///
+/// The grid is as square as possible, and panels in a last row that is not
+/// full are widened to use its whole width.
+fn tile(area: Rect, n: usize) -> Vec<Rect> {
+    if n == 0 {
+        return Vec::new();
+    }
+    let cols = (1..=n).find(|cols| cols * cols >= n).unwrap_or(n);
+    let rows = n.div_ceil(cols);
+    let row_areas = Layout::vertical(vec![Constraint::Ratio(1, rows as u32); rows]).split(area);
+    let mut tiles = Vec::with_capacity(n);
+    for (row, row_area) in row_areas.iter().enumerate() {
+        let count = (n - row * cols).min(cols);
+        let constraints = vec![Constraint::Ratio(1, count as u32); count];
+        tiles.extend(Layout::horizontal(constraints).split(*row_area).iter());
+    }
+    tiles
+}
+
+/// What to do in response to keys typed in the split view.
+#[derive(Debug, PartialEq, Eq)]
+enum Action {
+    /// Send input to the focused shell.
+    Input(Vec<u8>),
+    /// Focus the panel at this index.
+    Focus(usize),
+    /// Focus the next panel.
+    Next,
+    /// Leave the split view.
+    Quit,
+}
+
+/// Splits typed input into shell input and Ctrl+] commands.
+///
+/// After Ctrl+], a digit focuses that panel, Tab or `o` focuses the next one,
+/// `q` quits, and a second Ctrl+] sends Ctrl+] itself.
+#[derive(Debug, Default)]
+struct Keys {
+    prefixed: bool,
+}
+
+impl Keys {
+    fn feed(&mut self, data: &[u8]) -> Vec<Action> {
+        let mut actions = Vec::new();
+        let mut input = Vec::new();
+        for &byte in data {
+            if !std::mem::take(&mut self.prefixed) {
+                match byte {
+                    PREFIX => self.prefixed = true,
+                    _ => input.push(byte),
+                }
+                continue;
+            }
+            let action = match byte {
+                b'q' | b'Q' => Action::Quit,
+                b'\t' | b'o' => Action::Next,
+                b'1'..=b'9' => Action::Focus((byte - b'1') as usize),
+                PREFIX => {
+                    input.push(PREFIX);
+                    continue;
+                }
+                _ => continue,
+            };
+            if !input.is_empty() {
+                actions.push(Action::Input(std::mem::take(&mut input)));
+            }
+            actions.push(action);
+        }
+        if !input.is_empty() {
+            actions.push(Action::Input(input));
+        }
+        actions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::backend::TestBackend;
+    use ratatui::style::{Color, Modifier};
+
+    use super::*;
+
+    #[test]
+    fn tiles_grid() {
+        let area = Rect::new(0, 0, 100, 40);
+        assert_eq!(tile(area, 1), [area]);
+        assert_eq!(tile(area, 2), [Rect::new(0, 0, 50, 40), Rect::new(50, 0, 50, 40)]);
+
+        let tiles = tile(area, 3);
+        assert_eq!(tiles[..2], [Rect::new(0, 0, 50, 20), Rect::new(50, 0, 50, 20)]);
+        assert_eq!(tiles[2], Rect::new(0, 20, 100, 20));
+
+        assert_eq!(tile(area, 9).len(), 9);
+        assert!(tile(area, 0).is_empty());
+    }
+
+    #[test]
+    fn prefix_commands() {
+        let mut keys = Keys::default();
+        assert_eq!(keys.feed(b"ls\r"), [Action::Input(b"ls\r".to_vec())]);
+        assert_eq!(
+            keys.feed(b"a\x1d2b\x1d\x1d\x1dq"),
+            [
+                Action::Input(b"a".to_vec()),
+                Action::Focus(1),
+                Action::Input(b"b\x1d".to_vec()),
+                Action::Quit,
+            ],
+        );
+
+        // The prefix can arrive in a separate read from its command.
+        assert_eq!(keys.feed(b"\x1d"), []);
+        assert_eq!(keys.feed(b"\tx"), [Action::Next, Action::Input(b"x".to_vec())]);
+        assert_eq!(keys.feed(b"\x1dzy"), [Action::Input(b"y".to_vec())]);
+    }
+
+    #[test]
+    fn focus_skips_closed() {
+        let mut view = SplitView::new(&[Sid(1), Sid(2), Sid(3)]);
+        view.panels[1].closed = true;
+        view.focus_next();
+        assert_eq!(view.focused_shell(), Some(Sid(3)));
+        view.focus_next();
+        assert_eq!(view.focused_shell(), Some(Sid(1)));
+        view.focus(1);
+        assert_eq!(view.focused_shell(), None);
+        view.focus(7);
+        assert_eq!(view.focused, 1);
+    }
+
+    #[test]
+    fn draws_panels() {
+        let mut view = SplitView::new(&[Sid(1), Sid(4)]);
+        view.tiles = tile(Rect::new(0, 0, 40, 6), 2);
+        for panel in &mut view.panels {
+            panel.screen.resize(4, 18);
+        }
+        view.output(Sid(4), b"hello\r\n\x1b[1;31mworld\x1b[0m");
+
+        let mut terminal = Terminal::new(TestBackend::new(40, 6)).unwrap();
+        terminal.draw(|f| view.draw(f, &Theme::mono())).unwrap();
+        let buffer = terminal.backend().buffer();
+        let line = |y: u16| (0..40).map(|x| buffer[(x, y)].symbol()).collect::<String>();
+        assert!(line(0).starts_with("┌ [1] Terminal 1 "));
+        assert!(line(0).contains("┌ [2] Terminal 4 "));
+        assert!(line(1).contains("│hello"));
+        assert!(line(2).contains("│world"));
+        assert_eq!(buffer[(21, 2)].fg, Color::Indexed(1));
+        assert!(buffer[(21, 2)].modifier.contains(Modifier::BOLD));
+    }
+}
@@ -9,7 +9,11 @@ use tracing::{debug, error};
 
 use crate::client::SshxClient;
 
-pub async fn run_terminal_session(client: &mut SshxClient, shell_id: Sid) -> Result<()> {
+/// Attach to a remote shell, returning its exit code once the session ends.
+///
+/// The exit code is 0 if the host did not report one, or if the session was
+/// left without the remote shell exiting.
+pub async fn run_terminal_session(client: &mut SshxClient, shell_id: Sid) -> Result<i32> {
     // Subscribe to the shell
     client.subscribe_to_shell(shell_id).await?;
 
@@ -21,6 +25,10 @@ pub async fn run_terminal_session(client: &mut SshxClient, shell_id: Sid) -> Res
         .context("Failed to resize shell")?;
 
 
+    if !client.can_write() {
+        eprintln!("[read-only] Watching terminal {shell_id}, input is disabled (Ctrl+] q leaves)");
+    }
+
     // Enable raw mode for direct terminal control
     enable_raw_mode()?;
 
@@ -38,7 +46,8 @@ pub async fn run_terminal_session(client: &mut SshxClient, shell_id: Sid) -> Res
 
     // Return cleanup error if that failed, otherwise original result
     cleanup_result.context("Failed to restore terminal")?;
-    result
+    result?;
+    Ok(client.exit_code(shell_id).unwrap_or(0))
 }
 
 async fn run_session_loop(
@@ -94,7 +103,10 @@ async fn run_session_loop(
                             break;
                         }
 
-                        // Send input to remote shell
+                        // Send input to remote shell, unless only watching
+                        if !client.can_write() {
+                            continue;
+                        }
                         if let Err(e) = client.send_input(shell_id, data).await {
                             error!("Failed to send input: {}", e);
                             break;
@@ -0,0 +1,27 @@
+//! Share a single command in a new session, closing it once the command exits.
+//!
+//! Run with `cargo run --example share_command -- htop`.
+
+use anyhow::Result;
+use sshx::host::{share_command, ShareOptions};
+use sshx::terminal::ShellCommand;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::fmt::init();
+
+    let mut args = std::env::args().skip(1);
+    let program = args.next().unwrap_or_else(|| "top".into());
+    let command = ShellCommand {
+        args: args.collect(),
+        ..ShellCommand::from(program)
+    };
+    let server = std::env::var("SSHX_SERVER").unwrap_or_else(|_| "https://sshx.stream".into());
+
+    let mut session = share_command(&server, command, ShareOptions::default()).await?;
+    println!("Open this link to run the command: {}", session.url());
+
+    let stats = session.wait().await?;
+    println!("Command exited after {:?}", stats.duration);
+    Ok(())
+}
@@ -0,0 +1,5 @@
+//! Subcommands and live output of the `sshx` binary, kept out of `main.rs`.
+
+pub mod config;
+pub mod sessions;
+pub mod status;
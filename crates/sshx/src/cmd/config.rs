@@ -0,0 +1,220 @@
+//! The `sshx config` subcommand, for inspecting the configuration.
+
+use std::path::Path;
+
+use ansi_term::Color::Fixed;
+use anyhow::Result;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, Subcommand};
+use sshx::{
+    config::Config,
+    host::default_name,
+    share::ShareTarget,
+    terminal::{get_default_shell, TermCaps},
+};
+
+use crate::{name_from_git, style, Args};
+
+/// Actions on the configuration.
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Print the effective configuration and where each value came from,
+    /// without connecting. Secrets are redacted.
+    Show,
+}
+
+/// Print the effective value of each setting, with the source it came from.
+///
+/// Values that are derived at startup, like the default shell and session
+/// name, are resolved the same way as when opening a session.
+#[tokio::main]
+pub async fn show_config(
+    args: &Args,
+    matches: &ArgMatches,
+    file: Option<(&Path, &str)>,
+) -> Result<()> {
+    let file_keys = match file {
+        Some((_, source)) => Config::keys(source)?,
+        None => Vec::new(),
+    };
+    let source = |id: &str| match matches.value_source(id) {
+        Some(ValueSource::CommandLine) => "command line",
+        Some(ValueSource::EnvVariable) => "environment",
+        _ if file_keys.contains(&id.replace('_', "-")) => "config file",
+        _ => "default",
+    };
+
+    let shell = match &args.shell {
+        Some(shell) => shell.clone(),
+        None => get_default_shell().await,
+    };
+    let git_name = match args.name_from_git && args.name.is_none() {
+        true => name_from_git(args.cwd.as_deref()).await,
+        false => None,
+    };
+    let name = args.name.clone().or(git_name).unwrap_or_else(default_name);
+
+    match file {
+        Some((path, _)) => println!("Config file: {}\n", path.display()),
+        None => println!("Config file: none\n"),
+    }
+    for (id, value) in config_rows(args, shell, name) {
+        let key = id.replace('_', "-");
+        let value = if value.is_empty() {
+            "none".into()
+        } else {
+            value
+        };
+        let source = style(Fixed(8)).paint(format!("({})", source(id)));
+        println!("  {key:<22} {value}  {source}");
+    }
+    Ok(())
+}
+
+/// Returns the effective value of each setting that can come from the command
+/// line or the configuration file, given the resolved shell and session name.
+fn config_rows(args: &Args, shell: String, name: String) -> Vec<(&'static str, String)> {
+    let redacted = |set: bool| match set {
+        true => "<redacted>".to_string(),
+        false => "none".to_string(),
+    };
+    let or_none = |value: Option<String>| value.unwrap_or_else(|| "none".into());
+
+    let dashboard = match &args.dashboard {
+        None => "disabled".to_string(),
+        Some(None) => "new dashboard".to_string(),
+        Some(Some(_)) => "existing dashboard <redacted>".to_string(),
+    };
+    let headers = args
+        .headers
+        .iter()
+        .map(|(name, _)| format!("{name}: <redacted>"));
+    let share = args.share.as_ref().map(|target| match target {
+        ShareTarget::Mailto(_) => "mailto".to_string(),
+        ShareTarget::Webhook(_) => "webhook <redacted>".to_string(),
+    });
+    let status_file = args
+        .status_file
+        .as_ref()
+        .map(|path| path.display().to_string());
+    let term = args
+        .term
+        .clone()
+        .unwrap_or_else(|| TermCaps::from_env().term);
+    let labels = args
+        .labels
+        .iter()
+        .map(|(key, value)| format!("{key}={value}"));
+    let resolve = args.resolve.iter().map(|(host, ip)| format!("{host}:{ip}"));
+    let jump = args.jump.as_ref().map(|jump| match jump.port {
+        Some(port) => format!("{}:{port}", jump.destination),
+        None => jump.destination.clone(),
+    });
+
+    vec![
+        ("server", args.server.clone()),
+        ("shell", shell),
+        ("shell_args", format!("{:?}", args.shell_args)),
+        ("login_shell", args.login_shell.to_string()),
+        (
+            "cwd",
+            or_none(args.cwd.as_ref().map(|cwd| cwd.display().to_string())),
+        ),
+        ("init", or_none(args.init.clone())),
+        (
+            "motd",
+            or_none(
+                args.motd
+                    .as_ref()
+                    .map(|motd| motd.escape_debug().to_string()),
+            ),
+        ),
+        ("term", term),
+        (
+            "respawn",
+            or_none(args.respawn.map(|n| format!("{n} retries"))),
+        ),
+        ("respawn_always", args.respawn_always.to_string()),
+        ("keep_alive", args.keep_alive.to_string()),
+        ("name", name),
+        ("name_from_git", args.name_from_git.to_string()),
+        ("labels", labels.collect::<Vec<_>>().join(", ")),
+        ("dashboard", dashboard),
+        ("enable_readers", args.enable_readers.to_string()),
+        ("allow_spectators", args.allow_spectators.to_string()),
+        (
+            "max_clients",
+            or_none(args.max_clients.map(|n| n.to_string())),
+        ),
+        (
+            "write_window",
+            or_none(args.write_window.map(|m| format!("{m} min"))),
+        ),
+        ("write_window_renew", args.write_window_renew.to_string()),
+        ("start_locked", args.start_locked.to_string()),
+        ("share", or_none(share)),
+        ("share_write_url", args.share_write_url.to_string()),
+        (
+            "happy_eyeballs_delay",
+            format!("{} ms", args.happy_eyeballs_delay),
+        ),
+        ("ws_path_prefix", args.ws_path_prefix.clone()),
+        ("ws_subprotocol", or_none(args.ws_subprotocol.clone())),
+        ("headers", headers.collect::<Vec<_>>().join(", ")),
+        ("auth_token", redacted(args.auth_token.is_some())),
+        ("resolve", resolve.collect::<Vec<_>>().join(", ")),
+        ("jump", or_none(jump)),
+        ("insecure", args.insecure.to_string()),
+        ("session_max_age", format!("{} days", args.session_max_age)),
+        ("quiet", args.quiet.to_string()),
+        ("output", format!("{:?}", args.output).to_lowercase()),
+        ("status_file", or_none(status_file)),
+        ("no_color", args.no_color.to_string()),
+        ("no_hyperlinks", args.no_hyperlinks.to_string()),
+        ("verbose", args.verbose.to_string()),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::{CommandFactory, Parser};
+    use sshx::config::Config;
+
+    use super::config_rows;
+    use crate::Args;
+
+    #[test]
+    fn config_show_lists_every_setting() {
+        let args = Args::parse_from(["sshx"]);
+        let rows = config_rows(&args, "sh".into(), "name".into());
+        let shown = |id: &str| rows.iter().any(|(row, _)| row.replace('_', "-") == id);
+
+        // Serde lists every field of the config file when a key is unknown.
+        let err = Config::parse("not-a-key = true").unwrap_err().to_string();
+        let (_, fields) = err.split_once("expected one of").unwrap();
+        let keys: Vec<&str> = fields.split('`').skip(1).step_by(2).collect();
+        assert!(keys.contains(&"server"));
+        for key in keys {
+            assert!(
+                shown(key),
+                "config key {key} is missing from `sshx config show`"
+            );
+        }
+
+        // Flags that only trigger an action are not settings.
+        let actions = [
+            "service",
+            "test-connection",
+            "config",
+            "no-config",
+            "help",
+            "version",
+        ];
+        for arg in Args::command().get_arguments() {
+            let id = arg.get_id().as_str().replace('_', "-");
+            if !actions.contains(&id.as_str()) {
+                assert!(shown(&id), "flag {id} is missing from `sshx config show`");
+            }
+        }
+    }
+}
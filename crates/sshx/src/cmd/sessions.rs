@@ -0,0 +1,98 @@
+//! The `sshx sessions` subcommand, for sessions opened from this machine.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use clap::Subcommand;
+use sshx::{
+    connection::{connect_with_fallback, ConnectionConfig},
+    sessions::{query_owned, SavedSession, SessionStore},
+};
+use sshx_core::proto::CloseRequest;
+use tracing::warn;
+
+use crate::format_duration;
+
+/// Actions on sessions saved in the local session store.
+#[derive(Subcommand, Debug)]
+pub enum SessionsCommand {
+    /// List sessions opened from this machine that are still active.
+    List,
+    /// Close a session opened from this machine, even if its process is gone.
+    Close {
+        /// Name of the session, as shown by `sshx sessions list`.
+        name: String,
+    },
+}
+
+/// Run a `sshx sessions` command on the sessions in the local store.
+pub async fn manage_sessions(
+    command: SessionsCommand,
+    config: ConnectionConfig,
+    store: Option<SessionStore>,
+    insecure: bool,
+) -> Result<()> {
+    let store = store.context("no configuration directory to store sessions in, set $HOME")?;
+    match command {
+        SessionsCommand::List => {
+            let saved = store.active()?;
+            let mut servers: BTreeMap<&str, Vec<SavedSession>> = BTreeMap::new();
+            for session in &saved {
+                servers
+                    .entry(session.server.as_str())
+                    .or_default()
+                    .push(session.clone());
+            }
+
+            let mut active = Vec::new();
+            let mut gone = Vec::new();
+            for (server, sessions) in servers {
+                match query_owned(server, &sessions, insecure).await {
+                    Ok(owned) => {
+                        gone.extend(sessions.into_iter().filter(|saved| {
+                            !owned.iter().any(|session| session.name == saved.name)
+                        }));
+                        active.extend(owned.into_iter().map(|session| (server, session)));
+                    }
+                    Err(err) => warn!("failed to list sessions on {server}: {err:#}"),
+                }
+            }
+            // Sessions the server no longer knows about were closed or expired.
+            for saved in &gone {
+                store.mark_closed(&saved.name)?;
+            }
+
+            if active.is_empty() {
+                println!("No active sessions");
+                return Ok(());
+            }
+            println!(
+                "{:<12} {:>5} {:>6} {:>10}  SERVER",
+                "NAME", "USERS", "SHELLS", "IDLE"
+            );
+            for (server, session) in active {
+                println!(
+                    "{:<12} {:>5} {:>6} {:>10}  {server}",
+                    session.name,
+                    session.user_count,
+                    session.shell_count,
+                    format_duration(Duration::from_millis(session.idle_ms)),
+                );
+            }
+        }
+        SessionsCommand::Close { name } => {
+            let saved = store.active()?.into_iter().find(|saved| saved.name == name);
+            let saved = saved.with_context(|| format!("no saved session named {name}"))?;
+            let mut connection = connect_with_fallback(&saved.server, &saved.name, config).await?;
+            let req = CloseRequest {
+                name: saved.name.clone(),
+                token: saved.token.clone(),
+            };
+            connection.transport.close(req).await?;
+            store.mark_closed(&name)?;
+            println!("Closed session {name}");
+        }
+    }
+    Ok(())
+}
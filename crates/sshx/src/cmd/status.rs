@@ -0,0 +1,91 @@
+//! Live status line shown below the greeting, and the JSON status report.
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use ansi_term::Color::{Fixed, Green};
+use sshx::{controller::ControllerEvent, status::SessionStatus};
+use tokio_stream::{Stream, StreamExt};
+use tracing::warn;
+
+use crate::style;
+
+/// Format the live status line from the latest user count and latency.
+fn format_status(users: Option<usize>, latency: Option<Duration>) -> String {
+    let mut parts = Vec::new();
+    match users {
+        Some(1) => parts.push("1 user".to_string()),
+        Some(n) => parts.push(format!("{n} users")),
+        None => (),
+    }
+    if let Some(latency) = latency {
+        parts.push(format!("{}ms latency", latency.as_millis()));
+    }
+    if parts.is_empty() {
+        parts.push("connecting".to_string());
+    }
+    parts.join(", ")
+}
+
+/// Show a status line below the greeting, redrawn in place on every event.
+pub async fn print_status(events: impl Stream<Item = ControllerEvent>) {
+    let mut users = None;
+    let mut latency = None;
+    tokio::pin!(events);
+    while let Some(event) = events.next().await {
+        match event {
+            ControllerEvent::UsersChanged(n) => users = Some(n),
+            ControllerEvent::LatencyMeasured(d) => latency = Some(d),
+            _ => continue,
+        }
+        print!(
+            "\r\x1b[2K  {arr}  Status: {status}",
+            arr = style(Green).paint("➜"),
+            status = style(Fixed(8)).paint(format_status(users, latency)),
+        );
+        std::io::stdout().flush().ok();
+    }
+}
+
+/// Report the session status as JSON on each change, to stdout or a file.
+pub async fn report_status(
+    events: impl Stream<Item = ControllerEvent>,
+    mut status: SessionStatus,
+    stdout: bool,
+    file: Option<PathBuf>,
+) {
+    let report = |status: &SessionStatus| {
+        if stdout {
+            println!("{}", status.to_json());
+        }
+        if let Some(path) = &file {
+            if let Err(err) = status.write_to(path) {
+                warn!(path = %path.display(), %err, "failed to write status file");
+            }
+        }
+    };
+    report(&status);
+    tokio::pin!(events);
+    while let Some(event) = events.next().await {
+        if status.update(&event) {
+            report(&status);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::format_status;
+
+    #[test]
+    fn status_formatting() {
+        assert_eq!(format_status(None, None), "connecting");
+        assert_eq!(format_status(Some(1), None), "1 user");
+        let latency = Some(Duration::from_millis(42));
+        assert_eq!(format_status(Some(3), latency), "3 users, 42ms latency");
+        assert_eq!(format_status(None, latency), "42ms latency");
+    }
+}
@@ -0,0 +1,216 @@
+//! Configuration file with default values for command-line flags.
+//!
+//! The file is written in TOML, with keys that mirror the command-line flags
+//! of `sshx`. Values are applied with the following precedence, from highest
+//! to lowest: command-line flags, environment variables, the configuration
+//! file, and finally built-in defaults.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::dial::JumpHost;
+
+/// Default flag values loaded from a configuration file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    /// Address of the remote sshx server.
+    pub server: Option<String>,
+    /// Local shell command to run in the terminal.
+    pub shell: Option<String>,
+    /// Arguments passed to the shell, without re-parsing.
+    pub shell_args: Option<Vec<String>>,
+    /// Quiet mode, only prints the URL to stdout.
+    pub quiet: Option<bool>,
+    /// Session name displayed in the title.
+    pub name: Option<String>,
+    /// Name the session after the git repository and branch.
+    pub name_from_git: Option<bool>,
+    /// Labels to attach to the session, as a table of `key = "value"`.
+    pub labels: Option<BTreeMap<String, String>>,
+    /// Enable read-only access mode.
+    pub enable_readers: Option<bool>,
+    /// Enable verbose output.
+    pub verbose: Option<bool>,
+    /// Register sessions with a dashboard, optionally joining an existing key.
+    pub dashboard: Option<DashboardConfig>,
+    /// Launch the shell as a login shell.
+    pub login_shell: Option<bool>,
+    /// Working directory to start the shell in.
+    pub cwd: Option<PathBuf>,
+    /// Command to run in each new shell once it starts.
+    pub init: Option<String>,
+    /// Banner shown in each new shell, or `@PATH` to read it from a file.
+    pub motd: Option<String>,
+    /// Terminal type exported to shells as `TERM`.
+    pub term: Option<String>,
+    /// Times to replace each shell that crashed or exited with an error.
+    pub respawn: Option<u32>,
+    /// Also replace shells that exited successfully.
+    pub respawn_always: Option<bool>,
+    /// Keep the session open after the last shell exits.
+    pub keep_alive: Option<bool>,
+    /// Disable colored output.
+    pub no_color: Option<bool>,
+    /// Print links as plain text instead of OSC-8 hyperlinks.
+    pub no_hyperlinks: Option<bool>,
+    /// Path prefix of the server's WebSocket routes.
+    pub ws_path_prefix: Option<String>,
+    /// WebSocket subprotocol to request, for gateways that require one.
+    pub ws_subprotocol: Option<String>,
+    /// Head start in milliseconds for each server address when connecting.
+    pub happy_eyeballs_delay: Option<u64>,
+    /// SSH bastion to tunnel server connections through, as `[user@]host[:port]`.
+    pub jump: Option<JumpHost>,
+    /// Skip TLS certificate verification. Only for local testing.
+    pub insecure: Option<bool>,
+    /// Maximum number of viewers connected to the session at once.
+    pub max_clients: Option<u32>,
+    /// Minutes that viewers can write before the session becomes view-only.
+    pub write_window: Option<u32>,
+    /// Start the write window when each viewer joins.
+    pub write_window_renew: Option<bool>,
+    /// Days after which sessions are forgotten by `sshx sessions`.
+    pub session_max_age: Option<u64>,
+}
+
+/// Value of the `dashboard` key, either a flag or an existing dashboard key.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum DashboardConfig {
+    /// Whether to register with a newly generated dashboard.
+    Enabled(bool),
+    /// Join the existing dashboard with this key.
+    Key(String),
+}
+
+impl DashboardConfig {
+    /// Convert to the representation used by the `--dashboard` flag.
+    pub fn into_flag(self) -> Option<Option<String>> {
+        match self {
+            Self::Enabled(true) => Some(None),
+            Self::Enabled(false) => None,
+            Self::Key(key) => Some(Some(key)),
+        }
+    }
+}
+
+impl Config {
+    /// Parse a configuration from TOML source text.
+    pub fn parse(source: &str) -> Result<Self> {
+        Ok(toml::from_str(source)?)
+    }
+
+    /// Load a configuration from the file at the given path.
+    pub fn load(path: &Path) -> Result<Self> {
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read config file {}", path.display()))?;
+        Self::parse(&source).with_context(|| format!("invalid config file {}", path.display()))
+    }
+
+    /// Returns the keys whose values differ between two configuration sources,
+    /// in sorted order. Both sources must be valid configurations.
+    pub fn changed_keys(old: &str, new: &str) -> Result<Vec<String>> {
+        Self::parse(new)?;
+        let old: toml::Table = old.parse()?;
+        let new: toml::Table = new.parse()?;
+        let mut keys: Vec<String> = old.keys().chain(new.keys()).cloned().collect();
+        keys.sort();
+        keys.dedup();
+        keys.retain(|key| old.get(key) != new.get(key));
+        Ok(keys)
+    }
+
+    /// Returns the keys set in a configuration source, in sorted order.
+    pub fn keys(source: &str) -> Result<Vec<String>> {
+        let table: toml::Table = source.parse()?;
+        Ok(table.keys().cloned().collect())
+    }
+
+    /// Search for the default configuration file, returning its path if found.
+    ///
+    /// This checks `$XDG_CONFIG_HOME/sshx/config.toml` (defaulting to
+    /// `~/.config`), and then `~/.sshxrc`.
+    pub fn find() -> Option<PathBuf> {
+        let home = env::var_os("HOME").map(PathBuf::from);
+        let candidates = [
+            Self::dir().map(|dir| dir.join("config.toml")),
+            home.map(|dir| dir.join(".sshxrc")),
+        ];
+        candidates.into_iter().flatten().find(|path| path.is_file())
+    }
+
+    /// Returns the sshx configuration directory, which may not exist yet.
+    ///
+    /// This is `$XDG_CONFIG_HOME/sshx`, defaulting to `~/.config/sshx`.
+    pub fn dir() -> Option<PathBuf> {
+        let config_home = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .filter(|path| path.is_absolute())
+            .or_else(|| Some(PathBuf::from(env::var_os("HOME")?).join(".config")))?;
+        Some(config_home.join("sshx"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, DashboardConfig};
+
+    #[test]
+    fn parse_config() {
+        let config = Config::parse(
+            r#"
+            server = "https://example.com"
+            enable-readers = true
+            dashboard = "abc123"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.server.as_deref(), Some("https://example.com"));
+        assert_eq!(config.enable_readers, Some(true));
+        assert_eq!(config.dashboard, Some(DashboardConfig::Key("abc123".into())));
+        assert_eq!(config.shell, None);
+    }
+
+    #[test]
+    fn labels_table() {
+        let config = Config::parse("[labels]\nticket = \"1234\"\nteam = \"infra\"").unwrap();
+        let labels = config.labels.unwrap();
+        assert_eq!(labels.len(), 2);
+        assert_eq!(labels["ticket"], "1234");
+    }
+
+    #[test]
+    fn dashboard_flag() {
+        let config = Config::parse("dashboard = true").unwrap();
+        assert_eq!(config.dashboard.unwrap().into_flag(), Some(None));
+        let config = Config::parse("dashboard = false").unwrap();
+        assert_eq!(config.dashboard.unwrap().into_flag(), None);
+    }
+
+    #[test]
+    fn jump_host() {
+        let config = Config::parse("jump = \"admin@bastion:2222\"").unwrap();
+        assert_eq!(config.jump.unwrap().destination, "admin@bastion");
+        assert!(Config::parse("jump = \"bastion:ssh\"").is_err());
+    }
+
+    #[test]
+    fn changed_keys() {
+        let old = "name = \"a\"\nquiet = true\n[labels]\nteam = \"infra\"";
+        let new = "name = \"b\"\nquiet = true\ninit = \"ls\"\n[labels]\nteam = \"infra\"";
+        assert_eq!(Config::changed_keys(old, new).unwrap(), ["init", "name"]);
+        assert!(Config::changed_keys(old, old).unwrap().is_empty());
+        assert!(Config::changed_keys(old, "nmae = \"b\"").is_err());
+        assert_eq!(Config::keys(old).unwrap(), ["labels", "name", "quiet"]);
+    }
+
+    #[test]
+    fn unknown_key() {
+        assert!(Config::parse("sever = \"https://example.com\"").is_err());
+    }
+}
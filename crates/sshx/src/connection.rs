@@ -3,13 +3,20 @@
 //! This module provides high-level connection management that automatically
 //! attempts gRPC first, then falls back to WebSocket if gRPC fails.
 
-use anyhow::{Context, Result};
-use sshx_core::proto::OpenRequest;
+use std::net::IpAddr;
 use std::time::Duration;
-use tokio::time::timeout;
+
+use anyhow::{Context, Result};
+use sshx_core::proto::CloseRequest;
+use sshx_core::redact_url;
+use tokio::sync::mpsc;
+use tokio::time::{timeout, Instant};
 use tracing::{debug, info, warn};
 
-use crate::transport::{grpc_to_websocket_url, GrpcTransport, SshxTransport, WebSocketTransport};
+use crate::dial::Dialer;
+use crate::transport::{
+    grpc_to_websocket_url_with, GrpcTransport, SshxTransport, WebSocketTransport, WsConnectOptions,
+};
 
 /// Connection timeout for gRPC connectivity test.
 pub const GRPC_TIMEOUT: Duration = Duration::from_secs(3);
@@ -18,7 +25,7 @@ pub const GRPC_TIMEOUT: Duration = Duration::from_secs(3);
 pub const WEBSOCKET_TIMEOUT: Duration = Duration::from_secs(5);
 
 /// Connection strategy configuration.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct ConnectionConfig {
     /// Whether to enable verbose error reporting during fallback attempts.
     pub verbose_errors: bool,
@@ -26,14 +33,81 @@ pub struct ConnectionConfig {
     pub grpc_timeout: Option<Duration>,
     /// Custom timeout for WebSocket connection attempts.
     pub websocket_timeout: Option<Duration>,
+    /// Channel that receives a step-by-step report of the connection attempt.
+    pub diagnostics: Option<mpsc::UnboundedSender<Diagnostic>>,
+    /// Dialer used to open TCP connections for both transports.
+    pub dialer: Dialer,
+    /// Settings for the WebSocket fallback connection.
+    pub ws: WsConnectOptions,
+    /// Extra HTTP headers attached to requests of both transports.
+    pub headers: Vec<(String, String)>,
 }
 
-impl Default for ConnectionConfig {
-    fn default() -> Self {
-        Self {
-            verbose_errors: false,
-            grpc_timeout: None,
-            websocket_timeout: None,
+impl ConnectionConfig {
+    /// Send a diagnostic report for one step, if diagnostics are enabled.
+    fn report<T, E: std::fmt::Display>(
+        &self,
+        step: DiagnosticStep,
+        start: Instant,
+        result: &Result<T, E>,
+        detail: impl FnOnce(&T) -> String,
+    ) {
+        if let Some(tx) = &self.diagnostics {
+            let (success, detail) = match result {
+                Ok(value) => (true, detail(value)),
+                Err(err) => (false, format!("{err:#}")),
+            };
+            tx.send(Diagnostic {
+                step,
+                success,
+                detail,
+                elapsed: Some(start.elapsed()),
+            })
+            .ok();
+        }
+    }
+}
+
+/// One step of a connection attempt, reported when diagnostics are enabled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// Which step of the connection this report describes.
+    pub step: DiagnosticStep,
+    /// Whether the step succeeded.
+    pub success: bool,
+    /// Human-readable result or error message.
+    pub detail: String,
+    /// How long the step took, if it was timed.
+    pub elapsed: Option<Duration>,
+}
+
+/// Steps reported during [`connect_with_fallback`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagnosticStep {
+    /// DNS resolution of the server hostname.
+    DnsLookup,
+    /// Establishing the gRPC connection.
+    GrpcConnect,
+    /// Probing the gRPC connection with an `Open` call.
+    GrpcProbe,
+    /// WebSocket URL derived from the server address.
+    WebSocketUrl,
+    /// Establishing the WebSocket connection.
+    WebSocketConnect,
+    /// The connection method that was finally chosen.
+    MethodChosen,
+}
+
+impl DiagnosticStep {
+    /// Short label used when printing this step.
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::DnsLookup => "DNS lookup",
+            Self::GrpcConnect => "gRPC connect",
+            Self::GrpcProbe => "gRPC probe",
+            Self::WebSocketUrl => "WebSocket URL",
+            Self::WebSocketConnect => "WebSocket connect",
+            Self::MethodChosen => "Method",
         }
     }
 }
@@ -72,7 +146,8 @@ pub enum ConnectionMethod {
 ///
 /// # Behavior
 /// 1. Attempts gRPC connection with 3-second timeout
-/// 2. Tests gRPC connectivity by making an actual `Open` call
+/// 2. Tests gRPC connectivity with a `Close` call that the server rejects,
+///    which proves the RPC path works without creating a session
 /// 3. If gRPC fails, converts URL and attempts WebSocket connection
 /// 4. Returns the first successful connection method
 ///
@@ -83,7 +158,7 @@ pub enum ConnectionMethod {
 /// # async fn main() -> anyhow::Result<()> {
 /// let config = ConnectionConfig::default();
 /// let result = connect_with_fallback("https://sshx.io", "my-session", config).await?;
-/// 
+///
 /// match result.method {
 ///     ConnectionMethod::Grpc => println!("Connected via gRPC"),
 ///     ConnectionMethod::WebSocketFallback => println!("Connected via WebSocket fallback"),
@@ -96,14 +171,20 @@ pub async fn connect_with_fallback(
     session_name: &str,
     config: ConnectionConfig,
 ) -> Result<ConnectionResult> {
-    debug!(%origin, %session_name, "attempting connection with fallback");
+    debug!(origin = %redact_url(origin), %session_name, "attempting connection with fallback");
+
+    // A jump host resolves the server itself, so local DNS doesn't matter.
+    if config.diagnostics.is_some() && config.dialer.jump.is_none() {
+        resolve_host(origin, &config).await;
+    }
 
     // First, try gRPC connection
     match try_grpc_connection(origin, &config).await {
         Ok(transport) => {
             if config.verbose_errors {
-                info!(%origin, "gRPC connection successful");
+                info!(origin = %redact_url(origin), "gRPC connection successful");
             }
+            report_method(&config, &ConnectionMethod::Grpc);
             return Ok(ConnectionResult {
                 transport,
                 method: ConnectionMethod::Grpc,
@@ -111,9 +192,9 @@ pub async fn connect_with_fallback(
         }
         Err(e) => {
             if config.verbose_errors {
-                warn!(%origin, error = %e, "gRPC connection failed, attempting WebSocket fallback");
+                warn!(origin = %redact_url(origin), error = %e, "gRPC connection failed, attempting WebSocket fallback");
             } else {
-                debug!(%origin, error = %e, "gRPC connection failed, attempting WebSocket fallback");
+                debug!(origin = %redact_url(origin), error = %e, "gRPC connection failed, attempting WebSocket fallback");
             }
         }
     }
@@ -122,8 +203,9 @@ pub async fn connect_with_fallback(
     match try_websocket_connection(origin, session_name, &config).await {
         Ok(transport) => {
             if config.verbose_errors {
-                info!(%origin, "WebSocket fallback connection successful");
+                info!(origin = %redact_url(origin), "WebSocket fallback connection successful");
             }
+            report_method(&config, &ConnectionMethod::WebSocketFallback);
             Ok(ConnectionResult {
                 transport,
                 method: ConnectionMethod::WebSocketFallback,
@@ -131,7 +213,7 @@ pub async fn connect_with_fallback(
         }
         Err(e) => {
             if config.verbose_errors {
-                warn!(%origin, error = %e, "WebSocket fallback also failed");
+                warn!(origin = %redact_url(origin), error = %e, "WebSocket fallback also failed");
             }
             Err(e).context(format!(
                 "Both gRPC and WebSocket connections failed for {}",
@@ -141,62 +223,124 @@ pub async fn connect_with_fallback(
     }
 }
 
+/// Report the DNS resolution result of the server's hostname.
+async fn resolve_host(origin: &str, config: &ConnectionConfig) {
+    let start = Instant::now();
+    let result = async {
+        let url = url::Url::parse(origin).context("invalid server URL")?;
+        let host = url.host_str().context("server URL has no host")?;
+        let port = url.port_or_known_default().unwrap_or(443);
+        let addrs = config.dialer.resolve(host, port).await?;
+        Ok::<_, anyhow::Error>(addrs)
+    }
+    .await;
+    config.report(DiagnosticStep::DnsLookup, start, &result, |addrs| {
+        let addrs: Vec<_> = addrs.iter().map(|addr| addr.ip().to_string()).collect();
+        addrs.join(", ")
+    });
+}
+
+/// Report the connection method that was chosen.
+fn report_method(config: &ConnectionConfig, method: &ConnectionMethod) {
+    if let Some(tx) = &config.diagnostics {
+        let detail = match method {
+            ConnectionMethod::Grpc => "gRPC",
+            ConnectionMethod::WebSocketFallback => "WebSocket fallback",
+        };
+        tx.send(Diagnostic {
+            step: DiagnosticStep::MethodChosen,
+            success: true,
+            detail: detail.into(),
+            elapsed: None,
+        })
+        .ok();
+    }
+}
+
 /// Attempt to establish a gRPC connection and test its connectivity.
 ///
 /// This function not only connects to the gRPC endpoint but also performs
-/// a real connectivity test by attempting an `Open` call to ensure the
-/// connection is actually working.
+/// a real connectivity test by making an RPC call to ensure the connection
+/// is actually working. The probe is a `Close` request with an invalid token,
+/// so it has no side effects on the server.
 async fn try_grpc_connection(
     origin: &str,
     config: &ConnectionConfig,
 ) -> Result<Box<dyn SshxTransport>> {
     let timeout_duration = config.grpc_timeout.unwrap_or(GRPC_TIMEOUT);
-    
-    debug!(%origin, timeout_ms = timeout_duration.as_millis(), "attempting gRPC connection");
 
-    // First, test connectivity with a separate connection to avoid consuming the main transport
-    debug!(%origin, "testing gRPC connectivity with Open call");
-    let mut test_transport = timeout(timeout_duration, GrpcTransport::connect(origin))
-        .await
-        .context("gRPC connection timed out")?
-        .context("gRPC connection failed")?;
+    debug!(origin = %redact_url(origin), timeout_ms = timeout_duration.as_millis(), "attempting gRPC connection");
+
+    // First, test connectivity with a separate connection to avoid consuming the
+    // main transport
+    debug!(origin = %redact_url(origin), "testing gRPC connectivity with probe call");
+    let start = Instant::now();
+    let test_transport = timeout(
+        timeout_duration,
+        GrpcTransport::connect_with(origin, &config.dialer, &config.headers),
+    )
+    .await
+    .context("gRPC connection timed out")
+    .and_then(|result| result.context("gRPC connection failed"));
+    config.report(DiagnosticStep::GrpcConnect, start, &test_transport, |_| {
+        "connected".into()
+    });
+    let mut test_transport = test_transport?;
 
-    let test_request = OpenRequest {
-        origin: origin.to_string(),
-        encrypted_zeros: vec![0u8; 32].into(), // Dummy encrypted zeros for connectivity test
+    // Test the connection with a request that the server is expected to reject
+    let test_request = CloseRequest {
         name: "connectivity-test".to_string(),
-        write_password_hash: None,
+        token: String::new(),
+    };
+    let start = Instant::now();
+    let test_result = timeout(timeout_duration, test_transport.close(test_request))
+        .await
+        .map(|result| match result {
+            Err(e) if is_server_rejection(&e) => Ok(()),
+            result => result,
+        });
+    let probe = match &test_result {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(e)) => Err(format!("{e:#}")),
+        Err(_) => Err("timed out".into()),
     };
+    config.report(DiagnosticStep::GrpcProbe, start, &probe, |_| "ok".into());
 
-    // Test the connection with the dummy request
-    let test_result = timeout(timeout_duration, test_transport.open(test_request)).await;
-    
     match test_result {
         Ok(Ok(_)) => {
-            // Open succeeded - connection is definitely working
-            debug!(%origin, "gRPC connectivity test succeeded");
+            // The server answered the RPC - connection is definitely working
+            debug!(origin = %redact_url(origin), "gRPC connectivity test succeeded");
         }
         Ok(Err(e)) => {
             // Open failed with an error - gRPC is not working properly
-            debug!(%origin, error = %e, "gRPC connectivity test failed with error");
+            debug!(origin = %redact_url(origin), error = %e, "gRPC connectivity test failed with error");
             return Err(anyhow::anyhow!("gRPC connectivity test failed: {}", e));
         }
         Err(_) => {
             // Timeout during Open call - connection is not working properly
-            debug!(%origin, "gRPC connectivity test timed out");
+            debug!(origin = %redact_url(origin), "gRPC connectivity test timed out");
             return Err(anyhow::anyhow!("gRPC connectivity test timed out"));
         }
     }
 
     // Now create a fresh transport for actual use (don't reuse the test transport)
-    let transport = timeout(timeout_duration, GrpcTransport::connect(origin))
-        .await
-        .context("gRPC connection timed out")?
-        .context("gRPC connection failed")?;
+    let transport = timeout(
+        timeout_duration,
+        GrpcTransport::connect_with(origin, &config.dialer, &config.headers),
+    )
+    .await
+    .context("gRPC connection timed out")?
+    .context("gRPC connection failed")?;
 
     Ok(Box::new(transport))
 }
 
+/// Check whether an error is a genuine response from an sshx gRPC server.
+fn is_server_rejection(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<tonic::Status>()
+        .is_some_and(|status| status.code() == tonic::Code::Unauthenticated)
+}
+
 /// Attempt to establish a WebSocket connection.
 async fn try_websocket_connection(
     origin: &str,
@@ -204,15 +348,31 @@ async fn try_websocket_connection(
     config: &ConnectionConfig,
 ) -> Result<Box<dyn SshxTransport>> {
     let timeout_duration = config.websocket_timeout.unwrap_or(WEBSOCKET_TIMEOUT);
-    let ws_url = grpc_to_websocket_url(origin, session_name);
-    
-    debug!(%ws_url, timeout_ms = timeout_duration.as_millis(), "attempting WebSocket connection");
+    let ws_url = grpc_to_websocket_url_with(origin, session_name, &config.ws.path_prefix);
+
+    debug!(ws_url = %redact_url(&ws_url), timeout_ms = timeout_duration.as_millis(), "attempting WebSocket connection");
+    if let Some(tx) = &config.diagnostics {
+        tx.send(Diagnostic {
+            step: DiagnosticStep::WebSocketUrl,
+            success: true,
+            detail: ws_url.clone(),
+            elapsed: None,
+        })
+        .ok();
+    }
 
     // Attempt to connect with timeout
-    let transport = timeout(timeout_duration, WebSocketTransport::connect(&ws_url))
+    let start = Instant::now();
+    let connect =
+        WebSocketTransport::connect_with(&ws_url, &config.dialer, &config.ws, &config.headers);
+    let transport = timeout(timeout_duration, connect)
         .await
-        .context("WebSocket connection timed out")?
-        .context("WebSocket connection failed")?;
+        .context("WebSocket connection timed out")
+        .and_then(|result| result.context("WebSocket connection failed"));
+    config.report(DiagnosticStep::WebSocketConnect, start, &transport, |_| {
+        "connected".into()
+    });
+    let transport = transport?;
 
     Ok(Box::new(transport))
 }
@@ -229,29 +389,53 @@ async fn try_websocket_connection(
 /// # Returns
 /// `true` if gRPC connectivity is available, `false` otherwise
 pub async fn test_grpc_connectivity(origin: &str, timeout_duration: Duration) -> bool {
-    debug!(%origin, "testing gRPC connectivity");
-    
+    debug!(origin = %redact_url(origin), "testing gRPC connectivity");
+
     let result = timeout(timeout_duration, async {
         // Try to create a basic gRPC client connection
         GrpcTransport::connect(origin).await
-    }).await;
+    })
+    .await;
 
     match result {
         Ok(Ok(_)) => {
-            debug!(%origin, "gRPC connectivity test passed");
+            debug!(origin = %redact_url(origin), "gRPC connectivity test passed");
             true
         }
         Ok(Err(e)) => {
-            debug!(%origin, error = %e, "gRPC connectivity test failed");
+            debug!(origin = %redact_url(origin), error = %e, "gRPC connectivity test failed");
             false
         }
         Err(_) => {
-            debug!(%origin, "gRPC connectivity test timed out");
+            debug!(origin = %redact_url(origin), "gRPC connectivity test timed out");
             false
         }
     }
 }
 
+/// Add a scheme to a server address given without one, like `host:8051`.
+///
+/// Bare hosts use `https://`, except loopback addresses such as `localhost`,
+/// which use `http://` for local development. Addresses that already have a
+/// scheme are returned unchanged.
+pub fn normalize_server_url(server: &str) -> String {
+    let server = server.trim();
+    if server.contains("://") {
+        return server.to_string();
+    }
+    let authority = server.split(['/', '?', '#']).next().unwrap_or_default();
+    let host = match authority.strip_prefix('[') {
+        Some(rest) => rest.split(']').next().unwrap_or_default(),
+        None => authority.split(':').next().unwrap_or_default(),
+    };
+    let loopback = host.eq_ignore_ascii_case("localhost")
+        || host.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback());
+    let scheme = if loopback { "http" } else { "https" };
+    let url = format!("{scheme}://{server}");
+    debug!(server = %redact_url(&url), "inferred scheme for server address");
+    url
+}
+
 /// Create a connection configuration for verbose error reporting.
 ///
 /// This is useful for debugging connection issues or when you want
@@ -273,9 +457,9 @@ pub fn custom_timeout_config(
     websocket_timeout: Duration,
 ) -> ConnectionConfig {
     ConnectionConfig {
-        verbose_errors: false,
         grpc_timeout: Some(grpc_timeout),
         websocket_timeout: Some(websocket_timeout),
+        ..Default::default()
     }
 }
 
@@ -302,19 +486,116 @@ mod tests {
         let grpc_timeout = Duration::from_secs(5);
         let ws_timeout = Duration::from_secs(10);
         let config = custom_timeout_config(grpc_timeout, ws_timeout);
-        
+
         assert_eq!(config.grpc_timeout, Some(grpc_timeout));
         assert_eq!(config.websocket_timeout, Some(ws_timeout));
     }
 
+    #[test]
+    fn test_normalize_server_url() {
+        assert_eq!(normalize_server_url("sshx.io"), "https://sshx.io");
+        assert_eq!(
+            normalize_server_url("my.server:8051"),
+            "https://my.server:8051"
+        );
+        assert_eq!(
+            normalize_server_url(" my.server/sub "),
+            "https://my.server/sub"
+        );
+        assert_eq!(
+            normalize_server_url("localhost:8051"),
+            "http://localhost:8051"
+        );
+        assert_eq!(
+            normalize_server_url("127.0.0.1:8051"),
+            "http://127.0.0.1:8051"
+        );
+        assert_eq!(normalize_server_url("[::1]:8051"), "http://[::1]:8051");
+        assert_eq!(
+            normalize_server_url("[2001:db8::1]:8051"),
+            "https://[2001:db8::1]:8051"
+        );
+        assert_eq!(
+            normalize_server_url("http://my.server:8051"),
+            "http://my.server:8051"
+        );
+        assert_eq!(
+            normalize_server_url("https://localhost"),
+            "https://localhost"
+        );
+    }
+
     #[test]
     fn test_connection_method_equality() {
         assert_eq!(ConnectionMethod::Grpc, ConnectionMethod::Grpc);
-        assert_eq!(ConnectionMethod::WebSocketFallback, ConnectionMethod::WebSocketFallback);
+        assert_eq!(
+            ConnectionMethod::WebSocketFallback,
+            ConnectionMethod::WebSocketFallback
+        );
         assert_ne!(ConnectionMethod::Grpc, ConnectionMethod::WebSocketFallback);
     }
 
-    // Note: Testing the actual connection logic would require mocking the transport
-    // implementations, which is complex with the current design. The actual connection
-    // testing would be done through integration tests with real servers.
-}
\ No newline at end of file
+    #[tokio::test]
+    async fn test_diagnostics_report() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let config = ConnectionConfig {
+            diagnostics: Some(tx),
+            ..Default::default()
+        };
+        let result = connect_with_fallback("http://127.0.0.1:1", "test", config).await;
+        assert!(result.is_err());
+
+        let mut steps = Vec::new();
+        while let Some(diagnostic) = rx.recv().await {
+            steps.push((diagnostic.step, diagnostic.success));
+        }
+        assert_eq!(
+            steps,
+            [
+                (DiagnosticStep::DnsLookup, true),
+                (DiagnosticStep::GrpcConnect, false),
+                (DiagnosticStep::WebSocketUrl, true),
+                (DiagnosticStep::WebSocketConnect, false),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_logs_redact_fragment() {
+        use std::io::Write;
+        use std::sync::{Arc, Mutex};
+
+        #[derive(Clone, Default)]
+        struct Buffer(Arc<Mutex<Vec<u8>>>);
+        impl Write for Buffer {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::TRACE)
+            .with_writer(move || writer.clone())
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let origin = "http://127.0.0.1:1/#secretkey";
+        let result = connect_with_fallback(origin, "test", verbose_config()).await;
+        assert!(result.is_err());
+
+        let logs = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert!(logs.contains("127.0.0.1:1"), "expected connection logs");
+        assert!(!logs.contains("secretkey"), "logs leaked the URL fragment");
+    }
+
+    // Note: Testing the actual connection logic would require mocking the
+    // transport implementations, which is complex with the current design.
+    // The actual connection testing would be done through integration tests
+    // with real servers.
+}
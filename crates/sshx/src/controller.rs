@@ -2,22 +2,28 @@
 
 use std::collections::HashMap;
 use std::pin::pin;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use anyhow::{Context, Result};
 use sshx_core::proto::{
     client_update::ClientMessage, server_update::ServerMessage,
-    ClientUpdate, CloseRequest, NewShell, OpenRequest,
+    ClientUpdate, CloseRequest, NewShell, OpenRequest, ServerUpdate, TerminalData,
 };
-use sshx_core::{rand_alphanumeric, Sid};
-use tokio::sync::mpsc;
+use sshx_core::{rand_alphanumeric, redact_url, Sid, Uid};
+use tokio::sync::{broadcast, mpsc};
 use tokio::task;
 use tokio::time::{self, Duration, Instant, MissedTickBehavior};
-use tokio_stream::{wrappers::ReceiverStream, StreamExt};
+use tokio_stream::wrappers::{BroadcastStream, ReceiverStream};
+use tokio_stream::{Stream, StreamExt};
 use tracing::{debug, error, warn};
 
+use crate::dial::Dialer;
 use crate::encrypt::Encrypt;
 use crate::runner::{Runner, ShellData};
-use crate::transport::{SshxTransport, GrpcTransport, WebSocketTransport, grpc_to_websocket_url};
+use crate::transport::{
+    SshxTransport, GrpcTransport, WebSocketTransport, grpc_to_websocket_url_with,
+    WsConnectOptions,
+};
 use crate::connection::ConnectionMethod;
 
 /// Interval for sending empty heartbeat messages to the server.
@@ -26,6 +32,97 @@ const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(2);
 /// Interval to automatically reestablish connections.
 const RECONNECT_INTERVAL: Duration = Duration::from_secs(60);
 
+/// Capacity of the broadcast channel used for controller events.
+const EVENT_CAPACITY: usize = 64;
+
+/// Stream of messages received from the server over a channel.
+type ServerStream = Box<dyn Stream<Item = Result<ServerUpdate, tonic::Status>> + Send + Unpin>;
+
+/// An event emitted by the controller, for programmatic monitoring.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControllerEvent {
+    /// A streaming channel to the server was established.
+    Connected {
+        /// Transport method used for the connection.
+        method: ConnectionMethod,
+    },
+    /// A user joined the session.
+    UserJoined(Uid),
+    /// A user left the session, see [`ControllerEvent::UserJoined`].
+    UserLeft(Uid),
+    /// The number of users connected to the session changed.
+    ///
+    /// Servers report this shortly after the channel starts and on changes.
+    UsersChanged(usize),
+    /// A new shell was opened on this machine.
+    ShellOpened(Sid),
+    /// A shell on this machine was closed.
+    ShellClosed(Sid),
+    /// The process of a shell exited on its own, with its exit code if it
+    /// had one. This is not sent for shells closed from the browser.
+    ShellExited(Sid, Option<i32>),
+    /// One-way latency from the server, derived from its ping timestamps.
+    ///
+    /// This depends on clocks being roughly in sync between client and server.
+    LatencyMeasured(Duration),
+    /// The channel to the server dropped, and will be retried after a delay.
+    Disconnected,
+    /// The session was closed gracefully.
+    Closed,
+}
+
+/// Summary of a session's activity, returned when it is closed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ControllerStats {
+    /// Time since the controller opened or attached to the session.
+    pub duration: Duration,
+    /// Bytes of encrypted terminal output sent to the server.
+    pub bytes_sent: u64,
+    /// Bytes of encrypted user input received from the server.
+    pub bytes_received: u64,
+    /// Largest number of users connected at once, if the server reports it.
+    ///
+    /// This is `None` for older servers that do not send user counts, see
+    /// [`ControllerEvent::UsersChanged`].
+    pub peak_users: Option<usize>,
+    /// Number of shells opened on this machine.
+    pub shells_opened: u64,
+    /// Number of times the channel to the server dropped and was retried.
+    pub reconnects: u64,
+    /// Most recently measured latency from the server, if any.
+    pub latency: Option<Duration>,
+}
+
+/// Limits on web clients of a session, enforced by the server.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ClientLimits {
+    /// Maximum number of web clients connected at once, if limited.
+    pub max_clients: Option<u32>,
+    /// How long web clients keep write access before becoming read-only.
+    pub write_window: Option<Duration>,
+    /// Whether the write window starts when each client joins, rather than
+    /// when the session is opened.
+    pub write_window_renew: bool,
+    /// Whether the session starts locked, showing nothing to web clients until
+    /// it is unlocked through a [`LockHandle`].
+    pub start_locked: bool,
+}
+
+/// Handle to lock or unlock a session while its controller is running.
+#[derive(Clone, Debug)]
+pub struct LockHandle(mpsc::Sender<ClientMessage>);
+
+impl LockHandle {
+    /// Lock or unlock the session for web clients.
+    ///
+    /// While locked, output is held back and input is rejected. Output from
+    /// while the session was locked is never shown to viewers.
+    pub async fn set_locked(&self, locked: bool) -> Result<()> {
+        let msg = ClientMessage::Locked(locked);
+        self.0.send(msg).await.context("controller was dropped")
+    }
+}
+
 /// Handles a single session's communication with the remote server.
 pub struct Controller {
     origin: String,
@@ -37,11 +134,22 @@ pub struct Controller {
     token: String,
     url: String,
     write_url: Option<String>,
+    host_url: Option<String>,
+    /// Key-less URL for spectators, if the server accepted them.
+    spectator_url: Option<String>,
 
     /// Transport layer (gRPC or WebSocket)
     transport: Box<dyn SshxTransport>,
     /// Last successful connection method for this session
     last_connection_method: Option<ConnectionMethod>,
+    /// Channel validated by `attach()`, used by the first call to `run()`.
+    pending_channel: Option<(mpsc::Sender<ClientUpdate>, ServerStream)>,
+    /// Dialer used to open new connections when reconnecting.
+    dialer: Dialer,
+    /// Settings for WebSocket connections, used when reconnecting.
+    ws_options: WsConnectOptions,
+    /// Extra HTTP headers attached to requests when reconnecting.
+    headers: Vec<(String, String)>,
 
     /// Channels with backpressure routing messages to each shell task.
     shells_tx: HashMap<Sid, mpsc::Sender<ShellData>>,
@@ -49,6 +157,13 @@ pub struct Controller {
     output_tx: mpsc::Sender<ClientMessage>,
     /// Owned receiving end of the `output_tx` channel.
     output_rx: mpsc::Receiver<ClientMessage>,
+    /// Broadcast channel for controller events, ignored if nobody listens.
+    events_tx: broadcast::Sender<ControllerEvent>,
+
+    /// When this controller was created, for the session duration.
+    started: Instant,
+    /// Counters accumulated while processing the channel.
+    stats: ControllerStats,
 }
 
 impl Controller {
@@ -62,25 +177,39 @@ impl Controller {
         runner: Runner,
         enable_readers: bool,
     ) -> Result<Self> {
-        debug!(%origin, "connecting to server via legacy method");
+        debug!(origin = %redact_url(origin), "connecting to server via legacy method");
         
         // Create a gRPC transport for backward compatibility
         let transport = Box::new(GrpcTransport::connect(origin).await?) as Box<dyn SshxTransport>;
-        Self::with_transport(origin, name, runner, enable_readers, transport).await
+        let (limits, labels) = Default::default();
+        Self::with_transport(origin, name, runner, enable_readers, false, limits, labels, transport)
+            .await
     }
 
     /// Construct a new controller with a pre-established transport connection.
     ///
     /// This is the new preferred method that accepts any transport type,
     /// allowing for gRPC→WebSocket fallback logic to be handled externally.
+    /// The `labels` are attached to the session and shown in dashboards.
+    ///
+    /// With `allow_spectators`, an unencrypted copy of all terminal output is
+    /// also sent to the server, which relays it to anyone with the spectator
+    /// URL. This gives up end-to-end encryption of output for the session.
+    ///
+    /// The `limits` ask the server to cap how many web clients can join, and
+    /// for how long they can write.
+    #[allow(clippy::too_many_arguments)]
     pub async fn with_transport(
         origin: &str,
         name: &str,
         runner: Runner,
         enable_readers: bool,
+        allow_spectators: bool,
+        limits: ClientLimits,
+        labels: HashMap<String, String>,
         mut transport: Box<dyn SshxTransport>,
     ) -> Result<Self> {
-        debug!(%origin, transport_type = transport.connection_type(), "creating controller with transport");
+        debug!(origin = %redact_url(origin), transport_type = transport.connection_type(), "creating controller with transport");
 
         let encryption_key = rand_alphanumeric(14); // 83.3 bits of entropy
 
@@ -93,7 +222,9 @@ impl Controller {
             let write_password = rand_alphanumeric(14); // 83.3 bits of entropy
             let task = {
                 let write_password = write_password.clone();
-                task::spawn_blocking(move || Encrypt::new(&write_password))
+                task::spawn_blocking(move || {
+                    Encrypt::hash_write_token(&Encrypt::new(&write_password).zeros())
+                })
             };
             (Some(write_password), Some(task))
         } else {
@@ -102,7 +233,7 @@ impl Controller {
 
         let encrypt = kdf_task.await?;
         let write_password_hash = if let Some(task) = kdf_write_password_task {
-            Some(task.await?.zeros().into())
+            Some(task.await?.into_bytes().into())
         } else {
             None
         };
@@ -112,16 +243,34 @@ impl Controller {
             encrypted_zeros: encrypt.zeros().into(),
             name: name.into(),
             write_password_hash,
+            labels,
+            allow_spectators,
+            max_clients: limits.max_clients.unwrap_or(0),
+            write_window_secs: limits
+                .write_window
+                .map_or(0, |window| window.as_secs().try_into().unwrap_or(u32::MAX)),
+            write_window_renew: limits.write_window_renew,
+            start_locked: limits.start_locked,
         };
         
         let mut resp = transport.open(req).await?;
         resp.url = resp.url + "#" + &encryption_key;
 
+        let host_url = format!(
+            "{},{},{}",
+            resp.url,
+            write_password.as_deref().unwrap_or_default(),
+            resp.host_key,
+        );
         let write_url = if let Some(write_password) = write_password {
             Some(resp.url.clone() + "," + &write_password)
         } else {
             None
         };
+        let spectator_url = Some(resp.spectator_url).filter(|url| !url.is_empty());
+        if allow_spectators && spectator_url.is_none() {
+            warn!("server does not support spectators, ignoring --allow-spectators");
+        }
 
         let (output_tx, output_rx) = mpsc::channel(64);
         // Remember the successful connection method for reconnections
@@ -140,11 +289,101 @@ impl Controller {
             token: resp.token,
             url: resp.url,
             write_url,
+            host_url: Some(host_url),
+            spectator_url,
+            transport,
+            last_connection_method: connection_method,
+            pending_channel: None,
+            dialer: Dialer::default(),
+            ws_options: WsConnectOptions::default(),
+            headers: Vec::new(),
+            shells_tx: HashMap::new(),
+            output_tx,
+            output_rx,
+            events_tx: broadcast::channel(EVENT_CAPACITY).0,
+            started: Instant::now(),
+            stats: ControllerStats::default(),
+        })
+    }
+
+    /// Attach to an existing session instead of opening a new one.
+    ///
+    /// This requires the session name and token returned by the server when
+    /// the session was opened, along with the session URL printed at that
+    /// time. The URL carries the encryption key in its fragment, followed by
+    /// the write password if read-only mode is enabled, and the host key for
+    /// the host link. Pass the spectator URL if the session allows spectators.
+    ///
+    /// The token is validated up front by starting a channel, which is then
+    /// reused by `run()`, so this returns an error if the session no longer
+    /// exists or the token is rejected.
+    pub async fn attach(
+        origin: &str,
+        name: &str,
+        token: &str,
+        url: &str,
+        spectator_url: Option<&str>,
+        runner: Runner,
+        mut transport: Box<dyn SshxTransport>,
+    ) -> Result<Self> {
+        debug!(origin = %redact_url(origin), %name, transport_type = transport.connection_type(), "attaching to existing session");
+
+        let (base_url, fragment) = url
+            .split_once('#')
+            .context("session URL is missing the encryption key")?;
+        let mut parts = fragment.splitn(3, ',');
+        let encryption_key = parts.next().unwrap_or_default();
+        let write_password = parts.next().filter(|password| !password.is_empty());
+        let host_key = parts.next();
+
+        let encrypt = {
+            let encryption_key = encryption_key.to_string();
+            task::spawn_blocking(move || Encrypt::new(&encryption_key)).await?
+        };
+
+        let (tx, rx) = mpsc::channel(16);
+        send_msg(&tx, ClientMessage::Hello(format!("{name},{token}"))).await?;
+        let messages = transport
+            .channel(ReceiverStream::new(rx))
+            .await
+            .with_context(|| format!("failed to attach to session {name}"))?;
+
+        let (output_tx, output_rx) = mpsc::channel(64);
+        let connection_method = match transport.connection_type() {
+            "gRPC" => Some(ConnectionMethod::Grpc),
+            "WebSocket" => Some(ConnectionMethod::WebSocketFallback),
+            _ => None,
+        };
+
+        let url = format!("{base_url}#{encryption_key}");
+        let write_url = write_password.map(|write_password| format!("{url},{write_password}"));
+        let host_url = host_key.map(|host_key| {
+            format!("{url},{},{host_key}", write_password.unwrap_or_default())
+        });
+
+        Ok(Self {
+            origin: origin.into(),
+            runner,
+            encrypt,
+            encryption_key: encryption_key.into(),
+            name: name.into(),
+            token: token.into(),
+            url,
+            write_url,
+            host_url,
+            spectator_url: spectator_url.map(String::from),
             transport,
             last_connection_method: connection_method,
+            pending_channel: Some((tx, messages)),
+            dialer: Dialer::default(),
+            ws_options: WsConnectOptions::default(),
+            headers: Vec::new(),
             shells_tx: HashMap::new(),
             output_tx,
             output_rx,
+            events_tx: broadcast::channel(EVENT_CAPACITY).0,
+            started: Instant::now(),
+            stats: ControllerStats::default(),
         })
     }
 
@@ -157,22 +396,44 @@ impl Controller {
         // For reconnection, use the specific connection method that worked initially
         match &self.last_connection_method {
             Some(ConnectionMethod::Grpc) => {
-                debug!(%origin, "reconnecting via gRPC (remembered preference)");
-                Ok(Box::new(GrpcTransport::connect(origin).await?))
+                debug!(origin = %redact_url(origin), "reconnecting via gRPC (remembered preference)");
+                Ok(Box::new(GrpcTransport::connect_with(origin, &self.dialer, &self.headers).await?))
             }
             Some(ConnectionMethod::WebSocketFallback) => {
-                let ws_url = grpc_to_websocket_url(origin, session_name);
-                debug!(%ws_url, "reconnecting via WebSocket (remembered preference)");
-                Ok(Box::new(WebSocketTransport::connect(&ws_url).await?))
+                let prefix = &self.ws_options.path_prefix;
+                let ws_url = grpc_to_websocket_url_with(origin, session_name, prefix);
+                debug!(ws_url = %redact_url(&ws_url), "reconnecting via WebSocket (remembered preference)");
+                let transport = WebSocketTransport::connect_with(
+                    &ws_url,
+                    &self.dialer,
+                    &self.ws_options,
+                    &self.headers,
+                );
+                Ok(Box::new(transport.await?))
             }
             None => {
                 // Fallback to gRPC if no preference (shouldn't happen after initial connection)
-                debug!(%origin, "no remembered preference, defaulting to gRPC");
-                Ok(Box::new(GrpcTransport::connect(origin).await?))
+                debug!(origin = %redact_url(origin), "no remembered preference, defaulting to gRPC");
+                Ok(Box::new(GrpcTransport::connect_with(origin, &self.dialer, &self.headers).await?))
             }
         }
     }
 
+    /// Set the dialer used to open new connections when reconnecting.
+    pub fn set_dialer(&mut self, dialer: Dialer) {
+        self.dialer = dialer;
+    }
+
+    /// Set the WebSocket connection settings used when reconnecting.
+    pub fn set_ws_options(&mut self, options: WsConnectOptions) {
+        self.ws_options = options;
+    }
+
+    /// Set the extra HTTP headers attached to requests when reconnecting.
+    pub fn set_headers(&mut self, headers: Vec<(String, String)>) {
+        self.headers = headers;
+    }
+
     /// Returns the name of the session.
     pub fn name(&self) -> &str {
         &self.name
@@ -188,11 +449,54 @@ impl Controller {
         self.write_url.as_deref()
     }
 
+    /// Returns the URL that identifies its holder as the host, if known.
+    ///
+    /// The host can pause the session from the web interface.
+    pub fn host_url(&self) -> Option<&str> {
+        self.host_url.as_deref()
+    }
+
+    /// Returns the key-less spectator URL, if spectators are allowed.
+    pub fn spectator_url(&self) -> Option<&str> {
+        self.spectator_url.as_deref()
+    }
+
+    /// Returns the token for this session, used to attach to it later.
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
     /// Returns the encryption key for this session, hidden from the server.
     pub fn encryption_key(&self) -> &str {
         &self.encryption_key
     }
 
+    /// Returns the transport method of the most recent connection.
+    pub fn connection_method(&self) -> Option<&ConnectionMethod> {
+        self.last_connection_method.as_ref()
+    }
+
+    /// Subscribe to a stream of events emitted by this controller.
+    ///
+    /// Events are dropped if no stream is listening, and slow listeners may
+    /// miss events rather than blocking the controller.
+    pub fn events(&self) -> impl Stream<Item = ControllerEvent> + Send + 'static {
+        BroadcastStream::new(self.events_tx.subscribe()).filter_map(Result::ok)
+    }
+
+    /// Returns a handle to lock or unlock the session while it runs.
+    pub fn lock_handle(&self) -> LockHandle {
+        LockHandle(self.output_tx.clone())
+    }
+
+    /// Returns a summary of this session's activity so far.
+    pub fn stats(&self) -> ControllerStats {
+        ControllerStats {
+            duration: self.started.elapsed(),
+            ..self.stats.clone()
+        }
+    }
+
     /// Run the controller forever, listening for requests from the server.
     pub async fn run(&mut self) -> ! {
         let mut last_retry = Instant::now();
@@ -204,6 +508,8 @@ impl Controller {
                 }
                 let secs = 2_u64.pow(retries.min(4));
                 error!(%err, "disconnected, retrying in {secs}s...");
+                self.stats.reconnects += 1;
+                self.events_tx.send(ControllerEvent::Disconnected).ok();
                 time::sleep(Duration::from_secs(secs)).await;
                 retries += 1;
             }
@@ -213,15 +519,24 @@ impl Controller {
 
     /// Helper function used by `run()` that can return errors.
     async fn try_channel(&mut self) -> Result<()> {
-        let (tx, rx) = mpsc::channel(16);
+        let (tx, mut messages) = match self.pending_channel.take() {
+            Some(channel) => channel,
+            None => {
+                let (tx, rx) = mpsc::channel(16);
 
-        let hello = ClientMessage::Hello(format!("{},{}", self.name, self.token));
-        send_msg(&tx, hello).await?;
+                let hello = ClientMessage::Hello(format!("{},{}", self.name, self.token));
+                send_msg(&tx, hello).await?;
 
-        // Create a new transport connection for reconnection
-        let mut transport = self.connect_transport(&self.origin, &self.name).await?;
-        let resp = transport.channel(ReceiverStream::new(rx)).await?;
-        let mut messages = resp; // A stream of server messages.
+                // Create a new transport connection for reconnection
+                let mut transport = self.connect_transport(&self.origin, &self.name).await?;
+                let messages = transport.channel(ReceiverStream::new(rx)).await?;
+                (tx, messages) // A stream of server messages.
+            }
+        };
+        if let Some(method) = &self.last_connection_method {
+            let method = method.clone();
+            self.events_tx.send(ControllerEvent::Connected { method }).ok();
+        }
 
         let mut interval = time::interval(HEARTBEAT_INTERVAL);
         interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
@@ -234,7 +549,22 @@ impl Controller {
                 }
                 msg = self.output_rx.recv() => {
                     let msg = msg.context("unreachable: output_tx was closed?")?;
+                    let spectator_msg = match &msg {
+                        ClientMessage::Data(data) => {
+                            self.stats.bytes_sent += data.data.len() as u64;
+                            self.spectator_url.is_some().then(|| self.spectator_copy(data))
+                        }
+                        ClientMessage::ExitedShell(exit) => {
+                            let event = ControllerEvent::ShellExited(Sid(exit.id), exit.exit_code);
+                            self.events_tx.send(event).ok();
+                            None
+                        }
+                        _ => None,
+                    };
                     send_msg(&tx, msg).await?;
+                    if let Some(msg) = spectator_msg {
+                        send_msg(&tx, msg).await?;
+                    }
                     continue;
                 }
                 item = messages.next() => {
@@ -249,6 +579,7 @@ impl Controller {
 
             match message {
                 ServerMessage::Input(input) => {
+                    self.stats.bytes_received += input.data.len() as u64;
                     let data = self.encrypt.segment(0x200000000, input.offset, &input.data);
                     if let Some(sender) = self.shells_tx.get(&Sid(input.id)) {
                         // This line applies backpressure if the shell task is overloaded.
@@ -289,9 +620,28 @@ impl Controller {
                     }
                 }
                 ServerMessage::Ping(ts) => {
+                    let now = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default();
+                    let latency = now.saturating_sub(Duration::from_millis(ts));
+                    self.stats.latency = Some(latency);
+                    self.events_tx
+                        .send(ControllerEvent::LatencyMeasured(latency))
+                        .ok();
                     // Echo back the timestamp, for stateless latency measurement.
                     send_msg(&tx, ClientMessage::Pong(ts)).await?;
                 }
+                ServerMessage::UserJoined(id) => {
+                    self.events_tx.send(ControllerEvent::UserJoined(Uid(id))).ok();
+                }
+                ServerMessage::UserLeft(id) => {
+                    self.events_tx.send(ControllerEvent::UserLeft(Uid(id))).ok();
+                }
+                ServerMessage::UserCount(count) => {
+                    let count = count as usize;
+                    self.stats.peak_users = Some(self.stats.peak_users.unwrap_or(0).max(count));
+                    self.events_tx.send(ControllerEvent::UsersChanged(count)).ok();
+                }
                 ServerMessage::Error(err) => {
                     error!(?err, "error received from server");
                 }
@@ -299,15 +649,27 @@ impl Controller {
         }
     }
 
+    /// Decrypt a chunk of terminal output into a plaintext copy for spectators.
+    fn spectator_copy(&self, data: &TerminalData) -> ClientMessage {
+        let stream_num = 0x100000000 | data.id as u64;
+        let plaintext = self.encrypt.segment(stream_num, data.seq, &data.data);
+        ClientMessage::SpectatorData(TerminalData {
+            data: plaintext.into(),
+            ..*data
+        })
+    }
+
     /// Entry point to start a new terminal task on the client.
     fn spawn_shell_task(&mut self, id: Sid, center: (i32, i32)) {
         let (shell_tx, shell_rx) = mpsc::channel(16);
         let opt = self.shells_tx.insert(id, shell_tx);
         debug_assert!(opt.is_none(), "shell ID cannot be in existing tasks");
+        self.stats.shells_opened += 1;
 
         let runner = self.runner.clone();
         let encrypt = self.encrypt.clone();
         let output_tx = self.output_tx.clone();
+        let events_tx = self.events_tx.clone();
         tokio::spawn(async move {
             debug!(%id, "spawning new shell");
             let new_shell = NewShell {
@@ -319,23 +681,26 @@ impl Controller {
                 error!(%id, ?err, "failed to send shell creation message");
                 return;
             }
+            events_tx.send(ControllerEvent::ShellOpened(id)).ok();
             if let Err(err) = runner.run(id, encrypt, shell_rx, output_tx.clone()).await {
                 let err = ClientMessage::Error(err.to_string());
                 output_tx.send(err).await.ok();
             }
             output_tx.send(ClientMessage::ClosedShell(id.0)).await.ok();
+            events_tx.send(ControllerEvent::ShellClosed(id)).ok();
         });
     }
 
-    /// Terminate this session gracefully.
-    pub async fn close(&mut self) -> Result<()> {
+    /// Terminate this session gracefully, returning a summary of its activity.
+    pub async fn close(&mut self) -> Result<ControllerStats> {
         debug!("closing session");
         let req = CloseRequest {
             name: self.name.clone(),
             token: self.token.clone(),
         };
         self.transport.close(req).await?;
-        Ok(())
+        self.events_tx.send(ControllerEvent::Closed).ok();
+        Ok(self.stats())
     }
 }
 
@@ -0,0 +1,453 @@
+//! TCP dialing with happy-eyeballs address selection.
+//!
+//! Dual-stack servers resolve to both IPv6 and IPv4 addresses. Trying them one
+//! at a time can stall for seconds on a broken IPv6 route, so connections are
+//! raced instead: each address gets a short head start before the next one is
+//! dialed concurrently, alternating between address families (RFC 8305).
+//!
+//! Servers that are only reachable through an SSH bastion can be dialed with a
+//! [`JumpHost`] instead, which tunnels the connection through `ssh -W`.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::process::Stdio;
+use std::str::FromStr;
+use std::time::Duration;
+
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use serde::Deserialize;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::net::{lookup_host, TcpListener, TcpStream};
+use tokio::process::Command;
+use tokio::time;
+use tonic::transport::Uri;
+use tracing::{debug, warn};
+use url::Url;
+
+/// Default head start given to each connection attempt.
+pub const HAPPY_EYEBALLS_DELAY: Duration = Duration::from_millis(250);
+
+/// Opens TCP connections to servers, shared by all transports.
+#[derive(Debug, Clone)]
+pub struct Dialer {
+    /// Head start given to each address before the next one is also dialed.
+    pub happy_eyeballs_delay: Duration,
+    /// Static addresses for hostnames, used instead of DNS resolution.
+    ///
+    /// Only the TCP connection is redirected; TLS still uses the hostname for
+    /// SNI and certificate validation.
+    pub overrides: HashMap<String, Vec<IpAddr>>,
+    /// SSH bastion to tunnel connections through, instead of dialing directly.
+    pub jump: Option<JumpHost>,
+    /// Accept any TLS certificate from the server. Only for local testing.
+    pub insecure: bool,
+}
+
+impl Default for Dialer {
+    fn default() -> Self {
+        Self {
+            happy_eyeballs_delay: HAPPY_EYEBALLS_DELAY,
+            overrides: HashMap::new(),
+            jump: None,
+            insecure: false,
+        }
+    }
+}
+
+impl Dialer {
+    /// Resolve a hostname to this address instead of querying DNS.
+    ///
+    /// Adding several addresses for the same host races them all.
+    pub fn add_override(&mut self, host: &str, ip: IpAddr) {
+        let host = host.to_ascii_lowercase();
+        self.overrides.entry(host).or_default().push(ip);
+    }
+
+    /// Resolve a host and port to socket addresses, applying any overrides.
+    pub async fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        // IPv6 literals in URLs are bracketed, which the resolver won't accept.
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if let Some(ips) = self.overrides.get(&host.to_ascii_lowercase()) {
+            debug!(%host, ?ips, "using static resolution override");
+            return Ok(ips.iter().map(|&ip| SocketAddr::new(ip, port)).collect());
+        }
+        Ok(lookup_host((host, port)).await?.collect())
+    }
+
+    /// Connect to a host and port, racing all resolved addresses.
+    ///
+    /// With a jump host, the bastion resolves the host instead, unless it has
+    /// an override.
+    pub async fn connect(&self, host: &str, port: u16) -> io::Result<TcpStream> {
+        if let Some(jump) = &self.jump {
+            let bare = host.trim_start_matches('[').trim_end_matches(']');
+            return match self.overrides.get(&bare.to_ascii_lowercase()) {
+                Some(ips) => jump.connect(&ips[0].to_string(), port).await,
+                None => jump.connect(host, port).await,
+            };
+        }
+        let addrs = self.resolve(host, port).await?;
+        let stream = race(&addrs, self.happy_eyeballs_delay, TcpStream::connect).await?;
+        stream.set_nodelay(true)?;
+        Ok(stream)
+    }
+
+    /// Connect to the host of a URL, using the scheme's default port.
+    pub async fn connect_url(&self, url: &Url) -> io::Result<TcpStream> {
+        let host = url.host_str().ok_or_else(|| invalid_input("URL has no host"))?;
+        let port = url
+            .port_or_known_default()
+            .ok_or_else(|| invalid_input("URL has no port"))?;
+        self.connect(host, port).await
+    }
+
+    /// TLS connector for WebSocket connections, if the default is overridden.
+    pub fn ws_connector(&self) -> Option<tokio_tungstenite::Connector> {
+        self.insecure
+            .then(|| tokio_tungstenite::Connector::Rustls(crate::tls::insecure_config()))
+    }
+
+    /// Connect to the host of a URI, as used by the gRPC transport.
+    pub async fn connect_uri(&self, uri: &Uri) -> io::Result<TcpStream> {
+        let host = uri.host().ok_or_else(|| invalid_input("URI has no host"))?;
+        let port = match (uri.port_u16(), uri.scheme_str()) {
+            (Some(port), _) => port,
+            (None, Some("https")) => 443,
+            (None, _) => 80,
+        };
+        self.connect(host, port).await
+    }
+}
+
+/// An SSH bastion that connections are tunneled through with `ssh -W`.
+///
+/// The `ssh` client runs in batch mode, so it never prompts for a password or
+/// host key confirmation. Authentication must come from the SSH agent or keys
+/// in `~/.ssh/config`, and the bastion must already be in `known_hosts`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct JumpHost {
+    /// Destination passed to `ssh`, as `[user@]host`.
+    pub destination: String,
+    /// SSH port of the bastion, if not the default.
+    pub port: Option<u16>,
+}
+
+impl FromStr for JumpHost {
+    type Err = String;
+
+    /// Parse a jump host from `[user@]host[:port]`, with IPv6 hosts bracketed.
+    fn from_str(arg: &str) -> Result<Self, Self::Err> {
+        let (user, host) = match arg.rsplit_once('@') {
+            Some((user, host)) => (Some(user), host),
+            None => (None, arg),
+        };
+        let (host, port) = match host.rsplit_once(':') {
+            Some((host, port)) if !host.contains(':') || host.ends_with(']') => {
+                let port = port.parse().map_err(|_| format!("invalid SSH port: {port}"))?;
+                (host, Some(port))
+            }
+            _ => (host, None),
+        };
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if host.is_empty() || user.is_some_and(str::is_empty) {
+            return Err("expected a jump host in the form [USER@]HOST[:PORT]".into());
+        }
+        // Keep the destination from being parsed as an ssh option.
+        if arg.starts_with('-') {
+            return Err(format!("invalid jump host: {arg}"));
+        }
+        let destination = match user {
+            Some(user) => format!("{user}@{host}"),
+            None => host.into(),
+        };
+        Ok(Self { destination, port })
+    }
+}
+
+impl TryFrom<String> for JumpHost {
+    type Error = String;
+
+    fn try_from(arg: String) -> Result<Self, Self::Error> {
+        arg.parse()
+    }
+}
+
+impl JumpHost {
+    /// Build the `ssh` command that forwards its stdio to a host and port.
+    pub fn command(&self, host: &str, port: u16) -> Command {
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let target = match host.contains(':') {
+            true => format!("[{host}]:{port}"),
+            false => format!("{host}:{port}"),
+        };
+        let mut command = Command::new("ssh");
+        command.args(["-o", "BatchMode=yes", "-o", "ExitOnForwardFailure=yes"]);
+        if let Some(port) = self.port {
+            command.arg("-p").arg(port.to_string());
+        }
+        command.arg("-W").arg(target).arg("--").arg(&self.destination);
+        command
+    }
+
+    /// Open a tunnel to a host and port through the bastion.
+    ///
+    /// Transports expect a TCP stream, so the tunnel is bridged to a loopback
+    /// connection that is returned. The `ssh` process exits when it closes.
+    pub async fn connect(&self, host: &str, port: u16) -> io::Result<TcpStream> {
+        debug!(jump = %self.destination, %host, port, "opening SSH tunnel");
+        let mut child = self
+            .command(host, port)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|err| io::Error::new(err.kind(), format!("failed to run ssh: {err}")))?;
+
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+        let (stream, (mut bridge, peer)) =
+            tokio::try_join!(TcpStream::connect(listener.local_addr()?), listener.accept())?;
+        if peer != stream.local_addr()? {
+            let msg = "unexpected connection to the SSH tunnel";
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, msg));
+        }
+        stream.set_nodelay(true)?;
+
+        let stdin = child.stdin.take().expect("ssh stdin is piped");
+        let stdout = child.stdout.take().expect("ssh stdout is piped");
+        let stderr = child.stderr.take().expect("ssh stderr is piped");
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(stderr).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                warn!("ssh: {line}");
+            }
+        });
+        tokio::spawn(async move {
+            let mut tunnel = tokio::io::join(stdout, stdin);
+            if let Err(err) = tokio::io::copy_bidirectional(&mut bridge, &mut tunnel).await {
+                debug!(%err, "SSH tunnel closed");
+            }
+            drop(child);
+        });
+        Ok(stream)
+    }
+}
+
+fn invalid_input(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+/// Order addresses to alternate between families, keeping the first family
+/// returned by the resolver in front.
+pub fn interleave(addrs: &[SocketAddr]) -> Vec<SocketAddr> {
+    let Some(first) = addrs.first() else {
+        return Vec::new();
+    };
+    let (preferred, other): (Vec<SocketAddr>, Vec<SocketAddr>) = addrs
+        .iter()
+        .partition(|addr| addr.is_ipv6() == first.is_ipv6());
+    let mut ordered = Vec::with_capacity(addrs.len());
+    let (mut preferred, mut other) = (preferred.into_iter(), other.into_iter());
+    loop {
+        match (preferred.next(), other.next()) {
+            (None, None) => return ordered,
+            (a, b) => ordered.extend(a.into_iter().chain(b)),
+        }
+    }
+}
+
+/// Race connection attempts to each address, starting a new one whenever the
+/// delay passes or an attempt fails, and return the first to succeed.
+pub async fn race<T, F, Fut>(addrs: &[SocketAddr], delay: Duration, connect: F) -> io::Result<T>
+where
+    F: Fn(SocketAddr) -> Fut,
+    Fut: Future<Output = io::Result<T>>,
+{
+    let mut queue = interleave(addrs).into_iter();
+    let mut attempts = FuturesUnordered::new();
+    let mut last_err = None;
+
+    loop {
+        if attempts.is_empty() {
+            match queue.next() {
+                Some(addr) => attempts.push(attempt(addr, &connect)),
+                None => {
+                    return Err(last_err.unwrap_or_else(|| {
+                        io::Error::new(io::ErrorKind::NotFound, "no addresses to connect to")
+                    }))
+                }
+            }
+        }
+        tokio::select! {
+            Some((addr, result)) = attempts.next() => match result {
+                Ok(stream) => return Ok(stream),
+                Err(err) => {
+                    debug!(%addr, %err, "connection attempt failed");
+                    last_err = Some(err);
+                    if let Some(addr) = queue.next() {
+                        attempts.push(attempt(addr, &connect));
+                    }
+                }
+            },
+            _ = time::sleep(delay), if queue.len() > 0 => {
+                let addr = queue.next().expect("queue is not empty");
+                debug!(%addr, "starting next connection attempt");
+                attempts.push(attempt(addr, &connect));
+            }
+        }
+    }
+}
+
+async fn attempt<T, F, Fut>(addr: SocketAddr, connect: &F) -> (SocketAddr, io::Result<T>)
+where
+    F: Fn(SocketAddr) -> Fut,
+    Fut: Future<Output = io::Result<T>>,
+{
+    (addr, connect(addr).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future;
+    use std::net::SocketAddr;
+    use std::time::Duration;
+
+    use tokio::net::TcpListener;
+    use tokio::time::Instant;
+
+    use super::{interleave, race, Dialer, JumpHost};
+
+    fn addrs(list: &[&str]) -> Vec<SocketAddr> {
+        list.iter().map(|addr| addr.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn interleaves_families() {
+        let resolved = addrs(&["[::1]:80", "[::2]:80", "[::3]:80", "1.1.1.1:80", "2.2.2.2:80"]);
+        let expected = addrs(&["[::1]:80", "1.1.1.1:80", "[::2]:80", "2.2.2.2:80", "[::3]:80"]);
+        assert_eq!(interleave(&resolved), expected);
+
+        let resolved = addrs(&["1.1.1.1:80", "[::1]:80"]);
+        assert_eq!(interleave(&resolved), resolved);
+        assert!(interleave(&[]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn resolve_override() -> std::io::Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let port = listener.local_addr()?.port();
+
+        let mut dialer = Dialer::default();
+        dialer.add_override("SSHX.invalid", "127.0.0.1".parse().unwrap());
+        let addrs = dialer.resolve("sshx.invalid", port).await?;
+        assert_eq!(addrs, [listener.local_addr()?]);
+
+        let stream = dialer.connect("sshx.invalid", port).await?;
+        assert_eq!(stream.peer_addr()?, listener.local_addr()?);
+        assert!(Dialer::default().connect("sshx.invalid", port).await.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn secure_by_default() {
+        assert!(Dialer::default().ws_connector().is_none());
+        let dialer = Dialer {
+            insecure: true,
+            ..Default::default()
+        };
+        assert!(dialer.ws_connector().is_some());
+    }
+
+    #[test]
+    fn parse_jump_host() {
+        let jump: JumpHost = "admin@bastion.example.com:2222".parse().unwrap();
+        assert_eq!(jump.destination, "admin@bastion.example.com");
+        assert_eq!(jump.port, Some(2222));
+
+        let jump: JumpHost = "[2001:db8::1]".parse().unwrap();
+        assert_eq!((jump.destination.as_str(), jump.port), ("2001:db8::1", None));
+        let jump: JumpHost = "me@[2001:db8::1]:22".parse().unwrap();
+        assert_eq!((jump.destination.as_str(), jump.port), ("me@2001:db8::1", Some(22)));
+
+        assert!("bastion:ssh".parse::<JumpHost>().is_err());
+        assert!("@bastion".parse::<JumpHost>().is_err());
+        assert!("-oProxyCommand=x".parse::<JumpHost>().is_err());
+    }
+
+    #[test]
+    fn jump_command() {
+        let jump: JumpHost = "admin@bastion:2222".parse().unwrap();
+        let command = jump.command("sshx.internal", 443);
+        let command = command.as_std();
+        assert_eq!(command.get_program(), "ssh");
+        let args: Vec<_> = command.get_args().map(|arg| arg.to_str().unwrap()).collect();
+        assert_eq!(
+            args,
+            [
+                "-o", "BatchMode=yes", "-o", "ExitOnForwardFailure=yes", "-p", "2222",
+                "-W", "sshx.internal:443", "--", "admin@bastion",
+            ],
+        );
+
+        let jump: JumpHost = "bastion".parse().unwrap();
+        let command = jump.command("[::1]", 8051);
+        let args: Vec<_> = command.as_std().get_args().collect();
+        assert_eq!(args[4..], ["-W", "[::1]:8051", "--", "bastion"]);
+    }
+
+    #[tokio::test]
+    async fn falls_back_after_delay() {
+        // A stub connector where IPv6 hangs forever, like a dead route.
+        let resolved = addrs(&["[::1]:80", "1.1.1.1:80"]);
+        let start = Instant::now();
+        let winner = race(&resolved, Duration::from_millis(50), |addr| async move {
+            if addr.is_ipv6() {
+                future::pending().await
+            } else {
+                Ok(addr)
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(winner, resolved[1]);
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[tokio::test]
+    async fn prefers_first_family() {
+        let resolved = addrs(&["[::1]:80", "1.1.1.1:80"]);
+        let winner = race(&resolved, Duration::from_millis(250), |addr| async move {
+            Ok::<_, std::io::Error>(addr)
+        })
+        .await
+        .unwrap();
+        assert_eq!(winner, resolved[0]);
+    }
+
+    #[tokio::test]
+    async fn skips_failed_attempts() {
+        let resolved = addrs(&["[::1]:80", "1.1.1.1:80"]);
+        let start = Instant::now();
+        let winner = race(&resolved, Duration::from_secs(10), |addr| async move {
+            if addr.is_ipv6() {
+                Err(std::io::ErrorKind::ConnectionRefused.into())
+            } else {
+                Ok(addr)
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(winner, resolved[1]);
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        let err = race(&resolved, Duration::from_secs(10), |_| async {
+            Err::<(), _>(std::io::Error::from(std::io::ErrorKind::ConnectionRefused))
+        })
+        .await
+        .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::ConnectionRefused);
+    }
+}
@@ -40,6 +40,23 @@ impl Encrypt {
         zeros.to_vec()
     }
 
+    /// Hash a write password token for storage on the server.
+    ///
+    /// The token is the encrypted zero block of the write password, which is
+    /// what browsers present to gain write access. Rather than storing it
+    /// directly, the server receives an Argon2id hash in PHC string format with
+    /// a random salt, so a leaked hash is expensive to brute-force.
+    pub fn hash_write_token(token: &[u8]) -> String {
+        use argon2::password_hash::{PasswordHasher, SaltString};
+        use argon2::Argon2;
+        let salt = SaltString::encode_b64(sshx_core::rand_alphanumeric(16).as_bytes())
+            .expect("16-byte salt has a valid length");
+        Argon2::default()
+            .hash_password(token, &salt)
+            .expect("failed to hash write token with argon2")
+            .to_string()
+    }
+
     /// Encrypt a segment of data from a stream.
     ///
     /// Note that in CTR mode, the encryption operation is the same as the
@@ -93,6 +110,20 @@ mod tests {
         }
     }
 
+    #[test]
+    fn write_token_hash() {
+        use argon2::password_hash::{PasswordHash, PasswordVerifier};
+        let token = Encrypt::new("write password").zeros();
+        let hash = Encrypt::hash_write_token(&token);
+        assert!(hash.starts_with("$argon2id$"));
+        assert_ne!(hash, Encrypt::hash_write_token(&token), "salt should be random");
+
+        let parsed = PasswordHash::new(&hash).unwrap();
+        let argon2 = argon2::Argon2::default();
+        assert!(argon2.verify_password(&token, &parsed).is_ok());
+        assert!(argon2.verify_password(b"wrong", &parsed).is_err());
+    }
+
     #[test]
     #[should_panic]
     fn zero_stream_num() {
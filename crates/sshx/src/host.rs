@@ -0,0 +1,173 @@
+//! High-level helper for sharing a command in a new session with one call.
+//!
+//! ```no_run
+//! # async fn example() -> anyhow::Result<()> {
+//! use sshx::host::{share_command, ShareOptions};
+//! use sshx::terminal::ShellCommand;
+//!
+//! let command = ShellCommand::from("htop");
+//! let options = ShareOptions::default();
+//! let mut session = share_command("https://sshx.stream", command, options).await?;
+//! println!("Link: {}", session.url());
+//! session.wait().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use tokio_stream::StreamExt;
+
+use crate::connection::{connect_with_fallback, ConnectionConfig, ConnectionMethod};
+use crate::controller::{ClientLimits, Controller, ControllerEvent, ControllerStats, LockHandle};
+use crate::runner::Runner;
+use crate::terminal::ShellCommand;
+
+/// Options for [`share_command`], all of which have defaults.
+#[derive(Debug, Clone, Default)]
+pub struct ShareOptions {
+    /// Name shown for the session, [`default_name`] if not set.
+    pub name: Option<String>,
+    /// Generate a separate write URL, making the main URL read-only.
+    pub enable_readers: bool,
+    /// Send unencrypted output to the server for spectators.
+    pub allow_spectators: bool,
+    /// Limits on web clients, enforced by the server.
+    pub limits: ClientLimits,
+    /// Labels attached to the session and shown in dashboards.
+    pub labels: HashMap<String, String>,
+    /// How to reach the server, used again when reconnecting.
+    pub connection: ConnectionConfig,
+}
+
+/// A running session opened by [`share_command`].
+pub struct SessionHandle {
+    controller: Controller,
+    method: ConnectionMethod,
+}
+
+/// Returns the default session name, which is `user@host`.
+pub fn default_name() -> String {
+    let mut name = whoami::username();
+    if let Ok(host) = whoami::fallible::hostname() {
+        // Trim domain information like .lan or .local
+        let host = host.split('.').next().unwrap_or(&host);
+        name += "@";
+        name += host;
+    }
+    name
+}
+
+/// Open a new session on `server` where each terminal runs `command`.
+///
+/// This connects over gRPC with fallback to WebSocket, then opens the session.
+/// Nothing runs until [`SessionHandle::wait`] or [`SessionHandle::run`] is
+/// called, and terminals are only started when a web client opens one.
+pub async fn share_command(
+    server: &str,
+    command: ShellCommand,
+    options: ShareOptions,
+) -> Result<SessionHandle> {
+    let name = options.name.unwrap_or_else(default_name);
+    let dialer = options.connection.dialer.clone();
+    let ws_options = options.connection.ws.clone();
+    let headers = options.connection.headers.clone();
+
+    let connection = connect_with_fallback(server, &name, options.connection).await?;
+    let mut controller = Controller::with_transport(
+        server,
+        &name,
+        Runner::Shell(command),
+        options.enable_readers,
+        options.allow_spectators,
+        options.limits,
+        options.labels,
+        connection.transport,
+    )
+    .await?;
+    controller.set_dialer(dialer);
+    controller.set_ws_options(ws_options);
+    controller.set_headers(headers);
+
+    Ok(SessionHandle {
+        controller,
+        method: connection.method,
+    })
+}
+
+impl SessionHandle {
+    /// Returns the name of the session.
+    pub fn name(&self) -> &str {
+        self.controller.name()
+    }
+
+    /// Returns the URL of the session.
+    pub fn url(&self) -> &str {
+        self.controller.url()
+    }
+
+    /// Returns the write URL of the session, if readers are enabled.
+    pub fn write_url(&self) -> Option<&str> {
+        self.controller.write_url()
+    }
+
+    /// Returns the key-less spectator URL, if spectators are allowed.
+    pub fn spectator_url(&self) -> Option<&str> {
+        self.controller.spectator_url()
+    }
+
+    /// Returns the transport method used to open the session.
+    pub fn method(&self) -> &ConnectionMethod {
+        &self.method
+    }
+
+    /// Returns a handle to lock or unlock the session while it runs.
+    pub fn lock_handle(&self) -> LockHandle {
+        self.controller.lock_handle()
+    }
+
+    /// Returns the underlying controller, such as to subscribe to events.
+    pub fn controller(&self) -> &Controller {
+        &self.controller
+    }
+
+    /// Run the session forever, serving terminals to web clients.
+    pub async fn run(&mut self) -> ! {
+        self.controller.run().await
+    }
+
+    /// Run the session until the command has exited in every terminal that
+    /// was opened, then close it.
+    ///
+    /// This waits for at least one terminal, so it does not return before a
+    /// web client has opened the session.
+    pub async fn wait(&mut self) -> Result<ControllerStats> {
+        let mut events = Box::pin(self.controller.events());
+        let finished = async move {
+            let mut open = 0_usize;
+            while let Some(event) = events.next().await {
+                match event {
+                    ControllerEvent::ShellOpened(_) => open += 1,
+                    ControllerEvent::ShellClosed(_) => {
+                        open = open.saturating_sub(1);
+                        if open == 0 {
+                            break;
+                        }
+                    }
+                    _ => (),
+                }
+            }
+        };
+        tokio::select! {
+            _ = self.controller.run() => unreachable!(),
+            _ = finished => (),
+        }
+        self.close().await
+    }
+
+    /// Close the session gracefully, returning a summary of its activity.
+    pub async fn close(&mut self) -> Result<ControllerStats> {
+        self.controller.close().await
+    }
+}
@@ -6,10 +6,17 @@
 #![deny(unsafe_code)]
 #![warn(missing_docs)]
 
+pub mod config;
 pub mod connection;
 pub mod controller;
+pub mod dial;
 pub mod encrypt;
+pub mod host;
 pub mod runner;
 pub mod service;
+pub mod sessions;
+pub mod share;
+pub mod status;
 pub mod terminal;
+pub mod tls;
 pub mod transport;
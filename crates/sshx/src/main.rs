@@ -1,12 +1,43 @@
-use std::process::ExitCode;
+use std::collections::BTreeSet;
+use std::io::IsTerminal;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::process::{ExitCode, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 
-use ansi_term::Color::{Cyan, Fixed, Green};
-use anyhow::Result;
-use clap::Parser;
+use ansi_term::Color::{Cyan, Fixed, Green, Red};
+use ansi_term::Style;
+use anyhow::{Context, Result};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
 use serde::{Deserialize, Serialize};
-use sshx::{controller::Controller, runner::Runner, service, terminal::get_default_shell, connection::{connect_with_fallback, ConnectionConfig, verbose_config}};
+use sshx::{
+    config::Config,
+    connection::{
+        connect_with_fallback, normalize_server_url, verbose_config, ConnectionConfig, Diagnostic,
+    },
+    controller::{ClientLimits, Controller, ControllerEvent, ControllerStats},
+    dial::{Dialer, JumpHost, HAPPY_EYEBALLS_DELAY},
+    host::{default_name, share_command, ShareOptions},
+    service,
+    sessions::{SavedSession, SessionStore},
+    share::{ShareLinks, ShareTarget},
+    status::SessionStatus,
+    terminal::{get_default_shell, Respawn, ShellCommand, TermCaps},
+    transport::{parse_header, WsConnectOptions, DEFAULT_WS_PATH_PREFIX},
+};
 use tokio::signal;
-use tracing::{error, warn};
+use tokio::sync::mpsc;
+use tokio::time::{self, Instant};
+use tokio_stream::{Stream, StreamExt};
+use tracing::{error, info, warn};
+
+use crate::cmd::config::{show_config, ConfigCommand};
+use crate::cmd::sessions::{manage_sessions, SessionsCommand};
+use crate::cmd::status::{print_status, report_status};
+
+mod cmd;
 
 /// A secure web-based, collaborative terminal.
 #[derive(Parser, Debug)]
@@ -31,30 +62,139 @@ Examples:
   sshx --server https://your-server.com --dashboard --service install
   sshx --shell /bin/bash --name server1 --service install
   sshx --verbose       Show connection method and detailed debugging info
+
+Configuration:
+  Default flags are read from $XDG_CONFIG_HOME/sshx/config.toml or ~/.sshxrc,
+  with keys named after the long flags (e.g. `enable-readers = true`).
+  Precedence is: command-line flags > environment > config file > defaults.
+  Run `sshx config show` to see the resolved values and their sources.
 "
 )]
 struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
     /// Address of the remote sshx server.
     #[clap(long, default_value = "https://sshx.stream", env = "SSHX_SERVER")]
     server: String,
 
     /// Local shell command to run in the terminal.
+    ///
+    /// Defaults to $SHELL, then the login shell from /etc/passwd, then the
+    /// first of a few common shells that exists.
     #[clap(long)]
     shell: Option<String>,
 
+    /// Argument passed to the shell as-is, without re-parsing (repeatable).
+    #[clap(long = "shell-arg", value_name = "ARG", allow_hyphen_values = true)]
+    shell_args: Vec<String>,
+
+    /// Launch the shell as a login shell.
+    #[clap(long)]
+    login_shell: bool,
+
+    /// Working directory to start the shell in.
+    #[clap(long, value_name = "DIR")]
+    cwd: Option<PathBuf>,
+
+    /// Command to run in each new shell once it starts.
+    #[clap(long, value_name = "CMD")]
+    init: Option<String>,
+
+    /// Banner shown to everyone in each new shell, before the prompt. Use
+    /// `@PATH` to read it from a file. Escapes like `\e[1m` and `\n` are
+    /// interpreted in text given directly.
+    #[clap(long, value_name = "TEXT|@PATH")]
+    motd: Option<String>,
+
+    /// Terminal type exported to shells as `TERM`. Defaults to the `TERM` of
+    /// this process, or `xterm-256color` if that is unset or `dumb`, as under
+    /// a service manager. Viewers' browsers report their own capabilities for
+    /// display only, so shells always see this value.
+    #[clap(long, value_name = "TERM", env = "SSHX_TERM")]
+    term: Option<String>,
+
+    /// Start a new shell in place of one that crashed or exited with an error,
+    /// up to RETRIES times for each shell (3 if not given).
+    #[clap(long, value_name = "RETRIES", num_args = 0..=1, default_missing_value = "3")]
+    respawn: Option<u32>,
+
+    /// With `--respawn`, also replace shells that exited successfully.
+    #[clap(long, requires = "respawn")]
+    respawn_always: bool,
+
+    /// Keep the session open after the last shell exits, until interrupted.
+    /// Otherwise the session closes once the processes of all open shells
+    /// have exited, and `sshx` exits with the status of the last one.
+    #[clap(long)]
+    keep_alive: bool,
+
     /// Quiet mode, only prints the URL to stdout.
     #[clap(short, long)]
     quiet: bool,
 
+    /// Format of output on stdout. With `json`, a line with the session
+    /// status is printed whenever it changes, instead of the greeting.
+    #[clap(long, value_enum, value_name = "FORMAT", default_value_t = OutputFormat::Text)]
+    output: OutputFormat,
+
+    /// Keep the session status as JSON in this file while it runs, including
+    /// the connection method, reconnect count and latency. The file is only
+    /// readable by you, and removed when the session ends.
+    #[clap(long, value_name = "PATH")]
+    status_file: Option<PathBuf>,
+
+    /// Forget sessions in the local store used by `sshx sessions` after this
+    /// many days.
+    #[clap(long, value_name = "DAYS", default_value_t = 30)]
+    session_max_age: u64,
+
     /// Session name displayed in the title (defaults to user@hostname).
     #[clap(long)]
     name: Option<String>,
 
+    /// Name the session `repo/branch` after the git repository in the working
+    /// directory, falling back to user@hostname outside of a repository.
+    #[clap(long, conflicts_with = "name")]
+    name_from_git: bool,
+
+    /// Label to attach to the session as `key=value`, shown in dashboards
+    /// (repeatable).
+    #[clap(long = "label", value_name = "KEY=VALUE", value_parser = parse_label)]
+    labels: Vec<(String, String)>,
+
     /// Enable read-only access mode - generates separate URLs for viewers and
     /// editors.
     #[clap(long)]
     enable_readers: bool,
 
+    /// Also send terminal output to the server unencrypted, so it can be
+    /// watched from a view-only link that does not contain the key. This gives
+    /// up end-to-end encryption of output for the whole session.
+    #[clap(long)]
+    allow_spectators: bool,
+
+    /// Refuse new viewers once this many are connected to the session. Your
+    /// own `sshx` connection and key-less spectators do not count.
+    #[clap(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    max_clients: Option<u32>,
+
+    /// Make viewers read-only this many minutes after the session opens. The
+    /// change is shown in their browsers as it happens.
+    #[clap(long, value_name = "MINUTES", value_parser = clap::value_parser!(u32).range(1..))]
+    write_window: Option<u32>,
+
+    /// Start the `--write-window` when each viewer joins, instead of once for
+    /// the whole session.
+    #[clap(long)]
+    write_window_renew: bool,
+
+    /// Open the session locked, so viewers see no output and cannot type
+    /// until you send SIGUSR1 to this process. Output from before then is
+    /// never shown.
+    #[clap(long)]
+    start_locked: bool,
+
     /// Enable verbose output showing connection details and fallback attempts.
     #[clap(short, long, env = "SSHX_VERBOSE")]
     verbose: bool,
@@ -63,15 +203,303 @@ struct Args {
     #[clap(long, value_parser = ["install", "uninstall", "status", "start", "stop"])]
     service: Option<String>,
 
+    /// Disable colored output (also disabled by NO_COLOR or a non-TTY stdout).
+    #[clap(long)]
+    no_color: bool,
+
+    /// Print links as plain text instead of clickable OSC-8 hyperlinks.
+    #[clap(long)]
+    no_hyperlinks: bool,
+
+    /// Check that the server is reachable and exit, without opening a session.
+    #[clap(long)]
+    test_connection: bool,
+
+    /// Head start in milliseconds given to each server address before the
+    /// next one is also dialed, so a broken IPv6 route doesn't stall
+    /// connecting.
+    #[clap(long, value_name = "MS", default_value_t = HAPPY_EYEBALLS_DELAY.as_millis() as u64)]
+    happy_eyeballs_delay: u64,
+
+    /// Path prefix of the server's WebSocket routes, for reverse proxies that
+    /// rewrite them.
+    #[clap(long, value_name = "PREFIX", env = "SSHX_WS_PATH_PREFIX", default_value = DEFAULT_WS_PATH_PREFIX)]
+    ws_path_prefix: String,
+
+    /// WebSocket subprotocol to request, for gateways that require one.
+    #[clap(long, value_name = "PROTOCOL", env = "SSHX_WS_SUBPROTOCOL")]
+    ws_subprotocol: Option<String>,
+
+    /// Extra HTTP header sent with every request to the server, as
+    /// `"Name: Value"`, e.g. for Cloudflare Access service tokens (repeatable).
+    #[clap(long = "header", value_name = "HEADER", value_parser = parse_header)]
+    headers: Vec<(String, String)>,
+
+    /// Bearer token for servers started with `--auth-token`. This only gates
+    /// access to the server; sessions are still end-to-end encrypted.
+    #[clap(long, value_name = "TOKEN", env = "SSHX_AUTH_TOKEN")]
+    auth_token: Option<String>,
+
+    /// Connect to this address for a server hostname instead of using DNS, as
+    /// `HOST:IP`. TLS still verifies the hostname (repeatable).
+    #[clap(long = "resolve", value_name = "HOST:IP", value_parser = parse_resolve)]
+    resolve: Vec<(String, IpAddr)>,
+
+    /// Tunnel server connections through this SSH bastion, as
+    /// `[USER@]HOST[:PORT]`. Runs `ssh -W` in batch mode, so authentication
+    /// must come from the SSH agent or configured keys.
+    #[clap(long, value_name = "HOST", env = "SSHX_JUMP")]
+    jump: Option<JumpHost>,
+
+    /// Skip TLS certificate verification on all connections, for testing
+    /// against local servers with self-signed certificates. Never use this
+    /// over a real network.
+    #[clap(long)]
+    insecure: bool,
+
     /// Register this session with a dashboard.
     /// If no key provided, generates a new dashboard.
     /// If key provided, joins existing dashboard.
     #[clap(long, value_name = "KEY")]
     dashboard: Option<Option<String>>,
+
+    /// After connecting, share the session links: `mailto` or `mailto:ADDRESS`
+    /// prints a prefilled email link, and a Slack or Discord incoming webhook
+    /// URL posts a message. Only the read-only link is shared by default.
+    #[clap(long, value_name = "TARGET")]
+    share: Option<ShareTarget>,
+
+    /// Include the writable link when sharing with `--share`. Anyone who sees
+    /// the message can then type in your shell.
+    #[clap(long, requires = "share")]
+    share_write_url: bool,
+
+    /// Path to a configuration file with default flag values.
+    #[clap(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Ignore any configuration file.
+    #[clap(long, conflicts_with = "config")]
+    no_config: bool,
+}
+
+/// Subcommands, for actions other than sharing a terminal.
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Manage sessions opened from this machine.
+    #[clap(subcommand)]
+    Sessions(SessionsCommand),
+    /// Inspect the configuration.
+    #[clap(subcommand)]
+    Config(ConfigCommand),
+}
+
+/// Format of output printed to stdout.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum OutputFormat {
+    /// Greeting and live status line for people.
+    Text,
+    /// One JSON status object per line, for scripts.
+    Json,
+}
+
+impl Args {
+    /// Fill in flags that were not set on the command line or environment.
+    fn apply_config(&mut self, config: Config, matches: &ArgMatches) {
+        let unset = |id: &str| {
+            matches!(
+                matches.value_source(id),
+                None | Some(ValueSource::DefaultValue)
+            )
+        };
+        if let Some(server) = config.server.filter(|_| unset("server")) {
+            self.server = server;
+        }
+        if let Some(shell) = config.shell.filter(|_| unset("shell")) {
+            self.shell = Some(shell);
+        }
+        if let Some(shell_args) = config.shell_args.filter(|_| unset("shell_args")) {
+            self.shell_args = shell_args;
+        }
+        if let Some(quiet) = config.quiet.filter(|_| unset("quiet")) {
+            self.quiet = quiet;
+        }
+        if let Some(from_git) = config.name_from_git.filter(|_| unset("name_from_git")) {
+            self.name_from_git = from_git;
+        }
+        if let Some(name) = config.name.filter(|_| unset("name") && !self.name_from_git) {
+            self.name = Some(name);
+        }
+        if let Some(labels) = config.labels.filter(|_| unset("labels")) {
+            self.labels = labels.into_iter().collect();
+        }
+        if let Some(enable) = config.enable_readers.filter(|_| unset("enable_readers")) {
+            self.enable_readers = enable;
+        }
+        if let Some(verbose) = config.verbose.filter(|_| unset("verbose")) {
+            self.verbose = verbose;
+        }
+        if let Some(dashboard) = config.dashboard.filter(|_| unset("dashboard")) {
+            self.dashboard = dashboard.into_flag();
+        }
+        if let Some(login) = config.login_shell.filter(|_| unset("login_shell")) {
+            self.login_shell = login;
+        }
+        if let Some(cwd) = config.cwd.filter(|_| unset("cwd")) {
+            self.cwd = Some(cwd);
+        }
+        if let Some(init) = config.init.filter(|_| unset("init")) {
+            self.init = Some(init);
+        }
+        if let Some(motd) = config.motd.filter(|_| unset("motd")) {
+            self.motd = Some(motd);
+        }
+        if let Some(term) = config.term.filter(|_| unset("term")) {
+            self.term = Some(term);
+        }
+        if let Some(retries) = config.respawn.filter(|_| unset("respawn")) {
+            self.respawn = Some(retries);
+        }
+        if let Some(always) = config.respawn_always.filter(|_| unset("respawn_always")) {
+            self.respawn_always = always;
+        }
+        if let Some(keep_alive) = config.keep_alive.filter(|_| unset("keep_alive")) {
+            self.keep_alive = keep_alive;
+        }
+        if let Some(no_color) = config.no_color.filter(|_| unset("no_color")) {
+            self.no_color = no_color;
+        }
+        if let Some(no_links) = config.no_hyperlinks.filter(|_| unset("no_hyperlinks")) {
+            self.no_hyperlinks = no_links;
+        }
+        if let Some(prefix) = config.ws_path_prefix.filter(|_| unset("ws_path_prefix")) {
+            self.ws_path_prefix = prefix;
+        }
+        if let Some(protocol) = config.ws_subprotocol.filter(|_| unset("ws_subprotocol")) {
+            self.ws_subprotocol = Some(protocol);
+        }
+        if let Some(delay) = config
+            .happy_eyeballs_delay
+            .filter(|_| unset("happy_eyeballs_delay"))
+        {
+            self.happy_eyeballs_delay = delay;
+        }
+        if let Some(jump) = config.jump.filter(|_| unset("jump")) {
+            self.jump = Some(jump);
+        }
+        if let Some(insecure) = config.insecure.filter(|_| unset("insecure")) {
+            self.insecure = insecure;
+        }
+        if let Some(max) = config.max_clients.filter(|_| unset("max_clients")) {
+            self.max_clients = Some(max);
+        }
+        if let Some(minutes) = config.write_window.filter(|_| unset("write_window")) {
+            self.write_window = Some(minutes);
+        }
+        if let Some(renew) = config
+            .write_window_renew
+            .filter(|_| unset("write_window_renew"))
+        {
+            self.write_window_renew = renew;
+        }
+        if let Some(days) = config.session_max_age.filter(|_| unset("session_max_age")) {
+            self.session_max_age = days;
+        }
+    }
+}
+
+/// Parse a session label from a `key=value` argument.
+fn parse_label(arg: &str) -> Result<(String, String), String> {
+    match arg.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.into(), value.into())),
+        _ => Err("expected a label in the form KEY=VALUE".into()),
+    }
+}
+
+/// Load the banner for `--motd`, reading it from a file if prefixed by `@`.
+fn load_motd(arg: &str) -> Result<String> {
+    match arg.strip_prefix('@') {
+        Some(path) => {
+            let text = std::fs::read_to_string(path)
+                .with_context(|| format!("failed to read --motd file {path}"))?;
+            Ok(format_motd(&text, false))
+        }
+        None => Ok(format_motd(arg, true)),
+    }
+}
+
+/// Prepare a banner to be written to a shell as terminal output.
+///
+/// Line breaks become `\r\n`, since the banner does not pass through the
+/// terminal's line discipline. With `escapes`, the sequences `\e`, `\n`, `\t`
+/// and `\\` are interpreted, so that banners on the command line can be styled.
+fn format_motd(text: &str, escapes: bool) -> String {
+    let text = text.replace("\r\n", "\n");
+    let mut motd = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if escapes => match chars.next() {
+                Some('e') => motd.push('\x1b'),
+                Some('n') => motd.push_str("\r\n"),
+                Some('t') => motd.push('\t'),
+                Some('\\') => motd.push('\\'),
+                Some(c) => {
+                    motd.push('\\');
+                    motd.push(c);
+                }
+                None => motd.push('\\'),
+            },
+            '\n' => motd.push_str("\r\n"),
+            c => motd.push(c),
+        }
+    }
+    if !motd.is_empty() && !motd.ends_with("\r\n") {
+        motd.push_str("\r\n");
+    }
+    motd
+}
+
+/// Parse a static resolution from a `host:ip` argument.
+fn parse_resolve(arg: &str) -> Result<(String, IpAddr), String> {
+    let (host, ip) = arg
+        .split_once(':')
+        .filter(|(host, _)| !host.is_empty())
+        .ok_or("expected a resolution in the form HOST:IP")?;
+    let ip = ip.trim_start_matches('[').trim_end_matches(']');
+    let ip = ip
+        .parse()
+        .map_err(|_| format!("invalid IP address: {ip}"))?;
+    Ok((host.into(), ip))
+}
+
+/// Whether ANSI colors and styles are enabled for printed output.
+static COLOR_ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Returns the given style, or a plain style if colors are disabled.
+fn style(style: impl Into<Style>) -> Style {
+    if COLOR_ENABLED.load(Ordering::Relaxed) {
+        style.into()
+    } else {
+        Style::new()
+    }
+}
+
+/// Whether URLs are printed as OSC-8 hyperlinks.
+static HYPERLINKS_ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Style a URL as a link, wrapped in an OSC-8 hyperlink if enabled.
+fn hyperlink(url: &str) -> String {
+    let text = style(Cyan.underline()).paint(url);
+    if HYPERLINKS_ENABLED.load(Ordering::Relaxed) {
+        format!("\x1b]8;;{url}\x1b\\{text}\x1b]8;;\x1b\\")
+    } else {
+        text.to_string()
+    }
 }
 
 /// Dashboard registration request payload
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
 struct RegisterDashboardRequest {
     session_name: String,
@@ -96,9 +524,15 @@ struct DashboardInfo {
     url: String,
 }
 
-/// Extract relative URL from full URL (removes domain for reverse proxy compatibility)
+/// Extract relative URL from full URL (removes domain for reverse proxy
+/// compatibility)
+///
+/// The server builds session URLs from its `public_base_url` option, so any
+/// subpath it is mounted under is kept in the relative path, and the dashboard
+/// resolves it against its own origin.
 fn make_relative_url(full_url: &str) -> String {
-    if let Ok(url) = url::Url::parse(full_url) {
+    let full_url = full_url.trim();
+    let relative = if let Ok(url) = url::Url::parse(full_url) {
         // Return path + query + fragment for reverse proxy compatibility
         let mut relative = url.path().to_string();
         if let Some(query) = url.query() {
@@ -113,44 +547,68 @@ fn make_relative_url(full_url: &str) -> String {
     } else {
         // If parsing fails, assume it's already relative
         full_url.to_string()
-    }
+    };
+
+    // Always return exactly one leading slash, so that the result can never be
+    // read as a protocol-relative URL like `//evil.com` by the dashboard.
+    let path = relative.trim_start_matches(['/', '\\']);
+    format!("/{path}")
 }
 
-/// Register session with the dashboard
-async fn register_with_dashboard(
-    server_url: &str,
+/// Build the request that registers a session with a dashboard.
+fn dashboard_request(
     controller: &Controller,
     display_name: &str,
     dashboard_key: Option<String>,
-) -> Result<DashboardInfo> {
-    let dashboard_url = format!("{}/api/dashboards/register", server_url);
-
-    let request = RegisterDashboardRequest {
+) -> RegisterDashboardRequest {
+    RegisterDashboardRequest {
         session_name: controller.name().to_string(),
         url: make_relative_url(controller.url()),
         write_url: controller.write_url().map(make_relative_url),
         display_name: display_name.to_string(),
         dashboard_key,
-    };
+    }
+}
+
+/// Register session with the dashboard
+async fn register_with_dashboard(
+    server_url: &str,
+    request: &RegisterDashboardRequest,
+    insecure: bool,
+) -> Result<DashboardInfo> {
+    let dashboard_url = format!("{}/api/dashboards/register", server_url);
 
-    let client = reqwest::Client::new();
-    let response = client.post(&dashboard_url).json(&request).send().await?;
+    let client = reqwest::Client::builder()
+        .danger_accept_invalid_certs(insecure)
+        .build()?;
+    let response = client.post(&dashboard_url).json(request).send().await?;
 
     if response.status().is_success() {
         let response_data: RegisterDashboardResponse = response.json().await?;
-        println!("\n  {} Session registered to dashboard", Green.paint("✓"));
-        
+        println!(
+            "\n  {} Session registered to dashboard",
+            style(Green).paint("✓")
+        );
+
         Ok(DashboardInfo {
             key: response_data.dashboard_key,
             url: response_data.dashboard_url,
         })
     } else {
         warn!("Failed to register with dashboard: {}", response.status());
-        Err(anyhow::anyhow!("Dashboard registration failed with status: {}", response.status()))
+        Err(anyhow::anyhow!(
+            "Dashboard registration failed with status: {}",
+            response.status()
+        ))
     }
 }
 
-fn print_greeting(shell: &str, controller: &Controller, connection_method: &sshx::connection::ConnectionMethod, dashboard_info: Option<&DashboardInfo>) {
+fn print_greeting(
+    shell: &str,
+    controller: &Controller,
+    connection_method: &sshx::connection::ConnectionMethod,
+    dashboard_info: Option<&DashboardInfo>,
+) {
     let version_str = match option_env!("CARGO_PKG_VERSION") {
         Some(version) => format!("v{version}"),
         None => String::from("[dev]"),
@@ -159,91 +617,437 @@ fn print_greeting(shell: &str, controller: &Controller, connection_method: &sshx
         sshx::connection::ConnectionMethod::Grpc => "gRPC",
         sshx::connection::ConnectionMethod::WebSocketFallback => "WebSocket",
     };
-    if let Some(write_url) = controller.write_url() {
-        if let Some(dashboard) = dashboard_info {
+
+    let mut rows = Vec::new();
+    match controller.write_url() {
+        Some(write_url) => {
+            rows.push(("Read-only link:", hyperlink(controller.url())));
+            rows.push(("Writable link:", hyperlink(write_url)));
+        }
+        None => rows.push(("Link:", hyperlink(controller.url()))),
+    }
+    let host_url = controller.host_url().unwrap_or_default();
+    rows.push(("Host link:", hyperlink(host_url)));
+    if let Some(dashboard) = dashboard_info {
+        rows.push(("Dashboard:", hyperlink(&dashboard.url)));
+        let key = style(Fixed(8)).paint(&dashboard.key);
+        rows.push(("Dashboard ID:", key.to_string()));
+    }
+    rows.push(("Shell:", style(Fixed(8)).paint(shell).to_string()));
+    rows.push((
+        "Transport:",
+        style(Fixed(8)).paint(transport_str).to_string(),
+    ));
+
+    // Align the values of all rows after the longest label.
+    let width = rows.iter().map(|(label, _)| label.len()).max().unwrap_or(0);
+    println!(
+        "\n  {sshx} {version}\n",
+        sshx = style(Green.bold()).paint("sshx"),
+        version = style(Green).paint(&version_str),
+    );
+    for (label, value) in rows {
+        println!(
+            "  {arr}  {label:<width$} {value}",
+            arr = style(Green).paint("➜")
+        );
+    }
+    println!();
+}
+
+/// Share the session links with the target given to `--share`.
+async fn share_session(target: &ShareTarget, controller: &Controller, write: bool) -> Result<()> {
+    let links = ShareLinks::select(controller.url(), controller.write_url(), write)?;
+    match target {
+        ShareTarget::Mailto(to) => {
+            let mailto = links.mailto(to.as_deref(), controller.name());
             println!(
-                r#"
-  {sshx} {version}
-
-  {arr}  Read-only link: {link_v}
-  {arr}  Writable link:  {link_e}
-  {arr}  Dashboard:      {dashboard_url_v}
-  {arr}  Dashboard ID:   {dashboard_id_v}
-  {arr}  Shell:          {shell_v}
-  {arr}  Transport:      {transport_v}
-"#,
-                sshx = Green.bold().paint("sshx"),
-                version = Green.paint(&version_str),
-                arr = Green.paint("➜"),
-                link_v = Cyan.underline().paint(controller.url()),
-                link_e = Cyan.underline().paint(write_url),
-                dashboard_url_v = Cyan.underline().paint(&dashboard.url),
-                dashboard_id_v = Fixed(8).paint(&dashboard.key),
-                shell_v = Fixed(8).paint(shell),
-                transport_v = Fixed(8).paint(transport_str),
+                "  {} Share by email: {}\n",
+                style(Green).paint("➜"),
+                hyperlink(&mailto)
             );
-        } else {
+        }
+        ShareTarget::Webhook(webhook) => {
+            links.post(webhook, controller.name()).await?;
+            let host = webhook.host_str().unwrap_or_default();
             println!(
-                r#"
-  {sshx} {version}
-
-  {arr}  Read-only link: {link_v}
-  {arr}  Writable link:  {link_e}
-  {arr}  Shell:          {shell_v}
-  {arr}  Transport:      {transport_v}
-"#,
-                sshx = Green.bold().paint("sshx"),
-                version = Green.paint(&version_str),
-                arr = Green.paint("➜"),
-                link_v = Cyan.underline().paint(controller.url()),
-                link_e = Cyan.underline().paint(write_url),
-                shell_v = Fixed(8).paint(shell),
-                transport_v = Fixed(8).paint(transport_str),
+                "  {} Shared session links to {host}\n",
+                style(Green).paint("✓")
             );
         }
-    } else {
-        if let Some(dashboard) = dashboard_info {
-            println!(
-                r#"
-  {sshx} {version}
-
-  {arr}  Link:         {link_v}
-  {arr}  Dashboard:    {dashboard_url_v}
-  {arr}  Dashboard ID: {dashboard_id_v}
-  {arr}  Shell:        {shell_v}
-  {arr}  Transport:    {transport_v}
-"#,
-                sshx = Green.bold().paint("sshx"),
-                version = Green.paint(&version_str),
-                arr = Green.paint("➜"),
-                link_v = Cyan.underline().paint(controller.url()),
-                dashboard_url_v = Cyan.underline().paint(&dashboard.url),
-                dashboard_id_v = Fixed(8).paint(&dashboard.key),
-                shell_v = Fixed(8).paint(shell),
-                transport_v = Fixed(8).paint(transport_str),
+    }
+    Ok(())
+}
+
+/// Warn loudly that output of this session is readable by the server.
+fn print_spectator_warning(spectator_url: &str) {
+    eprintln!(
+        "  {warn}  Spectator link: {link}\n     {note}\n",
+        warn = style(Red.bold()).paint("⚠"),
+        link = hyperlink(spectator_url),
+        note = style(Red).paint(
+            "Terminal output is NOT end-to-end encrypted: the server and anyone \
+             with this link can read it."
+        ),
+    );
+}
+
+/// Derive a session name like `repo/branch` from the git repository in a
+/// directory, or the current one. Returns `None` outside of a repository, or
+/// if `git` is not installed.
+async fn name_from_git(dir: Option<&Path>) -> Option<String> {
+    let mut command = tokio::process::Command::new("git");
+    command.args(["rev-parse", "--show-toplevel", "--abbrev-ref", "HEAD"]);
+    if let Some(dir) = dir {
+        command.current_dir(dir);
+    }
+    command
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true);
+    let output = time::timeout(Duration::from_secs(5), command.output())
+        .await
+        .ok()?
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8(output.stdout).ok()?;
+    let mut lines = stdout.lines();
+    git_session_name(lines.next()?, lines.next()?)
+}
+
+/// Format a session name from a repository's root directory and branch.
+fn git_session_name(toplevel: &str, branch: &str) -> Option<String> {
+    let repo = Path::new(toplevel).file_name()?.to_str()?;
+    Some(format!("{repo}/{}", branch.trim()))
+}
+
+/// Print how to unlock a session opened with `--start-locked`.
+fn print_locked_notice() {
+    eprintln!(
+        "  🔒 Session is locked: viewers see nothing until you run\n\n      kill -USR1 {}\n",
+        std::process::id(),
+    );
+}
+
+/// Listen for SIGUSR1, which unlocks a session opened with `--start-locked`.
+#[cfg(unix)]
+fn listen_for_unlock() -> Result<impl std::future::Future<Output = ()> + Send> {
+    use signal::unix::{signal, SignalKind};
+    let mut sigusr1 =
+        signal(SignalKind::user_defined1()).context("failed to listen for SIGUSR1")?;
+    Ok(async move {
+        sigusr1.recv().await;
+    })
+}
+
+#[cfg(not(unix))]
+fn listen_for_unlock() -> Result<std::future::Pending<()>> {
+    anyhow::bail!("--start-locked is only supported on Unix, where SIGUSR1 unlocks the session")
+}
+
+/// Listen for SIGHUP, which reloads the configuration file.
+#[cfg(unix)]
+fn listen_for_reload() -> Result<impl Stream<Item = ()> + Send> {
+    use signal::unix::{signal, SignalKind};
+    let sighup = signal(SignalKind::hangup()).context("failed to listen for SIGHUP")?;
+    Ok(futures_util::stream::unfold(
+        sighup,
+        |mut sighup| async move { sighup.recv().await.map(|()| ((), sighup)) },
+    ))
+}
+
+#[cfg(not(unix))]
+fn listen_for_reload() -> Result<tokio_stream::Pending<()>> {
+    Ok(tokio_stream::pending())
+}
+
+/// Keys of the configuration file that are applied on reload, without a
+/// restart. The display name is only updated on the dashboard.
+const RELOADABLE_KEYS: [&str; 2] = ["dashboard", "name"];
+
+/// Configuration file that the flags were loaded from.
+struct ConfigReload {
+    path: PathBuf,
+    /// Contents of the file when it was last loaded.
+    source: String,
+    /// Flags from the command line, which take precedence over the file.
+    matches: ArgMatches,
+}
+
+impl ConfigReload {
+    /// Load the configuration file, remembering it for later reloads.
+    fn load(path: PathBuf, matches: &ArgMatches) -> Result<(Config, Self)> {
+        let source = std::fs::read_to_string(&path)
+            .with_context(|| format!("failed to read config file {}", path.display()))?;
+        let config = Config::parse(&source)
+            .with_context(|| format!("invalid config file {}", path.display()))?;
+        let matches = matches.clone();
+        Ok((
+            config,
+            Self {
+                path,
+                source,
+                matches,
+            },
+        ))
+    }
+
+    /// Read the file again, returning the new flags and the keys that changed.
+    fn reload(&mut self) -> Result<(Args, Vec<String>)> {
+        let (config, new) = Self::load(self.path.clone(), &self.matches)?;
+        let changed = Config::changed_keys(&self.source, &new.source)?;
+        let mut args = Args::from_arg_matches(&self.matches)?;
+        args.apply_config(config, &self.matches);
+        *self = new;
+        Ok((args, changed))
+    }
+}
+
+/// Applies changes to the configuration file while a session runs.
+struct Reloader {
+    config: ConfigReload,
+    server: String,
+    insecure: bool,
+    /// Whether the display name comes from git, rather than the file.
+    name_from_git: bool,
+    /// Whether the session is currently registered with a dashboard.
+    registered: bool,
+    /// Value of `--dashboard` that the session was registered with.
+    dashboard: Option<Option<String>>,
+    registration: RegisterDashboardRequest,
+}
+
+impl Reloader {
+    /// Reload the configuration on each SIGHUP, until the session ends.
+    async fn run(mut self, hangup: impl Stream<Item = ()>) {
+        tokio::pin!(hangup);
+        while hangup.next().await.is_some() {
+            if let Err(err) = self.reload().await {
+                warn!("Reloading the config file failed: {err:#}");
+            }
+        }
+    }
+
+    async fn reload(&mut self) -> Result<()> {
+        let (args, changed) = self.config.reload()?;
+        let mut applied = Vec::new();
+        let mut restart: Vec<&str> = changed
+            .iter()
+            .map(String::as_str)
+            .filter(|key| !RELOADABLE_KEYS.contains(key))
+            .collect();
+        let mut register = false;
+
+        if changed.iter().any(|key| key == "name") && !self.name_from_git {
+            let name = args.name.unwrap_or_else(default_name);
+            if name != self.registration.display_name {
+                self.registration.display_name = name;
+                register |= self.registered;
+                applied.push("name");
+            }
+        }
+        if changed.iter().any(|key| key == "dashboard") && args.dashboard != self.dashboard {
+            match &args.dashboard {
+                // Dashboards have no way to remove a session that is still open.
+                None => restart.push("dashboard"),
+                Some(key) => {
+                    // Keep the generated key, unless joining a specific dashboard.
+                    if key.is_some() || !self.registered {
+                        self.registration.dashboard_key = key.clone();
+                        register = true;
+                    }
+                    self.dashboard = args.dashboard;
+                    applied.push("dashboard");
+                }
+            }
+        }
+
+        if register {
+            let dashboard =
+                register_with_dashboard(&self.server, &self.registration, self.insecure).await?;
+            info!("Session registered to dashboard {}", dashboard.url);
+            self.registration.dashboard_key = Some(dashboard.key);
+            self.registered = true;
+        }
+        let path = self.config.path.display();
+        match applied.is_empty() {
+            true => info!("Reloaded {path}, nothing to apply"),
+            false => info!(
+                "Reloaded {path}, applied changes to: {}",
+                applied.join(", ")
+            ),
+        }
+        if !restart.is_empty() {
+            warn!(
+                "Changes to {} need a restart to take effect",
+                restart.join(", ")
             );
+        }
+        Ok(())
+    }
+}
+
+/// Wait until the processes of all open shells have exited, returning the
+/// exit code of the last one.
+///
+/// Shells closed from the browser don't count, so this only finishes once a
+/// shell exits on its own and no others are left, like the end of an SSH
+/// session.
+async fn last_shell_exit(events: impl Stream<Item = ControllerEvent>) -> Option<i32> {
+    tokio::pin!(events);
+    let mut open = BTreeSet::new();
+    while let Some(event) = events.next().await {
+        match event {
+            ControllerEvent::ShellOpened(id) => {
+                open.insert(id);
+            }
+            ControllerEvent::ShellClosed(id) => {
+                open.remove(&id);
+            }
+            ControllerEvent::ShellExited(id, exit_code) => {
+                open.remove(&id);
+                if open.is_empty() {
+                    return exit_code;
+                }
+            }
+            _ => (),
+        }
+    }
+    std::future::pending().await
+}
+
+fn print_shell_exited(exit_code: Option<i32>) {
+    let status = match exit_code {
+        Some(code) => format!("exited with status {code}"),
+        None => "was killed".into(),
+    };
+    eprintln!(
+        "\n  {arr}  Shell {status}, closing session",
+        arr = style(Green).paint("➜"),
+    );
+}
+
+/// Print a short summary of the session's activity after it has closed.
+fn print_summary(stats: &ControllerStats) {
+    let shells = match stats.shells_opened {
+        1 => "1 shell".to_string(),
+        n => format!("{n} shells"),
+    };
+    let mut parts = vec![
+        format!("lasted {}", format_duration(stats.duration)),
+        shells,
+        format!("{} sent", format_bytes(stats.bytes_sent)),
+        format!("{} received", format_bytes(stats.bytes_received)),
+    ];
+    if let Some(peak) = stats.peak_users {
+        parts.push(format!("peak of {peak} users"));
+    }
+    if stats.reconnects > 0 {
+        parts.push(format!("{} reconnects", stats.reconnects));
+    }
+    println!(
+        "\n  {arr}  Session ended: {summary}\n",
+        arr = style(Green).paint("➜"),
+        summary = style(Fixed(8)).paint(parts.join(", ")),
+    );
+}
+
+/// Format a duration compactly, e.g. `1h 02m 03s`.
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (h, m, s) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if h > 0 {
+        format!("{h}h {m:02}m {s:02}s")
+    } else if m > 0 {
+        format!("{m}m {s:02}s")
+    } else {
+        format!("{s}s")
+    }
+}
+
+/// Format a byte count with a binary unit suffix, e.g. `1.5 KiB`.
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{value:.1} {}", UNITS[unit])
+}
+
+/// Print a step-by-step report of the connection attempt to stderr.
+async fn print_diagnostics(mut rx: mpsc::UnboundedReceiver<Diagnostic>) {
+    eprintln!(
+        "\n  {}",
+        style(Green.bold()).paint("Connection diagnostics")
+    );
+    while let Some(diagnostic) = rx.recv().await {
+        let mark = if diagnostic.success {
+            style(Green).paint("✓")
         } else {
-            println!(
-                r#"
-  {sshx} {version}
-
-  {arr}  Link:      {link_v}
-  {arr}  Shell:     {shell_v}
-  {arr}  Transport: {transport_v}
-"#,
-                sshx = Green.bold().paint("sshx"),
-                version = Green.paint(&version_str),
-                arr = Green.paint("➜"),
-                link_v = Cyan.underline().paint(controller.url()),
-                shell_v = Fixed(8).paint(shell),
-                transport_v = Fixed(8).paint(transport_str),
+            style(Red).paint("✗")
+        };
+        let elapsed = match diagnostic.elapsed {
+            Some(elapsed) => format!(" ({}ms)", elapsed.as_millis()),
+            None => String::new(),
+        };
+        eprintln!(
+            "  {mark} {:<18} {}{}",
+            diagnostic.step.label(),
+            diagnostic.detail,
+            style(Fixed(8)).paint(elapsed),
+        );
+    }
+}
+
+/// Run the connection steps against a server, printing a pass/fail summary.
+async fn test_connection(server: &str, config: ConnectionConfig) -> Result<()> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let config = ConnectionConfig {
+        diagnostics: Some(tx),
+        ..config
+    };
+    let diagnostics_task = tokio::spawn(print_diagnostics(rx));
+
+    let start = Instant::now();
+    let result = connect_with_fallback(server, "connectivity-test", config).await;
+    let elapsed = start.elapsed();
+    diagnostics_task.await?;
+
+    match result {
+        Ok(result) => {
+            let method = match result.method {
+                sshx::connection::ConnectionMethod::Grpc => "gRPC",
+                sshx::connection::ConnectionMethod::WebSocketFallback => "WebSocket fallback",
+            };
+            eprintln!(
+                "\n  {} PASS: {server} is reachable via {method} ({}ms)\n",
+                style(Green).paint("✓"),
+                elapsed.as_millis(),
             );
+            Ok(())
+        }
+        Err(err) => {
+            eprintln!(
+                "\n  {} FAIL: {server} is not reachable\n",
+                style(Red).paint("✗")
+            );
+            Err(err)
         }
     }
 }
 
 #[tokio::main]
-async fn start(args: Args) -> Result<()> {
+async fn start(mut args: Args, reload: Option<ConfigReload>) -> Result<ExitCode> {
+    args.server = normalize_server_url(&args.server);
+
     // Handle service commands if present
     if let Some(cmd) = args.service {
         return match cmd.as_str() {
@@ -262,7 +1066,68 @@ async fn start(args: Args) -> Result<()> {
             "start" => service::start(),
             "stop" => service::stop(),
             _ => Err(anyhow::anyhow!("Invalid service command")),
+        }
+        .map(|()| ExitCode::SUCCESS);
+    }
+
+    if args.share.is_some() && !args.enable_readers && !args.share_write_url {
+        anyhow::bail!(
+            "--share needs --enable-readers for a read-only link, or --share-write-url to \
+             share the writable link"
+        );
+    }
+
+    // Warn here rather than at parse time, so enabling it from the
+    // configuration file is never silent either.
+    if args.insecure {
+        sshx::tls::warn_insecure();
+    }
+
+    // Listen before the session opens, since SIGUSR1 and SIGHUP terminate by
+    // default.
+    let unlock_signal = args.start_locked.then(listen_for_unlock).transpose()?;
+    let hangup = reload.is_some().then(listen_for_reload).transpose()?;
+
+    if let Some(token) = args.auth_token.take() {
+        args.headers
+            .push(("authorization".into(), format!("Bearer {token}")));
+    }
+    let mut dialer = Dialer {
+        happy_eyeballs_delay: Duration::from_millis(args.happy_eyeballs_delay),
+        jump: args.jump.clone(),
+        insecure: args.insecure,
+        ..Default::default()
+    };
+    for (host, ip) in &args.resolve {
+        dialer.add_override(host, *ip);
+    }
+    let ws_options = WsConnectOptions {
+        path_prefix: args.ws_path_prefix,
+        subprotocol: args.ws_subprotocol,
+    };
+    if args.test_connection {
+        let config = ConnectionConfig {
+            dialer,
+            ws: ws_options,
+            headers: args.headers,
+            ..Default::default()
         };
+        return test_connection(&args.server, config)
+            .await
+            .map(|()| ExitCode::SUCCESS);
+    }
+    let session_max_age = Duration::from_secs(args.session_max_age.saturating_mul(24 * 60 * 60));
+    let session_store =
+        SessionStore::open_default().map(|store| store.with_max_age(session_max_age));
+    if let Some(Command::Sessions(command)) = args.command {
+        let config = ConnectionConfig {
+            dialer,
+            ws: ws_options,
+            headers: args.headers,
+            ..Default::default()
+        };
+        let result = manage_sessions(command, config, session_store, args.insecure).await;
+        return result.map(|()| ExitCode::SUCCESS);
     }
 
     let shell = match args.shell {
@@ -270,49 +1135,112 @@ async fn start(args: Args) -> Result<()> {
         None => get_default_shell().await,
     };
 
-    let name = args.name.unwrap_or_else(|| {
-        let mut name = whoami::username();
-        if let Ok(host) = whoami::fallible::hostname() {
-            // Trim domain information like .lan or .local
-            let host = host.split('.').next().unwrap_or(&host);
-            name += "@";
-            name += host;
+    let git_name = match args.name_from_git && args.name.is_none() {
+        true => name_from_git(args.cwd.as_deref()).await,
+        false => None,
+    };
+    let name = args.name.or(git_name).unwrap_or_else(default_name);
+
+    if let Some(cwd) = &args.cwd {
+        if !cwd.is_dir() {
+            anyhow::bail!("--cwd {} is not an existing directory", cwd.display());
         }
-        name
-    });
+    }
+
+    let command = ShellCommand {
+        program: shell.clone(),
+        args: args.shell_args,
+        login: args.login_shell,
+        cwd: args.cwd,
+        init: args.init,
+        motd: args.motd.as_deref().map(load_motd).transpose()?,
+        caps: match args.term {
+            Some(term) => TermCaps::from_env().with_term(term),
+            None => TermCaps::from_env(),
+        },
+        respawn: Respawn {
+            max_retries: args.respawn.unwrap_or(0),
+            on_success: args.respawn_always,
+        },
+    };
 
-    let runner = Runner::Shell(shell.clone());
-    
     // Create connection configuration based on verbose flag
-    let connection_config = if args.verbose {
+    let mut connection_config = if args.verbose {
         verbose_config()
     } else {
         ConnectionConfig::default()
     };
-    
-    // Establish connection with automatic fallback
-    let connection_result = connect_with_fallback(&args.server, &name, connection_config).await?;
-    
+    connection_config.dialer = dialer;
+    connection_config.ws = ws_options;
+    connection_config.headers = args.headers;
+    let diagnostics_task = args.verbose.then(|| {
+        let (tx, rx) = mpsc::unbounded_channel();
+        connection_config.diagnostics = Some(tx);
+        tokio::spawn(print_diagnostics(rx))
+    });
+
+    let options = ShareOptions {
+        name: Some(name.clone()),
+        enable_readers: args.enable_readers,
+        allow_spectators: args.allow_spectators,
+        limits: ClientLimits {
+            max_clients: args.max_clients,
+            write_window: args
+                .write_window
+                .map(|mins| Duration::from_secs(60 * u64::from(mins))),
+            write_window_renew: args.write_window_renew,
+            start_locked: args.start_locked,
+        },
+        labels: args.labels.into_iter().collect(),
+        connection: connection_config,
+    };
+
+    // Establish connection with automatic fallback, then open the session
+    let session = share_command(&args.server, command, options).await;
+    if let Some(task) = diagnostics_task {
+        task.await?;
+    }
+    let mut session = session?;
+
     // Report connection method if verbose
     if args.verbose {
-        match connection_result.method {
+        match session.method() {
             sshx::connection::ConnectionMethod::Grpc => {
-                eprintln!("  {} Connected via gRPC", Green.paint("✓"));
+                eprintln!("  {} Connected via gRPC", style(Green).paint("✓"));
             }
             sshx::connection::ConnectionMethod::WebSocketFallback => {
-                eprintln!("  {} Connected via WebSocket fallback", Green.paint("✓"));
+                eprintln!(
+                    "  {} Connected via WebSocket fallback",
+                    style(Green).paint("✓")
+                );
             }
         }
     }
-    
-    let mut controller = Controller::with_transport(&args.server, &name, runner, args.enable_readers, connection_result.transport).await?;
+    let controller = session.controller();
+
+    // Remember the token, so the session can be closed with `sshx sessions`.
+    if let Some(store) = &session_store {
+        let saved = SavedSession::new(
+            controller.name(),
+            controller.token(),
+            controller.url(),
+            &args.server,
+        );
+        if let Err(err) = store.add(saved) {
+            warn!("failed to save session token: {err:#}");
+        }
+    }
 
     // Register with dashboard if requested
-    let dashboard_info = if let Some(dashboard_option) = args.dashboard {
+    let mut registration = dashboard_request(controller, &name, None);
+    let dashboard_info = if let Some(dashboard_option) = args.dashboard.clone() {
         // dashboard_option is Some(key) if key provided, None if just --dashboard
-        let dashboard_key = dashboard_option;
-        match register_with_dashboard(&args.server, &controller, &name, dashboard_key).await {
-            Ok(info) => Some(info),
+        registration.dashboard_key = dashboard_option;
+        match register_with_dashboard(&args.server, &registration, args.insecure).await {
+            Ok(info) => {
+                registration.dashboard_key = Some(info.key.clone());
+                Some(info)
+            }
             Err(e) => {
                 warn!("Dashboard registration failed: {}", e);
                 None
@@ -322,52 +1250,184 @@ async fn start(args: Args) -> Result<()> {
         None
     };
 
-    if args.quiet {
+    let json_output = args.output == OutputFormat::Json;
+    // With JSON output, the URLs are in the status lines printed below.
+    if args.quiet && !json_output {
         if let Some(write_url) = controller.write_url() {
             println!("{}", write_url);
         } else {
             println!("{}", controller.url());
         }
-    } else {
-        print_greeting(&shell, &controller, &connection_result.method, dashboard_info.as_ref());
+    } else if !json_output {
+        print_greeting(
+            &shell,
+            controller,
+            session.method(),
+            dashboard_info.as_ref(),
+        );
+    }
+    if let Some(spectator_url) = controller.spectator_url() {
+        print_spectator_warning(spectator_url);
+    }
+    if let Some(unlock_signal) = unlock_signal {
+        if !args.quiet {
+            print_locked_notice();
+        }
+        let lock = controller.lock_handle();
+        let quiet = args.quiet;
+        tokio::spawn(async move {
+            unlock_signal.await;
+            match lock.set_locked(false).await {
+                Ok(()) if !quiet => eprintln!("\r\x1b[2K  🔓 Session unlocked\n"),
+                Ok(()) => (),
+                Err(err) => warn!("Unlocking the session failed: {err}"),
+            }
+        });
+    }
+    if let (Some(hangup), Some(config)) = (hangup, reload) {
+        let reloader = Reloader {
+            config,
+            server: args.server.clone(),
+            insecure: args.insecure,
+            name_from_git: args.name_from_git,
+            registered: dashboard_info.is_some(),
+            dashboard: args.dashboard.clone(),
+            registration,
+        };
+        tokio::spawn(reloader.run(hangup));
+    }
+    if let Some(target) = &args.share {
+        if let Err(err) = share_session(target, controller, args.share_write_url).await {
+            warn!("Sharing the session failed: {err}");
+        }
     }
 
+    // Live status is only useful when a person is watching the terminal.
+    let dumb_term = std::env::var("TERM").is_ok_and(|term| term == "dumb");
+    let live_status = !args.quiet && !json_output && !dumb_term;
+    let status_task = (live_status && std::io::stdout().is_terminal())
+        .then(|| tokio::spawn(print_status(controller.events())));
+    let report_task = (json_output || args.status_file.is_some()).then(|| {
+        let status = SessionStatus::new(controller);
+        let events = controller.events();
+        tokio::spawn(report_status(
+            events,
+            status,
+            json_output,
+            args.status_file.clone(),
+        ))
+    });
+
+    let shell_exit = match args.keep_alive {
+        true => None,
+        false => Some(last_shell_exit(controller.events())),
+    };
     let exit_signal = signal::ctrl_c();
     tokio::pin!(exit_signal);
-    tokio::select! {
-        _ = controller.run() => unreachable!(),
-        Ok(()) = &mut exit_signal => (),
+    let exit_code = tokio::select! {
+        _ = session.run() => unreachable!(),
+        Ok(()) = &mut exit_signal => None,
+        Some(exit_code) = async { Some(shell_exit?.await) } => Some(exit_code),
     };
-    controller.close().await?;
+    if let Some(task) = status_task {
+        task.abort();
+        print!("\r\x1b[2K");
+    }
+    if let Some(task) = report_task {
+        task.abort();
+    }
+    if let Some(path) = &args.status_file {
+        std::fs::remove_file(path).ok();
+    }
+    if let Some(exit_code) = exit_code.filter(|_| !args.quiet) {
+        print_shell_exited(exit_code);
+    }
+    let stats = session.close().await?;
+    if let Some(store) = &session_store {
+        store.mark_closed(session.name()).ok();
+    }
+    if !args.quiet && !json_output {
+        print_summary(&stats);
+    }
 
-    Ok(())
+    Ok(match exit_code {
+        Some(Some(0)) | None => ExitCode::SUCCESS,
+        // Codes outside a byte, such as negative ones on Windows, must not wrap to 0.
+        Some(Some(code)) => {
+            ExitCode::from(u8::try_from(code).ok().filter(|c| *c != 0).unwrap_or(1))
+        }
+        Some(None) => ExitCode::FAILURE,
+    })
 }
 
 fn main() -> ExitCode {
-    let args = Args::parse();
+    let matches = Args::command().get_matches();
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+
+    let config_path = match &args.config {
+        _ if args.no_config => None,
+        Some(path) => Some(path.clone()),
+        None => Config::find(),
+    };
+    let mut config_reload = None;
+    if let Some(path) = config_path {
+        match ConfigReload::load(path, &matches) {
+            Ok((config, reload)) => {
+                args.apply_config(config, &matches);
+                config_reload = Some(reload);
+            }
+            Err(err) => {
+                eprintln!("error: {err:#}");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
 
-    let default_level = if args.quiet { 
-        "error" 
+    if let Some(Command::Config(ConfigCommand::Show)) = &args.command {
+        let file = config_reload
+            .as_ref()
+            .map(|reload| (&*reload.path, &*reload.source));
+        return match show_config(&args, &matches, file) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(err) => {
+                eprintln!("error: {err:#}");
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    let default_level = if args.quiet {
+        "error"
     } else if args.verbose {
         "debug"
-    } else { 
-        "info" 
+    } else {
+        "info"
     };
 
+    let color = !args.no_color && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty());
+    COLOR_ENABLED.store(color && std::io::stdout().is_terminal(), Ordering::Relaxed);
+    let dumb_term = std::env::var("TERM").is_ok_and(|term| term == "dumb");
+    let hyperlinks = !args.no_hyperlinks && !dumb_term && std::io::stdout().is_terminal();
+    HYPERLINKS_ENABLED.store(hyperlinks, Ordering::Relaxed);
+
     tracing_subscriber::fmt()
         .with_env_filter(std::env::var("RUST_LOG").unwrap_or(default_level.into()))
         .with_writer(std::io::stderr)
+        .with_ansi(color && std::io::stderr().is_terminal())
         .init();
 
-    match start(args) {
-        Ok(()) => ExitCode::SUCCESS,
+    match start(args, config_reload) {
+        Ok(code) => code,
         Err(err) => {
             // Provide user-friendly error messages
             let error_msg = format!("{}", err);
             if error_msg.contains("Both gRPC and WebSocket connections failed") {
                 error!("❌ Unable to connect to the sshx server.");
                 error!("   Please check:");
-                error!("   • Server URL is correct: {}", std::env::var("SSHX_SERVER").unwrap_or_else(|_| "https://sshx.io".to_string()));
+                error!(
+                    "   • Server URL is correct: {}",
+                    std::env::var("SSHX_SERVER").unwrap_or_else(|_| "https://sshx.io".to_string())
+                );
                 error!("   • Network connectivity is available");
                 error!("   • Server is running and accessible");
                 error!("   Use --verbose for detailed connection diagnostics");
@@ -381,3 +1441,107 @@ fn main() -> ExitCode {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{
+        format_bytes, format_duration, format_motd, git_session_name, make_relative_url,
+        parse_label, parse_resolve,
+    };
+
+    #[test]
+    fn summary_formatting() {
+        assert_eq!(format_duration(Duration::from_millis(4500)), "4s");
+        assert_eq!(format_duration(Duration::from_secs(125)), "2m 05s");
+        assert_eq!(format_duration(Duration::from_secs(3723)), "1h 02m 03s");
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(3 << 30), "3.0 GiB");
+    }
+
+    #[test]
+    fn git_names() {
+        let name = git_session_name("/home/me/src/sshx", "main\n");
+        assert_eq!(name.as_deref(), Some("sshx/main"));
+        let name = git_session_name("/work/api", "feature/login");
+        assert_eq!(name.as_deref(), Some("api/feature/login"));
+        assert_eq!(
+            git_session_name("/work/api", "HEAD").as_deref(),
+            Some("api/HEAD")
+        );
+        assert_eq!(git_session_name("/", "main"), None);
+    }
+
+    #[test]
+    fn label_argument() {
+        assert_eq!(
+            parse_label("ticket=1234"),
+            Ok(("ticket".into(), "1234".into()))
+        );
+        assert_eq!(parse_label("note=a=b"), Ok(("note".into(), "a=b".into())));
+        assert_eq!(parse_label("empty="), Ok(("empty".into(), "".into())));
+        assert!(parse_label("ticket").is_err());
+        assert!(parse_label("=1234").is_err());
+    }
+
+    #[test]
+    fn motd_formatting() {
+        assert_eq!(format_motd("", true), "");
+        assert_eq!(format_motd("hi", true), "hi\r\n");
+        assert_eq!(format_motd("a\\nb\\tc\n", true), "a\r\nb\tc\r\n");
+        assert_eq!(
+            format_motd("\\e[1mbold\\e[0m", true),
+            "\x1b[1mbold\x1b[0m\r\n"
+        );
+        assert_eq!(format_motd(r"C:\\dir \x", true), "C:\\dir \\x\r\n");
+        assert_eq!(format_motd("a\r\nb\n", false), "a\r\nb\r\n");
+        assert_eq!(format_motd(r"\e[1m", false), "\\e[1m\r\n");
+    }
+
+    #[test]
+    fn resolve_argument() {
+        let v4 = parse_resolve("sshx.io:10.0.0.1").unwrap();
+        assert_eq!(v4, ("sshx.io".into(), "10.0.0.1".parse().unwrap()));
+        let v6 = parse_resolve("sshx.io:[2001:db8::1]").unwrap();
+        assert_eq!(v6.1, "2001:db8::1".parse::<std::net::IpAddr>().unwrap());
+        assert_eq!(parse_resolve("sshx.io:::1").unwrap().1.to_string(), "::1");
+        assert!(parse_resolve("sshx.io").is_err());
+        assert!(parse_resolve(":10.0.0.1").is_err());
+        assert!(parse_resolve("sshx.io:nope").is_err());
+    }
+
+    #[test]
+    fn relative_url_keeps_key() {
+        assert_eq!(
+            make_relative_url("https://sshx.io/s/abc123#key,writepw"),
+            "/s/abc123#key,writepw"
+        );
+        assert_eq!(
+            make_relative_url("https://sshx.io/sub/s/abc#k"),
+            "/sub/s/abc#k"
+        );
+    }
+
+    #[test]
+    fn relative_url_query_only() {
+        assert_eq!(make_relative_url("https://sshx.io?a=1"), "/?a=1");
+        assert_eq!(make_relative_url("?a=1"), "/?a=1");
+    }
+
+    #[test]
+    fn relative_url_malformed() {
+        assert_eq!(make_relative_url("s/abc#key"), "/s/abc#key");
+        assert_eq!(make_relative_url("/s/abc#key"), "/s/abc#key");
+        assert_eq!(make_relative_url(""), "/");
+        assert_eq!(make_relative_url("  /s/abc  "), "/s/abc");
+    }
+
+    #[test]
+    fn relative_url_protocol_relative() {
+        assert_eq!(make_relative_url("//evil.com/s/abc"), "/evil.com/s/abc");
+        assert_eq!(make_relative_url("/\\evil.com"), "/evil.com");
+        assert_eq!(make_relative_url("https://sshx.io//evil.com"), "/evil.com");
+    }
+}
@@ -1,26 +1,31 @@
 //! Defines tasks that control the behavior of a single shell in the client.
 
+use std::time::Duration;
+
 use anyhow::Result;
 use encoding_rs::{CoderResult, UTF_8};
-use sshx_core::proto::{client_update::ClientMessage, TerminalData};
+use sshx_core::proto::{client_update::ClientMessage, ShellExit, TerminalData};
 use sshx_core::Sid;
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
     sync::mpsc,
+    time,
 };
+use tracing::{debug, info};
 
 use crate::encrypt::Encrypt;
-use crate::terminal::Terminal;
+use crate::terminal::{ShellCommand, Terminal};
 
 const CONTENT_CHUNK_SIZE: usize = 1 << 16; // Send at most this many bytes at a time.
 const CONTENT_ROLLING_BYTES: usize = 8 << 20; // Store at least this much content.
 const CONTENT_PRUNE_BYTES: usize = 12 << 20; // Prune when we exceed this length.
+const RESPAWN_DELAY: Duration = Duration::from_millis(500); // Pause before a respawn.
 
 /// Variants of terminal behavior that are used by the controller.
 #[derive(Debug, Clone)]
 pub enum Runner {
     /// Spawns the specified shell as a subprocess, forwarding PTYs.
-    Shell(String),
+    Shell(ShellCommand),
 
     /// Mock runner that only echos its input, useful for testing.
     Echo,
@@ -56,12 +61,12 @@ impl Runner {
 async fn shell_task(
     id: Sid,
     encrypt: Encrypt,
-    shell: &str,
+    shell: &ShellCommand,
     mut shell_rx: mpsc::Receiver<ShellData>,
     output_tx: mpsc::Sender<ClientMessage>,
 ) -> Result<()> {
-    let mut term = Terminal::new(shell).await?;
-    term.set_winsize(24, 80)?;
+    let mut winsize = (24, 80);
+    let mut term = start_terminal(shell, winsize).await?;
 
     let mut content = String::new(); // content from the terminal
     let mut content_offset = 0; // bytes before the first character of `content`
@@ -70,14 +75,46 @@ async fn shell_task(
     let mut seq_outdated = 0; // number of times seq has been outdated
     let mut buf = [0u8; 4096]; // buffer for reading
     let mut finished = false; // set when this is done
+    let mut exited = None; // exit code, set when the shell process ended on its own
+    let mut respawns = 0; // number of times the shell process was replaced
+    let mut pending_init = shell.init.as_deref(); // init command not yet sent
+
+    // The banner is stored like any other output, so it is only sent once, and
+    // later viewers see it in the shell's history.
+    if let Some(motd) = &shell.motd {
+        content.push_str(motd);
+    }
 
     while !finished {
         tokio::select! {
             result = term.read(&mut buf) => {
-                let n = result?;
+                let n = match result {
+                    Ok(n) => n,
+                    // Linux reports EIO on the PTY master once the shell has exited.
+                    #[cfg(unix)]
+                    Err(err) if err.raw_os_error() == Some(nix::libc::EIO) => 0,
+                    Err(err) => return Err(err.into()),
+                };
                 if n == 0 {
-                    finished = true;
+                    let exit_code = term.exit_code().await;
+                    if shell.respawn.should_respawn(exit_code, respawns) {
+                        respawns += 1;
+                        info!(%id, ?exit_code, respawns, "respawning shell after exit");
+                        let max = shell.respawn.max_retries;
+                        content.push_str(&respawn_notice(exit_code, respawns, max));
+                        time::sleep(RESPAWN_DELAY).await;
+                        term = start_terminal(shell, winsize).await?;
+                        pending_init = shell.init.as_deref();
+                    } else {
+                        finished = true;
+                        exited = Some(exit_code);
+                    }
                 } else {
+                    // The shell's first output (usually its prompt) means it is
+                    // ready to read the init command.
+                    if let Some(init) = pending_init.take() {
+                        term.write_all(format!("{init}\n").as_bytes()).await?;
+                    }
                     content.reserve(decoder.max_utf8_buffer_length(n).unwrap());
                     let (result, _, _) = decoder.decode_to_string(&buf[..n], &mut content, false);
                     debug_assert!(result == CoderResult::InputEmpty);
@@ -97,7 +134,8 @@ async fn shell_task(
                         }
                     }
                     Some(ShellData::Size(rows, cols)) => {
-                        term.set_winsize(rows as u16, cols as u16)?;
+                        winsize = (rows as u16, cols as u16);
+                        term.set_winsize(winsize.0, winsize.1)?;
                     }
                     None => finished = true, // Server closed this shell.
                 }
@@ -136,9 +174,31 @@ async fn shell_task(
             content.drain(..pruned);
         }
     }
+
+    if let Some(exit_code) = exited {
+        debug!(%id, ?exit_code, "shell process exited");
+        let exit = ShellExit { id: id.0, exit_code };
+        output_tx.send(ClientMessage::ExitedShell(exit)).await?;
+    }
     Ok(())
 }
 
+/// Spawn the process for a shell at the given size.
+async fn start_terminal(shell: &ShellCommand, (rows, cols): (u16, u16)) -> Result<Terminal> {
+    let mut term = Terminal::spawn(shell).await?;
+    term.set_winsize(rows, cols)?;
+    Ok(term)
+}
+
+/// Line shown to viewers in place of a shell that exited and was respawned.
+fn respawn_notice(exit_code: Option<i32>, respawns: u32, max: u32) -> String {
+    let status = match exit_code {
+        Some(code) => format!("exited with status {code}"),
+        None => "was killed".into(),
+    };
+    format!("\r\n\x1b[33m[sshx] Shell {status}, starting a new one ({respawns}/{max})\x1b[0m\r\n")
+}
+
 /// Find the last char boundary before an index in O(1) time.
 fn prev_char_boundary(s: &str, i: usize) -> usize {
     (0..=i)
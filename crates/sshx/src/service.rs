@@ -14,7 +14,8 @@ fn generate_service_file(
     name: Option<&str>,
     shell: Option<&str>,
 ) -> String {
-    let mut exec_start = "/usr/local/bin/sshx".to_string();
+    // A service keeps its session open when viewers exit their shells.
+    let mut exec_start = "/usr/local/bin/sshx --keep-alive".to_string();
 
     // Add server argument if not default
     if server != "https://sshx.io" {
@@ -49,6 +50,7 @@ After=network.target
 [Service]
 Type=simple
 ExecStart={}
+ExecReload=/bin/kill -HUP $MAINPID
 Restart=on-failure
 RestartSec=5
 User=root
@@ -0,0 +1,356 @@
+//! Local record of sessions opened from this machine.
+//!
+//! The control token of each session is saved when it opens, so that
+//! `sshx sessions list` and `sshx sessions close` can manage it later, even
+//! after the process that opened it is gone. Tokens allow closing a session,
+//! so the file is only readable by the current user.
+//!
+//! Several `sshx` processes may share the file, so every change holds an
+//! exclusive lock on a separate lock file while it reads and rewrites it.
+//! Entries are pruned once they are older than the store's maximum age.
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+
+/// Name of the session store file in the configuration directory.
+pub const STORE_FILE: &str = "sessions.json";
+
+/// Default age after which saved sessions are pruned, 30 days.
+pub const DEFAULT_MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// A session opened from this machine, with the token needed to control it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SavedSession {
+    /// Name of the session on the server.
+    pub name: String,
+    /// Token returned by the server when the session was opened.
+    pub token: String,
+    /// Address of the server hosting the session.
+    pub server: String,
+    /// URL to join the session, including the encryption key.
+    #[serde(default)]
+    pub url: String,
+    /// When the session was opened, in UNIX seconds.
+    #[serde(default)]
+    pub opened_at: u64,
+    /// When the session was closed, in UNIX seconds, if it has been.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub closed_at: Option<u64>,
+}
+
+impl SavedSession {
+    /// Describe a session that was just opened.
+    pub fn new(name: &str, token: &str, url: &str, server: &str) -> Self {
+        Self {
+            name: name.into(),
+            token: token.into(),
+            server: server.into(),
+            url: url.into(),
+            opened_at: unix_now(),
+            closed_at: None,
+        }
+    }
+
+    /// Returns whether the session has been closed.
+    pub fn is_closed(&self) -> bool {
+        self.closed_at.is_some()
+    }
+
+    /// Returns whether the entry is old enough to be pruned at time `now`.
+    fn is_expired(&self, now: u64, max_age: Duration) -> bool {
+        let last_change = self.closed_at.unwrap_or(self.opened_at);
+        now.saturating_sub(last_change) > max_age.as_secs()
+    }
+}
+
+/// An active session reported by the server, see [`query_owned`].
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OwnedSession {
+    /// Name of the session on the server.
+    pub name: String,
+    /// Number of open shells.
+    pub shell_count: usize,
+    /// Number of connected users.
+    pub user_count: usize,
+    /// Milliseconds since the session was last accessed.
+    pub idle_ms: u64,
+}
+
+/// JSON file holding the saved sessions.
+#[derive(Debug, Clone)]
+pub struct SessionStore {
+    path: PathBuf,
+    max_age: Duration,
+}
+
+impl SessionStore {
+    /// Use the store file at the given path.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            max_age: DEFAULT_MAX_AGE,
+        }
+    }
+
+    /// Set the age after which entries are pruned on the next change.
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    /// Use the store file in the default configuration directory.
+    pub fn open_default() -> Option<Self> {
+        Config::dir().map(|dir| Self::new(dir.join(STORE_FILE)))
+    }
+
+    /// Returns the path of the store file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Read all saved sessions, which is empty if the file does not exist.
+    pub fn load(&self) -> Result<Vec<SavedSession>> {
+        match fs::read_to_string(&self.path) {
+            Ok(source) => serde_json::from_str(&source)
+                .with_context(|| format!("invalid session store {}", self.path.display())),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(err) => Err(err)
+                .with_context(|| format!("failed to read session store {}", self.path.display())),
+        }
+    }
+
+    /// Read the sessions that have not been closed.
+    pub fn active(&self) -> Result<Vec<SavedSession>> {
+        let mut sessions = self.load()?;
+        sessions.retain(|saved| !saved.is_closed());
+        Ok(sessions)
+    }
+
+    /// Modify the saved sessions and write them back to the file.
+    ///
+    /// This holds the store's lock throughout, and prunes expired entries.
+    pub fn update<T>(&self, f: impl FnOnce(&mut Vec<SavedSession>) -> T) -> Result<T> {
+        let _lock = self.lock()?;
+        let mut sessions = self.load()?;
+        let result = f(&mut sessions);
+        let now = unix_now();
+        sessions.retain(|saved| !saved.is_expired(now, self.max_age));
+        self.save(&sessions)?;
+        Ok(result)
+    }
+
+    /// Record a newly opened session.
+    pub fn add(&self, session: SavedSession) -> Result<()> {
+        self.update(|sessions| {
+            sessions.retain(|saved| saved.name != session.name);
+            sessions.push(session);
+        })
+    }
+
+    /// Mark a session as closed, returning whether it was saved and open.
+    pub fn mark_closed(&self, name: &str) -> Result<bool> {
+        self.update(|sessions| {
+            let open = sessions
+                .iter_mut()
+                .find(|saved| saved.name == name && !saved.is_closed());
+            match open {
+                Some(saved) => {
+                    saved.closed_at = Some(unix_now());
+                    true
+                }
+                None => false,
+            }
+        })
+    }
+
+    /// Forget a session, returning it if it was saved.
+    pub fn remove(&self, name: &str) -> Result<Option<SavedSession>> {
+        self.update(|sessions| {
+            let index = sessions.iter().position(|saved| saved.name == name)?;
+            Some(sessions.remove(index))
+        })
+    }
+
+    /// Wait for exclusive access to the store, released when dropped.
+    ///
+    /// The store file itself is replaced on every write, so the lock is held
+    /// on a separate file next to it.
+    fn lock(&self) -> Result<File> {
+        let context = || format!("failed to lock session store {}", self.path.display());
+        self.create_dir().with_context(context)?;
+        let mut lock_name = self.path.file_name().unwrap_or_default().to_os_string();
+        lock_name.push(".lock");
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(false);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let file = options.open(self.path.with_file_name(lock_name)).with_context(context)?;
+        file.lock().with_context(context)?;
+        Ok(file)
+    }
+
+    fn create_dir(&self) -> std::io::Result<()> {
+        let Some(dir) = self.path.parent() else {
+            return Ok(());
+        };
+        let mut builder = fs::DirBuilder::new();
+        builder.recursive(true);
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        builder.create(dir)
+    }
+
+    /// Replace the file atomically, creating the directory if needed.
+    fn save(&self, sessions: &[SavedSession]) -> Result<()> {
+        let context = || format!("failed to write session store {}", self.path.display());
+        self.create_dir().with_context(context)?;
+
+        let mut tmp_name = self.path.file_name().unwrap_or_default().to_os_string();
+        tmp_name.push(".tmp");
+        let tmp = self.path.with_file_name(tmp_name);
+
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(&tmp).with_context(context)?;
+        serde_json::to_writer_pretty(&mut file, sessions)?;
+        writeln!(file).and_then(|_| file.sync_all()).with_context(context)?;
+        fs::rename(&tmp, &self.path).with_context(context)
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Ask a server which of the saved sessions are still active.
+///
+/// The server only reports sessions whose token is valid, so sessions missing
+/// from the result have been closed or expired.
+pub async fn query_owned(
+    server: &str,
+    sessions: &[SavedSession],
+    insecure: bool,
+) -> Result<Vec<OwnedSession>> {
+    #[derive(Serialize)]
+    struct Query<'a> {
+        name: &'a str,
+        token: &'a str,
+    }
+
+    let query: Vec<_> = sessions
+        .iter()
+        .map(|session| Query {
+            name: &session.name,
+            token: &session.token,
+        })
+        .collect();
+    let client = reqwest::Client::builder()
+        .danger_accept_invalid_certs(insecure)
+        .build()?;
+    let url = format!("{}/api/sessions/owned", server.trim_end_matches('/'));
+    let response = client.post(url).json(&query).send().await?.error_for_status()?;
+    Ok(response.json().await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{SavedSession, SessionStore};
+
+    fn saved(name: &str) -> SavedSession {
+        SavedSession {
+            name: name.into(),
+            token: format!("token-{name}"),
+            server: "https://sshx.io".into(),
+            url: format!("https://sshx.io/s/{name}#key"),
+            opened_at: super::unix_now(),
+            closed_at: None,
+        }
+    }
+
+    fn temp_store(test: &str) -> SessionStore {
+        let dir = std::env::temp_dir().join(format!("sshx-{test}-{}", std::process::id()));
+        SessionStore::new(dir.join("sessions.json"))
+    }
+
+    #[test]
+    fn add_and_remove() -> anyhow::Result<()> {
+        let store = temp_store("sessions");
+        assert!(store.load()?.is_empty());
+
+        store.add(saved("abc"))?;
+        store.add(saved("def"))?;
+        store.add(saved("abc"))?;
+        assert_eq!(store.load()?, [saved("def"), saved("abc")]);
+
+        assert_eq!(store.remove("abc")?, Some(saved("abc")));
+        assert_eq!(store.remove("abc")?, None);
+        assert_eq!(store.load()?, [saved("def")]);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(store.path())?.permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        std::fs::remove_dir_all(store.path().parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn close_and_prune() -> anyhow::Result<()> {
+        let store = temp_store("prune").with_max_age(Duration::from_secs(3600));
+        let stale = SavedSession {
+            opened_at: super::unix_now() - 7200,
+            ..saved("old")
+        };
+        store.add(saved("abc"))?;
+        store.add(saved("def"))?;
+        assert!(store.mark_closed("abc")?);
+        assert!(!store.mark_closed("abc")?);
+
+        let names = |sessions: Vec<SavedSession>| -> Vec<String> {
+            sessions.into_iter().map(|saved| saved.name).collect()
+        };
+        assert_eq!(names(store.active()?), ["def"]);
+        store.add(stale)?;
+        assert_eq!(names(store.load()?), ["abc", "def"]);
+
+        std::fs::remove_dir_all(store.path().parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn concurrent_writers() -> anyhow::Result<()> {
+        let store = temp_store("concurrent");
+        std::thread::scope(|scope| {
+            for thread in 0..8 {
+                let store = &store;
+                scope.spawn(move || {
+                    for i in 0..5 {
+                        store.add(saved(&format!("s{thread}-{i}"))).unwrap();
+                    }
+                });
+            }
+        });
+        assert_eq!(store.load()?.len(), 40);
+
+        std::fs::remove_dir_all(store.path().parent().unwrap())?;
+        Ok(())
+    }
+}
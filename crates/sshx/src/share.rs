@@ -0,0 +1,188 @@
+//! Sharing session links by email or through chat webhooks.
+//!
+//! Writable links give full control of the shell, so they are only shared
+//! externally when explicitly requested. Otherwise a read-only link is required.
+
+use std::str::FromStr;
+
+use anyhow::{bail, Result};
+use serde_json::json;
+use url::Url;
+
+/// Where to share the session links after connecting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShareTarget {
+    /// Print a `mailto:` URL, optionally addressed to recipients.
+    Mailto(Option<String>),
+    /// Post a message to a Slack or Discord incoming webhook.
+    Webhook(Url),
+}
+
+impl FromStr for ShareTarget {
+    type Err = String;
+
+    /// Parse `mailto`, `mailto:ADDRESS` or an `https://` webhook URL.
+    fn from_str(arg: &str) -> Result<Self, Self::Err> {
+        if arg == "mailto" {
+            return Ok(Self::Mailto(None));
+        }
+        if let Some(to) = arg.strip_prefix("mailto:") {
+            return Ok(Self::Mailto(Some(to.into()).filter(|to: &String| !to.is_empty())));
+        }
+        match Url::parse(arg) {
+            Ok(url) if matches!(url.scheme(), "http" | "https") => Ok(Self::Webhook(url)),
+            _ => Err("expected `mailto`, `mailto:ADDRESS` or a webhook URL".into()),
+        }
+    }
+}
+
+/// Links of a session that are included when sharing it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShareLinks {
+    /// Link for viewers who cannot type in the session.
+    pub read_only: Option<String>,
+    /// Link that can type in the session, only included on request.
+    pub writable: Option<String>,
+}
+
+impl ShareLinks {
+    /// Choose links from a session's URL and its separate write URL, which
+    /// only exists when read-only access is enabled.
+    ///
+    /// Without a write URL the main URL is writable, so it is only shared with
+    /// `include_write`.
+    pub fn select(url: &str, write_url: Option<&str>, include_write: bool) -> Result<Self> {
+        match write_url {
+            Some(write_url) => Ok(Self {
+                read_only: Some(url.into()),
+                writable: include_write.then(|| write_url.into()),
+            }),
+            None if include_write => Ok(Self {
+                read_only: None,
+                writable: Some(url.into()),
+            }),
+            None => bail!(
+                "the session link is writable; use --enable-readers to share a read-only link, \
+                 or --share-write-url to share it anyway"
+            ),
+        }
+    }
+
+    /// Plain-text message describing the session and its links.
+    pub fn message(&self, session_name: &str) -> String {
+        let mut message = format!("Join my sshx session \"{session_name}\"");
+        if let Some(url) = &self.read_only {
+            message += &format!("\nRead-only: {url}");
+        }
+        if let Some(url) = &self.writable {
+            message += &format!("\nWritable: {url}");
+        }
+        message
+    }
+
+    /// Build a `mailto:` URL with the message prefilled as the email body.
+    pub fn mailto(&self, to: Option<&str>, session_name: &str) -> String {
+        format!(
+            "mailto:{}?subject={}&body={}",
+            to.map(|to| encode(to, b"@,+")).unwrap_or_default(),
+            encode(&format!("sshx session: {session_name}"), b""),
+            encode(&self.message(session_name), b""),
+        )
+    }
+
+    /// JSON payload for an incoming webhook, which is `content` for Discord
+    /// and `text` for Slack and compatible services.
+    pub fn webhook_payload(&self, webhook: &Url, session_name: &str) -> serde_json::Value {
+        let discord = webhook
+            .host_str()
+            .is_some_and(|host| host.ends_with("discord.com") || host.ends_with("discordapp.com"));
+        match discord {
+            true => json!({ "content": self.message(session_name) }),
+            false => json!({ "text": self.message(session_name) }),
+        }
+    }
+
+    /// Post the message to an incoming webhook.
+    pub async fn post(&self, webhook: &Url, session_name: &str) -> Result<()> {
+        let response = reqwest::Client::new()
+            .post(webhook.clone())
+            .json(&self.webhook_payload(webhook, session_name))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            bail!("webhook responded with status {}", response.status());
+        }
+        Ok(())
+    }
+}
+
+/// Percent-encode all but unreserved characters and the given extra bytes.
+fn encode(text: &str, keep: &[u8]) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for &byte in text.as_bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) || keep.contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded += &format!("%{byte:02X}");
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ShareLinks, ShareTarget};
+
+    const URL: &str = "https://sshx.io/s/abc#key";
+    const WRITE_URL: &str = "https://sshx.io/s/abc#key,secret";
+
+    #[test]
+    fn parse_target() {
+        assert_eq!("mailto".parse(), Ok(ShareTarget::Mailto(None)));
+        assert_eq!("mailto:".parse(), Ok(ShareTarget::Mailto(None)));
+        assert_eq!("mailto:a@b.io".parse(), Ok(ShareTarget::Mailto(Some("a@b.io".into()))));
+        let webhook = "https://hooks.slack.com/services/T0/B0/x".parse::<ShareTarget>();
+        assert!(matches!(webhook, Ok(ShareTarget::Webhook(_))));
+        assert!("slack".parse::<ShareTarget>().is_err());
+        assert!("file:///etc/passwd".parse::<ShareTarget>().is_err());
+    }
+
+    #[test]
+    fn write_link_is_opt_in() {
+        let links = ShareLinks::select(URL, Some(WRITE_URL), false).unwrap();
+        assert_eq!(links.read_only.as_deref(), Some(URL));
+        assert_eq!(links.writable, None);
+        assert!(!links.message("demo").contains("secret"));
+
+        let links = ShareLinks::select(URL, Some(WRITE_URL), true).unwrap();
+        assert_eq!(links.writable.as_deref(), Some(WRITE_URL));
+
+        // Without read-only access, the only link is writable.
+        assert!(ShareLinks::select(URL, None, false).is_err());
+        let links = ShareLinks::select(URL, None, true).unwrap();
+        assert_eq!((links.read_only, links.writable.as_deref()), (None, Some(URL)));
+    }
+
+    #[test]
+    fn mailto_url() {
+        let links = ShareLinks::select(URL, Some(WRITE_URL), false).unwrap();
+        assert_eq!(
+            links.mailto(Some("a@b.io,c@d.io"), "my demo"),
+            "mailto:a@b.io,c@d.io?subject=sshx%20session%3A%20my%20demo\
+             &body=Join%20my%20sshx%20session%20%22my%20demo%22%0ARead-only%3A%20\
+             https%3A%2F%2Fsshx.io%2Fs%2Fabc%23key",
+        );
+        assert!(links.mailto(None, "x").starts_with("mailto:?subject="));
+    }
+
+    #[test]
+    fn webhook_payload() {
+        let links = ShareLinks::select(URL, Some(WRITE_URL), true).unwrap();
+        let text = format!("Join my sshx session \"demo\"\nRead-only: {URL}\nWritable: {WRITE_URL}");
+
+        let slack = "https://hooks.slack.com/services/T0/B0/x".parse().unwrap();
+        assert_eq!(links.webhook_payload(&slack, "demo"), serde_json::json!({ "text": text }));
+        let discord = "https://discord.com/api/webhooks/1/x".parse().unwrap();
+        assert_eq!(links.webhook_payload(&discord, "demo"), serde_json::json!({ "content": text }));
+    }
+}
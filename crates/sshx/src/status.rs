@@ -0,0 +1,153 @@
+//! Machine-readable status of a running session, for monitoring.
+//!
+//! The host reports this as JSON with `--output json` and `--status-file`, so
+//! operators can alert on sessions that silently fell back to WebSocket or
+//! keep reconnecting. The fields are described by `status.schema.json` in the
+//! crate root, which must be kept in sync with [`SessionStatus`].
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::connection::ConnectionMethod;
+use crate::controller::{Controller, ControllerEvent};
+
+/// Connection health of a session, kept up to date from controller events.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SessionStatus {
+    /// Name of the session on the server.
+    pub name: String,
+    /// URL to join the session, including the encryption key.
+    pub url: String,
+    /// URL with write access, if read-only mode is enabled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub write_url: Option<String>,
+    /// Transport of the current connection, either `grpc` or `websocket`.
+    pub method: &'static str,
+    /// Whether WebSocket is used because the gRPC connection failed.
+    pub fallback: bool,
+    /// Whether the channel to the server is currently up.
+    pub connected: bool,
+    /// Number of times the channel to the server dropped and was retried.
+    pub reconnects: u64,
+    /// Most recent one-way latency from the server, in milliseconds.
+    pub latency_ms: Option<u64>,
+    /// Number of users connected, if the server reports it.
+    pub users: Option<usize>,
+}
+
+impl SessionStatus {
+    /// Start tracking the status of a session before its channel is opened.
+    pub fn new(controller: &Controller) -> Self {
+        let mut status = Self {
+            name: controller.name().into(),
+            url: controller.url().into(),
+            write_url: controller.write_url().map(String::from),
+            method: "grpc",
+            fallback: false,
+            connected: false,
+            reconnects: 0,
+            latency_ms: None,
+            users: None,
+        };
+        if let Some(method) = controller.connection_method() {
+            status.set_method(method);
+        }
+        status
+    }
+
+    fn set_method(&mut self, method: &ConnectionMethod) {
+        (self.method, self.fallback) = match method {
+            ConnectionMethod::Grpc => ("grpc", false),
+            ConnectionMethod::WebSocketFallback => ("websocket", true),
+        };
+    }
+
+    /// Apply a controller event, returning whether any field changed.
+    pub fn update(&mut self, event: &ControllerEvent) -> bool {
+        let old = self.clone();
+        match event {
+            ControllerEvent::Connected { method } => {
+                self.set_method(method);
+                self.connected = true;
+            }
+            ControllerEvent::Disconnected => {
+                self.connected = false;
+                self.reconnects += 1;
+            }
+            ControllerEvent::LatencyMeasured(latency) => {
+                self.latency_ms = Some(latency.as_millis() as u64);
+            }
+            ControllerEvent::UsersChanged(users) => self.users = Some(*users),
+            ControllerEvent::Closed => self.connected = false,
+            _ => (),
+        }
+        *self != old
+    }
+
+    /// Serialize the status as a single line of JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("status serializes to JSON")
+    }
+
+    /// Replace the contents of a file with the status, atomically.
+    ///
+    /// The file contains the session URLs, so it is only readable by the
+    /// current user on Unix.
+    pub fn write_to(&self, path: &Path) -> io::Result<()> {
+        let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+        tmp_name.push(".tmp");
+        let tmp = path.with_file_name(tmp_name);
+
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(&tmp)?;
+        writeln!(file, "{}", self.to_json())?;
+        file.sync_all()?;
+        fs::rename(&tmp, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use sshx_core::Sid;
+
+    use super::SessionStatus;
+    use crate::connection::ConnectionMethod;
+    use crate::controller::ControllerEvent;
+
+    fn status() -> SessionStatus {
+        SessionStatus {
+            name: "abc".into(),
+            url: "https://sshx.io/s/abc#key".into(),
+            write_url: None,
+            method: "grpc",
+            fallback: false,
+            connected: false,
+            reconnects: 0,
+            latency_ms: None,
+            users: None,
+        }
+    }
+
+    #[test]
+    fn tracks_events() {
+        let mut status = status();
+        let method = ConnectionMethod::WebSocketFallback;
+        assert!(status.update(&ControllerEvent::Connected { method }));
+        assert_eq!((status.connected, status.fallback), (true, true));
+        assert!(status.update(&ControllerEvent::LatencyMeasured(Duration::from_millis(42))));
+        assert!(status.update(&ControllerEvent::Disconnected));
+        assert!(!status.update(&ControllerEvent::ShellOpened(Sid(1))));
+        assert_eq!(
+            status.to_json(),
+            r#"{"name":"abc","url":"https://sshx.io/s/abc#key","method":"websocket","fallback":true,"connected":false,"reconnects":1,"latency_ms":42,"users":null}"#
+        );
+    }
+}
@@ -2,6 +2,8 @@
 
 #![allow(unsafe_code)]
 
+use std::path::PathBuf;
+
 cfg_if::cfg_if! {
     if #[cfg(unix)] {
         mod unix;
@@ -14,11 +16,153 @@ cfg_if::cfg_if! {
     }
 }
 
+/// Description of a shell process to spawn inside a terminal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShellCommand {
+    /// Path or name of the shell executable.
+    pub program: String,
+    /// Arguments passed directly to the shell, without any re-parsing.
+    pub args: Vec<String>,
+    /// Launch the shell as a login shell.
+    ///
+    /// On Unix this prepends `-` to `argv[0]`, following the convention used
+    /// by `login(1)`. On Windows, `-l` is passed to Bash-like shells only.
+    pub login: bool,
+    /// Working directory for the shell, if not the current directory.
+    pub cwd: Option<PathBuf>,
+    /// Command written to the shell's input once it first produces output.
+    pub init: Option<String>,
+    /// Banner shown as terminal output before anything from the shell.
+    pub motd: Option<String>,
+    /// Terminal capabilities advertised to the shell through its environment.
+    pub caps: TermCaps,
+    /// Whether to start a new shell in place of one that exited.
+    pub respawn: Respawn,
+}
+
+/// Policy for starting a new shell process when one exits on its own.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Respawn {
+    /// Maximum number of times each shell is replaced, 0 to never respawn.
+    pub max_retries: u32,
+    /// Also replace shells that exited successfully, with status 0.
+    pub on_success: bool,
+}
+
+impl Respawn {
+    /// Whether to replace a shell that exited with this status, after it has
+    /// already been replaced `retries` times.
+    pub fn should_respawn(&self, exit_code: Option<i32>, retries: u32) -> bool {
+        retries < self.max_retries && (self.on_success || exit_code != Some(0))
+    }
+}
+
+impl From<String> for ShellCommand {
+    fn from(program: String) -> Self {
+        Self {
+            program,
+            args: Vec::new(),
+            login: false,
+            cwd: None,
+            init: None,
+            motd: None,
+            caps: TermCaps::default(),
+            respawn: Respawn::default(),
+        }
+    }
+}
+
+impl From<&str> for ShellCommand {
+    fn from(program: &str) -> Self {
+        Self::from(program.to_string())
+    }
+}
+
+/// Terminal capabilities, exported to shells as `TERM` and `COLORTERM`.
+///
+/// Viewers of a session may all use different terminals, so the host's
+/// terminal wins: shells are spawned with the capabilities of the terminal
+/// running `sshx`, and viewers only report their own for display.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TermCaps {
+    /// Value of `TERM` in the shell's environment.
+    pub term: String,
+    /// Value of `COLORTERM`, or unset if the terminal has no color hint.
+    pub colorterm: Option<String>,
+}
+
+impl Default for TermCaps {
+    fn default() -> Self {
+        Self {
+            term: "xterm-256color".into(),
+            colorterm: Some("truecolor".into()),
+        }
+    }
+}
+
+impl TermCaps {
+    /// Read the capabilities of the terminal that this process runs in.
+    pub fn from_env() -> Self {
+        Self::from_vars(std::env::var("TERM").ok(), std::env::var("COLORTERM").ok())
+    }
+
+    /// Build capabilities from `TERM` and `COLORTERM` values.
+    ///
+    /// Falls back to the defaults when `TERM` is missing or `dumb`, as happens
+    /// when running from a service manager without a terminal.
+    pub fn from_vars(term: Option<String>, colorterm: Option<String>) -> Self {
+        match term {
+            Some(term) if !term.is_empty() && term != "dumb" => Self {
+                term,
+                colorterm: colorterm.filter(|c| !c.is_empty()),
+            },
+            _ => Self::default(),
+        }
+    }
+
+    /// Replace the terminal type, keeping the color hint.
+    pub fn with_term(self, term: impl Into<String>) -> Self {
+        Self {
+            term: term.into(),
+            ..self
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use anyhow::Result;
 
-    use super::Terminal;
+    use super::{Respawn, ShellCommand, TermCaps, Terminal};
+
+    #[test]
+    fn respawn_policy() {
+        let respawn = Respawn {
+            max_retries: 2,
+            on_success: false,
+        };
+        assert!(respawn.should_respawn(Some(1), 0));
+        assert!(respawn.should_respawn(None, 1));
+        assert!(!respawn.should_respawn(Some(0), 0));
+        assert!(!respawn.should_respawn(Some(1), 2));
+        assert!(Respawn { on_success: true, ..respawn }.should_respawn(Some(0), 0));
+        assert!(!Respawn::default().should_respawn(Some(1), 0));
+    }
+
+    #[test]
+    fn term_caps_from_vars() {
+        assert_eq!(TermCaps::from_vars(None, None), TermCaps::default());
+        assert_eq!(
+            TermCaps::from_vars(Some("dumb".into()), Some("truecolor".into())),
+            TermCaps::default(),
+        );
+        let caps = TermCaps::from_vars(Some("screen".into()), Some("".into()));
+        assert_eq!(caps.term, "screen");
+        assert_eq!(caps.colorterm, None);
+        let caps = TermCaps::from_vars(None, None).with_term("linux");
+        assert_eq!(caps.term, "linux");
+        assert_eq!(caps.colorterm.as_deref(), Some("truecolor"));
+    }
 
     #[tokio::test]
     async fn winsize() -> Result<()> {
@@ -29,4 +173,115 @@ mod tests {
         assert_eq!(terminal.get_winsize()?, (120, 72));
         Ok(())
     }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn login_shell() -> Result<()> {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let command = ShellCommand {
+            login: true,
+            ..ShellCommand::from("/bin/sh")
+        };
+        let mut terminal = Terminal::spawn(&command).await?;
+        terminal.write_all(b"echo \"arg0=$0\"; exit\n").await?;
+
+        let mut output = String::new();
+        let mut buf = [0u8; 1024];
+        while !output.contains("arg0=-sh") {
+            let n = terminal.read(&mut buf).await?;
+            assert!(n > 0, "terminal closed before printing argv[0]");
+            output.push_str(&String::from_utf8_lossy(&buf[..n]));
+        }
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn exit_code() -> Result<()> {
+        use tokio::io::AsyncReadExt;
+
+        let command = ShellCommand {
+            args: vec!["-c".into(), "exit 7".into()],
+            ..ShellCommand::from("/bin/sh")
+        };
+        let mut terminal = Terminal::spawn(&command).await?;
+
+        let mut buf = [0u8; 1024];
+        while let Ok(n @ 1..) = terminal.read(&mut buf).await {
+            assert!(n <= buf.len());
+        }
+        assert_eq!(terminal.exit_code().await, Some(7));
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn shell_arguments() -> Result<()> {
+        use tokio::io::AsyncReadExt;
+
+        let command = ShellCommand {
+            args: vec!["-c".into(), "echo \"$0 'x'\"".into(), "a b;c".into()],
+            ..ShellCommand::from("/bin/sh")
+        };
+        let mut terminal = Terminal::spawn(&command).await?;
+
+        let mut output = String::new();
+        let mut buf = [0u8; 1024];
+        while !output.contains("a b;c 'x'") {
+            let n = terminal.read(&mut buf).await?;
+            assert!(n > 0, "terminal closed before printing arguments");
+            output.push_str(&String::from_utf8_lossy(&buf[..n]));
+        }
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn term_environment() -> Result<()> {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let command = ShellCommand {
+            caps: TermCaps {
+                term: "xterm-kitty".into(),
+                colorterm: None,
+            },
+            ..ShellCommand::from("/bin/sh")
+        };
+        let mut terminal = Terminal::spawn(&command).await?;
+        terminal
+            .write_all(b"echo \"term=$TERM color=${COLORTERM-unset}\"; exit\n")
+            .await?;
+
+        let mut output = String::new();
+        let mut buf = [0u8; 1024];
+        while !output.contains("term=xterm-kitty color=unset") {
+            let n = terminal.read(&mut buf).await?;
+            assert!(n > 0, "terminal closed before printing environment");
+            output.push_str(&String::from_utf8_lossy(&buf[..n]));
+        }
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn working_directory() -> Result<()> {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let command = ShellCommand {
+            cwd: Some("/tmp".into()),
+            ..ShellCommand::from("/bin/sh")
+        };
+        let mut terminal = Terminal::spawn(&command).await?;
+        terminal.write_all(b"echo \"cwd=$(pwd)\"; exit\n").await?;
+
+        let mut output = String::new();
+        let mut buf = [0u8; 1024];
+        while !output.contains("cwd=/tmp") {
+            let n = terminal.read(&mut buf).await?;
+            assert!(n > 0, "terminal closed before printing working directory");
+            output.push_str(&String::from_utf8_lossy(&buf[..n]));
+        }
+        Ok(())
+    }
 }
@@ -2,8 +2,10 @@ use std::convert::Infallible;
 use std::env;
 use std::ffi::{CStr, CString};
 use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::ffi::OsStrExt;
 use std::pin::Pin;
 use std::task::{Context, Poll};
+use std::time::Duration;
 
 use anyhow::Result;
 use close_fds::CloseFdsBuilder;
@@ -11,20 +13,30 @@ use nix::errno::Errno;
 use nix::libc::{login_tty, TIOCGWINSZ, TIOCSWINSZ};
 use nix::pty::{self, Winsize};
 use nix::sys::signal::{kill, Signal::SIGKILL};
-use nix::sys::wait::waitpid;
-use nix::unistd::{execvp, fork, ForkResult, Pid};
+use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+use nix::unistd::{chdir, execvp, fork, ForkResult, Pid};
 use pin_project::{pin_project, pinned_drop};
 use tokio::fs::{self, File};
 use tokio::io::{self, AsyncRead, AsyncWrite};
+use tokio::time;
 use tracing::{instrument, trace};
 
+use super::{ShellCommand, TermCaps};
+
 /// Returns the default shell on this system.
+///
+/// The shell is resolved in order from the `$SHELL` environment variable, the
+/// current user's entry in `/etc/passwd`, and then a list of common shell
+/// locations. If all of these fail, it returns `sh`.
 pub async fn get_default_shell() -> String {
     if let Ok(shell) = env::var("SHELL") {
         if !shell.is_empty() {
             return shell;
         }
     }
+    if let Some(shell) = passwd_shell(&whoami::username()).await {
+        return shell;
+    }
     for shell in [
         "/bin/bash",
         "/bin/sh",
@@ -38,10 +50,29 @@ pub async fn get_default_shell() -> String {
     String::from("sh")
 }
 
+/// Look up the login shell of a user from `/etc/passwd`, if it exists.
+async fn passwd_shell(username: &str) -> Option<String> {
+    let passwd = fs::read_to_string("/etc/passwd").await.ok()?;
+    parse_passwd_shell(&passwd, username)
+}
+
+fn parse_passwd_shell(passwd: &str, username: &str) -> Option<String> {
+    passwd.lines().find_map(|line| {
+        let fields: Vec<&str> = line.split(':').collect();
+        match fields[..] {
+            [user, _, _, _, _, _, shell] if user == username && !shell.is_empty() => {
+                Some(shell.to_string())
+            }
+            _ => None,
+        }
+    })
+}
+
 /// An object that stores the state for a terminal session.
 #[pin_project(PinnedDrop)]
 pub struct Terminal {
     child: Pid,
+    exited: bool,
     #[pin]
     master_read: File,
     #[pin]
@@ -50,12 +81,17 @@ pub struct Terminal {
 
 impl Terminal {
     /// Create a new terminal, with attached PTY.
-    #[instrument]
     pub async fn new(shell: &str) -> Result<Terminal> {
+        Self::spawn(&ShellCommand::from(shell)).await
+    }
+
+    /// Create a new terminal running the given shell command, with attached PTY.
+    #[instrument]
+    pub async fn spawn(command: &ShellCommand) -> Result<Terminal> {
         let result = pty::openpty(None, None)?;
 
         // The slave file descriptor was created by openpty() and is forked here.
-        let child = Self::fork_child(shell, result.slave.as_raw_fd())?;
+        let child = Self::fork_child(command, result.slave.as_raw_fd())?;
 
         // We need to clone the file object to prevent livelocks in Tokio, when multiple
         // reads and writes happen concurrently on the same file descriptor. This is a
@@ -68,41 +104,75 @@ impl Terminal {
 
         Ok(Self {
             child,
+            exited: false,
             master_read,
             master_write,
         })
     }
 
     /// Entry point for the child process, which spawns a shell.
-    fn fork_child(shell: &str, slave_port: RawFd) -> Result<Pid> {
-        let shell = CString::new(shell.to_owned())?;
+    fn fork_child(command: &ShellCommand, slave_port: RawFd) -> Result<Pid> {
+        let shell = CString::new(command.program.clone())?;
+        let arg0 = if command.login {
+            let name = command.program.rsplit('/').next().unwrap_or_default();
+            CString::new(format!("-{name}"))?
+        } else {
+            shell.clone()
+        };
+        let mut argv = vec![arg0];
+        for arg in &command.args {
+            argv.push(CString::new(arg.as_str())?);
+        }
+        let cwd = match &command.cwd {
+            Some(cwd) => Some(CString::new(cwd.as_os_str().as_bytes())?),
+            None => None,
+        };
 
         // Safety: This does not use any async-signal-unsafe operations in the child
         // branch, such as memory allocation.
         match unsafe { fork() }? {
             ForkResult::Parent { child } => Ok(child),
-            ForkResult::Child => match Self::execv_child(&shell, slave_port) {
+            ForkResult::Child => match Self::execv_child(
+                &shell,
+                &argv,
+                cwd.as_deref(),
+                &command.caps,
+                slave_port,
+            ) {
                 Ok(infallible) => match infallible {},
                 Err(_) => std::process::exit(1),
             },
         }
     }
 
-    fn execv_child(shell: &CStr, slave_port: RawFd) -> Result<Infallible, Errno> {
+    fn execv_child(
+        shell: &CStr,
+        argv: &[CString],
+        cwd: Option<&CStr>,
+        caps: &TermCaps,
+        slave_port: RawFd,
+    ) -> Result<Infallible, Errno> {
         // Safety: The slave file descriptor was created by openpty().
         Errno::result(unsafe { login_tty(slave_port) })?;
         // Safety: This is called immediately before an execv(), and there are no other
         // threads in this process to interact with its file descriptor table.
         unsafe { CloseFdsBuilder::new().closefrom(3) };
 
+        if let Some(cwd) = cwd {
+            chdir(cwd)?;
+        }
+
         // Set terminal environment variables appropriately.
-        env::set_var("TERM", "xterm-256color");
-        env::set_var("COLORTERM", "truecolor");
+        env::set_var("TERM", &caps.term);
+        match &caps.colorterm {
+            Some(colorterm) => env::set_var("COLORTERM", colorterm),
+            None => env::remove_var("COLORTERM"),
+        }
         env::set_var("TERM_PROGRAM", "sshx");
         env::remove_var("TERM_PROGRAM_VERSION");
 
         // Start the process.
-        execvp(shell, &[shell])
+        execvp(shell, argv)
     }
 
     /// Get the window size of the TTY.
@@ -122,6 +192,29 @@ impl Terminal {
         unsafe { ioctl_set_winsize(self.master_read.as_raw_fd(), &winsize) }?;
         Ok(())
     }
+
+    /// Wait briefly for the shell process to exit, returning its exit code.
+    ///
+    /// This should be called after the PTY reaches end-of-file. Processes
+    /// killed by a signal report `128 + signal`, like most shells. Returns
+    /// `None` if the process has not exited after a short grace period.
+    pub async fn exit_code(&mut self) -> Option<i32> {
+        for _ in 0..50 {
+            match waitpid(self.child, Some(WaitPidFlag::WNOHANG)) {
+                Ok(WaitStatus::Exited(_, code)) => {
+                    self.exited = true;
+                    return Some(code);
+                }
+                Ok(WaitStatus::Signaled(_, signal, _)) => {
+                    self.exited = true;
+                    return Some(128 + signal as i32);
+                }
+                Ok(_) => time::sleep(Duration::from_millis(10)).await,
+                Err(_) => return None,
+            }
+        }
+        None
+    }
 }
 
 // Redirect terminal reads to the read file object.
@@ -161,6 +254,11 @@ impl PinnedDrop for Terminal {
         let child = *this.child;
         trace!(%child, "dropping terminal");
 
+        // The process was already reaped, so its PID may have been reused.
+        if *this.exited {
+            return;
+        }
+
         // Kill the child process on closure so that it doesn't keep running.
         kill(child, SIGKILL).ok();
 
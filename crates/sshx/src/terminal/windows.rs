@@ -9,15 +9,22 @@ use tokio::fs::{self, File};
 use tokio::io::{self, AsyncRead, AsyncWrite};
 use tracing::instrument;
 
+use super::ShellCommand;
+
 /// Returns the default shell on this system.
 ///
-/// For Windows, this is implemented currently to just look for shells at a
-/// couple locations. If it fails, it returns `cmd.exe`.
+/// For Windows, this checks the `%COMSPEC%` environment variable and then looks
+/// for shells at a couple locations. If it fails, it returns `cmd.exe`.
 ///
 /// Note: I can't get `powershell.exe` to work with ConPTY, since it returns
 /// error 8009001d. There's some magic environment variables that need to be set
 /// for Powershell to launch. This is why I don't typically use Windows!
 pub async fn get_default_shell() -> String {
+    if let Ok(shell) = std::env::var("COMSPEC") {
+        if !shell.is_empty() && fs::metadata(&shell).await.is_ok() {
+            return shell;
+        }
+    }
     for shell in [
         "C:\\Program Files\\Git\\bin\\bash.exe",
         "C:\\Windows\\System32\\cmd.exe",
@@ -42,13 +49,28 @@ pub struct Terminal {
 
 impl Terminal {
     /// Create a new terminal, with attached PTY.
-    #[instrument]
     pub async fn new(shell: &str) -> Result<Terminal> {
-        let mut command = Command::new(shell);
+        Self::spawn(&ShellCommand::from(shell)).await
+    }
+
+    /// Create a new terminal running the given shell command, with ConPTY.
+    #[instrument]
+    pub async fn spawn(shell: &ShellCommand) -> Result<Terminal> {
+        let mut command = Command::new(&shell.program);
+        if shell.login && shell.program.to_lowercase().contains("bash") {
+            command.arg("-l");
+        }
+        command.args(&shell.args);
+        if let Some(cwd) = &shell.cwd {
+            command.current_dir(cwd);
+        }
 
         // Set terminal environment variables appropriately.
-        command.env("TERM", "xterm-256color");
-        command.env("COLORTERM", "truecolor");
+        command.env("TERM", &shell.caps.term);
+        match &shell.caps.colorterm {
+            Some(colorterm) => command.env("COLORTERM", colorterm),
+            None => command.env_remove("COLORTERM"),
+        };
         command.env("TERM_PROGRAM", "sshx");
         command.env_remove("TERM_PROGRAM_VERSION");
 
@@ -78,6 +100,11 @@ impl Terminal {
         self.winsize = (rows, cols);
         Ok(())
     }
+
+    /// Wait briefly for the shell process to exit, returning its exit code.
+    pub async fn exit_code(&mut self) -> Option<i32> {
+        self.child.wait(Some(500)).ok().map(|code| code as i32)
+    }
 }
 
 // Redirect terminal reads to the read file object.
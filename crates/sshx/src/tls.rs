@@ -0,0 +1,88 @@
+//! Insecure TLS settings for testing against local servers.
+//!
+//! Servers with self-signed certificates fail verification with the default
+//! roots. For quick local testing, [`insecure_config`] accepts any certificate
+//! instead. This removes all protection against impersonation of the server,
+//! so it is never enabled by default.
+
+use std::io;
+use std::sync::Arc;
+
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{ClientConfig, DigitallySignedStruct, SignatureScheme};
+use tokio::net::TcpStream;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::TlsConnector;
+
+/// Returns a TLS client configuration that accepts any server certificate.
+///
+/// The configuration offers HTTP/2 through ALPN, so that it also works for
+/// gRPC connections.
+pub fn insecure_config() -> Arc<ClientConfig> {
+    let mut config = ClientConfig::builder()
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(AcceptAnyCertificate))
+        .with_no_client_auth();
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Arc::new(config)
+}
+
+/// Print a warning that certificate checks are off, shown whenever `--insecure`
+/// is enabled from any source and regardless of `--quiet`.
+pub fn warn_insecure() {
+    eprintln!(
+        "\n  WARNING: --insecure is set, so TLS certificates are not verified. Anyone on \
+         the network\n  can impersonate the server and read your terminal. This is for \
+         testing only!\n"
+    );
+}
+
+/// Start a TLS session over a connected stream without verifying the server.
+pub async fn connect_insecure(stream: TcpStream, host: &str) -> io::Result<TlsStream<TcpStream>> {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let name = ServerName::try_from(host.to_owned())
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    TlsConnector::from(insecure_config()).connect(name, stream).await
+}
+
+/// Certificate verifier that skips all checks.
+#[derive(Debug)]
+struct AcceptAnyCertificate;
+
+impl ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        rustls::crypto::ring::default_provider()
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
@@ -9,14 +9,22 @@ use sshx_core::proto::{
     sshx_service_client::SshxServiceClient, CloseRequest, OpenRequest, OpenResponse,
 };
 use tokio_stream::wrappers::ReceiverStream;
-use tonic::transport::Channel;
-use tonic::Request;
+use hyper_util::rt::TokioIo;
+use tonic::transport::{Channel, Endpoint, Uri};
+use tower::service_fn;
+use tonic::metadata::{Ascii, MetadataKey, MetadataValue};
+use tonic::service::{interceptor::InterceptedService, Interceptor};
+use tonic::{Request, Status};
 use tracing::debug;
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::{mpsc, Mutex};
 use tokio::time::{timeout, Duration};
-use tokio_tungstenite::{connect_async, tungstenite::Message};
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::handshake::client::Request as WsRequest;
+use tokio_tungstenite::tungstenite::http::header::{HeaderName, HeaderValue, SEC_WEBSOCKET_PROTOCOL};
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::{client_async_tls_with_config, tungstenite::Message};
 use futures_util::{SinkExt, StreamExt, stream::SplitSink, stream::SplitStream};
 use tokio_tungstenite::WebSocketStream;
 use tokio_tungstenite::MaybeTlsStream;
@@ -26,6 +34,8 @@ use sshx_core::proto::{
     ClientUpdate, ServerUpdate, client_update::ClientMessage, server_update::ServerMessage, 
     CliRequest, CliResponse, cli_request, cli_response, ChannelStartRequest
 };
+use sshx_core::redact_url;
+use crate::dial::Dialer;
 use pin_project::pin_project;
 use std::pin::Pin;
 use std::task::{Context as TaskContext, Poll};
@@ -100,19 +110,95 @@ pub trait SshxTransport: Send + Sync + std::fmt::Debug {
     fn connection_type(&self) -> &'static str;
 }
 
+/// HTTP headers that are managed by the transports or stripped by proxies,
+/// and so can't be set with [`parse_header`].
+const RESERVED_HEADERS: [&str; 11] = [
+    "connection",
+    "content-length",
+    "content-type",
+    "host",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Parse an extra HTTP header from a `Name: Value` argument.
+///
+/// Names are lowercased. Hop-by-hop headers and those owned by the gRPC or
+/// WebSocket protocols are rejected.
+pub fn parse_header(arg: &str) -> Result<(String, String), String> {
+    let (name, value) = arg
+        .split_once(':')
+        .ok_or("expected a header in the form \"Name: Value\"")?;
+    let name = HeaderName::from_bytes(name.trim().as_bytes())
+        .map_err(|_| format!("invalid header name: {:?}", name.trim()))?;
+    let value = value.trim();
+    HeaderValue::from_str(value).map_err(|_| format!("invalid value for header {name}"))?;
+    let name = name.as_str();
+    if RESERVED_HEADERS.contains(&name)
+        || name.starts_with("grpc-")
+        || name.starts_with("sec-websocket-")
+        || name.ends_with("-bin")
+    {
+        return Err(format!("header {name} cannot be set"));
+    }
+    Ok((name.into(), value.into()))
+}
+
+/// Interceptor that attaches extra headers to every gRPC request.
+#[derive(Debug, Clone, Default)]
+pub struct HeaderInterceptor {
+    headers: Arc<Vec<(MetadataKey<Ascii>, MetadataValue<Ascii>)>>,
+}
+
+impl HeaderInterceptor {
+    /// Create an interceptor from headers validated by [`parse_header`].
+    ///
+    /// Headers that are not valid gRPC metadata are skipped.
+    pub fn new(headers: &[(String, String)]) -> Self {
+        let headers = headers
+            .iter()
+            .filter_map(|(name, value)| {
+                let key = MetadataKey::from_bytes(name.as_bytes()).ok()?;
+                Some((key, value.parse().ok()?))
+            })
+            .collect();
+        Self {
+            headers: Arc::new(headers),
+        }
+    }
+}
+
+impl Interceptor for HeaderInterceptor {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        for (key, value) in self.headers.iter() {
+            request.metadata_mut().insert(key.clone(), value.clone());
+        }
+        Ok(request)
+    }
+}
+
 /// gRPC transport implementation wrapping the existing tonic client.
 #[derive(Debug)]
 pub struct GrpcTransport {
-    client: SshxServiceClient<Channel>,
+    client: SshxServiceClient<InterceptedService<Channel, HeaderInterceptor>>,
 }
 
 impl GrpcTransport {
-    /// Create a new gRPC transport from an existing client.
+    /// Create a new gRPC transport from a connected channel.
     ///
     /// # Arguments
-    /// * `client` - Pre-connected gRPC client
-    pub fn new(client: SshxServiceClient<Channel>) -> Self {
-        Self { client }
+    /// * `channel` - Pre-connected gRPC channel
+    /// * `headers` - Extra headers attached to every request
+    pub fn new(channel: Channel, headers: &[(String, String)]) -> Self {
+        let interceptor = HeaderInterceptor::new(headers);
+        Self {
+            client: SshxServiceClient::with_interceptor(channel, interceptor),
+        }
     }
 
     /// Create a new gRPC transport by connecting to a server.
@@ -123,9 +209,48 @@ impl GrpcTransport {
     /// # Returns
     /// A connected gRPC transport instance
     pub async fn connect(origin: &str) -> Result<Self, tonic::transport::Error> {
-        debug!(%origin, "connecting via gRPC");
-        let client = SshxServiceClient::connect(String::from(origin)).await?;
-        Ok(Self::new(client))
+        Self::connect_with(origin, &Dialer::default(), &[]).await
+    }
+
+    /// Create a new gRPC transport, opening TCP connections with a dialer and
+    /// attaching extra headers to every request.
+    pub async fn connect_with(
+        origin: &str,
+        dialer: &Dialer,
+        headers: &[(String, String)],
+    ) -> Result<Self, tonic::transport::Error> {
+        debug!(origin = %redact_url(origin), "connecting via gRPC");
+        let endpoint = Endpoint::from_shared(String::from(origin))?;
+        if dialer.insecure && endpoint.uri().scheme_str() == Some("https") {
+            // Tonic always verifies certificates, so the connector negotiates
+            // TLS itself, behind a plaintext endpoint with the same origin.
+            let origin = endpoint.uri().clone();
+            let host = origin.host().unwrap_or_default();
+            let port = origin.port_u16().unwrap_or(443);
+            let dialer = dialer.clone();
+            let connector = service_fn(move |uri: Uri| {
+                let dialer = dialer.clone();
+                async move {
+                    let stream = dialer.connect_uri(&uri).await?;
+                    let host = uri.host().unwrap_or_default();
+                    crate::tls::connect_insecure(stream, host).await.map(TokioIo::new)
+                }
+            });
+            let channel = Endpoint::from_shared(format!("http://{host}:{port}"))?
+                .origin(origin)
+                .connect_with_connector(connector)
+                .await?;
+            return Ok(Self::new(channel, headers));
+        }
+        let dialer = dialer.clone();
+        let connector = service_fn(move |uri: Uri| {
+            let dialer = dialer.clone();
+            async move { dialer.connect_uri(&uri).await.map(TokioIo::new) }
+        });
+        let channel = endpoint
+            .connect_with_connector(connector)
+            .await?;
+        Ok(Self::new(channel, headers))
     }
 }
 
@@ -165,6 +290,34 @@ impl SshxTransport for GrpcTransport {
     }
 }
 
+/// Describe why the server closed a WebSocket, from its close code and reason.
+///
+/// The server uses application close codes for failures such as a missing
+/// session, which are mapped to actionable messages here.
+pub fn describe_close(code: u16, reason: &str) -> String {
+    let message = match code {
+        1001 => "server is going away, it may be restarting",
+        4403 => "access to the session was denied",
+        4404 => "session not found or expired",
+        4429 => "too many failed authentication attempts",
+        4500 => "server failed to connect to the session",
+        _ => "connection closed by the server",
+    };
+    if reason.is_empty() {
+        format!("{message} (code {code})")
+    } else {
+        format!("{message} (code {code}: {reason})")
+    }
+}
+
+/// Senders for responses to in-flight requests, by request ID.
+///
+/// This holds why the connection closed once it has, so no new requests are
+/// accepted and the senders of outstanding ones have been dropped.
+type PendingRequests = Arc<
+    Mutex<Result<HashMap<String, tokio::sync::oneshot::Sender<cli_response::CliResponseMessage>>, String>>,
+>;
+
 /// WebSocket transport implementation for CLI communication.
 ///
 /// This transport provides WebSocket-based communication using JSON
@@ -175,7 +328,7 @@ pub struct WebSocketTransport {
     /// Channel for receiving server messages.
     server_rx: Arc<Mutex<mpsc::Receiver<ServerUpdate>>>,
     /// Request correlation map for matching responses.
-    pending_requests: Arc<Mutex<HashMap<String, tokio::sync::oneshot::Sender<cli_response::CliResponseMessage>>>>,
+    pending_requests: PendingRequests,
     /// Background task handle for the WebSocket reader.
     _reader_task: tokio::task::JoinHandle<()>,
     /// Next request ID counter.
@@ -191,10 +344,29 @@ impl WebSocketTransport {
     /// # Returns
     /// A connected WebSocket transport instance
     pub async fn connect(endpoint: &str) -> Result<Self> {
-        debug!(%endpoint, "connecting via WebSocket");
+        Self::connect_with(endpoint, &Dialer::default(), &WsConnectOptions::default(), &[]).await
+    }
+
+    /// Create a new WebSocket transport, opening TCP connections with a dialer
+    /// and attaching extra headers to the upgrade request.
+    pub async fn connect_with(
+        endpoint: &str,
+        dialer: &Dialer,
+        options: &WsConnectOptions,
+        headers: &[(String, String)],
+    ) -> Result<Self> {
+        debug!(endpoint = %redact_url(endpoint), "connecting via WebSocket");
         
         let url = Url::parse(endpoint).context("Failed to parse WebSocket URL")?;
-        let (ws_stream, _) = connect_async(url).await
+        let mut request = options.request(endpoint)?;
+        for (name, value) in headers {
+            let name = HeaderName::from_bytes(name.as_bytes())?;
+            request.headers_mut().append(name, HeaderValue::from_str(value)?);
+        }
+        let stream = dialer.connect_url(&url).await
+            .context("Failed to connect to WebSocket")?;
+        let (ws_stream, _) =
+            client_async_tls_with_config(request, stream, None, dialer.ws_connector()).await
             .context("Failed to connect to WebSocket")?;
         
         let (write, read) = ws_stream.split();
@@ -203,8 +375,7 @@ impl WebSocketTransport {
         let (server_tx, server_rx) = mpsc::channel(256);
         let server_rx = Arc::new(Mutex::new(server_rx));
         
-        let pending_requests: Arc<Mutex<HashMap<String, tokio::sync::oneshot::Sender<cli_response::CliResponseMessage>>>> = 
-            Arc::new(Mutex::new(HashMap::new()));
+        let pending_requests: PendingRequests = Arc::new(Mutex::new(Ok(HashMap::new())));
         
         let next_request_id = Arc::new(Mutex::new(0));
         
@@ -228,11 +399,12 @@ impl WebSocketTransport {
     fn spawn_reader_task(
         mut read: SplitStream<WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>>>,
         server_tx: mpsc::Sender<ServerUpdate>,
-        pending_requests: Arc<Mutex<HashMap<String, tokio::sync::oneshot::Sender<cli_response::CliResponseMessage>>>>,
+        pending_requests: PendingRequests,
     ) -> tokio::task::JoinHandle<()> {
         tokio::spawn(async move {
             debug!("WebSocket reader task started");
             let mut message_count = 0u64;
+            let mut closed = String::from("WebSocket connection closed");
             while let Some(msg) = read.next().await {
                 message_count += 1;
                 match msg {
@@ -247,16 +419,27 @@ impl WebSocketTransport {
                     }
                     Ok(Message::Close(frame)) => {
                         debug!(message_count = %message_count, ?frame, "WebSocket connection closed by server");
+                        if let Some(frame) = frame {
+                            closed = describe_close(frame.code.into(), &frame.reason);
+                            if frame.code != CloseCode::Normal {
+                                let error = ServerMessage::Error(closed.clone());
+                                let update = ServerUpdate { server_message: Some(error) };
+                                server_tx.send(update).await.ok();
+                            }
+                        }
                         break;
                     }
                     Err(e) => {
                         debug!(message_count = %message_count, "WebSocket error: {}", e);
+                        closed = format!("WebSocket connection closed: {e}");
                         break;
                     }
                     _ => {} // Ignore other message types
                 }
             }
             debug!(message_count = %message_count, "WebSocket reader task exiting");
+            // Fail outstanding requests now, rather than after their timeouts.
+            *pending_requests.lock().await = Err(closed);
         })
     }
     
@@ -264,12 +447,12 @@ impl WebSocketTransport {
     async fn handle_binary_message(
         data: &[u8],
         server_tx: &mpsc::Sender<ServerUpdate>,
-        pending_requests: &Arc<Mutex<HashMap<String, tokio::sync::oneshot::Sender<cli_response::CliResponseMessage>>>>,
+        pending_requests: &PendingRequests,
     ) -> Result<()> {
         // Try to parse as CLI response first
         if let Ok(response) = CliResponse::decode(data) {
-            // Handle streaming messages (sent with "server_update" ID)
-            if response.id == "server_update" {
+            // Handle streaming messages, which are not replies to any request
+            if response.streaming {
                 debug!("Received server update: {:?}", response.cli_response_message);
                 if let Some(msg) = response.cli_response_message {
                     let server_update = Self::cli_response_to_server_update(msg)?;
@@ -280,7 +463,7 @@ impl WebSocketTransport {
             
             // Handle request-response messages
             let mut pending = pending_requests.lock().await;
-            if let Some(sender) = pending.remove(&response.id) {
+            if let Some(sender) = pending.as_mut().ok().and_then(|p| p.remove(&response.id)) {
                 if let Some(msg) = response.cli_response_message {
                     let _ = sender.send(msg);
                 }
@@ -312,9 +495,18 @@ impl WebSocketTransport {
             cli_response::CliResponseMessage::Resize(resize) => {
                 ServerMessage::Resize(resize)
             }
+            cli_response::CliResponseMessage::UserJoined(id) => {
+                ServerMessage::UserJoined(id)
+            }
+            cli_response::CliResponseMessage::UserLeft(id) => {
+                ServerMessage::UserLeft(id)
+            }
             cli_response::CliResponseMessage::Ping(timestamp) => {
                 ServerMessage::Ping(timestamp)
             }
+            cli_response::CliResponseMessage::UserCount(count) => {
+                ServerMessage::UserCount(count)
+            }
             cli_response::CliResponseMessage::Error(message) => {
                 ServerMessage::Error(message)
             }
@@ -344,7 +536,10 @@ impl WebSocketTransport {
         let (tx, rx) = tokio::sync::oneshot::channel();
         {
             let mut pending = self.pending_requests.lock().await;
-            pending.insert(id.clone(), tx);
+            match pending.as_mut() {
+                Ok(pending) => pending.insert(id.clone(), tx),
+                Err(closed) => anyhow::bail!("{closed}"),
+            };
         }
         
         let mut buf = Vec::new();
@@ -361,15 +556,18 @@ impl WebSocketTransport {
         match timeout(Duration::from_secs(30), rx).await {
             Ok(Ok(response)) => Ok(response),
             Ok(Err(_)) => {
-                // Remove from pending if still there
-                let mut pending = self.pending_requests.lock().await;
-                pending.remove(&id);
-                Err(anyhow::anyhow!("Request sender was dropped"))
+                // The reader dropped all pending requests when it exited
+                match &*self.pending_requests.lock().await {
+                    Err(closed) => Err(anyhow::anyhow!("{closed}")),
+                    Ok(_) => Err(anyhow::anyhow!("Request sender was dropped")),
+                }
             }
             Err(_) => {
                 // Remove from pending on timeout
                 let mut pending = self.pending_requests.lock().await;
-                pending.remove(&id);
+                if let Ok(pending) = pending.as_mut() {
+                    pending.remove(&id);
+                }
                 Err(anyhow::anyhow!("Request timed out"))
             }
         }
@@ -420,9 +618,10 @@ impl SshxTransport for WebSocketTransport {
             .context("Failed to start WebSocket channel")?;
         
         // Verify we got the expected response
-        match response {
-            cli_response::CliResponseMessage::StartChannel(_) => {
+        let resume = match response {
+            cli_response::CliResponseMessage::StartChannel(start) => {
                 debug!("WebSocket channel started successfully");
+                start.sequence_numbers
             }
             cli_response::CliResponseMessage::Error(message) => {
                 return Err(anyhow::anyhow!("Server error starting channel: {}", message));
@@ -430,10 +629,16 @@ impl SshxTransport for WebSocketTransport {
             _ => {
                 return Err(anyhow::anyhow!("Unexpected response to StartChannel"));
             }
-        }
+        };
         
         // Create a channel for the streaming interface
         let (stream_tx, stream_rx) = mpsc::channel(256);
+
+        // Resume by resending output the server has not received yet
+        if let Some(seqnums) = resume {
+            let update = ServerUpdate { server_message: Some(ServerMessage::Sync(seqnums)) };
+            stream_tx.send(Ok(update)).await.ok();
+        }
         
         // Clone shared state for the outbound message handler
         let write = self.write.clone();
@@ -567,6 +772,15 @@ impl WebSocketTransport {
             ClientMessage::ClosedShell(shell_id) => {
                 Ok(cli_request::CliMessage::ClosedShell(shell_id))
             }
+            ClientMessage::ExitedShell(exit) => {
+                Ok(cli_request::CliMessage::ExitedShell(exit))
+            }
+            ClientMessage::SpectatorData(terminal_data) => {
+                Ok(cli_request::CliMessage::SpectatorData(terminal_data))
+            }
+            ClientMessage::Locked(locked) => {
+                Ok(cli_request::CliMessage::Locked(locked))
+            }
             ClientMessage::Pong(timestamp) => {
                 Ok(cli_request::CliMessage::Pong(timestamp))
             }
@@ -596,6 +810,54 @@ impl WebSocketTransport {
 /// assert_eq!(ws_url, "ws://localhost:8051/api/cli/test");
 /// ```
 pub fn grpc_to_websocket_url(grpc_url: &str, session_name: &str) -> String {
+    grpc_to_websocket_url_with(grpc_url, session_name, DEFAULT_WS_PATH_PREFIX)
+}
+
+/// Default path prefix of the server's WebSocket routes.
+pub const DEFAULT_WS_PATH_PREFIX: &str = "/api";
+
+/// Settings for WebSocket connections, shared by the CLI and `sshx-term`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WsConnectOptions {
+    /// Path prefix of the server's WebSocket routes.
+    pub path_prefix: String,
+    /// Subprotocol sent as `Sec-WebSocket-Protocol`, for gateways that route
+    /// on it. The server echoes it back in the upgrade response.
+    pub subprotocol: Option<String>,
+}
+
+impl Default for WsConnectOptions {
+    fn default() -> Self {
+        Self {
+            path_prefix: DEFAULT_WS_PATH_PREFIX.into(),
+            subprotocol: None,
+        }
+    }
+}
+
+impl WsConnectOptions {
+    /// Build the upgrade request for a WebSocket URL.
+    pub fn request(&self, url: &str) -> Result<WsRequest> {
+        let mut request = url.into_client_request()?;
+        if let Some(subprotocol) = &self.subprotocol {
+            let value = HeaderValue::from_str(subprotocol)
+                .context("invalid WebSocket subprotocol")?;
+            request.headers_mut().insert(SEC_WEBSOCKET_PROTOCOL, value);
+        }
+        Ok(request)
+    }
+}
+
+/// Convert a gRPC server URL to its WebSocket CLI endpoint, for servers behind
+/// a reverse proxy that serves the API routes under a different path prefix.
+///
+/// # Examples
+/// ```
+/// # use sshx::transport::grpc_to_websocket_url_with;
+/// let ws_url = grpc_to_websocket_url_with("https://example.com", "test", "/sshx/api");
+/// assert_eq!(ws_url, "wss://example.com/sshx/api/cli/test");
+/// ```
+pub fn grpc_to_websocket_url_with(grpc_url: &str, session_name: &str, prefix: &str) -> String {
     let url = grpc_url
         .replace("https://", "wss://")
         .replace("http://", "ws://");
@@ -603,7 +865,16 @@ pub fn grpc_to_websocket_url(grpc_url: &str, session_name: &str) -> String {
     // Handle the case where the URL might end with a slash
     let base = url.trim_end_matches('/');
     
-    format!("{}/api/cli/{}", base, session_name)
+    format!("{}{}/cli/{}", base, normalize_path_prefix(prefix), session_name)
+}
+
+/// Normalize a path prefix to have a leading slash and no trailing slash, or
+/// to be empty for the root.
+pub fn normalize_path_prefix(prefix: &str) -> String {
+    match prefix.trim_matches('/') {
+        "" => String::new(),
+        prefix => format!("/{prefix}"),
+    }
 }
 
 /// Test helper to create a mock transport for testing.
@@ -613,7 +884,7 @@ pub mod test_helpers {
     use tokio::sync::Mutex;
 
     /// Mock transport for testing that records method calls.
-    #[derive(Debug)]
+    #[derive(Debug, Default)]
     pub struct MockTransport {
         /// Record of all method calls made to this transport.
         pub calls: Arc<Mutex<Vec<String>>>,
@@ -624,10 +895,7 @@ pub mod test_helpers {
     impl MockTransport {
         /// Create a new mock transport.
         pub fn new() -> Self {
-            Self {
-                calls: Arc::new(Mutex::new(Vec::new())),
-                error: None,
-            }
+            Self::default()
         }
 
         /// Create a mock transport that will return errors.
@@ -650,6 +918,8 @@ pub mod test_helpers {
                 name: "test-session".to_string(),
                 token: "test-token".to_string(),
                 url: "https://test.com/s/test-session".to_string(),
+                host_key: "test-host-key".to_string(),
+                spectator_url: String::new(),
             })
         }
 
@@ -709,6 +979,183 @@ mod tests {
             grpc_to_websocket_url("https://sshx.io", "my-terminal"),
             "wss://sshx.io/api/cli/my-terminal"
         );
+
+        // Custom path prefix, with or without slashes
+        assert_eq!(
+            grpc_to_websocket_url_with("https://sshx.io/", "abc", "proxy/sshx/"),
+            "wss://sshx.io/proxy/sshx/cli/abc"
+        );
+        assert_eq!(
+            grpc_to_websocket_url_with("http://localhost:8051", "abc", "/"),
+            "ws://localhost:8051/cli/abc"
+        );
+    }
+
+    #[test]
+    fn test_parse_header() {
+        assert_eq!(
+            parse_header("CF-Access-Client-Id: abc.access"),
+            Ok(("cf-access-client-id".into(), "abc.access".into()))
+        );
+        assert_eq!(parse_header("X-Empty:"), Ok(("x-empty".into(), "".into())));
+        assert!(parse_header("no-colon").is_err());
+        assert!(parse_header("bad name: value").is_err());
+        assert!(parse_header("X-Newline: a\nb").is_err());
+        assert!(parse_header("Connection: close").is_err());
+        assert!(parse_header("Upgrade: h2c").is_err());
+        assert!(parse_header("grpc-timeout: 1S").is_err());
+        assert!(parse_header("Sec-WebSocket-Key: abc").is_err());
+    }
+
+    #[test]
+    fn test_header_interceptor() {
+        let headers = [("cf-access-client-id".to_string(), "abc".to_string())];
+        let mut interceptor = HeaderInterceptor::new(&headers);
+        let request = interceptor.call(Request::new(())).unwrap();
+        assert_eq!(request.metadata().get("cf-access-client-id").unwrap(), "abc");
+    }
+
+    #[tokio::test]
+    #[allow(clippy::result_large_err)]
+    async fn test_websocket_headers() {
+        use tokio::net::TcpListener;
+        use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut value = None;
+            let callback = |req: &Request, resp: Response| {
+                value = req.headers().get("cf-access-client-id").cloned();
+                Ok(resp)
+            };
+            tokio_tungstenite::accept_hdr_async(stream, callback).await.ok();
+            value
+        });
+
+        let headers = [("cf-access-client-id".to_string(), "abc".to_string())];
+        let endpoint = format!("ws://{addr}/api/cli/test");
+        let options = WsConnectOptions::default();
+        WebSocketTransport::connect_with(&endpoint, &Dialer::default(), &options, &headers)
+            .await
+            .unwrap();
+        assert_eq!(server.await.unwrap().unwrap(), "abc");
+    }
+
+    #[tokio::test]
+    async fn test_websocket_closed_fails_pending() {
+        use tokio::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            // Read the request, then drop the connection without responding.
+            ws.next().await;
+        });
+
+        let endpoint = format!("ws://{addr}/api/cli/test");
+        let mut transport = WebSocketTransport::connect(&endpoint).await.unwrap();
+        let request = OpenRequest {
+            origin: "test".to_string(),
+            encrypted_zeros: vec![].into(),
+            name: "test".to_string(),
+            write_password_hash: None,
+            labels: Default::default(),
+            allow_spectators: false,
+            max_clients: 0,
+            write_window_secs: 0,
+            write_window_renew: false,
+            start_locked: false,
+        };
+        let result = timeout(Duration::from_secs(5), transport.open(request.clone())).await;
+        let err = result.expect("pending request should fail promptly").unwrap_err();
+        assert!(format!("{err:#}").contains("connection closed"), "{err:#}");
+
+        let err = transport.open(request).await.unwrap_err();
+        assert!(format!("{err:#}").contains("connection closed"), "{err:#}");
+    }
+
+    #[tokio::test]
+    async fn test_websocket_close_code() {
+        use tokio::net::TcpListener;
+        use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            ws.next().await;
+            let frame = CloseFrame {
+                code: 4404.into(),
+                reason: "could not find the requested session".into(),
+            };
+            ws.close(Some(frame)).await.ok();
+        });
+
+        let endpoint = format!("ws://{addr}/api/cli/test");
+        let mut transport = WebSocketTransport::connect(&endpoint).await.unwrap();
+        let request = CloseRequest {
+            name: "test".into(),
+            token: "token".into(),
+        };
+        let err = transport.close(request).await.unwrap_err();
+        assert!(format!("{err:#}").contains("session not found or expired (code 4404"));
+    }
+
+    #[tokio::test]
+    async fn test_streaming_flag_routing() {
+        let (server_tx, mut server_rx) = mpsc::channel(1);
+        let pending_requests: PendingRequests = Arc::new(Mutex::new(Ok(HashMap::new())));
+        let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+        let reply_id = "server_update".to_string();
+        pending_requests.lock().await.as_mut().unwrap().insert(reply_id.clone(), reply_tx);
+
+        // A reply is routed by its ID, even if that looks like a streaming push.
+        let reply = CliResponse {
+            id: reply_id,
+            streaming: false,
+            cli_response_message: Some(cli_response::CliResponseMessage::CloseShell(1)),
+        };
+        let data = reply.encode_to_vec();
+        WebSocketTransport::handle_binary_message(&data, &server_tx, &pending_requests)
+            .await
+            .unwrap();
+        assert_eq!(reply_rx.await.unwrap(), cli_response::CliResponseMessage::CloseShell(1));
+        assert!(server_rx.try_recv().is_err());
+
+        let push = CliResponse {
+            id: String::new(),
+            streaming: true,
+            cli_response_message: Some(cli_response::CliResponseMessage::CloseShell(2)),
+        };
+        let data = push.encode_to_vec();
+        WebSocketTransport::handle_binary_message(&data, &server_tx, &pending_requests)
+            .await
+            .unwrap();
+        let update = server_rx.try_recv().unwrap();
+        assert_eq!(update.server_message, Some(ServerMessage::CloseShell(2)));
+    }
+
+    #[test]
+    fn test_describe_close() {
+        assert_eq!(describe_close(4404, ""), "session not found or expired (code 4404)");
+        assert_eq!(
+            describe_close(4500, "proxy redirect: timed out"),
+            "server failed to connect to the session (code 4500: proxy redirect: timed out)"
+        );
+        assert_eq!(describe_close(4999, ""), "connection closed by the server (code 4999)");
+    }
+
+    #[test]
+    fn test_normalize_path_prefix() {
+        assert_eq!(normalize_path_prefix("/api"), "/api");
+        assert_eq!(normalize_path_prefix("api/"), "/api");
+        assert_eq!(normalize_path_prefix("//a/b//"), "/a/b");
+        assert_eq!(normalize_path_prefix(""), "");
     }
 
     #[tokio::test]
@@ -721,6 +1168,12 @@ mod tests {
             encrypted_zeros: vec![].into(),
             name: "test".to_string(),
             write_password_hash: None,
+            labels: Default::default(),
+            allow_spectators: false,
+            max_clients: 0,
+            write_window_secs: 0,
+            write_window_renew: false,
+            start_locked: false,
         };
         
         let result = transport.open(request).await;
@@ -741,6 +1194,12 @@ mod tests {
             encrypted_zeros: vec![].into(),
             name: "test".to_string(),
             write_password_hash: None,
+            labels: Default::default(),
+            allow_spectators: false,
+            max_clients: 0,
+            write_window_secs: 0,
+            write_window_renew: false,
+            start_locked: false,
         };
         
         let result = transport.open(request).await;
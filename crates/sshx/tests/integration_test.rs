@@ -6,7 +6,6 @@ use sshx::transport::test_helpers::MockTransport;
 use sshx::transport::SshxTransport;
 use sshx_core::proto::OpenRequest;
 use std::time::Duration;
-use tokio;
 
 #[tokio::test]
 async fn test_connection_fallback_user_experience() -> Result<()> {
@@ -58,6 +57,12 @@ async fn test_mock_transport_functionality() -> Result<()> {
         encrypted_zeros: vec![].into(),
         name: "test".to_string(),
         write_password_hash: None,
+        labels: Default::default(),
+        allow_spectators: false,
+        max_clients: 0,
+        write_window_secs: 0,
+        write_window_renew: false,
+        start_locked: false,
     };
     
     let result = error_transport.open(request).await;
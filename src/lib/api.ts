@@ -16,8 +16,10 @@ export interface SessionInfo {
   shellCount: number;
   userCount: number;
   hasWritePassword: boolean;
+  defaultWritable: boolean;
   lastAccessed: number;
   users: string[];
+  labels: Record<string, string>;
   metadata?: SessionMetadata;
 }
 
@@ -39,6 +41,7 @@ export interface SessionListParams {
   page?: number;
   pageSize?: number;
   search?: string;
+  label?: string;
   sort?: string;
   order?: 'asc' | 'desc';
 }
@@ -90,6 +93,7 @@ export async function fetchSessions(dashboardKey: string, params: SessionListPar
   if (params.page) searchParams.set('page', params.page.toString());
   if (params.pageSize) searchParams.set('pageSize', params.pageSize.toString());
   if (params.search) searchParams.set('search', params.search);
+  if (params.label) searchParams.set('label', params.label);
   if (params.sort) searchParams.set('sort', params.sort);
   if (params.order) searchParams.set('order', params.order);
   
@@ -15,6 +15,8 @@ export type WsUser = {
   cursor: [number, number] | null;
   focus: number | null;
   canWrite: boolean;
+  hue?: number; // missing from older servers
+  terminal?: string;
 };
 
 /** Server message type, see the Rust version. */
@@ -23,8 +25,11 @@ export type WsServer = {
   invalidAuth?: [];
   users?: [Uid, WsUser][];
   userDiff?: [Uid, WsUser | null];
-  shells?: [Sid, WsWinsize][];
+  shells?: [Sid, WsWinsize, string | null][];
+  shellExit?: [Sid, number | null];
+  paused?: boolean;
   chunks?: [Sid, number, Uint8Array[]];
+  snapshot?: [Sid, number, Uint8Array[]];
   hear?: [Uid, string, string];
   shellLatency?: number | bigint;
   pong?: number | bigint;
@@ -35,13 +40,19 @@ export type WsServer = {
 export type WsClient = {
   authenticate?: [Uint8Array, Uint8Array | null];
   setName?: string;
+  setTerminal?: string;
   setCursor?: [number, number] | null;
   setFocus?: number | null;
   create?: [number, number];
   close?: Sid;
   move?: [Sid, WsWinsize | null];
+  setShellName?: [Sid, string];
   data?: [Sid, Uint8Array, bigint];
   subscribe?: [Sid, number];
+  resume?: [Sid, number][];
   chat?: string;
   ping?: bigint;
+  claimHost?: string;
+  setPaused?: boolean;
+  requestSnapshot?: Sid;
 };